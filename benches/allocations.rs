@@ -0,0 +1,165 @@
+//! Counts the allocations made while collecting trees of a few shapes, which
+//! is the baseline for any change to how trees are stored, and while
+//! formatting and writing them with [`Pretty`] through the [`blocking`],
+//! [`worker`], and [`async_spawn`] processors, with and without the buffer
+//! pool of [`Pretty`].
+//!
+//! Run with `cargo bench --bench allocations`, and again with
+//! `--features arena` to compare reusing the allocations of formatted trees,
+//! or with `--features smallvec-large` to compare inline capacities. Trees
+//! that are only collected aren't formatted, so they aren't recycled.
+//!
+//! [`Pretty`]: tracing_forest::formatter::pretty::Pretty
+//! [`blocking`]: tracing_forest::blocking
+//! [`worker`]: tracing_forest::worker
+//! [`async_spawn`]: tracing_forest::processor::sync::async_spawn
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing_forest::formatter::pretty::Pretty;
+use tracing_forest::layer::Tree;
+use tracing_forest::processor::sync::async_spawn;
+use tracing_forest::Processor;
+
+/// Counts allocations, including reallocations, made through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const TREES: usize = 10_000;
+
+/// Discards trees, so only the cost of collecting them is counted.
+struct Discard;
+
+impl Processor for Discard {
+    fn process(&self, tree: Tree) {
+        drop(tree);
+    }
+}
+
+/// Runs `request` once to register its callsites, which allocates, and then
+/// counts the allocations made by `run` calling it [`TREES`] times.
+fn count(name: &str, request: fn(), run: impl FnOnce(&dyn Fn())) {
+    request();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run(&|| {
+        for _ in 0..TREES {
+            request();
+        }
+    });
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{:<12} {:>6.1} allocations per tree",
+        name,
+        allocations as f64 / TREES as f64
+    );
+}
+
+/// Runs `requests` with `processor`, which is dropped before returning.
+fn with<P: Processor + Send + Sync>(processor: P, requests: &dyn Fn()) {
+    tracing::subscriber::with_default(processor.into_layer().into_subscriber(), requests);
+}
+
+fn event() {
+    tracing::info!(user = "alice", "handled request")
+}
+
+fn span() {
+    tracing::info_span!("request", method = "GET").in_scope(|| {
+        tracing::info!(user = "alice", "handled request");
+    });
+}
+
+fn nested() {
+    tracing::info_span!("request", method = "GET").in_scope(|| {
+        tracing::debug_span!("query", table = "users").in_scope(|| {
+            tracing::debug!(rows = 3, "fetched");
+        });
+        tracing::info!(user = "alice", "handled request");
+    });
+}
+
+fn children() {
+    tracing::info_span!("request").in_scope(|| {
+        for i in 0..10 {
+            tracing::info!(i, "step");
+        }
+    });
+}
+
+const SHAPES: [(&str, fn()); 4] = [
+    ("event", event),
+    ("span", span),
+    ("nested", nested),
+    ("10 children", children),
+];
+
+fn main() {
+    println!("collecting");
+    for (name, request) in SHAPES {
+        count(name, request, |requests| {
+            // Register the callsites with this subscriber too
+            with(Discard, &|| {
+                request();
+                requests();
+            })
+        });
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    for (pool, pool_size) in [("with the Pretty pool", 4), ("without the Pretty pool", 0)] {
+        let pretty = || Pretty::new().with_pool_size(pool_size);
+
+        println!("blocking, {}", pool);
+        for (name, request) in SHAPES {
+            count(name, request, |requests| {
+                with(tracing_forest::blocking(pretty(), io::sink), requests)
+            });
+        }
+
+        println!("worker, {}", pool);
+        for (name, request) in SHAPES {
+            count(name, request, |requests| {
+                let (processor, guard) = tracing_forest::worker(pretty(), io::sink);
+                with(processor, requests);
+                // Waits for every tree to be written
+                drop(guard);
+            });
+        }
+
+        println!("async, {}", pool);
+        for (name, request) in SHAPES {
+            count(name, request, |requests| {
+                runtime.block_on(async {
+                    let (processor, handle) = async_spawn(pretty(), io::sink);
+                    with(processor, requests);
+                    handle.await.unwrap();
+                })
+            });
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Measures the per-span overhead of collecting trees, with IDs generated when
+//! every root span is opened and with IDs generated lazily, and with
+//! timestamps read from the system clock and from the coarse clock.
+//!
+//! Run with `cargo bench --bench overhead`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tracing_forest::layer::{Tree, TreeLayer};
+use tracing_forest::Processor;
+use tracing_subscriber::Registry;
+
+/// Discards trees, so only the cost of collecting them is measured.
+struct Discard;
+
+impl Processor for Discard {
+    fn process(&self, tree: Tree) {
+        black_box(tree);
+    }
+}
+
+fn collect(c: &mut Criterion, name: &str, layer: TreeLayer<Discard>, request_id: bool) {
+    tracing::subscriber::with_default(layer.into_subscriber(), || {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                tracing::info_span!("request").in_scope(|| {
+                    tracing::info!(user = "alice", "handled request");
+                    if request_id {
+                        black_box(tracing_forest::id::<Registry>());
+                    }
+                });
+            })
+        });
+    });
+}
+
+fn overhead(c: &mut Criterion) {
+    collect(c, "eager ids", Discard.into_layer(), false);
+    collect(c, "lazy ids", Discard.into_layer().lazy_uuids(true), false);
+    collect(
+        c,
+        "lazy ids, requested",
+        Discard.into_layer().lazy_uuids(true),
+        true,
+    );
+    collect(
+        c,
+        "lazy ids, sampled out",
+        Discard.into_layer().lazy_uuids(true).sample_rate(0.0),
+        false,
+    );
+    collect(
+        c,
+        "coarse timestamps",
+        Discard.into_layer().coarse_timestamps(true),
+        false,
+    );
+}
+
+criterion_group!(benches, overhead);
+criterion_main!(benches);
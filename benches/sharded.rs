@@ -0,0 +1,45 @@
+//! Compares the throughput of a single processing task against sharding
+//! trees across several.
+//!
+//! Run with `cargo bench --bench sharded`.
+
+use std::time::{Duration, Instant};
+use tracing_forest::formatter::pretty::Pretty;
+use tracing_forest::processor::sync::{async_spawn, async_spawn_sharded, AsyncProcessor};
+use tracing_forest::Processor;
+
+const TREES: usize = 100_000;
+
+fn run(spawn: impl FnOnce() -> (AsyncProcessor, tokio::task::JoinHandle<()>)) -> Duration {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let (processor, handle) = spawn();
+        let start = Instant::now();
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            for i in 0..TREES {
+                tracing::info_span!("request", i).in_scope(|| {
+                    tracing::info!(user = "alice", "handling request");
+                    tracing::debug!("request handled");
+                });
+            }
+        });
+
+        handle.await.unwrap();
+        start.elapsed()
+    })
+}
+
+fn main() {
+    let single = run(|| async_spawn(Pretty::new(), std::io::sink));
+    println!("1 worker:  {:?}", single);
+
+    for workers in [2, 4, 8] {
+        let sharded = run(|| async_spawn_sharded(Pretty::new(), std::io::sink, workers));
+        println!("{} workers: {:?}", workers, sharded);
+    }
+}
@@ -0,0 +1,58 @@
+//! Measures the cost of collecting trees whose spans and events have few or
+//! many fields, which depends on how many fields are stored inline.
+//!
+//! Run with `cargo bench --bench storage`, and again with
+//! `--features smallvec-large` to compare inline capacities.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tracing_forest::layer::Tree;
+use tracing_forest::Processor;
+
+/// Discards trees, so only the cost of collecting them is measured.
+struct Discard;
+
+impl Processor for Discard {
+    fn process(&self, tree: Tree) {
+        black_box(tree);
+    }
+}
+
+fn storage(c: &mut Criterion) {
+    tracing::subscriber::with_default(Discard.into_layer().into_subscriber(), || {
+        c.bench_function("2 fields", |b| {
+            b.iter(|| {
+                tracing::info_span!("request", method = "GET").in_scope(|| {
+                    tracing::info!(user = "alice", "handled request");
+                });
+            })
+        });
+        c.bench_function("10 fields", |b| {
+            b.iter(|| {
+                tracing::info_span!("request", method = "GET", path = "/", status = 200, len = 0)
+                    .in_scope(|| {
+                        tracing::info!(
+                            user = "alice",
+                            rows = 3,
+                            cached = false,
+                            shard = 1,
+                            region = "eu",
+                            retries = 0,
+                            "handled request"
+                        );
+                    });
+            })
+        });
+        c.bench_function("10 children", |b| {
+            b.iter(|| {
+                tracing::info_span!("request").in_scope(|| {
+                    for i in 0..10 {
+                        tracing::info!(i, "step");
+                    }
+                });
+            })
+        });
+    });
+}
+
+criterion_group!(benches, storage);
+criterion_main!(benches);
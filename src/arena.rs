@@ -0,0 +1,230 @@
+//! Reusing the allocations of processed trees to collect the next ones.
+//!
+//! Collecting a tree allocates a few times for every node: the formatted
+//! value of each field, the message of each event, and the list of children
+//! of each span. Requests tend to log trees of the same shape over and over,
+//! so with the `arena` feature, these allocations are taken from an arena
+//! that processed trees are given back to with [`recycle`]. Once the arena is
+//! warm, collecting a tree only allocates for the spans themselves.
+//!
+//! Every node of a tree still owns its data, since processors may keep trees
+//! long after they're processed, like [`RecentTrees`], or send them to other
+//! threads. A tree that isn't recycled is freed normally. [`Pretty`] recycles
+//! every tree that it formats, and custom [`Formatter`]s and [`Processor`]s
+//! can call [`recycle`] once they're done with a tree.
+//!
+//! Recycled trees go to an arena of the thread that recycles them, which
+//! hands the buffers it has no room for to an arena that's shared by every
+//! thread. Threads take buffers from the shared arena in batches, so neither
+//! collecting nor recycling a tree holds its lock for long. The shared arena
+//! holds on to at most a few hundred kilobytes, each thread to a few
+//! kilobytes more, and neither keeps buffers that grew large.
+//!
+//! Run `cargo bench --bench allocations --features arena` to compare.
+//!
+//! [`RecentTrees`]: crate::processor::recent::RecentTrees
+//! [`Pretty`]: crate::formatter::pretty::Pretty
+//! [`Formatter`]: crate::formatter::Formatter
+//! [`Processor`]: crate::processor::Processor
+
+use crate::tree::{Tree, TreeKind};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+/// How many strings the shared arena keeps.
+const STRINGS: usize = 2048;
+
+/// How many lists of children the shared arena keeps.
+const CHILDREN: usize = 64;
+
+/// How many buffers of each kind a thread takes from the shared arena at once.
+const BATCH: usize = 32;
+
+/// How many buffers of each kind a thread keeps from the trees it recycles,
+/// before handing the rest to the shared arena.
+const KEEP: usize = 2 * BATCH;
+
+/// The most bytes a kept string can hold on to.
+const MAX_STRING: usize = 128;
+
+/// The most children a kept list of children can hold on to.
+const MAX_CHILDREN: usize = 16;
+
+/// Empty buffers, ready to be reused.
+struct Arena {
+    strings: Vec<String>,
+    children: Vec<Vec<Tree>>,
+}
+
+impl Arena {
+    const fn new() -> Self {
+        Arena {
+            strings: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Takes the buffers of every node of `tree` that aren't too large.
+    fn reclaim(&mut self, tree: Tree) {
+        match tree.kind {
+            TreeKind::Event(event) => {
+                if let Cow::Owned(message) = event.message {
+                    self.string(message);
+                }
+                for kv in event.fields {
+                    self.string(kv.value);
+                }
+            }
+            TreeKind::Span(span) => {
+                for kv in span.fields {
+                    self.string(kv.value);
+                }
+                let mut children = span.children;
+                for child in children.drain(..) {
+                    self.reclaim(child);
+                }
+                if children.capacity() <= MAX_CHILDREN {
+                    self.children.push(children);
+                }
+            }
+        }
+    }
+
+    fn string(&mut self, mut string: String) {
+        if string.capacity() <= MAX_STRING {
+            string.clear();
+            self.strings.push(string);
+        }
+    }
+
+    /// Removes the buffers of each kind beyond the first `keep`.
+    fn split_off(&mut self, keep: usize) -> Arena {
+        Arena {
+            strings: self.strings.split_off(keep.min(self.strings.len())),
+            children: self.children.split_off(keep.min(self.children.len())),
+        }
+    }
+
+    /// Moves as many buffers from `other` as the shared arena has room for.
+    fn fill(&mut self, other: &mut Arena) {
+        let room = STRINGS.saturating_sub(self.strings.len());
+        let start = other.strings.len().saturating_sub(room);
+        self.strings.extend(other.strings.drain(start..));
+
+        let room = CHILDREN.saturating_sub(self.children.len());
+        let start = other.children.len().saturating_sub(room);
+        self.children.extend(other.children.drain(start..));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.strings.is_empty() && self.children.is_empty()
+    }
+}
+
+static SHARED: Mutex<Arena> = Mutex::new(Arena::new());
+
+std::thread_local! {
+    static LOCAL: RefCell<Arena> = const { RefCell::new(Arena::new()) };
+}
+
+/// Takes a buffer from this thread's buffers that `pick` chooses, after
+/// refilling them from the shared arena if they ran out.
+fn take<T: Default>(pick: fn(&mut Arena) -> &mut Vec<T>) -> T {
+    // Threads that are exiting can't reach their buffers anymore
+    let local = LOCAL.try_with(|local| {
+        let mut local = local.borrow_mut();
+        let local = pick(&mut local);
+        if local.is_empty() {
+            #[allow(clippy::expect_used)]
+            let mut shared = SHARED.lock().expect("tree arena poisoned");
+            let shared = pick(&mut shared);
+            let start = shared.len().saturating_sub(BATCH);
+            local.extend(shared.drain(start..));
+        }
+        local.pop()
+    });
+    local.ok().flatten().unwrap_or_default()
+}
+
+/// Returns an empty string, reusing one from a recycled tree if there is one.
+pub(crate) fn string() -> String {
+    take(|arena| &mut arena.strings)
+}
+
+/// Returns an empty list of children, reusing one from a recycled tree if
+/// there is one.
+pub(crate) fn children() -> Vec<Tree> {
+    take(|arena| &mut arena.children)
+}
+
+/// Gives the allocations of `tree` back to the arena, for collecting the next
+/// trees.
+///
+/// This is an optimization, so processors that are done with a tree can call
+/// it instead of dropping the tree. Buffers that neither the arena of this
+/// thread nor the shared arena have room for are freed.
+///
+/// # Examples
+///
+/// ```
+/// use tracing_forest::layer::Tree;
+/// use tracing_forest::Processor;
+///
+/// struct Levels;
+///
+/// impl Processor for Levels {
+///     fn process(&self, tree: Tree) {
+///         println!("{}", tree.attrs.level);
+///         tracing_forest::arena::recycle(tree);
+///     }
+/// }
+/// ```
+pub fn recycle(tree: Tree) {
+    // Threads that are exiting can't reach their buffers anymore, and free
+    // the tree instead
+    let overflow = LOCAL.try_with(|local| {
+        let mut local = local.borrow_mut();
+        local.reclaim(tree);
+        local.split_off(KEEP)
+    });
+    let mut overflow = match overflow {
+        Ok(overflow) if !overflow.is_empty() => overflow,
+        _ => return,
+    };
+    #[allow(clippy::expect_used)]
+    SHARED
+        .lock()
+        .expect("tree arena poisoned")
+        .fill(&mut overflow);
+    // What the shared arena had no room for is freed after it's unlocked
+    drop(overflow);
+}
+
+/// How many empty buffers are held by the arena shared by every thread, as
+/// returned by [`pooled`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pooled {
+    /// Strings for messages and field values, of which at most 2048 are kept.
+    pub strings: usize,
+    /// Lists of the children of spans, of which at most 64 are kept.
+    pub children: usize,
+}
+
+/// Returns how many empty buffers are held by the arena shared by every
+/// thread, not counting those held by each thread.
+///
+/// # Examples
+///
+/// ```
+/// let pooled = tracing_forest::arena::pooled();
+/// assert!(pooled.strings <= 2048);
+/// ```
+pub fn pooled() -> Pooled {
+    #[allow(clippy::expect_used)]
+    let shared = SHARED.lock().expect("tree arena poisoned");
+    Pooled {
+        strings: shared.strings.len(),
+        children: shared.children.len(),
+    }
+}
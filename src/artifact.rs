@@ -0,0 +1,105 @@
+//! Log artifacts of failed tests, written by
+//! `#[tracing_forest::test(artifact)]`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Buffers the formatted trees of a test, and writes them to
+/// `target/forest/<test name>.log` if the test fails.
+///
+/// A test fails by panicking, so the buffer is written when the artifact is
+/// dropped while its thread is panicking, and discarded otherwise.
+#[doc(hidden)]
+pub struct Artifact {
+    name: &'static str,
+    buf: ArtifactWriter,
+}
+
+/// The writer of an [`Artifact`]'s buffer.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct ArtifactWriter(Arc<Mutex<Vec<u8>>>);
+
+impl Artifact {
+    /// Construct a new [`Artifact`] for the test at the module path `name`.
+    pub fn new(name: &'static str) -> Self {
+        Artifact {
+            name,
+            buf: ArtifactWriter(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    /// Returns a writer into the artifact's buffer.
+    pub fn writer(&self) -> ArtifactWriter {
+        self.buf.clone()
+    }
+
+    /// Returns the path the artifact is written to.
+    ///
+    /// This is under `$CARGO_TARGET_DIR` if it's set, and otherwise the
+    /// target directory that the test binary was built in.
+    pub fn path(&self) -> PathBuf {
+        let target = std::env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .or_else(|| {
+                // Test binaries are built into `target/<profile>/deps`
+                let exe = std::env::current_exe().ok()?;
+                Some(exe.parent()?.parent()?.parent()?.to_path_buf())
+            })
+            .unwrap_or_else(|| PathBuf::from("target"));
+
+        target
+            .join("forest")
+            .join(format!("{}.log", self.name.replace("::", "-")))
+    }
+
+    fn write(&self) -> io::Result<PathBuf> {
+        let path = self.path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        #[allow(clippy::expect_used)]
+        let buf = self.buf.0.lock().expect("artifact poisoned");
+        fs::write(&path, &buf[..])?;
+        Ok(path)
+    }
+}
+
+impl Drop for Artifact {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        match self.write() {
+            Ok(path) => eprintln!("tracing-forest: trees written to {}", path.display()),
+            Err(err) => eprintln!("tracing-forest: failed to write trees: {}", err),
+        }
+    }
+}
+
+impl Write for &ArtifactWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[allow(clippy::expect_used)]
+        self.0
+            .lock()
+            .expect("artifact poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for ArtifactWriter {
+    type Writer = &'a ArtifactWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
@@ -0,0 +1,122 @@
+//! Fields attached to every event of an async task, like a request ID.
+//!
+//! Run a future with [`scope`] to give it its own baggage, then [`insert`]
+//! fields into it from anywhere inside the task. Every event logged by the
+//! task gets the fields, whichever spans it's in, without adding them to
+//! each callsite.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[tracing_forest::test]
+//! # #[tokio::test]
+//! # async fn test_baggage() {
+//! # async fn charge() {
+//! #     tracing::info!("charged");
+//! # }
+//! tracing_forest::baggage::scope(async {
+//!     tracing_forest::baggage::insert("request_id", 42);
+//!
+//!     // Logged with `request_id = 42`
+//!     tracing::info!("received");
+//!     charge().await;
+//! })
+//! .await;
+//! # }
+//! ```
+//!
+//! [`scope`]: scope
+//! [`insert`]: insert
+
+use crate::layer::KeyValue;
+use crate::tree::Fields;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::future::Future;
+
+tokio::task_local! {
+    static BAGGAGE: RefCell<Vec<KeyValue>>;
+}
+
+/// Runs `future` with its own baggage, which starts as a copy of the baggage
+/// of the current task, if it has any.
+///
+/// Fields inserted inside of `future` aren't seen outside of it.
+/// [`spawn_in_tree`] does this for the tasks it spawns, so they keep the
+/// baggage of the task that spawned them.
+///
+/// [`spawn_in_tree`]: crate::spawn_in_tree
+pub fn scope<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    BAGGAGE.scope(RefCell::new(current()), future)
+}
+
+/// Runs `f` with its own baggage, like [`scope`] does for futures.
+pub fn sync_scope<R>(f: impl FnOnce() -> R) -> R {
+    BAGGAGE.sync_scope(RefCell::new(current()), f)
+}
+
+/// Inserts the field `key` into the baggage of the current task, replacing
+/// it if it was already there.
+///
+/// Like the fields of events, `value` is recorded with its [`Debug`]
+/// implementation, so strings are quoted.
+///
+/// Returns `false` and does nothing if the task isn't running inside of a
+/// [`scope`].
+pub fn insert(key: &'static str, value: impl Debug) -> bool {
+    // Formatting the value could log an event, which reads the baggage
+    let value = format!("{:?}", value);
+    BAGGAGE
+        .try_with(|baggage| {
+            let mut baggage = baggage.borrow_mut();
+            match baggage.iter_mut().find(|kv| kv.key == key) {
+                Some(kv) => kv.value = value,
+                None => baggage.push(KeyValue::new(key, value)),
+            }
+        })
+        .is_ok()
+}
+
+/// Removes the field `key` from the baggage of the current task, returning
+/// its value if it was there.
+pub fn remove(key: &str) -> Option<String> {
+    BAGGAGE
+        .try_with(|baggage| {
+            let mut baggage = baggage.borrow_mut();
+            let idx = baggage.iter().position(|kv| kv.key == key)?;
+            Some(baggage.remove(idx).value)
+        })
+        .ok()
+        .flatten()
+}
+
+/// Returns the value of the field `key` in the baggage of the current task.
+pub fn get(key: &str) -> Option<String> {
+    BAGGAGE
+        .try_with(|baggage| {
+            let baggage = baggage.borrow();
+            Some(baggage.iter().find(|kv| kv.key == key)?.value.clone())
+        })
+        .ok()
+        .flatten()
+}
+
+fn current() -> Vec<KeyValue> {
+    BAGGAGE
+        .try_with(|baggage| baggage.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// Adds the baggage of the current task to the `fields` of an event, unless
+/// the event already has a field with the same key.
+pub(crate) fn attach(fields: &mut Fields) {
+    let _ = BAGGAGE.try_with(|baggage| {
+        let baggage = baggage.borrow();
+        let recorded = fields.len();
+        for kv in baggage.iter() {
+            if !fields[..recorded].iter().any(|field| field.key == kv.key) {
+                fields.push(kv.clone());
+            }
+        }
+    });
+}
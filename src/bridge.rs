@@ -0,0 +1,255 @@
+//! Graft trees written by child processes into the trees of the current
+//! process.
+//!
+//! See [`graft`] for more details.
+
+use crate::layer;
+use crate::private::{DEBUG_ICON, ERROR_ICON, INFO_ICON, TRACE_ICON, WARN_ICON};
+use crate::tag::{Severity, TagData};
+use crate::tree::{Tree, TreeKind};
+use serde::de::Error;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::{Level, Span};
+
+/// Parse a [`Tree`] from a line written by the [`Json`] formatter with the
+/// default schema.
+///
+/// Span names, field keys, and tag messages are leaked so they can be stored
+/// in a [`Tree`], but each distinct string is only leaked once. Tags keep
+/// their message, and take their icon and severity from the level of their
+/// event.
+///
+/// # Errors
+///
+/// Returns an error if `line` isn't a serialized tree.
+///
+/// [`Json`]: crate::formatter::json::Json
+pub fn parse_tree(line: &str) -> serde_json::Result<Tree> {
+    parse_value(&serde_json::from_str(line)?)
+}
+
+/// Convert a [`Value`] with the default schema of the [`Json`] formatter into
+/// a [`Tree`], like [`parse_tree`].
+///
+/// [`Json`]: crate::formatter::json::Json
+pub(crate) fn parse_value(value: &Value) -> serde_json::Result<Tree> {
+    tree(value).ok_or_else(|| serde_json::Error::custom("expected a serialized tree"))
+}
+
+/// Read trees from `reader`, one per line, and graft each of them into `span`
+/// as its last child.
+///
+/// This lets multi-process tools, like build systems and test runners, have
+/// child processes log compact JSON trees to stdout or stderr, and combine
+/// them into one tree. Lines that aren't trees, like output from code that
+/// doesn't use `tracing`, are grafted as `INFO` events with the line as the
+/// message.
+///
+/// Trees are processed by the current [`TreeLayer`] instead if `span` isn't
+/// collected by one, such as when it's disabled.
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::bridge;
+/// let output = r#"{"level":"INFO","kind":{"Event":{"tag":null,"message":"built","fields":{}}}}"#;
+///
+/// tracing_forest::capture().run(|| {
+///     tracing::info_span!("build").in_scope(|| {
+///         bridge::graft(output.as_bytes(), &tracing::Span::current()).unwrap();
+///     });
+/// });
+/// ```
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+pub fn graft<R: BufRead>(reader: R, span: &Span) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tree = parse_tree(&line).unwrap_or_else(|_| Tree::event(Level::INFO, line));
+        layer::graft(span, tree);
+    }
+    Ok(())
+}
+
+/// Run `command` to completion, grafting the trees it writes to stdout and
+/// stderr into the current span.
+///
+/// Both streams of the command are replaced with pipes. See [`graft`] for
+/// details on how lines are grafted.
+///
+/// # Errors
+///
+/// Returns an error if the command can't be spawned, or reading its output
+/// fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::process::Command;
+/// # use tracing_forest::bridge;
+/// tracing::info_span!("test suite").in_scope(|| {
+///     let status = bridge::run(Command::new("cargo").arg("test")).expect("failed to run tests");
+///     assert!(status.success());
+/// });
+/// ```
+pub fn run(command: &mut Command) -> io::Result<ExitStatus> {
+    let span = Span::current();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = child.stderr.take().map(|stderr| {
+        let span = span.clone();
+        let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+        thread::spawn(move || {
+            tracing::dispatcher::with_default(&dispatch, || graft_raw(stderr, &span))
+        })
+    });
+
+    let stdout = match child.stdout.take() {
+        Some(stdout) => graft_raw(stdout, &span),
+        None => Ok(()),
+    };
+    let stderr = match stderr {
+        Some(handle) => handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("reading stderr panicked"))),
+        None => Ok(()),
+    };
+
+    let status = child.wait()?;
+    stdout.and(stderr).map(|_| status)
+}
+
+fn graft_raw<R: Read>(reader: R, span: &Span) -> io::Result<()> {
+    graft(BufReader::new(reader), span)
+}
+
+fn tree(value: &Value) -> Option<Tree> {
+    let object = value.as_object()?;
+    let level = object.get("level")?.as_str()?.parse::<Level>().ok()?;
+    let (kind, body) = object.get("kind")?.as_object()?.iter().next()?;
+    let body = body.as_object()?;
+
+    let mut tree = match kind.as_str() {
+        "Span" => span(level, body)?,
+        "Event" => event(level, body)?,
+        _ => return None,
+    };
+
+    #[cfg(feature = "uuid")]
+    if let Some(uuid) = object.get("uuid").and_then(Value::as_str) {
+        tree = tree.with_uuid(uuid.parse().ok()?);
+    }
+    #[cfg(feature = "chrono")]
+    if let Some(timestamp) = object.get("timestamp").and_then(Value::as_str) {
+        let timestamp = chrono::DateTime::parse_from_rfc3339(timestamp).ok()?;
+        tree = tree.with_timestamp(timestamp.with_timezone(&chrono::Utc));
+    }
+    for annotation in object
+        .get("annotations")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match annotation {
+            Value::String(note) => {
+                tree.annotate(note.clone());
+            }
+            Value::Object(field) => {
+                for (key, value) in field {
+                    tree.annotate_field(key.clone(), value.as_str()?);
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    Some(tree)
+}
+
+fn span(level: Level, body: &Map<String, Value>) -> Option<Tree> {
+    let name = intern(body.get("name")?.as_str()?);
+    let nanos = |key| body.get(key).and_then(Value::as_u64).unwrap_or(0);
+
+    let span = Tree::span(level, name).with_duration(Duration::from_nanos(nanos("nanos_total")));
+    let mut span = with_fields(span, body);
+    for child in body.get("children")?.as_array()? {
+        span.add_child(tree(child)?);
+    }
+
+    // Keep the recorded nested duration, even if it includes children that
+    // weren't serialized
+    if let TreeKind::Span(inner) = &mut span.kind {
+        inner.duration_nested = Duration::from_nanos(nanos("nanos_nested"));
+    }
+    Some(span)
+}
+
+fn event(level: Level, body: &Map<String, Value>) -> Option<Tree> {
+    let message = body.get("message")?.as_str()?.to_string();
+    let mut event = Tree::event(level, message);
+
+    if let Some(message) = body.get("tag").and_then(Value::as_str) {
+        event = event.with_tag(tag(level, message));
+    }
+    if let Some(template) = body.get("template").and_then(Value::as_str) {
+        event = event.with_template(template.to_string());
+    }
+    Some(with_fields(event, body))
+}
+
+fn with_fields(mut tree: Tree, body: &Map<String, Value>) -> Tree {
+    if let Some(fields) = body.get("fields").and_then(Value::as_object) {
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            tree = tree.with_field(intern(key), value);
+        }
+    }
+    tree
+}
+
+fn tag(level: Level, message: &str) -> TagData {
+    let (icon, severity) = match level {
+        Level::TRACE => (TRACE_ICON, Severity::Trace),
+        Level::DEBUG => (DEBUG_ICON, Severity::Debug),
+        Level::INFO => (INFO_ICON, Severity::Info),
+        Level::WARN => (WARN_ICON, Severity::Warn),
+        Level::ERROR => (ERROR_ICON, Severity::Error),
+    };
+    TagData {
+        message: intern(message),
+        icon,
+        severity,
+    }
+}
+
+/// Returns a `'static` copy of `string`, leaking each distinct string once.
+fn intern(string: &str) -> &'static str {
+    static INTERNED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+    let mut interned = INTERNED.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(&string) = interned.get(string) {
+        return string;
+    }
+    let string: &'static str = Box::leak(string.to_string().into_boxed_str());
+    interned.insert(string);
+    string
+}
@@ -0,0 +1,1301 @@
+//! Configure a [`TreeLayer`] in a single call chain.
+//!
+//! See [`LayerBuilder`] for more details.
+
+use crate::cfg_sync;
+#[cfg(feature = "config")]
+use crate::config::{ForestConfig, Format, Output};
+use crate::formatter::ascii::Ascii;
+use crate::formatter::pretty::Pretty;
+use crate::formatter::switch::{SwitchHandle, Switchable};
+use crate::formatter::{Ansi, Formatter, Icons, Transformed};
+use crate::layer::{FieldRules, Tree, TreeLayer};
+use crate::processor::blocking::{blocking, BlockingProcessor};
+use crate::processor::filter::Filter;
+use crate::processor::levels::Levels;
+use crate::processor::sample::Sample;
+use crate::processor::tee::Tee;
+use crate::processor::worker::{self, worker, ForestGuard, WorkerProcessor};
+use crate::processor::Processor;
+use crate::tag::{NoTag, Tag, TagParser, TagRegistry};
+#[cfg(feature = "uuid")]
+use crate::uuid::UuidVersion;
+#[cfg(feature = "config")]
+use crate::writer::RotatingFile;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use std::any;
+use std::io;
+use std::mem;
+use std::sync::Arc;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{MakeWriter, TestWriter};
+cfg_sync! {
+    use crate::processor::sync::{async_spawn, async_spawn_sharded, AsyncProcessor};
+    use tokio::task::JoinHandle;
+}
+
+/// A boxed [`Formatter`], as used by builders configured at runtime.
+pub type BoxFormatter = Box<dyn Formatter + Send + Sync>;
+
+/// A boxed [`Processor`], as used by [`DynLayerBuilder`].
+pub type BoxProcessor = Box<dyn Processor + Send + Sync>;
+
+/// A builder for [`TreeLayer`]s, combining a [`Formatter`], a writer, a
+/// [`Tag`] type, and a maximum level.
+///
+/// To initialize a new [`LayerBuilder`], see [`builder`].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::json::Json;
+/// # use tracing::Level;
+/// let _guard = tracing::subscriber::set_default({
+///     tracing_forest::builder()
+///         .formatter(Json::new(true))
+///         .writer(std::io::stderr)
+///         .max_level(Level::INFO)
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+/// ```
+pub struct LayerBuilder<F, W> {
+    formatter: F,
+    make_writer: W,
+    options: Options,
+}
+
+/// Options applied to the [`TreeLayer`] once it's built.
+struct Options {
+    tag_parser: TagParser,
+    max_level: LevelFilter,
+    sample_rate: f64,
+    field_rules: FieldRules,
+    transforms: Vec<fn(Tree) -> Tree>,
+    retroactive_verbosity: bool,
+    #[cfg(feature = "chrono")]
+    coarse_timestamps: bool,
+    #[cfg(feature = "chrono")]
+    wall_clock: Option<fn() -> DateTime<Utc>>,
+    #[cfg(feature = "uuid")]
+    uuid_version: UuidVersion,
+    #[cfg(feature = "uuid")]
+    lazy_uuids: bool,
+    #[cfg(feature = "uuid")]
+    id_field: Option<&'static str>,
+    ansi: Option<Ansi>,
+    icons: Option<Icons>,
+    /// The standard stream that the writer writes to, if it's known to be one.
+    stream: Option<Stream>,
+}
+
+/// A standard stream, which [`Ansi::Auto`] checks for a terminal.
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// Returns the standard stream that the writers made by `W` write to, or
+    /// `None` if they aren't known to write to one.
+    fn of<W: for<'a> MakeWriter<'a>>() -> Option<Stream> {
+        // Writers are compared by name, since they may not be `'static`
+        let writer = any::type_name::<<W as MakeWriter<'static>>::Writer>();
+        if writer == any::type_name::<io::Stdout>() {
+            Some(Stream::Stdout)
+        } else if writer == any::type_name::<io::Stderr>() {
+            Some(Stream::Stderr)
+        } else {
+            None
+        }
+    }
+}
+
+impl Options {
+    /// Returns whether output written to the configured writer should be
+    /// colored with `ansi`.
+    ///
+    /// Writers that aren't known to be stdout or stderr are never treated as
+    /// terminals.
+    fn ansi_enabled(&self, ansi: Ansi) -> bool {
+        match self.stream {
+            Some(Stream::Stdout) => ansi.enabled_for(&io::stdout()),
+            Some(Stream::Stderr) => ansi.enabled_for(&io::stderr()),
+            None => ansi.enabled_if(false),
+        }
+    }
+
+    fn apply<P: Processor>(self, layer: TreeLayer<P>) -> TreeLayer<P> {
+        let layer = layer
+            .tag_parser(self.tag_parser)
+            .max_level(self.max_level)
+            .sample_rate(self.sample_rate)
+            .field_rules(self.field_rules)
+            .retroactive_verbosity(self.retroactive_verbosity);
+        // Live events are written by the layer, not the formatter, to stdout
+        // unless another writer is set on the layer
+        let mut live = Pretty::new();
+        if let Some(ansi) = self.ansi {
+            live.set_ansi(ansi.enabled());
+        }
+        if let Some(icons) = self.icons {
+            live.set_icons(icons);
+        }
+        let layer = layer.live_formatter(live);
+        // Transforms that weren't taken by a processor with a queue run on
+        // the thread that finished the tree
+        let layer = self
+            .transforms
+            .into_iter()
+            .fold(layer, |layer, transform| layer.transform(transform));
+        #[cfg(feature = "chrono")]
+        let layer = layer.coarse_timestamps(self.coarse_timestamps);
+        #[cfg(feature = "chrono")]
+        let layer = match self.wall_clock {
+            Some(now) => layer.wall_clock(now),
+            None => layer,
+        };
+        #[cfg(feature = "uuid")]
+        let layer = layer
+            .uuid_version(self.uuid_version)
+            .lazy_uuids(self.lazy_uuids);
+        #[cfg(feature = "uuid")]
+        let layer = match self.id_field {
+            Some(name) => layer.id_field(name),
+            None => layer,
+        };
+        layer
+    }
+}
+
+/// Sensible combinations of formatting, writing, and filtering for common
+/// environments.
+///
+/// See [`LayerBuilder::preset`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// [`Pretty`] output to stdout, at `DEBUG` and above, colored according to
+    /// [`Ansi::Auto`] unless [`set_ansi`][LayerBuilder::set_ansi] is called.
+    Development,
+    /// Compact [`Json`] output to stdout, at `INFO` and above. Falls back to
+    /// uncolored [`Pretty`] output if the `json` feature is disabled.
+    ///
+    /// [`Json`]: crate::formatter::json::Json
+    Production,
+    /// Uncolored [`Pretty`] output to stdout, at `DEBUG` and above.
+    Ci,
+    /// Deterministic [`Pretty`] output captured by the test harness, at all
+    /// levels. See [`Pretty::with_snapshot`].
+    Test,
+}
+
+/// Initialize a new [`LayerBuilder`], which by default pretty prints all
+/// levels to stdout.
+pub fn builder() -> LayerBuilder<Pretty, fn() -> io::Stdout> {
+    LayerBuilder {
+        formatter: Pretty::new(),
+        make_writer: io::stdout,
+        options: Options {
+            tag_parser: TagParser::of::<NoTag>(),
+            max_level: LevelFilter::TRACE,
+            sample_rate: 1.0,
+            field_rules: FieldRules::new(),
+            transforms: Vec::new(),
+            retroactive_verbosity: false,
+            #[cfg(feature = "chrono")]
+            coarse_timestamps: false,
+            #[cfg(feature = "chrono")]
+            wall_clock: None,
+            #[cfg(feature = "uuid")]
+            uuid_version: UuidVersion::V4,
+            #[cfg(feature = "uuid")]
+            lazy_uuids: false,
+            #[cfg(feature = "uuid")]
+            id_field: None,
+            ansi: None,
+            icons: None,
+            stream: Some(Stream::Stdout),
+        },
+    }
+}
+
+/// Pretty print trees at `DEBUG` and above to stdout, installing the
+/// subscriber globally.
+///
+/// Output is colored according to [`Ansi::Auto`]. This is a shorthand for
+/// [`builder`] with [`Preset::Development`], followed by
+/// [`init`][LayerBuilder::init], so trees are written on a background thread
+/// until the returned guard is dropped.
+///
+/// ## Panics
+///
+/// Panics if a global default subscriber has already been set.
+///
+/// # Examples
+///
+/// ```
+/// let _guard = tracing_forest::init_pretty();
+/// tracing::info!("Hello, world!");
+/// ```
+pub fn init_pretty() -> ForestGuard {
+    builder().preset(Preset::Development).init()
+}
+
+/// Pretty print trees at `DEBUG` and above to stderr, installing the
+/// subscriber globally.
+///
+/// This is like [`init_pretty`], except that stdout is left for the
+/// program's own output, and color depends on whether stderr is a terminal.
+///
+/// ## Panics
+///
+/// Panics if a global default subscriber has already been set.
+pub fn init_pretty_stderr() -> ForestGuard {
+    builder()
+        .writer(io::stderr)
+        .set_ansi(Ansi::Auto)
+        .max_level(LevelFilter::DEBUG)
+        .init()
+}
+
+/// Write trees at `INFO` and above to stdout as compact JSON, installing the
+/// subscriber globally.
+///
+/// This is a shorthand for [`builder`] with [`Preset::Production`], followed
+/// by [`init`][LayerBuilder::init].
+///
+/// ## Panics
+///
+/// Panics if a global default subscriber has already been set.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn init_json() -> ForestGuard {
+    builder().preset(Preset::Production).init()
+}
+
+/// Write deterministic trees of all levels through the test harness, until
+/// the returned guard is dropped.
+///
+/// Unlike other initializers, the subscriber is only installed on the
+/// current thread, so that every test can call this. Trees from tasks running
+/// on other threads, like those of a multithreaded Tokio runtime, aren't
+/// captured. See [`Preset::Test`] for details.
+///
+/// # Examples
+///
+/// ```
+/// let _guard = tracing_forest::init_test();
+/// tracing::info!("written through the test harness");
+/// ```
+pub fn init_test() -> ForestGuard {
+    let subscriber = builder()
+        .preset(Preset::Test)
+        .blocking_layer()
+        .into_subscriber();
+    ForestGuard::scoped(tracing::subscriber::set_default(subscriber))
+}
+
+#[cfg(feature = "config")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+impl LayerBuilder<BoxFormatter, BoxMakeWriter> {
+    /// Initialize a new [`LayerBuilder`] from a [`ForestConfig`], such as
+    /// one loaded from a configuration file.
+    ///
+    /// The builder can still be modified afterwards, for example to set the
+    /// [`Tag`] type, which can't be configured.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the output file cannot be opened, or if the
+    /// `json` format is selected without the `json` feature.
+    pub fn from_config(config: &ForestConfig) -> io::Result<Self> {
+        let formatter: BoxFormatter = match config.format {
+            Format::Pretty => Box::new(Pretty::new().with_ansi(config.ansi)),
+            #[cfg(feature = "json")]
+            Format::Json => Box::new(crate::formatter::json::Json::new(true)),
+            #[cfg(not(feature = "json"))]
+            Format::Json => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the json format requires the `json` feature",
+                ))
+            }
+        };
+
+        let make_writer = match &config.output {
+            Output::Stdout => BoxMakeWriter::new(io::stdout),
+            Output::Stderr => BoxMakeWriter::new(io::stderr),
+            Output::File(path) => {
+                let (max_bytes, keep) = match config.rotation {
+                    Some(rotation) => (rotation.max_bytes, rotation.keep),
+                    None => (u64::MAX, 0),
+                };
+                BoxMakeWriter::new(RotatingFile::new(path, max_bytes)?.keep(keep))
+            }
+        };
+
+        let mut builder = builder()
+            .formatter(formatter)
+            .writer(make_writer)
+            .max_level(config.filter)
+            .sample_rate(config.sampling);
+        builder.options.stream = match &config.output {
+            Output::Stdout => Some(Stream::Stdout),
+            Output::Stderr => Some(Stream::Stderr),
+            Output::File(_) => None,
+        };
+        Ok(builder)
+    }
+}
+
+impl<F, W> LayerBuilder<F, W>
+where
+    F: 'static + Formatter + Send,
+    W: 'static + for<'a> MakeWriter<'a> + Send,
+{
+    /// Set the [`Formatter`] used to format trees.
+    pub fn formatter<F2>(self, formatter: F2) -> LayerBuilder<F2, W>
+    where
+        F2: 'static + Formatter + Send,
+    {
+        LayerBuilder {
+            formatter,
+            make_writer: self.make_writer,
+            options: self.options,
+        }
+    }
+
+    /// Set the writer that formatted trees are written to.
+    pub fn writer<W2>(self, make_writer: W2) -> LayerBuilder<F, W2>
+    where
+        W2: 'static + for<'a> MakeWriter<'a> + Send,
+    {
+        LayerBuilder {
+            formatter: self.formatter,
+            make_writer,
+            options: Options {
+                stream: Stream::of::<W2>(),
+                ..self.options
+            },
+        }
+    }
+
+    /// Apply `transform` to every tree before it's processed.
+    ///
+    /// Transforms run where trees are taken off of the queue, like on the
+    /// thread of [`worker_layer`][LayerBuilder::worker_layer] or the task of
+    /// [`async_layer`][LayerBuilder::async_layer], keeping their cost off of
+    /// the instrumented code. Layers without a queue run them on the thread
+    /// that finished the tree. Multiple transforms are applied in the order
+    /// they're added.
+    ///
+    /// Transforms are kept with the other options, so they apply however the
+    /// formatter or processor is set, before or after this is called. To
+    /// always run a transform on the thread that finished the tree, see
+    /// [`TreeLayer::transform`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::layer::Tree;
+    /// fn errors_are_warnings(mut tree: Tree) -> Tree {
+    ///     if tree.attrs.level == Level::ERROR {
+    ///         tree.attrs.level = Level::WARN;
+    ///     }
+    ///     tree
+    /// }
+    ///
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .set_transform(errors_are_warnings)
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn set_transform(mut self, transform: fn(Tree) -> Tree) -> Self {
+        self.options.transforms.push(transform);
+        self
+    }
+
+    /// Set the formatter to `formatter`, with its output made plain ASCII,
+    /// for environments that mangle UTF-8. See [`Ascii`] for details.
+    ///
+    /// This is the same as calling [`formatter`][LayerBuilder::formatter]
+    /// with an [`Ascii`] formatter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .ascii(Pretty::new())
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn ascii<F2>(self, formatter: F2) -> LayerBuilder<Ascii<F2>, W>
+    where
+        F2: 'static + Formatter + Send,
+    {
+        self.formatter(Ascii::new(formatter))
+    }
+
+    /// Set the formatter to `formatter`, made replaceable at runtime,
+    /// returning a [`SwitchHandle`] that replaces it. See [`Switchable`] for
+    /// details.
+    ///
+    /// This is the same as calling [`formatter`][LayerBuilder::formatter]
+    /// with a [`Switchable`] formatter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let (builder, handle) = tracing_forest::builder().switchable(Pretty::new());
+    /// let _guard = tracing::subscriber::set_default(builder.blocking_layer().into_subscriber());
+    ///
+    /// // When a remote debugging session attaches
+    /// handle.set(Json::new(true));
+    /// ```
+    pub fn switchable<F2>(self, formatter: F2) -> (LayerBuilder<Switchable, W>, SwitchHandle)
+    where
+        F2: 'static + Formatter + Send + Sync,
+    {
+        let (formatter, handle) = Switchable::new(formatter);
+        (self.formatter(formatter), handle)
+    }
+
+    /// Set the accepted [`Tag`] type.
+    pub fn tag<T: Tag>(mut self) -> Self {
+        self.options.tag_parser.set::<T>();
+        self
+    }
+
+    /// Tag events by the prefixes of their targets in `registry`, when they
+    /// aren't tagged by an `__event_tag` field or the [`Tag`] type.
+    ///
+    /// See [`TagRegistry`] for more details.
+    pub fn tag_registry(mut self, registry: TagRegistry) -> Self {
+        self.options.tag_parser.registry = Some(Arc::new(registry));
+        self
+    }
+
+    /// Set the most verbose level of spans and events that are collected.
+    pub fn max_level(mut self, max_level: impl Into<LevelFilter>) -> Self {
+        self.options.max_level = max_level.into();
+        self
+    }
+
+    /// Set the fraction of trees that are processed.
+    ///
+    /// See [`TreeLayer::sample_rate`] for details.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.options.sample_rate = rate;
+        self
+    }
+
+    /// Set the [`FieldRules`] that rename and drop fields as trees are
+    /// collected.
+    ///
+    /// See [`TreeLayer::field_rules`] for details.
+    pub fn field_rules(mut self, rules: FieldRules) -> Self {
+        self.options.field_rules = rules;
+        self
+    }
+
+    /// Set whether `TRACE` and `DEBUG` events are only kept in trees that
+    /// contain a `WARN` or `ERROR`.
+    ///
+    /// See [`TreeLayer::retroactive_verbosity`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::builder::Preset;
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .preset(Preset::Production)
+    ///         .max_level(tracing::Level::TRACE)
+    ///         .retroactive_verbosity(true)
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn retroactive_verbosity(mut self, retroactive: bool) -> Self {
+        self.options.retroactive_verbosity = retroactive;
+        self
+    }
+
+    /// Set whether spans and events are timestamped with a coarse clock.
+    ///
+    /// See [`TreeLayer::coarse_timestamps`] for details.
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn coarse_timestamps(mut self, coarse: bool) -> Self {
+        self.options.coarse_timestamps = coarse;
+        self
+    }
+
+    /// Set the function that reads the wall clock for timestamps.
+    ///
+    /// See [`TreeLayer::wall_clock`] for details.
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn wall_clock(mut self, now: fn() -> DateTime<Utc>) -> Self {
+        self.options.wall_clock = Some(now);
+        self
+    }
+
+    /// Set the version of [`Uuid`] generated for root spans.
+    ///
+    /// See [`TreeLayer::uuid_version`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn uuid_version(mut self, version: UuidVersion) -> Self {
+        self.options.uuid_version = version;
+        self
+    }
+
+    /// Set whether the [`Uuid`]s of root spans are only generated once
+    /// they're requested.
+    ///
+    /// See [`TreeLayer::lazy_uuids`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn lazy_uuids(mut self, lazy: bool) -> Self {
+        self.options.lazy_uuids = lazy;
+        self
+    }
+
+    /// Use the value of the `name` field of root spans as the [`Uuid`] of
+    /// their trees.
+    ///
+    /// See [`TreeLayer::id_field`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn id_field(mut self, name: &'static str) -> Self {
+        self.options.id_field = Some(name);
+        self
+    }
+
+    /// Set whether the formatter colors its output using ANSI escape codes,
+    /// overriding how it was configured.
+    ///
+    /// This is applied when the layer is built, so it also applies to
+    /// formatters and writers set afterwards. [`Ansi::Auto`] checks whether
+    /// the writer is a terminal, so output is only colored when it's written
+    /// to stdout or stderr. Formatters that never use color ignore this. See
+    /// [`Formatter::set_ansi`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::Ansi;
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .set_ansi(Ansi::Auto)
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn set_ansi(mut self, ansi: Ansi) -> Self {
+        self.options.ansi = Some(ansi);
+        self
+    }
+
+    /// Set how the formatter displays the icons of events, overriding how it
+    /// was configured.
+    ///
+    /// Like [`set_ansi`][LayerBuilder::set_ansi], this is applied when the
+    /// layer is built, and formatters that never display icons ignore it.
+    /// See [`Icons`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::Icons;
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .icons(Icons::Text)
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn icons(mut self, icons: Icons) -> Self {
+        self.options.icons = Some(icons);
+        self
+    }
+
+    /// Returns the formatter with the configured [`Ansi`] and [`Icons`]
+    /// settings applied, along with the writer and options to build a layer
+    /// from.
+    fn finish(mut self) -> (F, W, Options) {
+        if let Some(ansi) = self.options.ansi {
+            let ansi = self.options.ansi_enabled(ansi);
+            self.formatter.set_ansi(ansi);
+        }
+        if let Some(icons) = self.options.icons {
+            self.formatter.set_icons(icons);
+        }
+        (self.formatter, self.make_writer, self.options)
+    }
+
+    /// Replace the formatter, writer, and maximum level with those of a
+    /// [`Preset`], keeping the configured [`Tag`] type.
+    ///
+    /// Everything selected by the preset can still be overridden by calling
+    /// other builder methods afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::builder::Preset;
+    /// # use tracing::Level;
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .preset(Preset::Development)
+    ///         .max_level(Level::TRACE)
+    ///         .blocking_layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn preset(self, preset: Preset) -> LayerBuilder<BoxFormatter, BoxMakeWriter> {
+        let (formatter, make_writer, max_level): (BoxFormatter, BoxMakeWriter, LevelFilter) =
+            match preset {
+                Preset::Development => (
+                    Box::new(Pretty::new()),
+                    BoxMakeWriter::new(io::stdout),
+                    LevelFilter::DEBUG,
+                ),
+                #[cfg(feature = "json")]
+                Preset::Production => (
+                    Box::new(crate::formatter::json::Json::new(true)),
+                    BoxMakeWriter::new(io::stdout),
+                    LevelFilter::INFO,
+                ),
+                #[cfg(not(feature = "json"))]
+                Preset::Production => (
+                    Box::new(Pretty::new()),
+                    BoxMakeWriter::new(io::stdout),
+                    LevelFilter::INFO,
+                ),
+                Preset::Ci => (
+                    Box::new(Pretty::new()),
+                    BoxMakeWriter::new(io::stdout),
+                    LevelFilter::DEBUG,
+                ),
+                Preset::Test => (
+                    Box::new(Pretty::new().with_snapshot(true)),
+                    BoxMakeWriter::new(TestWriter::new()),
+                    LevelFilter::TRACE,
+                ),
+            };
+
+        // Like with `set_ansi`, `Ansi::Auto` is checked against the writer
+        // once the layer is built, in case it's replaced
+        let ansi = match preset {
+            Preset::Development => self.options.ansi.or(Some(Ansi::Auto)),
+            _ => self.options.ansi,
+        };
+        let stream = match preset {
+            Preset::Test => None,
+            _ => Some(Stream::Stdout),
+        };
+
+        LayerBuilder {
+            formatter,
+            make_writer,
+            options: Options {
+                max_level,
+                ansi,
+                stream,
+                ..self.options
+            },
+        }
+    }
+
+    /// Convert into a [`DynLayerBuilder`], whose type doesn't change as it's
+    /// configured, so it can be configured in branches at runtime.
+    pub fn into_dyn(self) -> DynLayerBuilder
+    where
+        F: Sync,
+        W: Sync,
+    {
+        DynLayerBuilder {
+            inner: LayerBuilder {
+                formatter: Box::new(self.formatter),
+                make_writer: BoxMakeWriter::new(self.make_writer),
+                options: self.options,
+            },
+            processor: None,
+            wrappers: Vec::new(),
+        }
+    }
+
+    /// Replace the formatter and writer with a [`Processor`], keeping the
+    /// options configured so far, like the [`Tag`] type and maximum level.
+    ///
+    /// The returned [`ProcessorBuilder`] can wrap the processor with
+    /// combinators before building the layer. Since a processor formats
+    /// trees however it likes, the [`Ansi`] setting doesn't apply to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .max_level(Level::INFO)
+    ///         .set_processor(blocking(Pretty::new(), std::io::stdout).compress())
+    ///         .tee(blocking(Json::new(true), std::io::stderr).sample(0.1))
+    ///         .layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn set_processor<P: Processor>(self, processor: P) -> ProcessorBuilder<P> {
+        ProcessorBuilder {
+            processor,
+            options: self.options,
+        }
+    }
+
+    /// Build a [`TreeLayer`] that formats and writes trees on the current
+    /// thread. See [`BlockingProcessor`] for details.
+    pub fn blocking_layer(self) -> TreeLayer<BlockingProcessor<F, W>> {
+        let (formatter, make_writer, mut options) = self.finish();
+        let transforms = mem::take(&mut options.transforms);
+        let processor = blocking(formatter, make_writer).with_transforms(transforms);
+        options.apply(TreeLayer::new(processor))
+    }
+
+    /// Build a [`worker_layer`][LayerBuilder::worker_layer] and install it as
+    /// the global default subscriber, returning the guard that keeps its
+    /// thread running.
+    ///
+    /// Dropping the guard writes any remaining trees and stops the thread, so
+    /// it should be kept until the program exits.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if a global default subscriber has already been set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// let _guard = tracing_forest::builder()
+    ///     .writer(std::io::stderr)
+    ///     .max_level(Level::INFO)
+    ///     .init();
+    /// ```
+    pub fn init(self) -> ForestGuard {
+        let (layer, guard) = self.worker_layer();
+        #[allow(clippy::expect_used)]
+        tracing::subscriber::set_global_default(layer.into_subscriber())
+            .expect("a global default subscriber has already been set");
+        guard
+    }
+
+    /// Build a [`TreeLayer`] that sends trees to be formatted and written on
+    /// a background thread, which runs until the returned guard is dropped.
+    /// See [`worker`] for details.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the thread can't be spawned.
+    pub fn worker_layer(self) -> (TreeLayer<WorkerProcessor>, ForestGuard) {
+        let (formatter, make_writer, mut options) = self.finish();
+        let formatter = Transformed::all(formatter, mem::take(&mut options.transforms));
+        let (processor, guard) = worker(formatter, make_writer);
+        (options.apply(TreeLayer::new(processor)), guard)
+    }
+
+    /// Build a [`TreeLayer`] that sends trees to the processors bound to their
+    /// level in `levels`, and formats and writes trees matching no bound
+    /// processor on the current thread, like
+    /// [`blocking_layer`][LayerBuilder::blocking_layer].
+    ///
+    /// This replaces any fallback processor already set on `levels`. See
+    /// [`Levels`] for details.
+    ///
+    /// # Examples
+    ///
+    /// Write `TRACE` and `DEBUG` trees to a file, `WARN` and `ERROR` trees to
+    /// stderr with an alert, and everything else to stdout:
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::blocking;
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// # use tracing_forest::processor::levels::Levels;
+    /// # let path = std::env::temp_dir().join("tracing-forest-levels-doc.log");
+    /// let file = std::fs::File::create(path).expect("failed to create log file");
+    /// let file = std::sync::Mutex::new(file);
+    ///
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .writer(std::io::stdout)
+    ///         .levels_layer(
+    ///             Levels::new()
+    ///                 .bind(Level::TRACE, Level::DEBUG, blocking(Pretty::new(), file))
+    ///                 .bind(Level::WARN, Level::ERROR, blocking(Pretty::new(), std::io::stderr))
+    ///                 .hook(Level::WARN, Level::ERROR, |tree| {
+    ///                     eprintln!("ALERT: {:?}", tree.attrs.level);
+    ///                 }),
+    ///         )
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn levels_layer(self, levels: Levels) -> TreeLayer<Levels>
+    where
+        F: Sync,
+        W: Sync,
+    {
+        let (formatter, make_writer, options) = self.finish();
+        let levels = levels.fallback(blocking(formatter, make_writer));
+        options.apply(TreeLayer::new(levels))
+    }
+
+    /// Build a [`TreeLayer`] that sends trees to be formatted and written on
+    /// a spawned task. See [`async_spawn`] for details, including why the
+    /// returned handle should be awaited.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if called from outside of the Tokio runtime.
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn async_layer(self) -> (TreeLayer<AsyncProcessor>, JoinHandle<()>) {
+        let (formatter, make_writer, mut options) = self.finish();
+        let formatter = Transformed::all(formatter, mem::take(&mut options.transforms));
+        let (processor, handle) = async_spawn(formatter, make_writer);
+        (options.apply(TreeLayer::new(processor)), handle)
+    }
+
+    /// Build a [`TreeLayer`] that shards trees across `workers` spawned tasks
+    /// to be formatted and written. See [`async_spawn_sharded`] for details.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `workers` is zero, or if called from outside of the Tokio
+    /// runtime.
+    #[cfg(feature = "sync")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn async_layer_sharded(self, workers: usize) -> (TreeLayer<AsyncProcessor>, JoinHandle<()>)
+    where
+        F: Sync,
+        W: Sync,
+    {
+        let (formatter, make_writer, mut options) = self.finish();
+        let formatter = Transformed::all(formatter, mem::take(&mut options.transforms));
+        let (processor, handle) = async_spawn_sharded(formatter, make_writer, workers);
+        (options.apply(TreeLayer::new(processor)), handle)
+    }
+}
+
+/// A builder for [`TreeLayer`]s with a user provided [`Processor`], which can
+/// be wrapped with the built-in combinators.
+///
+/// To initialize a new [`ProcessorBuilder`], see
+/// [`LayerBuilder::set_processor`].
+pub struct ProcessorBuilder<P> {
+    processor: P,
+    options: Options,
+}
+
+impl<P: Processor> ProcessorBuilder<P> {
+    /// Only process trees that match a predicate. See [`Processor::filter`]
+    /// for details.
+    pub fn filter<F>(self, predicate: F) -> ProcessorBuilder<Filter<P, F>>
+    where
+        F: 'static + Fn(&Tree) -> bool,
+    {
+        self.wrap(|processor| processor.filter(predicate))
+    }
+
+    /// Only process a fraction of trees. See [`Processor::sample`] for
+    /// details.
+    pub fn sample(self, rate: f64) -> ProcessorBuilder<Sample<P>> {
+        self.wrap(|processor| processor.sample(rate))
+    }
+
+    /// Send every tree to another processor as well. See [`Processor::tee`]
+    /// for details.
+    pub fn tee<Q: Processor>(self, processor: Q) -> ProcessorBuilder<Tee<P, Q>> {
+        self.wrap(|inner| inner.tee(processor))
+    }
+
+    /// Wrap the processor with `f`, for combinators without a method here.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     tracing_forest::builder()
+    ///         .set_processor(blocking(Pretty::new(), std::io::stdout))
+    ///         .wrap(Processor::compress)
+    ///         .layer()
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn wrap<Q, F>(self, f: F) -> ProcessorBuilder<Q>
+    where
+        Q: Processor,
+        F: FnOnce(P) -> Q,
+    {
+        ProcessorBuilder {
+            processor: f(self.processor),
+            options: self.options,
+        }
+    }
+
+    /// Build a [`TreeLayer`] that processes trees on the current thread.
+    pub fn layer(self) -> TreeLayer<P> {
+        self.options.apply(TreeLayer::new(self.processor))
+    }
+
+    /// Build a [`TreeLayer`] that sends trees to be processed on a background
+    /// thread, which runs until the returned guard is dropped. See
+    /// [`worker::spawn`] for details.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the thread can't be spawned.
+    pub fn worker_layer(mut self) -> (TreeLayer<WorkerProcessor>, ForestGuard)
+    where
+        P: Send,
+    {
+        let transforms = mem::take(&mut self.options.transforms);
+        let (processor, guard) = worker::spawn_transformed(self.processor, transforms);
+        (self.options.apply(TreeLayer::new(processor)), guard)
+    }
+
+    /// Build a [`worker_layer`][ProcessorBuilder::worker_layer] and install it
+    /// as the global default subscriber, returning the guard that keeps its
+    /// thread running.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if a global default subscriber has already been set.
+    pub fn init(self) -> ForestGuard
+    where
+        P: Send,
+    {
+        let (layer, guard) = self.worker_layer();
+        #[allow(clippy::expect_used)]
+        tracing::subscriber::set_global_default(layer.into_subscriber())
+            .expect("a global default subscriber has already been set");
+        guard
+    }
+}
+
+type Wrapper = Box<dyn FnOnce(BoxProcessor) -> BoxProcessor>;
+
+/// A builder for [`TreeLayer`]s that boxes its formatter, writer, and
+/// processor, so it keeps the same type however it's configured.
+///
+/// [`LayerBuilder`] and [`ProcessorBuilder`] change type when the formatter,
+/// writer, or processor is replaced or wrapped, so they can't be configured
+/// differently in the branches of an `if`. This builder can, at the cost of
+/// a dynamic call per tree. It has the methods of both builders, and
+/// combinators wrap the processor that the layer is built with, either the
+/// one given to [`set_processor`] or one that formats and writes trees, in
+/// the order they're added.
+///
+/// To initialize a new [`DynLayerBuilder`], see [`LayerBuilder::into_dyn`].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+/// # let (json, verbose, sample) = (true, false, Some(0.1));
+/// let mut builder = tracing_forest::builder().into_dyn();
+/// if json {
+///     builder = builder.formatter(Json::new(true)).writer(std::io::stderr);
+/// }
+/// if let Some(rate) = sample {
+///     builder = builder.sample(rate);
+/// }
+/// let level = if verbose { Level::TRACE } else { Level::INFO };
+///
+/// let _guard = tracing::subscriber::set_default(builder.max_level(level).layer().into_subscriber());
+/// ```
+///
+/// [`set_processor`]: DynLayerBuilder::set_processor
+pub struct DynLayerBuilder {
+    inner: LayerBuilder<BoxFormatter, BoxMakeWriter>,
+    processor: Option<BoxProcessor>,
+    wrappers: Vec<Wrapper>,
+}
+
+impl DynLayerBuilder {
+    /// Set the [`Formatter`] used to format trees.
+    pub fn formatter<F>(mut self, formatter: F) -> Self
+    where
+        F: 'static + Formatter + Send + Sync,
+    {
+        self.inner.formatter = Box::new(formatter);
+        self
+    }
+
+    /// Set the writer that formatted trees are written to.
+    pub fn writer<W>(mut self, make_writer: W) -> Self
+    where
+        W: 'static + for<'a> MakeWriter<'a> + Send + Sync,
+    {
+        self.inner.make_writer = BoxMakeWriter::new(make_writer);
+        self.inner.options.stream = Stream::of::<W>();
+        self
+    }
+
+    /// Apply `transform` to every tree before it's processed. See
+    /// [`LayerBuilder::set_transform`] for details.
+    pub fn set_transform(mut self, transform: fn(Tree) -> Tree) -> Self {
+        self.inner = self.inner.set_transform(transform);
+        self
+    }
+
+    /// Set the formatter to `formatter`, with its output made plain ASCII.
+    /// See [`LayerBuilder::ascii`] for details.
+    pub fn ascii<F>(self, formatter: F) -> Self
+    where
+        F: 'static + Formatter + Send + Sync,
+    {
+        self.formatter(Ascii::new(formatter))
+    }
+
+    /// Replace the formatter, writer, and maximum level with those of a
+    /// [`Preset`]. See [`LayerBuilder::preset`] for details.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.inner = self.inner.preset(preset);
+        self
+    }
+
+    /// Set the accepted [`Tag`] type.
+    pub fn tag<T: Tag>(mut self) -> Self {
+        self.inner = self.inner.tag::<T>();
+        self
+    }
+
+    /// Tag events by the prefixes of their targets in `registry`. See
+    /// [`LayerBuilder::tag_registry`] for details.
+    pub fn tag_registry(mut self, registry: TagRegistry) -> Self {
+        self.inner = self.inner.tag_registry(registry);
+        self
+    }
+
+    /// Set the most verbose level of spans and events that are collected.
+    pub fn max_level(mut self, max_level: impl Into<LevelFilter>) -> Self {
+        self.inner = self.inner.max_level(max_level);
+        self
+    }
+
+    /// Set the fraction of trees that are processed.
+    ///
+    /// See [`TreeLayer::sample_rate`] for details.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.inner = self.inner.sample_rate(rate);
+        self
+    }
+
+    /// Set the [`FieldRules`] that rename and drop fields as trees are
+    /// collected.
+    ///
+    /// See [`TreeLayer::field_rules`] for details.
+    pub fn field_rules(mut self, rules: FieldRules) -> Self {
+        self.inner = self.inner.field_rules(rules);
+        self
+    }
+
+    /// Set whether `TRACE` and `DEBUG` events are only kept in trees that
+    /// contain a `WARN` or `ERROR`.
+    ///
+    /// See [`TreeLayer::retroactive_verbosity`] for details.
+    pub fn retroactive_verbosity(mut self, retroactive: bool) -> Self {
+        self.inner = self.inner.retroactive_verbosity(retroactive);
+        self
+    }
+
+    /// Set whether spans and events are timestamped with a coarse clock.
+    ///
+    /// See [`TreeLayer::coarse_timestamps`] for details.
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn coarse_timestamps(mut self, coarse: bool) -> Self {
+        self.inner = self.inner.coarse_timestamps(coarse);
+        self
+    }
+
+    /// Set the function that reads the wall clock for timestamps.
+    ///
+    /// See [`TreeLayer::wall_clock`] for details.
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn wall_clock(mut self, now: fn() -> DateTime<Utc>) -> Self {
+        self.inner = self.inner.wall_clock(now);
+        self
+    }
+
+    /// Set the version of [`Uuid`] generated for root spans.
+    ///
+    /// See [`TreeLayer::uuid_version`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn uuid_version(mut self, version: UuidVersion) -> Self {
+        self.inner = self.inner.uuid_version(version);
+        self
+    }
+
+    /// Set whether the [`Uuid`]s of root spans are only generated once
+    /// they're requested.
+    ///
+    /// See [`TreeLayer::lazy_uuids`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn lazy_uuids(mut self, lazy: bool) -> Self {
+        self.inner = self.inner.lazy_uuids(lazy);
+        self
+    }
+
+    /// Use the value of the `name` field of root spans as the [`Uuid`] of
+    /// their trees.
+    ///
+    /// See [`TreeLayer::id_field`] for details.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn id_field(mut self, name: &'static str) -> Self {
+        self.inner = self.inner.id_field(name);
+        self
+    }
+
+    /// Set whether the formatter colors its output using ANSI escape codes.
+    /// See [`LayerBuilder::set_ansi`] for details.
+    pub fn set_ansi(mut self, ansi: Ansi) -> Self {
+        self.inner = self.inner.set_ansi(ansi);
+        self
+    }
+
+    /// Set how the formatter displays the icons of events. See
+    /// [`LayerBuilder::icons`] for details.
+    pub fn icons(mut self, icons: Icons) -> Self {
+        self.inner = self.inner.icons(icons);
+        self
+    }
+
+    /// Process trees with `processor` instead of formatting and writing them,
+    /// replacing any processor set before.
+    ///
+    /// Like [`LayerBuilder::set_processor`], the formatter, writer, and
+    /// [`Ansi`] setting don't apply to it.
+    pub fn set_processor<P>(mut self, processor: P) -> Self
+    where
+        P: 'static + Processor + Send + Sync,
+    {
+        self.processor = Some(Box::new(processor));
+        self
+    }
+
+    /// Only process trees that match a predicate. See [`Processor::filter`]
+    /// for details.
+    pub fn filter<F>(self, predicate: F) -> Self
+    where
+        F: 'static + Fn(&Tree) -> bool + Send + Sync,
+    {
+        self.wrap(|processor| processor.filter(predicate))
+    }
+
+    /// Only process a fraction of trees. See [`Processor::sample`] for
+    /// details.
+    pub fn sample(self, rate: f64) -> Self {
+        self.wrap(move |processor| processor.sample(rate))
+    }
+
+    /// Send every tree to another processor as well. See [`Processor::tee`]
+    /// for details.
+    pub fn tee<Q>(self, processor: Q) -> Self
+    where
+        Q: 'static + Processor + Send + Sync,
+    {
+        self.wrap(|inner| inner.tee(processor))
+    }
+
+    /// Wrap the processor with `f`, for combinators without a method here.
+    pub fn wrap<Q, F>(mut self, f: F) -> Self
+    where
+        Q: 'static + Processor + Send + Sync,
+        F: 'static + FnOnce(BoxProcessor) -> Q,
+    {
+        self.wrappers
+            .push(Box::new(move |processor| Box::new(f(processor))));
+        self
+    }
+
+    fn finish(self) -> (BoxProcessor, Options) {
+        let (formatter, make_writer, options) = self.inner.finish();
+        let processor = match self.processor {
+            Some(processor) => processor,
+            None => Box::new(blocking(formatter, make_writer)),
+        };
+        let processor = self
+            .wrappers
+            .into_iter()
+            .fold(processor, |processor, wrap| wrap(processor));
+        (processor, options)
+    }
+
+    /// Build a [`TreeLayer`] that processes trees on the current thread.
+    pub fn layer(self) -> TreeLayer<BoxProcessor> {
+        let (processor, options) = self.finish();
+        options.apply(TreeLayer::new(processor))
+    }
+
+    /// Build a [`TreeLayer`] that sends trees to be processed on a background
+    /// thread, which runs until the returned guard is dropped. See
+    /// [`worker::spawn`] for details.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the thread can't be spawned.
+    pub fn worker_layer(self) -> (TreeLayer<WorkerProcessor>, ForestGuard) {
+        let (processor, mut options) = self.finish();
+        let transforms = mem::take(&mut options.transforms);
+        let (processor, guard) = worker::spawn_transformed(processor, transforms);
+        (options.apply(TreeLayer::new(processor)), guard)
+    }
+
+    /// Build a [`worker_layer`][DynLayerBuilder::worker_layer] and install it
+    /// as the global default subscriber, returning the guard that keeps its
+    /// thread running.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if a global default subscriber has already been set.
+    pub fn init(self) -> ForestGuard {
+        let (layer, guard) = self.worker_layer();
+        #[allow(clippy::expect_used)]
+        tracing::subscriber::set_global_default(layer.into_subscriber())
+            .expect("a global default subscriber has already been set");
+        guard
+    }
+}
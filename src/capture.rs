@@ -0,0 +1,756 @@
+//! Capture trace trees in memory for inspecting in tests.
+//!
+//! See [`capture`] for more details.
+
+use crate::formatter::pretty::Pretty;
+use crate::formatter::Formatter;
+use crate::layer::{KeyValue, Tree, TreeEvent, TreeKind, TreeSpan, TreeVisitor};
+use crate::processor::Processor;
+use crate::tag::{NoTag, Tag, TagParser, TagRegistry};
+use crate::tree::{unquote, EventCounts};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::instrument::WithSubscriber;
+use tracing::level_filters::LevelFilter;
+use tracing::Level;
+use tracing_subscriber::filter::{FilterExt, Targets};
+use tracing_subscriber::layer::{Filter, Layer, Layered, SubscriberExt};
+use tracing_subscriber::Registry;
+
+type BoxFilter = Box<dyn Filter<Registry> + Send + Sync>;
+
+/// A builder for capturing the trees logged by some code.
+///
+/// To initialize a new [`Capture`], see [`capture`].
+pub struct Capture {
+    tag_parser: TagParser,
+    filter: Option<BoxFilter>,
+}
+
+/// Initialize a new [`Capture`], which collects every tree logged while running
+/// a closure or future instead of writing them.
+///
+/// The subscriber is only installed for the duration of the closure or future,
+/// so captures in concurrently running tests don't interfere with each other.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::assert_tree;
+/// let trees = tracing_forest::capture().run(|| {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::info!(user = "alice", "started");
+///     });
+/// });
+///
+/// assert_tree!(trees[0], span "request" [
+///     event INFO "start*" (user = "al*"),
+/// ]);
+/// ```
+pub fn capture() -> Capture {
+    Capture {
+        tag_parser: TagParser::of::<NoTag>(),
+        filter: None,
+    }
+}
+
+struct Captured(Arc<Mutex<Output>>);
+
+#[derive(Default)]
+struct Output {
+    trees: Vec<Tree>,
+    stats: CaptureStats,
+}
+
+impl Processor for Captured {
+    fn process(&self, tree: Tree) {
+        #[allow(clippy::expect_used)]
+        let mut output = self.0.lock().expect("captured trees poisoned");
+        output.stats.add(&tree);
+        output.trees.push(tree);
+    }
+}
+
+/// Aggregate statistics of the trees captured by [`Capture::run_with_stats`].
+///
+/// These are computed as each tree is captured, so tests and benchmarks can
+/// assert on coarse properties of what was logged without walking the trees.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// let (_, stats) = tracing_forest::capture().run_with_stats(|| {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::info_span!("db").in_scope(|| tracing::warn!("slow query"));
+///         tracing::info!("done");
+///     });
+/// });
+///
+/// assert_eq!(stats.trees, 1);
+/// assert_eq!(stats.spans, 2);
+/// assert_eq!(stats.events, 2);
+/// assert_eq!(stats.level(Level::WARN), 1);
+/// assert_eq!(stats.max_depth, 3);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// The number of trees captured.
+    pub trees: usize,
+    /// The number of spans in all trees.
+    pub spans: usize,
+    /// The number of events in all trees.
+    pub events: usize,
+    /// The most spans and events nested in each other, where a tree that's
+    /// a single event has a depth of `1`.
+    pub max_depth: usize,
+    counts: EventCounts,
+}
+
+impl CaptureStats {
+    /// Returns the number of events logged at `level`.
+    pub fn level(&self, level: Level) -> usize {
+        self.counts.level(level)
+    }
+
+    /// Returns the number of events tagged with `tag`, like `"security.alert"`.
+    pub fn tag(&self, tag: &str) -> usize {
+        self.counts.tag(tag)
+    }
+
+    /// Returns every tag of the captured events, and how many events had it,
+    /// sorted by tag.
+    pub fn tags(&self) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+        let mut tags = self.counts.tags().to_vec();
+        tags.sort_unstable();
+        tags.into_iter()
+    }
+
+    fn add(&mut self, tree: &Tree) {
+        self.trees += 1;
+        let _ = tree.walk(&mut Counter {
+            stats: self,
+            depth: 0,
+        });
+    }
+}
+
+/// Adds the spans and events of a tree to [`CaptureStats`].
+struct Counter<'a> {
+    stats: &'a mut CaptureStats,
+    /// How many spans are being walked.
+    depth: usize,
+}
+
+impl TreeVisitor for Counter<'_> {
+    type Error = Infallible;
+
+    fn enter_span(&mut self, _tree: &Tree, _span: &TreeSpan) -> Result<(), Infallible> {
+        self.stats.spans += 1;
+        self.depth += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+        Ok(())
+    }
+
+    fn exit_span(&mut self, _tree: &Tree, _span: &TreeSpan) -> Result<(), Infallible> {
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> Result<(), Infallible> {
+        self.stats.events += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth + 1);
+        self.stats.counts.add(tree.attrs.level, event.tag);
+        Ok(())
+    }
+}
+
+impl Capture {
+    /// Set the accepted [`Tag`] type.
+    pub fn tag<T: Tag>(mut self) -> Self {
+        self.tag_parser.set::<T>();
+        self
+    }
+
+    /// Tag events by the prefixes of their targets in `registry`.
+    ///
+    /// See [`TagRegistry`] for more details.
+    pub fn tag_registry(mut self, registry: TagRegistry) -> Self {
+        self.tag_parser.registry = Some(Arc::new(registry));
+        self
+    }
+
+    /// Only capture the spans and events enabled by `filter`, such as an
+    /// `EnvFilter` with the `env-filter` feature, or a [`Targets`].
+    ///
+    /// Events inside spans that are filtered out are attached to the closest
+    /// enabled parent span instead, or captured as trees of their own. If a
+    /// filter is already set, both filters must enable a span or event for it
+    /// to be captured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_subscriber::filter::Targets;
+    /// let trees = tracing_forest::capture()
+    ///     .set_filter(Targets::new().with_target("my_crate", Level::DEBUG))
+    ///     .run(|| {
+    ///         tracing::debug!(target: "my_crate::db", "connected");
+    ///         tracing::debug!(target: "hyper::client", "incidental");
+    ///     });
+    ///
+    /// assert_eq!(trees.len(), 1);
+    /// ```
+    pub fn set_filter<F>(mut self, filter: F) -> Self
+    where
+        F: 'static + Filter<Registry> + Send + Sync,
+    {
+        self.filter = Some(match self.filter.take() {
+            Some(existing) => Box::new(existing.and(filter)),
+            None => Box::new(filter),
+        });
+        self
+    }
+
+    /// Only capture spans and events whose target starts with one of
+    /// `targets`, at any level.
+    ///
+    /// This is shorthand for [`set_filter`] with a [`Targets`] filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let trees = tracing_forest::capture()
+    ///     .allow_targets(["my_crate", "my_other_crate"])
+    ///     .run(|| {
+    ///         tracing::info!(target: "my_crate", "relevant");
+    ///         tracing::info!(target: "dependency", "incidental");
+    ///     });
+    ///
+    /// assert_eq!(trees.len(), 1);
+    /// ```
+    ///
+    /// [`set_filter`]: Capture::set_filter
+    pub fn allow_targets<I>(self, targets: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let targets = targets
+            .into_iter()
+            .map(|target| (target.into(), LevelFilter::TRACE));
+        self.set_filter(Targets::new().with_targets(targets))
+    }
+
+    fn subscriber(self) -> (Layered<impl Layer<Registry>, Registry>, Arc<Mutex<Output>>) {
+        let output = Arc::new(Mutex::new(Output::default()));
+        let layer = Captured(output.clone())
+            .into_layer()
+            .tag_parser(self.tag_parser)
+            .with_filter(self.filter);
+        (Registry::default().with(layer), output)
+    }
+
+    /// Run `f`, returning the trees it logged.
+    pub fn run(self, f: impl FnOnce()) -> Vec<Tree> {
+        self.run_with_stats(f).0
+    }
+
+    /// Run `f`, returning the trees it logged and their [`CaptureStats`].
+    pub fn run_with_stats(self, f: impl FnOnce()) -> (Vec<Tree>, CaptureStats) {
+        let (subscriber, output) = self.subscriber();
+        tracing::subscriber::with_default(subscriber, f);
+        take(output)
+    }
+
+    /// Run a future to completion, returning the trees it logged.
+    ///
+    /// The subscriber follows the future across threads, so this works with
+    /// multi-threaded runtimes.
+    pub async fn run_async<Fut>(self, fut: Fut) -> Vec<Tree>
+    where
+        Fut: Future<Output = ()>,
+    {
+        self.run_async_with_stats(fut).await.0
+    }
+
+    /// Run a future to completion, returning the trees it logged and their
+    /// [`CaptureStats`].
+    ///
+    /// See [`Capture::run_async`] for details.
+    pub async fn run_async_with_stats<Fut>(self, fut: Fut) -> (Vec<Tree>, CaptureStats)
+    where
+        Fut: Future<Output = ()>,
+    {
+        let (subscriber, output) = self.subscriber();
+        fut.with_subscriber(subscriber).await;
+        take(output)
+    }
+}
+
+fn take(output: Arc<Mutex<Output>>) -> (Vec<Tree>, CaptureStats) {
+    #[allow(clippy::expect_used)]
+    let mut output = output.lock().expect("captured trees poisoned");
+    let output = std::mem::take(&mut *output);
+    (output.trees, output.stats)
+}
+
+/// Assertions about the levels logged in captured trees.
+///
+/// This is implemented for a single [`Tree`] and for slices of trees, like the
+/// ones returned by [`Capture::run`]. On failure, the first offending tree is
+/// pretty printed in the panic message.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// use tracing_forest::capture::Expect;
+///
+/// let trees = tracing_forest::capture().run(|| {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::warn!("retrying");
+///     });
+/// });
+///
+/// trees.expect_no_errors();
+/// trees.expect_levels_at_most(Level::WARN);
+/// ```
+///
+/// Spans can also be found by name to assert on their durations, so
+/// performance regressions are caught using the spans already in the code:
+///
+/// ```
+/// # use std::time::Duration;
+/// use tracing_forest::capture::Expect;
+///
+/// let trees = tracing_forest::capture().run(|| {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::info_span!("db_query").in_scope(|| {});
+///     });
+/// });
+///
+/// trees.span("db_query").expect_duration_lt(Duration::from_millis(50));
+/// ```
+pub trait Expect {
+    /// Panics if any span or event was logged at `ERROR`.
+    #[track_caller]
+    fn expect_no_errors(&self) {
+        self.expect_levels_at_most(Level::WARN);
+    }
+
+    /// Panics if any span or event was logged at a level more severe than
+    /// `level`.
+    #[track_caller]
+    fn expect_levels_at_most(&self, level: Level);
+
+    /// Returns every span named `name`, for asserting on their durations.
+    ///
+    /// Names are matched as globs, where `*` matches any sequence of
+    /// characters and `?` matches any single character.
+    fn span<'a>(&'a self, name: &'a str) -> Spans<'a>;
+}
+
+impl Expect for Tree {
+    #[track_caller]
+    fn expect_levels_at_most(&self, level: Level) {
+        let found = self.most_severe_level();
+        if found < level {
+            #[allow(clippy::expect_used)]
+            let rendered = Pretty::new()
+                .with_snapshot(true)
+                .render(self)
+                .expect("formatting to a buffer can't fail");
+            panic!(
+                "expected levels at most {}, but found {}:\n{}",
+                level, found, rendered
+            );
+        }
+    }
+
+    fn span<'a>(&'a self, name: &'a str) -> Spans<'a> {
+        std::slice::from_ref(self).span(name)
+    }
+}
+
+impl Expect for [Tree] {
+    #[track_caller]
+    fn expect_levels_at_most(&self, level: Level) {
+        for tree in self {
+            tree.expect_levels_at_most(level);
+        }
+    }
+
+    fn span<'a>(&'a self, name: &'a str) -> Spans<'a> {
+        let mut spans = Vec::new();
+        for tree in self {
+            find_spans(tree, name, &mut spans);
+        }
+        Spans { name, spans }
+    }
+}
+
+fn find_spans<'a>(tree: &'a Tree, name: &str, spans: &mut Vec<&'a TreeSpan>) {
+    if let TreeKind::Span(span) = &tree.kind {
+        if glob(name, span.name) {
+            spans.push(span);
+        }
+        for child in span.children.iter() {
+            find_spans(child, name, spans);
+        }
+    }
+}
+
+/// The spans with a name, found by [`Expect::span`].
+///
+/// Durations measured in tests depend on the machine running them, so
+/// budgets should leave room for slow CI runners.
+#[derive(Debug)]
+pub struct Spans<'a> {
+    name: &'a str,
+    spans: Vec<&'a TreeSpan>,
+}
+
+impl Spans<'_> {
+    /// Returns how many spans were found.
+    pub fn count(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Returns how long each span was entered for, in the order they were
+    /// opened.
+    pub fn durations(&self) -> Vec<Duration> {
+        self.spans.iter().map(|span| span.duration_total).collect()
+    }
+
+    /// Panics unless exactly `count` spans were found.
+    #[track_caller]
+    pub fn expect_count(&self, count: usize) -> &Self {
+        if self.count() != count {
+            panic!(
+                "expected {} spans named {:?}, but found {}",
+                count,
+                self.name,
+                self.count()
+            );
+        }
+        self
+    }
+
+    /// Panics if no spans were found, or if any of them was entered for
+    /// `max` or longer.
+    #[track_caller]
+    pub fn expect_duration_lt(&self, max: Duration) -> &Self {
+        for (idx, duration) in self.found().into_iter().enumerate() {
+            if duration >= max {
+                panic!(
+                    "expected span {:?} to take less than {:?}, but match {} of {} took {:?}",
+                    self.name,
+                    max,
+                    idx + 1,
+                    self.count(),
+                    duration
+                );
+            }
+        }
+        self
+    }
+
+    /// Panics if no spans were found, or if any of them was entered for
+    /// `min` or less.
+    #[track_caller]
+    pub fn expect_duration_gt(&self, min: Duration) -> &Self {
+        for (idx, duration) in self.found().into_iter().enumerate() {
+            if duration <= min {
+                panic!(
+                    "expected span {:?} to take more than {:?}, but match {} of {} took {:?}",
+                    self.name,
+                    min,
+                    idx + 1,
+                    self.count(),
+                    duration
+                );
+            }
+        }
+        self
+    }
+
+    /// Panics if no spans were found, or if they were entered for `max` or
+    /// longer in total, like many short queries that add up to a slow
+    /// request.
+    #[track_caller]
+    pub fn expect_total_duration_lt(&self, max: Duration) -> &Self {
+        let total = self.found().into_iter().sum::<Duration>();
+        if total >= max {
+            panic!(
+                "expected the {} spans named {:?} to take less than {:?} in total, but they took {:?}",
+                self.count(),
+                self.name,
+                max,
+                total
+            );
+        }
+        self
+    }
+
+    #[track_caller]
+    fn found(&self) -> Vec<Duration> {
+        if self.spans.is_empty() {
+            panic!("expected a span named {:?}, but found none", self.name);
+        }
+        self.durations()
+    }
+}
+
+/// A pattern that a [`Tree`] can be matched against.
+///
+/// Patterns are usually written with the [`assert_tree!`] macro. Span names,
+/// event messages, and field values are matched as globs, where `*` matches
+/// any sequence of characters and `?` matches any single character.
+///
+/// [`assert_tree!`]: crate::assert_tree
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Matches a span with a matching name, and children matching each
+    /// pattern in order.
+    Span {
+        level: Option<Level>,
+        name: &'static str,
+        children: Vec<Pattern>,
+    },
+    /// Matches an event with the same level, a matching message, and at least
+    /// the specified fields.
+    Event {
+        level: Level,
+        message: &'static str,
+        fields: Vec<(&'static str, &'static str)>,
+    },
+    /// Matches any remaining children of a span, and must be last.
+    Rest,
+}
+
+/// Where and why a tree didn't match a [`Pattern`].
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The path of span names and child indices to the mismatch.
+    pub path: Vec<String>,
+    /// What was expected at the path.
+    pub expected: String,
+    /// What was found at the path.
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "      at: {}", self.path.join(" > "))?;
+        writeln!(f, "expected: {}", self.expected)?;
+        write!(f, "   found: {}", self.found)
+    }
+}
+
+impl Pattern {
+    /// Match a tree against the pattern.
+    ///
+    /// # Errors
+    ///
+    /// Returns where and why the first mismatch occurred.
+    pub fn matches(&self, tree: &Tree) -> Result<(), Mismatch> {
+        let mut path = vec!["<root>".to_string()];
+        self.matches_at(tree, &mut path)
+    }
+
+    fn matches_at(&self, tree: &Tree, path: &mut Vec<String>) -> Result<(), Mismatch> {
+        let mismatch = |path: &Vec<String>, found: String| Mismatch {
+            path: path.clone(),
+            expected: self.summary(),
+            found,
+        };
+
+        match (self, &tree.kind) {
+            (Pattern::Rest, _) => Ok(()),
+            (
+                Pattern::Span {
+                    level,
+                    name,
+                    children,
+                },
+                TreeKind::Span(span),
+            ) => {
+                if !glob(name, span.name) || level.is_some_and(|l| l != tree.attrs.level) {
+                    return Err(mismatch(path, summary(tree)));
+                }
+
+                path.push(span.name.to_string());
+                let rest = matches!(children.last(), Some(Pattern::Rest));
+                let expected = children.len() - rest as usize;
+
+                for (idx, (pattern, child)) in children.iter().zip(&span.children).enumerate() {
+                    path.push(format!("[{}]", idx));
+                    pattern.matches_at(child, path)?;
+                    path.pop();
+                }
+
+                let found = span.children.len();
+                if found < expected || (!rest && found > expected) {
+                    return Err(Mismatch {
+                        path: path.clone(),
+                        expected: format!("{} children", expected),
+                        found: format!("{} children", found),
+                    });
+                }
+                path.pop();
+                Ok(())
+            }
+            (
+                Pattern::Event {
+                    level,
+                    message,
+                    fields,
+                },
+                TreeKind::Event(event),
+            ) => {
+                let fields_match = fields.iter().all(|(key, value)| {
+                    event
+                        .fields
+                        .iter()
+                        .any(|kv| kv.key == *key && glob(value, &unquote(&kv.value)))
+                });
+
+                if *level != tree.attrs.level || !glob(message, &event.message) || !fields_match {
+                    return Err(mismatch(path, summary(tree)));
+                }
+                Ok(())
+            }
+            _ => Err(mismatch(path, summary(tree))),
+        }
+    }
+
+    fn summary(&self) -> String {
+        match self {
+            Pattern::Span {
+                level: Some(level),
+                name,
+                ..
+            } => format!("span {} {:?}", level, name),
+            Pattern::Span { name, .. } => format!("span {:?}", name),
+            Pattern::Event {
+                level,
+                message,
+                fields,
+            } => {
+                let mut out = format!("event {} {:?}", level, message);
+                if !fields.is_empty() {
+                    let fields = fields
+                        .iter()
+                        .map(|(key, value)| format!("{} = {:?}", key, value))
+                        .collect::<Vec<_>>();
+                    out.push_str(&format!(" ({})", fields.join(", ")));
+                }
+                out
+            }
+            Pattern::Rest => "..".to_string(),
+        }
+    }
+
+    fn write_indented(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:indent$}{}", "", self.summary(), indent = depth * 4)?;
+        if let Pattern::Span { children, .. } = self {
+            writeln!(f, " [")?;
+            for child in children {
+                child.write_indented(depth + 1, f)?;
+            }
+            write!(f, "{:indent$}]", "", indent = depth * 4)?;
+        }
+        writeln!(f, ",")
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(0, f)
+    }
+}
+
+fn summary(tree: &Tree) -> String {
+    match &tree.kind {
+        TreeKind::Span(span) => format!("span {} {:?}", tree.attrs.level, span.name),
+        TreeKind::Event(event) => {
+            let mut out = format!("event {} {:?}", tree.attrs.level, event.message);
+            if !event.fields.is_empty() {
+                let fields = event
+                    .fields
+                    .iter()
+                    .map(|KeyValue { key, value, .. }| format!("{} = {}", key, value))
+                    .collect::<Vec<_>>();
+                out.push_str(&format!(" ({})", fields.join(", ")));
+            }
+            out
+        }
+    }
+}
+
+/// Renders a tree in the same notation as [`Pattern`]s.
+pub(crate) fn outline(tree: &Tree, depth: usize, out: &mut String) {
+    out.push_str(&format!(
+        "{:indent$}{}",
+        "",
+        summary(tree),
+        indent = depth * 4
+    ));
+    if let TreeKind::Span(span) = &tree.kind {
+        out.push_str(" [\n");
+        for child in span.children.iter() {
+            outline(child, depth + 1, out);
+        }
+        out.push_str(&format!("{:indent$}]", "", indent = depth * 4));
+    }
+    out.push_str(",\n");
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_matches(tree: &Tree, pattern: &Pattern) {
+    if let Err(mismatch) = pattern.matches(tree) {
+        let mut actual = String::new();
+        outline(tree, 0, &mut actual);
+        panic!(
+            "tree does not match pattern\n{}\n\npattern:\n{}\nactual:\n{}",
+            mismatch, pattern, actual
+        );
+    }
+}
+
+/// Matches `text` against a glob `pattern`.
+pub(crate) fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,120 @@
+//! A coarse clock for timestamping trees on hot paths.
+//!
+//! See [`TreeLayer::coarse_timestamps`] for more details.
+//!
+//! [`TreeLayer::coarse_timestamps`]: crate::layer::TreeLayer::coarse_timestamps
+
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the coarse clock is updated.
+pub(crate) const RESOLUTION: Duration = Duration::from_millis(1);
+
+/// The coarse time in milliseconds since the Unix epoch, or zero if the clock
+/// isn't running.
+static NOW_MILLIS: AtomicI64 = AtomicI64::new(0);
+
+/// The coarse monotonic time in milliseconds since [`EPOCH`], which is updated
+/// along with [`NOW_MILLIS`].
+static MONOTONIC_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// The instant that monotonic time is measured from.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Whether the thread updating the clock is running, and how many [`Clock`]s
+/// are keeping it running.
+static STATE: Mutex<State> = Mutex::new(State {
+    running: false,
+    users: 0,
+});
+
+struct State {
+    running: bool,
+    users: usize,
+}
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    // The state is always consistent, even if a thread panicked holding it
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Keeps the thread updating the coarse clock running.
+///
+/// The thread is started when the first [`Clock`] is acquired, and stops on
+/// its next tick after the last one is dropped.
+pub(crate) struct Clock(());
+
+impl Clock {
+    /// Starts the thread updating the coarse clock, if it isn't running yet.
+    pub(crate) fn acquire() -> Self {
+        let mut state = state();
+        state.users += 1;
+        if !state.running {
+            update();
+            let spawned = thread::Builder::new()
+                .name("tracing-forest-clock".to_string())
+                .spawn(tick);
+            state.running = spawned.is_ok();
+            if !state.running {
+                // Without the thread, the time would never change
+                NOW_MILLIS.store(0, Ordering::Relaxed);
+            }
+        }
+        Clock(())
+    }
+}
+
+impl Drop for Clock {
+    fn drop(&mut self) {
+        state().users -= 1;
+    }
+}
+
+/// Updates the coarse clock until nothing uses it anymore.
+fn tick() {
+    loop {
+        thread::sleep(RESOLUTION);
+        let mut state = state();
+        if state.users == 0 {
+            state.running = false;
+            NOW_MILLIS.store(0, Ordering::Relaxed);
+            return;
+        }
+        update();
+    }
+}
+
+fn update() {
+    MONOTONIC_MILLIS.store(elapsed().as_millis() as u64, Ordering::Relaxed);
+    NOW_MILLIS.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+}
+
+fn elapsed() -> Duration {
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
+
+/// Returns the coarse time, which lags behind the current time by up to a
+/// few milliseconds, or the current time if the clock isn't running.
+pub(crate) fn now() -> DateTime<Utc> {
+    match NOW_MILLIS.load(Ordering::Relaxed) {
+        0 => Utc::now(),
+        millis => DateTime::from_timestamp_millis(millis).unwrap_or_else(Utc::now),
+    }
+}
+
+/// Returns the monotonic time since an arbitrary point, for measuring how much
+/// time passed since an earlier call.
+///
+/// If `coarse` is set and the clock is running, this is as coarse as [`now`]
+/// and doesn't read the monotonic clock. Otherwise, it's precise.
+pub(crate) fn monotonic(coarse: bool) -> Duration {
+    match coarse && NOW_MILLIS.load(Ordering::Relaxed) != 0 {
+        true => Duration::from_millis(MONOTONIC_MILLIS.load(Ordering::Relaxed)),
+        false => elapsed(),
+    }
+}
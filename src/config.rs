@@ -0,0 +1,135 @@
+//! Load the configuration of a [`TreeLayer`] from a file.
+//!
+//! See [`ForestConfig`] for more details.
+//!
+//! [`TreeLayer`]: crate::layer::TreeLayer
+
+use serde::de::{Deserializer, Error};
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+
+/// The logging setup of an application, which can be deserialized from any
+/// format supported by `serde`, like TOML or YAML.
+///
+/// Every field is optional, and defaults to pretty printing all levels to
+/// stdout. To build a [`TreeLayer`] from it, see
+/// [`LayerBuilder::from_config`].
+///
+/// # Examples
+///
+/// ```toml
+/// format = "json"
+/// filter = "info"
+/// output = "/var/log/app.log"
+/// sampling = 0.5
+///
+/// [rotation]
+/// max_bytes = 10_000_000
+/// keep = 3
+/// ```
+///
+/// ```
+/// # use tracing_forest::builder::LayerBuilder;
+/// # use tracing_forest::config::ForestConfig;
+/// let config: ForestConfig = serde_json::from_str(r#"{ "filter": "debug", "ansi": true }"#)
+///     .expect("invalid config");
+///
+/// let _guard = tracing::subscriber::set_default({
+///     LayerBuilder::from_config(&config)
+///         .expect("failed to open output")
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+/// ```
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+/// [`LayerBuilder::from_config`]: crate::builder::LayerBuilder::from_config
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForestConfig {
+    /// How trees are formatted.
+    pub format: Format,
+    /// The most verbose level that is collected, like `"info"` or `"off"`.
+    #[serde(deserialize_with = "level_filter")]
+    pub filter: LevelFilter,
+    /// Whether levels are colored using ANSI escape codes, for the `pretty`
+    /// format.
+    pub ansi: bool,
+    /// Where formatted trees are written.
+    pub output: Output,
+    /// When the output file is rotated. This is ignored unless the output is
+    /// a file.
+    pub rotation: Option<Rotation>,
+    /// The fraction of trees that are written, between `0.0` and `1.0`.
+    pub sampling: f64,
+}
+
+impl Default for ForestConfig {
+    fn default() -> Self {
+        ForestConfig {
+            format: Format::Pretty,
+            filter: LevelFilter::TRACE,
+            ansi: false,
+            output: Output::Stdout,
+            rotation: None,
+            sampling: 1.0,
+        }
+    }
+}
+
+/// The format of a [`ForestConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Format with [`Pretty`][crate::formatter::pretty::Pretty].
+    Pretty,
+    /// Format with compact [`Json`], which requires the `json` feature.
+    ///
+    /// [`Json`]: crate::formatter::json::Json
+    Json,
+}
+
+/// The output of a [`ForestConfig`], deserialized from `"stdout"`,
+/// `"stderr"`, or the path of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Write to stdout.
+    Stdout,
+    /// Write to stderr.
+    Stderr,
+    /// Append to the file at the path.
+    File(PathBuf),
+}
+
+impl<'de> Deserialize<'de> for Output {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let output = String::deserialize(deserializer)?;
+        Ok(match output.as_str() {
+            "stdout" => Output::Stdout,
+            "stderr" => Output::Stderr,
+            _ => Output::File(output.into()),
+        })
+    }
+}
+
+/// When the output file of a [`ForestConfig`] is rotated. See
+/// [`RotatingFile`][crate::writer::RotatingFile] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rotation {
+    /// The size in bytes that the file is rotated at.
+    pub max_bytes: u64,
+    /// How many old files are kept.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+const fn default_keep() -> usize {
+    5
+}
+
+fn level_filter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LevelFilter, D::Error> {
+    let filter = String::deserialize(deserializer)?;
+    filter.parse().map_err(D::Error::custom)
+}
@@ -0,0 +1,135 @@
+//! Reporting internal errors of the layer and processors.
+//!
+//! Logging shouldn't take down the program it's observing, so errors that
+//! happen while formatting, writing, or sending trees are passed to an error
+//! handler instead of panicking. By default, the handler writes a warning to
+//! stderr the first time each [kind] of error happens, and ignores it after
+//! that.
+//!
+//! To handle errors differently, such as to count them in metrics, see
+//! [`set_error_handler`].
+//!
+//! [kind]: ForestError::kind
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+/// An internal error of a [`TreeLayer`] or [`Processor`].
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+/// [`Processor`]: crate::processor::Processor
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ForestError {
+    /// A formatter failed to format a tree, which was discarded.
+    Format(io::Error),
+    /// Writing a formatted tree failed, so it was lost.
+    Write(io::Error),
+    /// The processing thread or task has stopped, so trees sent to it are
+    /// discarded.
+    ChannelClosed,
+    /// A processor failed to store or deliver trees.
+    Processor {
+        /// The processor that failed, like `"sqlite"`.
+        processor: &'static str,
+        /// Why it failed.
+        error: io::Error,
+    },
+}
+
+/// The kind of a [`ForestError`], without any details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// See [`ForestError::Format`].
+    Format,
+    /// See [`ForestError::Write`].
+    Write,
+    /// See [`ForestError::ChannelClosed`].
+    ChannelClosed,
+    /// See [`ForestError::Processor`].
+    Processor,
+}
+
+impl ForestError {
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ForestError::Format(_) => ErrorKind::Format,
+            ForestError::Write(_) => ErrorKind::Write,
+            ForestError::ChannelClosed => ErrorKind::ChannelClosed,
+            ForestError::Processor { .. } => ErrorKind::Processor,
+        }
+    }
+}
+
+impl fmt::Display for ForestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForestError::Format(err) => write!(f, "failed to format a tree: {}", err),
+            ForestError::Write(err) => write!(f, "failed to write a tree: {}", err),
+            ForestError::ChannelClosed => f.write_str("the processing thread has stopped"),
+            ForestError::Processor { processor, error } => {
+                write!(f, "the {} processor failed: {}", processor, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ForestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ForestError::Format(err)
+            | ForestError::Write(err)
+            | ForestError::Processor { error: err, .. } => Some(err),
+            ForestError::ChannelClosed => None,
+        }
+    }
+}
+
+static HANDLER: RwLock<fn(ForestError)> = RwLock::new(warn_once);
+
+/// Set the function that internal errors are passed to, replacing the
+/// default handler for the whole process.
+///
+/// The handler runs wherever the error happened, which can be the processing
+/// thread of an async processor, so it should be quick and must not log with
+/// `tracing`, since that could fail again.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::atomic::{AtomicU64, Ordering};
+/// # use tracing_forest::error::ForestError;
+/// static ERRORS: AtomicU64 = AtomicU64::new(0);
+///
+/// tracing_forest::set_error_handler(|_: ForestError| {
+///     ERRORS.fetch_add(1, Ordering::Relaxed);
+/// });
+/// ```
+pub fn set_error_handler(handler: fn(ForestError)) {
+    *HANDLER.write().unwrap_or_else(|err| err.into_inner()) = handler;
+}
+
+/// Pass `error` to the current error handler.
+pub(crate) fn report(error: ForestError) {
+    let handler = *HANDLER.read().unwrap_or_else(|err| err.into_inner());
+    handler(error);
+}
+
+/// The default error handler, which writes each kind of error to stderr the
+/// first time it happens.
+fn warn_once(error: ForestError) {
+    static WARNED: AtomicU8 = AtomicU8::new(0);
+
+    let bit = 1 << error.kind() as u8;
+    if WARNED.fetch_or(bit, Ordering::Relaxed) & bit == 0 {
+        let _ = writeln!(
+            io::stderr(),
+            "tracing-forest: {} (further errors like this are ignored)",
+            error
+        );
+    }
+}
@@ -1,3 +1,6 @@
+// Most failures can only happen while collecting trees
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
 /* layer */
 #[cold]
 pub fn span_not_in_context<T>() -> T {
@@ -14,12 +17,18 @@ pub fn multiple_tags_on_event() -> ! {
     panic!("More than one tag was passed to an event, this is likely a mistake");
 }
 
+/* tree */
+#[cold]
+pub fn event_child<T>() -> T {
+    panic!("Events can't have children, only spans can");
+}
+
 /* id */
 #[cold]
 pub fn subscriber_not_found<'a, S>() -> &'a S {
     panic!(
         "Subscriber could not be downcasted to `{}`",
-        std::any::type_name::<S>()
+        core::any::type_name::<S>()
     );
 }
 
@@ -34,7 +43,7 @@ pub fn no_tree_layer<T>() -> T {
 }
 
 /* tag */
-use crate::layer::TAG_KEY;
+use crate::tag::TAG_KEY;
 
 #[cold]
 pub fn tag_unset(id: u64) -> ! {
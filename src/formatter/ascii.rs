@@ -0,0 +1,153 @@
+//! A [`Formatter`] that guarantees its output is plain ASCII.
+//!
+//! See [`Ascii`] for more details.
+
+use crate::formatter::{Formatter, Icons};
+use crate::layer::Tree;
+use crate::processor::Latency;
+use std::fmt::Write as _;
+use std::io;
+use std::time::Duration;
+
+/// A [`Formatter`] that makes the output of another formatter plain ASCII,
+/// for serial consoles, legacy syslog pipelines, and other environments that
+/// mangle UTF-8.
+///
+/// The icons of the formatter are displayed as [`Icons::Text`] instead of
+/// emoji. The box characters drawn by [`Pretty`], like `┝━`, and a few other
+/// common characters, like the `µ` of `µs`, are transliterated to ASCII
+/// characters of the same width, so trees stay aligned. Any other non-ASCII
+/// character, in a message or field value for example, is escaped as
+/// `\u{...}`, and bytes that aren't valid UTF-8 are escaped as `\x..`.
+///
+/// ANSI escape codes are ASCII, so colors are kept if the formatter is
+/// colored. To escape non-ASCII characters in JSON, use [`Json::with_ascii`]
+/// instead, which keeps the output valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{ascii::Ascii, pretty::Pretty};
+/// # use tracing_forest::Processor;
+/// let formatter = Ascii::new(Pretty::new());
+/// let layer = tracing_forest::blocking(formatter, std::io::stdout).into_layer();
+/// ```
+/// ```log
+/// INFO     request [ 1.21ms | 100.000% ]
+/// INFO     |- [INF] [info]: received | path: "/caf\u{e9}"
+/// INFO     `- [SEC] [security.access]: token accepted
+/// ```
+///
+/// [`Pretty`]: crate::formatter::pretty::Pretty
+/// [`Json::with_ascii`]: crate::formatter::json::Json::with_ascii
+pub struct Ascii<F> {
+    formatter: F,
+}
+
+impl<F: Formatter> Ascii<F> {
+    /// Construct a new [`Ascii`] formatter, which makes the output of
+    /// `formatter` plain ASCII.
+    pub fn new(mut formatter: F) -> Self {
+        formatter.set_icons(Icons::Text);
+        Ascii { formatter }
+    }
+}
+
+impl<F: Formatter> Formatter for Ascii<F> {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        let start = writer.len();
+        self.formatter.fmt(tree, writer)?;
+        to_ascii(writer, start);
+        Ok(())
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        let start = writer.len();
+        self.formatter.fmt_latency(latency, writer)?;
+        to_ascii(writer, start);
+        Ok(())
+    }
+
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let start = writer.len();
+        self.formatter.fmt_with_latency(tree, queued, writer)?;
+        to_ascii(writer, start);
+        Ok(())
+    }
+
+    fn set_ansi(&mut self, ansi: bool) {
+        self.formatter.set_ansi(ansi)
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        let icons = match icons {
+            Icons::Emoji => Icons::Text,
+            icons => icons,
+        };
+        self.formatter.set_icons(icons)
+    }
+}
+
+/// Rewrites the output written after `start` as plain ASCII.
+fn to_ascii(writer: &mut Vec<u8>, start: usize) {
+    if writer[start..].is_ascii() {
+        return;
+    }
+
+    let output = writer.split_off(start);
+    let mut ascii = String::with_capacity(output.len());
+    let mut rest = &output[..];
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                push_ascii(valid, &mut ascii);
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                // The bytes before the error were checked by `from_utf8`
+                push_ascii(std::str::from_utf8(valid).unwrap_or_default(), &mut ascii);
+                let len = err.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..len] {
+                    let _ = write!(ascii, "\\x{:02x}", byte);
+                }
+                rest = &invalid[len..];
+            }
+        }
+    }
+    writer.extend_from_slice(ascii.as_bytes());
+}
+
+fn push_ascii(text: &str, ascii: &mut String) {
+    for c in text.chars() {
+        match transliterate(c) {
+            Some(replacement) => ascii.push(replacement),
+            None => {
+                let _ = write!(ascii, "\\u{{{:x}}}", c as u32);
+            }
+        }
+    }
+}
+
+/// Returns `c` if it's ASCII, or an ASCII character that looks like it.
+fn transliterate(c: char) -> Option<char> {
+    let replacement = match c {
+        c if c.is_ascii() => c,
+        '│' | '┃' | '┝' | '├' => '|',
+        '━' | '─' | '–' | '—' => '-',
+        '┕' | '└' => '`',
+        '▸' | '→' => '>',
+        '•' | '·' => '*',
+        'µ' | 'μ' => 'u',
+        '‘' | '’' => '\'',
+        '“' | '”' => '"',
+        '\u{a0}' => ' ',
+        _ => return None,
+    };
+    Some(replacement)
+}
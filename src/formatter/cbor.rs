@@ -0,0 +1,46 @@
+//! A [`Formatter`] that formats logs as CBOR data items.
+//!
+//! See [`Cbor`] for more details.
+
+use crate::formatter::Formatter;
+use crate::layer::Tree;
+use std::io;
+
+/// Format logs as [CBOR] data items, a compact binary encoding of the same
+/// objects that the [`Json`] formatter writes with the default schema.
+///
+/// Each tree is written as one data item, so a file of trees is a CBOR
+/// sequence that can be read back with [`replay_cbor`].
+///
+/// [CBOR]: https://www.rfc-editor.org/rfc/rfc8949
+/// [`Json`]: crate::formatter::json::Json
+/// [`replay_cbor`]: crate::replay::replay_cbor
+pub struct Cbor {
+    #[doc(hidden)]
+    _priv: (),
+}
+
+impl Cbor {
+    /// Constructs a new [`Cbor`] formatter.
+    pub const fn new() -> Self {
+        Cbor { _priv: () }
+    }
+}
+
+impl Default for Cbor {
+    fn default() -> Self {
+        Cbor::new()
+    }
+}
+
+impl Formatter for Cbor {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        // Going through a JSON value keeps IDs and timestamps as strings, since
+        // they're serialized differently by formats that aren't human readable
+        let value = serde_json::to_value(&tree)?;
+        ciborium::into_writer(&value, writer).map_err(|err| match err {
+            ciborium::ser::Error::Io(err) => err,
+            ciborium::ser::Error::Value(msg) => io::Error::new(io::ErrorKind::InvalidData, msg),
+        })
+    }
+}
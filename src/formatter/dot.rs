@@ -0,0 +1,159 @@
+//! A [`Formatter`] that formats logs as GraphViz DOT graphs.
+//!
+//! See [`Dot`] for more details.
+
+use crate::formatter::pretty::{self, DurationDisplay, DurationFormat};
+use crate::formatter::Formatter;
+use crate::layer::{Tree, TreeEvent, TreeSpan, TreeVisitor};
+use std::io::{self, Write};
+use tracing::Level;
+
+/// Format logs as [DOT] graphs, for visualizing the structure of trees with
+/// GraphViz and similar tools.
+///
+/// Each tree is written as its own `digraph`, where spans are boxes labeled
+/// with their name, level, and duration, and events are notes labeled with
+/// their level and message. Edges point from parents to children.
+///
+/// # Examples
+///
+/// ```dot
+/// digraph forest {
+///     node [fontname="monospace"];
+///     n0 [shape=box, label="request\nINFO | 1.23ms"];
+///     n1 [shape=note, label="💬 [info]: handled\nINFO"];
+///     n0 -> n1;
+/// }
+/// ```
+///
+/// Render with `dot -Tsvg forest.dot -o forest.svg`.
+///
+/// [DOT]: https://graphviz.org/doc/info/lang.html
+pub struct Dot {
+    duration_format: DurationFormat,
+    #[doc(hidden)]
+    _priv: (),
+}
+
+impl Dot {
+    /// Constructs a new [`Dot`] formatter.
+    pub const fn new() -> Self {
+        Dot {
+            duration_format: DurationFormat::Auto,
+            _priv: (),
+        }
+    }
+
+    /// Sets how span durations are displayed in node labels.
+    pub const fn with_duration_format(mut self, duration_format: DurationFormat) -> Self {
+        self.duration_format = duration_format;
+        self
+    }
+}
+
+/// Writes the nodes and edges of the trees it walks.
+struct Nodes<'a> {
+    dot: &'a Dot,
+    writer: &'a mut Vec<u8>,
+    next: usize,
+    /// The IDs of the spans being walked, from the root.
+    parents: Vec<usize>,
+}
+
+impl Nodes<'_> {
+    /// Returns the ID of the next node, and its color attribute.
+    fn next(&mut self, tree: &Tree) -> (usize, &'static str) {
+        let id = self.next;
+        self.next += 1;
+
+        let color = match tree.attrs.level {
+            Level::WARN => ", color=orange",
+            Level::ERROR => ", color=red",
+            _ => "",
+        };
+        (id, color)
+    }
+
+    /// Writes the edge from the parent of the node `id`, if it has one.
+    fn edge(&mut self, id: usize) -> io::Result<()> {
+        match self.parents.last() {
+            Some(parent) => writeln!(self.writer, "    n{} -> n{};", parent, id),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TreeVisitor for Nodes<'_> {
+    type Error = io::Error;
+
+    fn enter_span(&mut self, tree: &Tree, span: &TreeSpan) -> io::Result<()> {
+        let (id, color) = self.next(tree);
+        let duration = span.duration_total.as_nanos() as f64;
+        writeln!(
+            self.writer,
+            "    n{} [shape=box, label=\"{}\\n{} | {}\"{}];",
+            id,
+            escape(span.name),
+            tree.attrs.level,
+            DurationDisplay(duration, self.dot.duration_format),
+            color
+        )?;
+        self.parents.push(id);
+        Ok(())
+    }
+
+    fn exit_span(&mut self, _: &Tree, _: &TreeSpan) -> io::Result<()> {
+        // The edge to a span is written after its descendants
+        let id = self.parents.pop().unwrap_or_default();
+        self.edge(id)
+    }
+
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> io::Result<()> {
+        let (id, color) = self.next(tree);
+        let (message, icon) = pretty::tag_and_icon(event, tree.attrs.level);
+        let label = format!("{} [{}]: {}", icon, message, event.message);
+        writeln!(
+            self.writer,
+            "    n{} [shape=note, label=\"{}\\n{}\"{}];",
+            id,
+            escape(&label),
+            tree.attrs.level,
+            color
+        )?;
+        self.edge(id)
+    }
+}
+
+impl Default for Dot {
+    fn default() -> Self {
+        Dot::new()
+    }
+}
+
+impl Formatter for Dot {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        writeln!(writer, "digraph forest {{")?;
+        writeln!(writer, "    node [fontname=\"monospace\"];")?;
+        tree.walk(&mut Nodes {
+            dot: self,
+            writer,
+            next: 0,
+            parents: Vec::new(),
+        })?;
+        writeln!(writer, "}}")
+    }
+}
+
+/// Escapes a string for use in a quoted DOT label.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
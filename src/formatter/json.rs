@@ -2,9 +2,22 @@
 //!
 //! See [`Json`] for more details.
 
+use crate::formatter::json::schema::{Document, SchemaVersion};
 use crate::formatter::Formatter;
-use crate::layer::Tree;
+use crate::layer::{Annotation, KeyValue, Tree, TreeEvent, TreeKind, TreeSpan, TreeVisitor};
+use crate::processor::Latency;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::{json, Map, Value};
+use std::fmt;
 use std::io::{self, Write};
+use tracing::Level;
+
+pub mod schema;
 
 /// Format logs as JSON objects.
 ///
@@ -18,6 +31,7 @@ use std::io::{self, Write};
 ///       "name": "first",
 ///       "nanos_total": 104667,
 ///       "nanos_nested": 13917,
+///       "nanos_self": 90750,
 ///       "children": [
 ///         {
 ///           "level": "TRACE",
@@ -26,6 +40,7 @@ use std::io::{self, Write};
 ///               "name": "second",
 ///               "nanos_total": 13917,
 ///               "nanos_nested": 0,
+///               "nanos_self": 13917,
 ///               "children": []
 ///             }
 ///           }
@@ -35,27 +50,732 @@ use std::io::{self, Write};
 ///   }
 /// }
 /// ```
+///
+/// # Google Cloud Logging
+///
+/// With [`with_gcp`], each event is written as its own line with the special
+/// fields that [Cloud Logging] recognizes, so logs from Cloud Run and GKE are
+/// shown with the right severity and grouped by trace:
+///
+/// ```json
+/// {"fields":{"attempt":"2"},"logging.googleapis.com/sourceLocation":{"file":"src/main.rs","function":"my_app::client","line":"42"},"logging.googleapis.com/trace":"projects/my-project/traces/a7f30b4451a5447aa71d0fa3d2c33b70","message":"retrying","severity":"WARNING","spans":["request","fetch"],"time":"2022-03-01T16:21:58.183128+00:00"}
+/// ```
+///
+/// [`with_gcp`]: Json::with_gcp
+/// [Cloud Logging]: https://cloud.google.com/logging/docs/structured-logging
+///
+/// # Elastic Common Schema
+///
+/// With [`with_ecs`], each span and event is written as its own line using
+/// [ECS] field names, so the output can be ingested by Elasticsearch without
+/// a transform:
+///
+/// ```json
+/// {"@timestamp":"2022-03-01T16:21:58.183128+00:00","ecs":{"version":"8.11.0"},"event":{"duration":104667,"kind":"event"},"log":{"level":"INFO"},"message":"request","span":{"id":"a71d0fa3d2c33b70"},"trace":{"id":"a7f30b4451a5447aa71d0fa3d2c33b70"}}
+/// {"@timestamp":"2022-03-01T16:21:58.183140+00:00","ecs":{"version":"8.11.0"},"labels":{"attempt":"2"},"log":{"level":"WARN","logger":"my_app::client","origin":{"file":{"line":42,"name":"src/main.rs"}}},"message":"retrying","span":{"id":"a71d0fa3d2c33b70"},"trace":{"id":"a7f30b4451a5447aa71d0fa3d2c33b70"}}
+/// ```
+///
+/// [`with_ecs`]: Json::with_ecs
+/// [ECS]: https://www.elastic.co/guide/en/ecs/current/index.html
+///
+/// # Untrusted input
+///
+/// Strings are always escaped losslessly, including control characters and
+/// ANSI escape codes, so field contents can't break out of their string or
+/// inject lines. When logged values can come from untrusted input, also
+/// [limit the length of strings][with_max_string_len] and
+/// [of records][with_max_record_len], so a single multi-megabyte value can't
+/// flood the output, and consider [escaping non-ASCII][with_ascii] for
+/// consumers that mishandle Unicode:
+///
+/// ```
+/// # use tracing_forest::formatter::json::Json;
+/// let formatter = Json::new(true)
+///     .with_max_string_len(Some(4096))
+///     .with_max_record_len(Some(1 << 20))
+///     .with_ascii(true);
+/// ```
+///
+/// [with_max_string_len]: Json::with_max_string_len
+/// [with_max_record_len]: Json::with_max_record_len
+/// [with_ascii]: Json::with_ascii
 pub struct Json {
     /// Whether or not the logs should have compact formatting.
     compact: bool,
+    latency: bool,
+    schema: SchemaVersion,
+    #[cfg(feature = "chrono")]
+    timestamps: Timestamps,
+    mode: Mode,
+    max_string_len: Option<usize>,
+    max_record_len: Option<usize>,
+    ascii: bool,
     #[doc(hidden)]
     _priv: (),
 }
 
+enum Mode {
+    Tree,
+    Gcp { project_id: String },
+    Ecs,
+}
+
+/// The version of ECS that [`Mode::Ecs`] documents conform to.
+const ECS_VERSION: &str = "8.11.0";
+
+/// Which timestamps the [`Json`] formatter writes for the spans and events
+/// inside of each tree.
+///
+/// Offsets are written as `offset_nanos`, the time since the parent span was
+/// opened, and `root_offset_nanos`, the time since the root span was opened,
+/// so latencies within a request can be read off directly. The root of a
+/// tree always keeps its absolute `timestamp`, which the offsets are
+/// relative to.
+///
+/// The absolute timestamp of the root is read from the wall clock, while
+/// offsets are measured by the monotonic clock, so they're never negative,
+/// even if the wall clock was stepped back while the tree was open. With
+/// [`Timestamps::Both`], each span and event has both.
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timestamps {
+    /// Only write absolute timestamps.
+    #[default]
+    Absolute,
+    /// Write offsets instead of absolute timestamps.
+    Relative,
+    /// Write both absolute timestamps and offsets.
+    Both,
+}
+
 impl Json {
     /// Construct a new [`Json`] formatter.
     pub const fn new(compact: bool) -> Self {
-        Json { compact, _priv: () }
+        Json {
+            compact,
+            latency: false,
+            schema: SchemaVersion::V1,
+            #[cfg(feature = "chrono")]
+            timestamps: Timestamps::Absolute,
+            mode: Mode::Tree,
+            max_string_len: None,
+            max_record_len: None,
+            ascii: false,
+            _priv: (),
+        }
     }
-}
 
-impl Formatter for Json {
-    fn fmt(&self, tree: Tree, mut writer: &mut Vec<u8>) -> io::Result<()> {
+    /// Sets whether each object has a `latency` field with the [`Latency`]
+    /// of processing it, as `queued_nanos` and `formatting_nanos`.
+    ///
+    /// `queued_nanos` is `null` for trees that weren't sent to a processing
+    /// task.
+    pub const fn with_latency(mut self, latency: bool) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the layout of the written objects.
+    ///
+    /// By default, [`SchemaVersion::V1`] is used, which serializes each
+    /// [`Tree`] as is. [`SchemaVersion::V2`] writes each tree as a
+    /// [`Document`], which has a [JSON Schema] with the `json-schema`
+    /// feature.
+    ///
+    /// [JSON Schema]: crate::formatter::json::schema
+    pub const fn with_schema(mut self, schema: SchemaVersion) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Sets which timestamps are written for the spans and events inside of
+    /// each tree.
+    ///
+    /// By default, only absolute timestamps are written. This only applies to
+    /// trees, and not to the lines written by [`with_gcp`] and [`with_ecs`].
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::json::{Json, Timestamps};
+    /// let formatter = Json::new(true).with_timestamps(Timestamps::Both);
+    /// ```
+    ///
+    /// [`with_gcp`]: Json::with_gcp
+    /// [`with_ecs`]: Json::with_ecs
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub const fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Write each event as a line of [Google Cloud Logging] structured JSON,
+    /// instead of writing each tree as one object.
+    ///
+    /// `severity` and `message` are always written, and `time` is written with
+    /// the `chrono` feature. With the `uuid` feature, events in spans link to
+    /// the trace `projects/{project_id}/traces/{id}`, where `id` is the root
+    /// span's ID. The names of the enclosing spans and the event's fields are
+    /// written as `spans` and `fields`.
+    ///
+    /// Lines are always compact, and [latency] isn't written.
+    ///
+    /// [Google Cloud Logging]: https://cloud.google.com/logging/docs/structured-logging
+    /// [latency]: Json::with_latency
+    pub fn with_gcp(mut self, project_id: impl Into<String>) -> Self {
+        self.mode = Mode::Gcp {
+            project_id: project_id.into(),
+        };
+        self
+    }
+
+    /// Write each span and event as a line of [Elastic Common Schema] JSON,
+    /// instead of writing each tree as one object.
+    ///
+    /// Spans are written before their children, with their name as `message`
+    /// and their duration in nanoseconds as `event.duration`. Events have
+    /// their target as `log.logger`, their source location as `log.origin`,
+    /// their fields as `labels`, and their tag in `tags`. With the `uuid`
+    /// feature, `trace.id` is the root span's ID, and every span is given a
+    /// `span.id` that's unique within the tree and shared with the events
+    /// directly inside it.
+    ///
+    /// Lines are always compact, and [latency] isn't written.
+    ///
+    /// [Elastic Common Schema]: https://www.elastic.co/guide/en/ecs/current/index.html
+    /// [latency]: Json::with_latency
+    pub fn with_ecs(mut self) -> Self {
+        self.mode = Mode::Ecs;
+        self
+    }
+
+    /// Sets the most bytes of each message, field value, and annotation that
+    /// are written.
+    ///
+    /// Longer strings are cut at a character boundary and end with a marker
+    /// counting the omitted bytes, like `…(1048576 bytes omitted)`. By
+    /// default, strings aren't limited.
+    pub const fn with_max_string_len(mut self, len: Option<usize>) -> Self {
+        self.max_string_len = len;
+        self
+    }
+
+    /// Sets the most bytes of each written line, not counting the newline.
+    ///
+    /// A line that's longer is replaced with a placeholder object recording
+    /// its level and length, like
+    /// `{"level":"INFO","truncated":{"bytes":2097152}}`. By default, lines
+    /// aren't limited.
+    pub const fn with_max_record_len(mut self, len: Option<usize>) -> Self {
+        self.max_record_len = len;
+        self
+    }
+
+    /// Sets whether non-ASCII characters are escaped as `\uXXXX`, so the
+    /// output is plain ASCII.
+    ///
+    /// This is lossless, and also escapes the line separators `U+2028` and
+    /// `U+2029` that some consumers treat as newlines. Defaults to `false`.
+    pub const fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    fn fmt_lines(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        if let Mode::Gcp { project_id } = &self.mode {
+            let trace = trace_id(&tree).map(|id| format!("projects/{}/traces/{}", project_id, id));
+            return tree.walk(&mut GcpEvents {
+                trace: trace.as_deref(),
+                spans: Vec::new(),
+                writer,
+            });
+        }
+
+        if let Mode::Ecs = self.mode {
+            let trace = trace_id(&tree);
+            let mut ecs = Ecs {
+                trace: trace.as_deref(),
+                spans: 0,
+                writer,
+            };
+            return ecs.write(&tree, None);
+        }
+
+        #[cfg(feature = "chrono")]
+        if self.timestamps != Timestamps::Absolute {
+            match self.schema {
+                SchemaVersion::V1 => {
+                    let mut value = serde_json::to_value(&tree)?;
+                    add_offsets(&tree, &mut value, self.timestamps, None);
+                    self.write(&value, writer)?;
+                }
+                SchemaVersion::V2 => {
+                    self.write(&Document::with_timestamps(&tree, self.timestamps), writer)?
+                }
+            }
+            return writeln!(writer);
+        }
+
+        match self.schema {
+            SchemaVersion::V1 => self.write(&tree, writer)?,
+            SchemaVersion::V2 => self.write(&Document::new(&tree), writer)?,
+        }
+        writeln!(writer)
+    }
+
+    fn write<T: Serialize>(&self, value: &T, mut writer: &mut Vec<u8>) -> io::Result<()> {
         if self.compact {
-            serde_json::to_writer(&mut writer, &tree)?;
+            serde_json::to_writer(&mut writer, value)?;
         } else {
-            serde_json::to_writer_pretty(&mut writer, &tree)?;
+            serde_json::to_writer_pretty(&mut writer, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the nanoseconds from `start` to `timestamp`, or `0` if the clock
+/// went backwards, which can only happen in trees that weren't recorded by a
+/// [`TreeLayer`], like ones parsed by [`bridge`].
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+/// [`bridge`]: crate::bridge
+#[cfg(feature = "chrono")]
+pub(crate) fn offset_nanos(start: DateTime<Utc>, timestamp: DateTime<Utc>) -> u64 {
+    let nanos = (timestamp - start).num_nanoseconds().unwrap_or(i64::MAX);
+    nanos.max(0) as u64
+}
+
+/// Adds the offsets of `tree` and its descendants to their serialized
+/// `value`, where `starts` are the timestamps of the parent and root spans.
+#[cfg(feature = "chrono")]
+fn add_offsets(
+    tree: &Tree,
+    value: &mut Value,
+    timestamps: Timestamps,
+    starts: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) {
+    let timestamp = tree.attrs.timestamp;
+    if let (Some((parent, root)), Some(object)) = (starts, value.as_object_mut()) {
+        object.insert(
+            "offset_nanos".into(),
+            offset_nanos(parent, timestamp).into(),
+        );
+        object.insert(
+            "root_offset_nanos".into(),
+            offset_nanos(root, timestamp).into(),
+        );
+        if timestamps == Timestamps::Relative {
+            object.remove("timestamp");
+        }
+    }
+
+    if let TreeKind::Span(span) = &tree.kind {
+        let root = starts.map_or(timestamp, |(_, root)| root);
+        let children = value
+            .pointer_mut("/kind/Span/children")
+            .and_then(Value::as_array_mut);
+        for (child, value) in span.children.iter().zip(children.into_iter().flatten()) {
+            add_offsets(child, value, timestamps, Some((timestamp, root)));
+        }
+    }
+}
+
+impl Formatter for Json {
+    fn fmt(&self, mut tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        if let Some(max) = self.max_string_len {
+            limit_strings(&mut tree, max);
+        }
+        let level = tree.attrs.level;
+
+        let start = writer.len();
+        self.fmt_lines(tree, writer)?;
+
+        // Escaping makes records longer, so it happens before they're limited
+        if self.ascii {
+            escape_non_ascii(writer, start);
+        }
+        if let Some(max) = self.max_record_len {
+            // Pretty printed trees span several lines, but are one record
+            let lines = !matches!(self.mode, Mode::Tree);
+            limit_records(writer, start, max, level, lines)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        if !self.latency || !matches!(self.mode, Mode::Tree) {
+            return Ok(());
+        }
+
+        // The latency is only known after formatting, so the last object is
+        // parsed back and written again with it
+        let mut records = serde_json::Deserializer::from_slice(writer).into_iter::<IgnoredAny>();
+        let mut start = None;
+        loop {
+            let offset = records.byte_offset();
+            match records.next() {
+                Some(record) => {
+                    record?;
+                    start = Some(offset);
+                }
+                None => break,
+            }
         }
+        let start = match start {
+            Some(start) => start,
+            // Nothing was written for the tree
+            None => return Ok(()),
+        };
+
+        let Record(mut entries) = serde_json::from_slice(&writer[start..])?;
+        let queued = latency.queued.map(|queued| queued.as_nanos() as u64);
+        let latency = json!({
+            "queued_nanos": queued,
+            "formatting_nanos": latency.formatting.as_nanos() as u64,
+        });
+        entries.push((
+            "latency".to_string(),
+            serde_json::value::to_raw_value(&latency)?,
+        ));
+
+        let mut record = Vec::with_capacity(writer.len() - start + 64);
+        self.write(&Record(entries), &mut record)?;
+        writer.truncate(start);
+        writer.extend_from_slice(&record);
         writeln!(writer)
     }
 }
+
+/// The entries of a JSON object in the order they were written, which
+/// [`Json::fmt_latency`] adds to without reformatting their values.
+struct Record(Vec<(String, Box<RawValue>)>);
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Entries;
+
+        impl<'de> Visitor<'de> for Entries {
+            type Value = Record;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Record, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Record(entries))
+            }
+        }
+
+        deserializer.deserialize_map(Entries)
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Writes every event of the tree it walks as a Cloud Logging entry.
+struct GcpEvents<'a> {
+    trace: Option<&'a str>,
+    /// The names of the spans being walked, from the root.
+    spans: Vec<&'static str>,
+    writer: &'a mut Vec<u8>,
+}
+
+impl TreeVisitor for GcpEvents<'_> {
+    type Error = io::Error;
+
+    fn enter_span(&mut self, _: &Tree, span: &TreeSpan) -> io::Result<()> {
+        self.spans.push(span.name);
+        Ok(())
+    }
+
+    fn exit_span(&mut self, _: &Tree, _: &TreeSpan) -> io::Result<()> {
+        self.spans.pop();
+        Ok(())
+    }
+
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> io::Result<()> {
+        let mut entry = Map::new();
+        let severity = match tree.attrs.level.as_str() {
+            "TRACE" | "DEBUG" => "DEBUG",
+            "WARN" => "WARNING",
+            level => level,
+        };
+        entry.insert("severity".into(), severity.into());
+        #[cfg(feature = "chrono")]
+        entry.insert("time".into(), tree.attrs.timestamp.to_rfc3339().into());
+        entry.insert("message".into(), event.message.as_ref().into());
+
+        if let Some(trace) = self.trace {
+            entry.insert("logging.googleapis.com/trace".into(), trace.into());
+        }
+
+        let mut location = Map::new();
+        if let Some(file) = event.file {
+            location.insert("file".into(), file.into());
+        }
+        if let Some(line) = event.line {
+            // Cloud Logging represents the line as an int64, which is a string in JSON
+            location.insert("line".into(), line.to_string().into());
+        }
+        location.insert("function".into(), event.target.into());
+        entry.insert(
+            "logging.googleapis.com/sourceLocation".into(),
+            Value::Object(location),
+        );
+
+        if let Some(tag) = event.tag {
+            entry.insert("tag".into(), tag.message.into());
+        }
+        entry.insert("spans".into(), self.spans.clone().into());
+        let fields = event
+            .fields
+            .iter()
+            .map(|kv| (kv.key.to_string(), field_value(kv)))
+            .collect::<Map<_, _>>();
+        entry.insert("fields".into(), Value::Object(fields));
+
+        serde_json::to_writer(&mut *self.writer, &entry)?;
+        writeln!(self.writer)
+    }
+}
+
+struct Ecs<'a> {
+    trace: Option<&'a str>,
+    spans: u64,
+    writer: &'a mut Vec<u8>,
+}
+
+impl Ecs<'_> {
+    fn write(&mut self, tree: &Tree, span_id: Option<&str>) -> io::Result<()> {
+        let mut doc = json!({
+            "ecs": { "version": ECS_VERSION },
+            "log": { "level": tree.attrs.level.as_str() },
+        });
+        #[cfg(feature = "chrono")]
+        {
+            doc["@timestamp"] = tree.attrs.timestamp.to_rfc3339().into();
+        }
+        if let Some(trace) = self.trace {
+            doc["trace"] = json!({ "id": trace });
+        }
+
+        match &tree.kind {
+            TreeKind::Span(span) => {
+                let span_id = self.trace.map(|trace| {
+                    // Derive IDs from the trace so they don't collide across trees
+                    let base = u64::from_str_radix(&trace[16..], 16).unwrap_or_default();
+                    self.spans += 1;
+                    format!("{:016x}", base.wrapping_add(self.spans - 1))
+                });
+
+                doc["message"] = span.name.into();
+                doc["event"] = json!({
+                    "kind": "event",
+                    "duration": span.duration_total.as_nanos() as u64,
+                });
+                if let Some(span_id) = &span_id {
+                    doc["span"] = json!({ "id": span_id });
+                }
+                self.line(&doc)?;
+
+                for child in span.children.iter() {
+                    self.write(child, span_id.as_deref())?;
+                }
+                Ok(())
+            }
+            TreeKind::Event(event) => {
+                doc["message"] = event.message.as_ref().into();
+                doc["log"]["logger"] = event.target.into();
+                if let Some(file) = event.file {
+                    doc["log"]["origin"] = json!({ "file": { "name": file, "line": event.line } });
+                }
+                if let Some(span_id) = span_id {
+                    doc["span"] = json!({ "id": span_id });
+                }
+                if let Some(tag) = event.tag {
+                    doc["tags"] = json!([tag.message]);
+                }
+                if !event.fields.is_empty() {
+                    doc["labels"] = event
+                        .fields
+                        .iter()
+                        .map(|kv| (kv.key.to_string(), field_value(kv)))
+                        .collect::<Map<_, _>>()
+                        .into();
+                }
+                self.line(&doc)
+            }
+        }
+    }
+
+    fn line(&mut self, doc: &Value) -> io::Result<()> {
+        serde_json::to_writer(&mut *self.writer, doc)?;
+        writeln!(self.writer)
+    }
+}
+
+/// Cuts every string of `tree` that's longer than `max` bytes.
+fn limit_strings(tree: &mut Tree, max: usize) {
+    for annotation in tree.annotations.iter_mut() {
+        match annotation {
+            Annotation::Note(note) => {
+                if let Some(limited) = limit_string(note, max) {
+                    *note = limited.into();
+                }
+            }
+            Annotation::Field(_, value) => {
+                if let Some(limited) = limit_string(value, max) {
+                    *value = limited;
+                }
+            }
+        }
+    }
+
+    match &mut tree.kind {
+        TreeKind::Span(span) => {
+            for child in span.children.iter_mut() {
+                limit_strings(child, max);
+            }
+        }
+        TreeKind::Event(event) => {
+            if let Some(limited) = limit_string(&event.message, max) {
+                event.message = limited.into();
+            }
+            for kv in event.fields.iter_mut() {
+                if let Some(limited) = limit_string(&kv.value, max) {
+                    kv.value = limited;
+                }
+                #[cfg(feature = "valuable")]
+                if let Some(structured) = &mut kv.structured {
+                    limit_value(structured, max);
+                }
+            }
+        }
+    }
+}
+
+/// Cuts every string nested in `value` that's longer than `max` bytes.
+#[cfg(feature = "valuable")]
+fn limit_value(value: &mut Value, max: usize) {
+    match value {
+        Value::String(string) => {
+            if let Some(limited) = limit_string(string, max) {
+                *string = limited;
+            }
+        }
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                limit_value(value, max);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                limit_value(value, max);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the JSON value of a field, keeping the structure of values
+/// recorded with `valuable`.
+fn field_value(kv: &KeyValue) -> Value {
+    #[cfg(feature = "valuable")]
+    if let Some(structured) = &kv.structured {
+        return structured.clone();
+    }
+    Value::from(kv.value.as_str())
+}
+
+/// Returns `string` cut to at most `max` bytes with a marker, or `None` if
+/// it's short enough.
+fn limit_string(string: &str, max: usize) -> Option<String> {
+    if string.len() <= max {
+        return None;
+    }
+    let mut end = max;
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!(
+        "{}…({} bytes omitted)",
+        &string[..end],
+        string.len() - end
+    ))
+}
+
+/// Replaces the records written after `start` that are longer than `max`
+/// bytes with placeholders. Each line is a record if `lines` is set, and
+/// everything written is one record otherwise.
+fn limit_records(
+    writer: &mut Vec<u8>,
+    start: usize,
+    max: usize,
+    level: Level,
+    lines: bool,
+) -> io::Result<()> {
+    let written = writer.split_off(start);
+    let records: Box<dyn Iterator<Item = &[u8]>> = if lines {
+        Box::new(written.split_inclusive(|&byte| byte == b'\n'))
+    } else {
+        Box::new(std::iter::once(written.as_slice()))
+    };
+    for record in records {
+        let len = record.strip_suffix(b"\n").unwrap_or(record).len();
+        if len <= max {
+            writer.extend_from_slice(record);
+            continue;
+        }
+        let placeholder = json!({
+            "level": level.as_str(),
+            "truncated": { "bytes": len },
+        });
+        serde_json::to_writer(&mut *writer, &placeholder)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Escapes the non-ASCII characters written after `start` as `\uXXXX`.
+///
+/// Valid JSON only has non-ASCII characters inside of strings, where escaping
+/// them doesn't change their value.
+fn escape_non_ascii(writer: &mut Vec<u8>, start: usize) {
+    if writer[start..].is_ascii() {
+        return;
+    }
+    let written = writer.split_off(start);
+    let mut units = [0; 2];
+    for c in String::from_utf8_lossy(&written).chars() {
+        if c.is_ascii() {
+            writer.push(c as u8);
+            continue;
+        }
+        for unit in c.encode_utf16(&mut units) {
+            writer.extend_from_slice(format!("\\u{:04x}", unit).as_bytes());
+        }
+    }
+}
+
+/// The 32 hex digit trace ID of a tree, if its root is a span.
+fn trace_id(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "uuid")]
+    if let TreeKind::Span(_) = tree.kind {
+        return Some(tree.attrs.uuid.to_simple().to_string());
+    }
+
+    let _ = tree;
+    None
+}
@@ -0,0 +1,340 @@
+//! Versioned JSON documents that separate span attributes, timing, events,
+//! and children.
+//!
+//! In a [`Document`], spans separate their [attributes], [timing], the events
+//! logged directly inside of them, and their child spans. Events and child
+//! spans keep their position among their siblings, so the original order can
+//! be reconstructed.
+//!
+//! # Examples
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "span": {
+//!     "attributes": {
+//!       "name": "request",
+//!       "level": "INFO",
+//!       "id": "a7f30b44-51a5-447a-a71d-0fa3d2c33b70",
+//!       "timestamp": "2022-03-01T16:21:58.183128+00:00"
+//!     },
+//!     "timing": { "total_nanos": 104667, "nested_nanos": 13917, "self_nanos": 90750 },
+//!     "events": [
+//!       {
+//!         "position": 1,
+//!         "level": "INFO",
+//!         "timestamp": "2022-03-01T16:21:58.183250+00:00",
+//!         "message": "done",
+//!         "tag": null,
+//!         "fields": { "user": "\"alice\"" }
+//!       }
+//!     ],
+//!     "children": [
+//!       {
+//!         "attributes": { "name": "db", "level": "INFO", "id": "a7f30b44-51a5-447a-a71d-0fa3d2c33b70", "timestamp": "2022-03-01T16:21:58.183131+00:00" },
+//!         "timing": { "total_nanos": 13917, "nested_nanos": 0, "self_nanos": 13917 },
+//!         "events": [],
+//!         "children": [],
+//!         "position": 0
+//!       }
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! [attributes]: SpanAttributes
+//! [timing]: Timing
+
+#[cfg(feature = "chrono")]
+use crate::formatter::json::{offset_nanos, Timestamps};
+use crate::layer::{Tree, TreeEvent, TreeKind, TreeSpan};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "json-schema")]
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// The layout of the objects written by the [`Json`] formatter.
+///
+/// [`Json`]: crate::formatter::json::Json
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaVersion {
+    /// Each [`Tree`] is serialized as is, with its children in the order they
+    /// occurred.
+    V1,
+    /// Each [`Tree`] is serialized as a [`Document`].
+    V2,
+}
+
+/// A tree in version 2 of the JSON schema.
+///
+/// Exactly one of `span` and `event` is present.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(JsonSchema))]
+pub struct Document<'a> {
+    /// The version of the schema, which is always `2`.
+    pub version: u32,
+    /// The root span of the tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span<'a>>,
+    /// The event, if the tree is an event outside of any span.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event<'a>>,
+}
+
+/// A span in a [`Document`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(JsonSchema))]
+pub struct Span<'a> {
+    /// What identifies the span.
+    pub attributes: SpanAttributes<'a>,
+    /// How long the span was entered for.
+    pub timing: Timing,
+    /// The events logged directly inside the span.
+    pub events: Vec<Event<'a>>,
+    /// The spans entered inside the span.
+    pub children: Vec<Span<'a>>,
+    /// The index of the span among its siblings, or `null` for the root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+}
+
+/// The attributes of a [`Span`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(JsonSchema))]
+pub struct SpanAttributes<'a> {
+    /// The name of the span.
+    pub name: &'a str,
+    /// The level of the span.
+    pub level: &'a str,
+    /// The ID of the tree, if the `uuid` feature is enabled.
+    pub id: Option<String>,
+    /// When the span was opened, in RFC 3339 format, if the `chrono` feature
+    /// is enabled and it isn't replaced by relative timestamps.
+    pub timestamp: Option<String>,
+    /// The time from when the parent span was opened to when the span was
+    /// opened, in nanoseconds, with relative timestamps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_nanos: Option<u64>,
+    /// The time from when the root span was opened to when the span was
+    /// opened, in nanoseconds, with relative timestamps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_offset_nanos: Option<u64>,
+}
+
+/// The timing of a [`Span`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(JsonSchema))]
+pub struct Timing {
+    /// The duration that the span was entered for, in nanoseconds.
+    pub total_nanos: u64,
+    /// The duration that child spans of the span were entered for, in
+    /// nanoseconds.
+    pub nested_nanos: u64,
+    /// The duration that the span was entered for outside of its child
+    /// spans, in nanoseconds.
+    pub self_nanos: u64,
+}
+
+/// An event in a [`Document`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(JsonSchema))]
+pub struct Event<'a> {
+    /// The index of the event among its siblings, or `0` for a root event.
+    pub position: usize,
+    /// The level of the event.
+    pub level: &'a str,
+    /// When the event occurred, in RFC 3339 format, if the `chrono` feature
+    /// is enabled and it isn't replaced by relative timestamps.
+    pub timestamp: Option<String>,
+    /// The time from when the parent span was opened to when the event
+    /// occurred, in nanoseconds, with relative timestamps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_nanos: Option<u64>,
+    /// The time from when the root span was opened to when the event
+    /// occurred, in nanoseconds, with relative timestamps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_offset_nanos: Option<u64>,
+    /// The message of the event.
+    pub message: &'a str,
+    /// The format string that the message was rendered from, if it was
+    /// recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<&'a str>,
+    /// The tag of the event, if any.
+    pub tag: Option<&'a str>,
+    /// The fields of the event, as they're displayed by the other formatters.
+    pub fields: BTreeMap<&'a str, Cow<'a, str>>,
+}
+
+impl<'a> Document<'a> {
+    /// Construct a [`Document`] borrowing from `tree`.
+    pub fn new(tree: &'a Tree) -> Self {
+        let (span, event) = match &tree.kind {
+            TreeKind::Span(span) => (Some(Span::new(tree, span, None)), None),
+            TreeKind::Event(event) => (None, Some(Event::new(tree, event, 0))),
+        };
+        Document {
+            version: 2,
+            span,
+            event,
+        }
+    }
+
+    /// Construct a [`Document`] borrowing from `tree`, with the offsets of
+    /// its spans and events written according to `timestamps`.
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn with_timestamps(tree: &'a Tree, timestamps: Timestamps) -> Self {
+        let mut document = Document::new(tree);
+        if timestamps == Timestamps::Absolute {
+            return document;
+        }
+        if let (Some(document), TreeKind::Span(span)) = (&mut document.span, &tree.kind) {
+            let start = tree.attrs.timestamp;
+            document.add_offsets(span, timestamps, start, start);
+        }
+        document
+    }
+}
+
+impl<'a> Span<'a> {
+    fn new(tree: &'a Tree, span: &'a TreeSpan, position: Option<usize>) -> Self {
+        let mut events = Vec::new();
+        let mut children = Vec::new();
+
+        for (position, child) in span.children.iter().enumerate() {
+            match &child.kind {
+                TreeKind::Span(span) => children.push(Span::new(child, span, Some(position))),
+                TreeKind::Event(event) => events.push(Event::new(child, event, position)),
+            }
+        }
+
+        Span {
+            attributes: SpanAttributes {
+                name: span.name,
+                level: tree.attrs.level.as_str(),
+                id: id(tree),
+                timestamp: timestamp(tree),
+                offset_nanos: None,
+                root_offset_nanos: None,
+            },
+            timing: Timing {
+                total_nanos: span.duration_total.as_nanos() as u64,
+                nested_nanos: span.duration_nested.as_nanos() as u64,
+                self_nanos: span.duration_self().as_nanos() as u64,
+            },
+            events,
+            children,
+            position,
+        }
+    }
+
+    /// Adds the offsets of the descendants of `span`, which was opened at
+    /// `start`, inside of a root span opened at `root`.
+    #[cfg(feature = "chrono")]
+    fn add_offsets(
+        &mut self,
+        span: &TreeSpan,
+        timestamps: Timestamps,
+        start: DateTime<Utc>,
+        root: DateTime<Utc>,
+    ) {
+        let mut events = self.events.iter_mut();
+        let mut children = self.children.iter_mut();
+        for child in span.children.iter() {
+            let timestamp = child.attrs.timestamp;
+            let offset = Some(offset_nanos(start, timestamp));
+            let root_offset = Some(offset_nanos(root, timestamp));
+            let relative = timestamps == Timestamps::Relative;
+
+            match &child.kind {
+                TreeKind::Span(span) => {
+                    if let Some(document) = children.next() {
+                        let attributes = &mut document.attributes;
+                        attributes.offset_nanos = offset;
+                        attributes.root_offset_nanos = root_offset;
+                        if relative {
+                            attributes.timestamp = None;
+                        }
+                        document.add_offsets(span, timestamps, timestamp, root);
+                    }
+                }
+                TreeKind::Event(_) => {
+                    if let Some(event) = events.next() {
+                        event.offset_nanos = offset;
+                        event.root_offset_nanos = root_offset;
+                        if relative {
+                            event.timestamp = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Event<'a> {
+    fn new(tree: &'a Tree, event: &'a TreeEvent, position: usize) -> Self {
+        let mut fields = BTreeMap::new();
+        for kv in event.fields.iter() {
+            fields
+                .entry(kv.key)
+                .and_modify(|value: &mut Cow<str>| {
+                    // Keep repeated keys instead of dropping them
+                    *value = Cow::Owned(format!("{}, {}", value, kv.value));
+                })
+                .or_insert(Cow::Borrowed(kv.value.as_str()));
+        }
+
+        Event {
+            position,
+            level: tree.attrs.level.as_str(),
+            timestamp: timestamp(tree),
+            offset_nanos: None,
+            root_offset_nanos: None,
+            message: &event.message,
+            template: event.template.as_deref(),
+            tag: event.tag.map(|tag| tag.message),
+            fields,
+        }
+    }
+}
+
+fn id(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "uuid")]
+    return Some(tree.attrs.uuid.to_string());
+
+    #[cfg(not(feature = "uuid"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+fn timestamp(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "chrono")]
+    return Some(tree.attrs.timestamp.to_rfc3339());
+
+    #[cfg(not(feature = "chrono"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+/// Returns the JSON Schema of [`Document`], which validates the output of the
+/// [`Json`] formatter with [`SchemaVersion::V2`].
+///
+/// The same schema is published as `schema/tree-v2.json` in the repository.
+///
+/// [`Json`]: crate::formatter::json::Json
+#[cfg(feature = "json-schema")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json-schema")))]
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(Document);
+    #[allow(clippy::expect_used)]
+    serde_json::to_string_pretty(&schema).expect("schemas are always valid JSON")
+}
@@ -0,0 +1,212 @@
+//! A [`Formatter`] that formats logs as Markdown.
+//!
+//! See [`Markdown`] for more details.
+
+use crate::formatter::pretty::{DurationDisplay, DurationFormat, Pretty, TagDisplay};
+use crate::formatter::{Formatter, Icons};
+use crate::layer::{KeyValue, Tree, TreeEvent, TreeKind, TreeSpan, TreeVisitor};
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// How a [`Markdown`] formatter renders trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownStyle {
+    /// The [`Pretty`] output, in a fenced code block.
+    Fenced,
+    /// The [`Pretty`] output, in an indented code block.
+    Indented,
+    /// A nested list, with levels as bold markers.
+    List,
+}
+
+/// Format logs as Markdown, for pasting into issues and incident documents.
+///
+/// # Examples
+///
+/// With [`MarkdownStyle::List`] and collapsible root spans:
+///
+/// ```markdown
+/// <details>
+/// <summary><b>INFO</b> <code>try_from_entry_ro</code> (7.47ms)</summary>
+///
+/// - **INFO** `try_from_entry_ro` (7.47ms)
+///   - **INFO** 💬 [filter.info]: Some filter info...
+///   - **WARN** 🚧 [filter.warn]: Some filter warning | status: "sad"
+///
+/// </details>
+/// ```
+pub struct Markdown {
+    style: MarkdownStyle,
+    details: bool,
+    pretty: Pretty,
+    duration_format: DurationFormat,
+    icons: Icons,
+    #[doc(hidden)]
+    _priv: (),
+}
+
+impl Markdown {
+    /// Constructs a new [`Markdown`] formatter with the given style.
+    pub const fn new(style: MarkdownStyle) -> Self {
+        Markdown {
+            style,
+            details: false,
+            pretty: Pretty::new().with_snapshot(true),
+            duration_format: DurationFormat::Auto,
+            icons: Icons::Emoji,
+            _priv: (),
+        }
+    }
+
+    /// Sets whether each root span is wrapped in a collapsible `<details>`
+    /// element, summarized by its level, name, and duration.
+    pub const fn with_details(mut self, details: bool) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Sets the [`Pretty`] formatter used by the code block styles.
+    ///
+    /// By default, this is a [`Pretty`] formatter in snapshot mode, since IDs
+    /// and timestamps are rarely useful in reports.
+    pub fn with_pretty(mut self, pretty: Pretty) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Sets how span durations are displayed by the list style.
+    pub const fn with_duration_format(mut self, duration_format: DurationFormat) -> Self {
+        self.duration_format = duration_format;
+        self
+    }
+
+    /// Sets how the icons of events are displayed by the list style.
+    ///
+    /// The code block styles use the icons of their [`Pretty`] formatter.
+    pub const fn with_icons(mut self, icons: Icons) -> Self {
+        self.icons = icons;
+        self
+    }
+
+    fn duration(&self, span: &TreeSpan) -> DurationDisplay {
+        DurationDisplay(span.duration_total.as_nanos() as f64, self.duration_format)
+    }
+}
+
+/// Writes the trees it walks as a nested list.
+struct List<'a> {
+    markdown: &'a Markdown,
+    depth: usize,
+    out: String,
+}
+
+impl List<'_> {
+    fn item(&mut self, tree: &Tree) {
+        let _ = write!(
+            self.out,
+            "{:indent$}- **{}** ",
+            "",
+            tree.attrs.level,
+            indent = self.depth * 2
+        );
+    }
+}
+
+impl TreeVisitor for List<'_> {
+    type Error = Infallible;
+
+    fn enter_span(&mut self, tree: &Tree, span: &TreeSpan) -> Result<(), Infallible> {
+        self.item(tree);
+        let _ = writeln!(
+            self.out,
+            "`{}` ({})",
+            span.name,
+            self.markdown.duration(span)
+        );
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn exit_span(&mut self, _: &Tree, _: &TreeSpan) -> Result<(), Infallible> {
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> Result<(), Infallible> {
+        self.item(tree);
+        let tag = TagDisplay {
+            event,
+            level: tree.attrs.level,
+            icons: self.markdown.icons,
+        };
+        let _ = write!(self.out, "{}: {}", tag, escape(&event.message));
+        for KeyValue { key, value, .. } in event.fields.iter() {
+            let _ = write!(self.out, " | {}: {}", key, escape(value));
+        }
+        self.out.push('\n');
+        Ok(())
+    }
+}
+
+impl Formatter for Markdown {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        let details = self.details && matches!(tree.kind, TreeKind::Span(_));
+
+        if let (true, TreeKind::Span(span)) = (details, &tree.kind) {
+            writeln!(writer, "<details>")?;
+            writeln!(
+                writer,
+                "<summary><b>{}</b> <code>{}</code> ({})</summary>\n",
+                tree.attrs.level,
+                span.name,
+                self.duration(span)
+            )?;
+        }
+        match self.style {
+            MarkdownStyle::Fenced => {
+                writeln!(writer, "```log")?;
+                self.pretty.fmt(tree, writer)?;
+                writeln!(writer, "```")?;
+            }
+            MarkdownStyle::Indented => {
+                let mut buf = Vec::new();
+                self.pretty.fmt(tree, &mut buf)?;
+                for line in String::from_utf8_lossy(&buf).lines() {
+                    writeln!(writer, "    {}", line)?;
+                }
+            }
+            MarkdownStyle::List => {
+                let mut list = List {
+                    markdown: self,
+                    depth: 0,
+                    out: String::new(),
+                };
+                let _ = tree.walk(&mut list);
+                writer.write_all(list.out.as_bytes())?;
+            }
+        }
+
+        if details {
+            writeln!(writer, "\n</details>")?;
+        }
+        writeln!(writer)
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        self.icons = icons;
+        self.pretty.set_icons(icons);
+    }
+}
+
+/// Escapes characters that Markdown would otherwise interpret.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,170 @@
+//! A [`Formatter`] that formats logs as Mermaid gantt charts.
+//!
+//! See [`Mermaid`] for more details.
+
+use crate::formatter::pretty::{DurationDisplay, DurationFormat};
+use crate::formatter::Formatter;
+use crate::layer::{Tree, TreeKind, TreeSpan, TreeVisitor};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Format logs as [Mermaid] gantt charts, for discussing latency in Markdown
+/// documents and wikis.
+///
+/// Each root span is written as its own chart, where every span in the tree
+/// is a bar labeled with its name and duration. Events aren't included, and
+/// trees that are just an event produce no output.
+///
+/// With the `chrono` feature, bars start when their span was opened relative
+/// to the root span. Without it, timestamps aren't recorded, so child spans
+/// are laid out one after another from the start of their parent.
+///
+/// Mermaid only has millisecond resolution, so bars are rounded to at least
+/// one millisecond. Labels show the exact durations.
+///
+/// # Examples
+///
+/// ````markdown
+/// ```mermaid
+/// gantt
+///     title request
+///     dateFormat x
+///     axisFormat %S.%L
+///     request (7.47ms) :n0, 0, 8
+///     db (4.51ms) :n1, 1, 6
+/// ```
+/// ````
+///
+/// [Mermaid]: https://mermaid.js.org/syntax/gantt.html
+pub struct Mermaid {
+    fenced: bool,
+    duration_format: DurationFormat,
+    #[doc(hidden)]
+    _priv: (),
+}
+
+impl Mermaid {
+    /// Constructs a new [`Mermaid`] formatter.
+    pub const fn new() -> Self {
+        Mermaid {
+            fenced: true,
+            duration_format: DurationFormat::Auto,
+            _priv: (),
+        }
+    }
+
+    /// Sets whether charts are wrapped in a fenced `mermaid` code block.
+    ///
+    /// This is enabled by default, so charts render when pasted into Markdown.
+    pub const fn with_fenced(mut self, fenced: bool) -> Self {
+        self.fenced = fenced;
+        self
+    }
+
+    /// Sets how span durations are displayed in bar labels.
+    pub const fn with_duration_format(mut self, duration_format: DurationFormat) -> Self {
+        self.duration_format = duration_format;
+        self
+    }
+}
+
+/// Writes a bar for every span of the tree it walks.
+struct Bars<'a> {
+    mermaid: &'a Mermaid,
+    root: &'a Tree,
+    writer: &'a mut Vec<u8>,
+    next: usize,
+    /// When each span being walked started, and where its next child starts
+    /// if its start isn't known.
+    starts: Vec<(Duration, Duration)>,
+}
+
+impl TreeVisitor for Bars<'_> {
+    type Error = io::Error;
+
+    fn enter_span(&mut self, tree: &Tree, span: &TreeSpan) -> io::Result<()> {
+        let id = self.next;
+        self.next += 1;
+
+        let start = match self.starts.last() {
+            Some((_, offset)) => child_offset(self.root, tree).unwrap_or(*offset),
+            None => Duration::ZERO,
+        };
+        let start_ms = start.as_millis();
+        let end_ms = (start + span.duration_total).as_micros().div_ceil(1000);
+        writeln!(
+            self.writer,
+            "    {} ({}) :n{}, {}, {}",
+            escape(span.name),
+            DurationDisplay(
+                span.duration_total.as_nanos() as f64,
+                self.mermaid.duration_format
+            ),
+            id,
+            start_ms,
+            end_ms.max(start_ms + 1)
+        )?;
+
+        self.starts.push((start, start));
+        Ok(())
+    }
+
+    fn exit_span(&mut self, _: &Tree, span: &TreeSpan) -> io::Result<()> {
+        let (start, _) = self.starts.pop().unwrap_or_default();
+        if let Some((_, offset)) = self.starts.last_mut() {
+            *offset = start + span.duration_total;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Mermaid {
+    fn default() -> Self {
+        Mermaid::new()
+    }
+}
+
+impl Formatter for Mermaid {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        let span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => return Ok(()),
+        };
+
+        if self.fenced {
+            writeln!(writer, "```mermaid")?;
+        }
+        writeln!(writer, "gantt")?;
+        writeln!(writer, "    title {}", escape(span.name))?;
+        writeln!(writer, "    dateFormat x")?;
+        writeln!(writer, "    axisFormat %S.%L")?;
+        tree.walk(&mut Bars {
+            mermaid: self,
+            root: &tree,
+            writer,
+            next: 0,
+            starts: Vec::new(),
+        })?;
+        if self.fenced {
+            writeln!(writer, "```")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns when `tree` started relative to `root`, if timestamps are known.
+fn child_offset(root: &Tree, tree: &Tree) -> Option<Duration> {
+    #[cfg(feature = "chrono")]
+    return (tree.attrs.timestamp - root.attrs.timestamp).to_std().ok();
+
+    #[cfg(not(feature = "chrono"))]
+    {
+        let _ = (root, tree);
+        None
+    }
+}
+
+/// Replaces characters that Mermaid treats as syntax in task names.
+fn escape(name: &str) -> String {
+    name.replace(':', "∶").replace(['#', ';'], " ")
+}
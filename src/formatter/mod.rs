@@ -3,24 +3,374 @@
 //! See [`Formatter`] for more details.
 
 use crate::layer::Tree;
-use std::io;
+use crate::processor::{transform, Latency};
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, IsTerminal};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub mod ascii;
+
+pub mod dot;
+
+pub mod markdown;
+
+pub mod mermaid;
 
 pub mod pretty;
 
+pub mod summary;
+
+pub mod switch;
+
 #[cfg(feature = "json")]
 pub mod json;
 
+#[cfg(feature = "cbor")]
+pub mod cbor;
+
 /// A type that formats [`Tree`]s into a buffer.
-/// 
-/// [`Formatter`] types are typically used by [`Processor`]s in order to break 
+///
+/// [`Formatter`] types are typically used by [`Processor`]s in order to break
 /// down processing responsibilities into smaller, composable units.
-/// 
-/// If you're implementing a custom formatter, see the [layer module] 
+///
+/// If you're implementing a custom formatter, see the [layer module]
 /// documentation for internal representation details.
-/// 
+///
+/// This trait is object safe, and is implemented for [`Box`] and [`Arc`] of any
+/// formatter so that formatters can be chosen at runtime.
+///
 /// [`Processor`]: crate::processor::Processor
 /// [layer module]: crate::layer
 pub trait Formatter {
     /// Format a [`Tree`] into a buffer for writing.
     fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Format a copy of a [`Tree`] into a string, for displaying trees that
+    /// were kept around, like on an error page, in a bug report, or in the
+    /// output of a test.
+    ///
+    /// Output that isn't valid UTF-8 is converted lossily.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the tree couldn't be formatted.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::{pretty::Pretty, Formatter};
+    /// let trees = tracing_forest::capture().run(|| {
+    ///     tracing::info_span!("request").in_scope(|| tracing::info!("handled"));
+    /// });
+    ///
+    /// let rendered = Pretty::new().with_snapshot(true).render(&trees[0]).unwrap();
+    /// assert!(rendered.ends_with("[info]: handled\n"));
+    /// ```
+    fn render(&self, tree: &Tree) -> io::Result<String> {
+        let mut buf = Vec::new();
+        self.fmt(tree.clone(), &mut buf)?;
+        Ok(String::from_utf8(buf)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()))
+    }
+
+    /// Add the [`Latency`] of a [`Tree`] to its output, after it was
+    /// formatted into `writer` by [`fmt`][Formatter::fmt].
+    ///
+    /// The default implementation leaves the output unchanged.
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        let _ = (latency, writer);
+        Ok(())
+    }
+
+    /// Format a [`Tree`] with [`fmt`][Formatter::fmt], and then add its
+    /// [`Latency`] with [`fmt_latency`][Formatter::fmt_latency], given how
+    /// long it was `queued` for.
+    ///
+    /// This is how processors format each tree. Formatters that pick another
+    /// formatter for each tree, like [`Switchable`], override it to format
+    /// the tree and its latency with the same one.
+    ///
+    /// [`Switchable`]: crate::formatter::switch::Switchable
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let start = Instant::now();
+        self.fmt(tree, writer)?;
+        let latency = Latency {
+            queued,
+            formatting: start.elapsed(),
+        };
+        self.fmt_latency(&latency, writer)
+    }
+
+    /// Set whether the output is colored using ANSI escape codes.
+    ///
+    /// This is called by [`LayerBuilder::set_ansi`], and the default
+    /// implementation ignores it, for formatters that never use color.
+    ///
+    /// [`LayerBuilder::set_ansi`]: crate::builder::LayerBuilder::set_ansi
+    fn set_ansi(&mut self, ansi: bool) {
+        let _ = ansi;
+    }
+
+    /// Set how the icons of tags are displayed.
+    ///
+    /// This is called by [`LayerBuilder::icons`], and the default
+    /// implementation ignores it, for formatters that never display icons.
+    ///
+    /// [`LayerBuilder::icons`]: crate::builder::LayerBuilder::icons
+    fn set_icons(&mut self, icons: Icons) {
+        let _ = icons;
+    }
+}
+
+impl<F: Formatter + ?Sized> Formatter for Box<F> {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.as_ref().fmt(tree, writer)
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.as_ref().fmt_latency(latency, writer)
+    }
+
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        self.as_ref().fmt_with_latency(tree, queued, writer)
+    }
+
+    fn set_ansi(&mut self, ansi: bool) {
+        self.as_mut().set_ansi(ansi)
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        self.as_mut().set_icons(icons)
+    }
+}
+
+impl<F: Formatter + ?Sized> Formatter for Arc<F> {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.as_ref().fmt(tree, writer)
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.as_ref().fmt_latency(latency, writer)
+    }
+
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        self.as_ref().fmt_with_latency(tree, queued, writer)
+    }
+
+    /// Only applies if this is the only reference to the formatter.
+    fn set_ansi(&mut self, ansi: bool) {
+        if let Some(formatter) = Arc::get_mut(self) {
+            formatter.set_ansi(ansi)
+        }
+    }
+
+    /// Only applies if this is the only reference to the formatter.
+    fn set_icons(&mut self, icons: Icons) {
+        if let Some(formatter) = Arc::get_mut(self) {
+            formatter.set_icons(icons)
+        }
+    }
+}
+
+/// Whether output is colored using ANSI escape codes.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{pretty::Pretty, Ansi};
+/// let pretty = Pretty::new().with_ansi(Ansi::Auto.enabled());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ansi {
+    /// Color output unless the `NO_COLOR` environment variable is set, if the
+    /// `CLICOLOR_FORCE` environment variable is set, or if the output is a
+    /// terminal, in that order of precedence.
+    ///
+    /// Following <https://no-color.org> and
+    /// <https://bixense.com/clicolors>, `NO_COLOR` counts as set when it's
+    /// not empty, and `CLICOLOR_FORCE` when it's neither empty nor `0`.
+    #[default]
+    Auto,
+    /// Always color output.
+    Always,
+    /// Never color output.
+    Never,
+}
+
+impl Ansi {
+    /// Returns whether output written to stdout should be colored.
+    pub fn enabled(self) -> bool {
+        self.enabled_for(&io::stdout())
+    }
+
+    /// Returns whether output written to `stream` should be colored.
+    pub fn enabled_for<T: IsTerminal>(self, stream: &T) -> bool {
+        self.enabled_if(stream.is_terminal())
+    }
+
+    /// Returns whether output should be colored, given whether it's written
+    /// to a terminal.
+    pub(crate) fn enabled_if(self, is_terminal: bool) -> bool {
+        self.resolve(
+            env::var_os("NO_COLOR").as_deref(),
+            env::var_os("CLICOLOR_FORCE").as_deref(),
+            is_terminal,
+        )
+    }
+
+    /// Returns whether output should be colored, given the values of the
+    /// `NO_COLOR` and `CLICOLOR_FORCE` environment variables, and whether the
+    /// output is a terminal.
+    ///
+    /// This is what [`Ansi::enabled_for`] does after reading the environment,
+    /// for programs that read their configuration from somewhere else.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::ffi::OsStr;
+    /// # use tracing_forest::formatter::Ansi;
+    /// assert!(!Ansi::Auto.resolve(Some(OsStr::new("1")), None, true));
+    /// assert!(Ansi::Auto.resolve(None, Some(OsStr::new("1")), false));
+    /// ```
+    pub fn resolve(
+        self,
+        no_color: Option<&OsStr>,
+        clicolor_force: Option<&OsStr>,
+        is_terminal: bool,
+    ) -> bool {
+        let no_color = no_color.filter(|value| !value.is_empty());
+        let clicolor_force = clicolor_force.filter(|value| !value.is_empty());
+        match self {
+            Ansi::Always => true,
+            Ansi::Never => false,
+            Ansi::Auto if no_color.is_some() => false,
+            Ansi::Auto if clicolor_force.is_some_and(|value| value != "0") => true,
+            Ansi::Auto => is_terminal,
+        }
+    }
+}
+
+/// How the icons of tags, and of the levels of untagged events, are
+/// displayed.
+///
+/// Emoji are wider than one column in some fonts, which breaks the alignment
+/// of trees, and some log viewers strip them.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{pretty::Pretty, Icons};
+/// let pretty = Pretty::new().with_icons(Icons::Text);
+/// ```
+/// ```log
+/// INFO     request [ 1.21ms | 100.000% ]
+/// INFO     ┝━ [INF] [info]: received
+/// INFO     ┕━ [SEC] [security.access]: token accepted
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Icons {
+    /// The icon of the tag, like `💬` for `INFO`.
+    #[default]
+    Emoji,
+    /// A fixed-width text marker. Untagged events are marked by their level,
+    /// like `[INF]`, and tagged events by the first three letters of the tag,
+    /// like `[SEC]` for `security.access`.
+    Text,
+    /// No icon at all.
+    Off,
+}
+
+/// A [`Formatter`] that transforms [`Tree`]s before passing them to another
+/// formatter.
+///
+/// Since formatting happens wherever a [`Processor`] processes trees, the
+/// transform runs on the processing task of an
+/// [`AsyncProcessor`][crate::processor::sync::AsyncProcessor], keeping the
+/// cost off of the instrumented code.
+///
+/// Transforms can be used for enrichment, like adding computed fields,
+/// reclassifying levels, or merging children.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::formatter::{pretty::Pretty, Transformed};
+/// # use tracing_forest::layer::Tree;
+/// fn quiet(mut tree: Tree) -> Tree {
+///     tree.attrs.level = Level::DEBUG;
+///     tree
+/// }
+///
+/// let formatter = Transformed::new(Pretty::new(), quiet);
+/// ```
+///
+/// [`Processor`]: crate::processor::Processor
+pub struct Transformed<F> {
+    formatter: F,
+    transforms: Vec<fn(Tree) -> Tree>,
+}
+
+impl<F: Formatter> Transformed<F> {
+    /// Construct a new [`Transformed`] formatter, which applies `transform` to
+    /// trees before formatting them with `formatter`.
+    pub fn new(formatter: F, transform: fn(Tree) -> Tree) -> Self {
+        Transformed::all(formatter, vec![transform])
+    }
+
+    /// Construct a new [`Transformed`] formatter, which applies `transforms`
+    /// to trees in order before formatting them with `formatter`.
+    pub(crate) fn all(formatter: F, transforms: Vec<fn(Tree) -> Tree>) -> Self {
+        Transformed {
+            formatter,
+            transforms,
+        }
+    }
+}
+
+impl<F: Formatter> Formatter for Transformed<F> {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.formatter
+            .fmt(transform(&self.transforms, tree), writer)
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.formatter.fmt_latency(latency, writer)
+    }
+
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        self.formatter
+            .fmt_with_latency(transform(&self.transforms, tree), queued, writer)
+    }
+
+    fn set_ansi(&mut self, ansi: bool) {
+        self.formatter.set_ansi(ansi)
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        self.formatter.set_icons(icons)
+    }
 }
@@ -2,18 +2,26 @@
 //!
 //! See [`Pretty`] for more details.
 
-use crate::formatter::Formatter;
-use crate::layer::{KeyValue, Tree, TreeAttrs, TreeEvent, TreeKind, TreeSpan};
+use crate::formatter::{Formatter, Icons};
+#[cfg(feature = "tracing-error")]
+use crate::layer::SpanTraceFrame;
+use crate::layer::{Annotation, KeyValue, Tree, TreeAttrs, TreeEvent, TreeKind, TreeSpan};
 use crate::private::{DEBUG_ICON, ERROR_ICON, INFO_ICON, TRACE_ICON, WARN_ICON};
+use crate::processor::buffers::{Buffers, Reuse};
+use crate::processor::Latency;
 use crate::tag::TagData;
-use std::fmt;
+use crate::tree::{level_index, EventCounts, LEVELS};
+use std::cmp::Reverse;
+use std::fmt::{self, Write as _};
 use std::io::{self, Write};
+use std::mem;
+use std::time::Duration;
 use tracing::Level;
 
 /// Format logs for pretty printing.
-/// 
+///
 /// # Examples
-/// 
+///
 /// ```log
 /// INFO     try_from_entry_ro [ 7.47ms | 6.523% / 100.000% ]
 /// INFO     ┝━ server::internal_search [ 6.98ms | 31.887% / 93.477% ]
@@ -34,7 +42,34 @@ use tracing::Level;
 /// WARN     │     ┕━ 🚧 [filter.warn]: Some filter warning lol
 /// TRACE    ┕━ 📍 [trace]: We finished!
 /// ```
+///
+/// [Annotations][crate::tree::Annotation] are written after the tree, one per
+/// line:
+///
+/// ```log
+/// INFO     request [ 1.21ms | 100.000% ]
+/// INFO     ┕━ 💬 [info]: done
+/// NOTE     sampled 1:100
+/// NOTE     redacted: 3
+/// ```
 pub struct Pretty {
+    width: Option<usize>,
+    duration_format: DurationFormat,
+    #[cfg(feature = "chrono")]
+    span_start: bool,
+    #[cfg(feature = "chrono")]
+    timestamps: Timestamps,
+    ansi: bool,
+    snapshot: bool,
+    latency: bool,
+    self_time: bool,
+    budget: Option<OutputBudget>,
+    child_order: ChildOrder,
+    collapse: bool,
+    fold_below: Option<Level>,
+    duration_column: Option<usize>,
+    icons: Icons,
+    pool: Buffers<Scratch>,
     #[doc(hidden)]
     _priv: (),
 }
@@ -42,15 +77,582 @@ pub struct Pretty {
 impl Pretty {
     /// Constructs a new [`Pretty`] formatter.
     pub const fn new() -> Self {
-        Pretty { _priv: () }
+        Pretty {
+            width: None,
+            duration_format: DurationFormat::Auto,
+            #[cfg(feature = "chrono")]
+            span_start: false,
+            #[cfg(feature = "chrono")]
+            timestamps: Timestamps::Absolute,
+            ansi: false,
+            snapshot: false,
+            latency: false,
+            self_time: false,
+            budget: None,
+            child_order: ChildOrder::Chronological,
+            collapse: false,
+            fold_below: None,
+            duration_column: None,
+            icons: Icons::Emoji,
+            pool: Buffers::new(),
+            _priv: (),
+        }
+    }
+
+    /// Soft-wraps long event messages and fields so that no line is wider
+    /// than `width` columns.
+    ///
+    /// Continuation lines are indented to line up with the start of the
+    /// message, and preserve the tree guide lines to their left. Wide
+    /// characters, like CJK and emoji, count as two columns.
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     wrapping [ 12.1µs | 100.000% ]
+    /// INFO     ┝━ 💬 [info]: a very long message that
+    /// INFO     │             does not fit on one line
+    /// INFO     ┕━ 💬 [info]: short
+    /// ```
+    pub const fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Soft-wraps long lines to the width of the terminal.
+    ///
+    /// On Unix, the width is queried from the terminal that stdout or stderr
+    /// is connected to. Otherwise, or if neither is a terminal, it's taken
+    /// from the `COLUMNS` environment variable. If the width cannot be
+    /// detected, lines are not wrapped.
+    pub fn with_terminal_width(mut self) -> Self {
+        self.width = terminal_width().or_else(|| {
+            std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.trim().parse().ok())
+        });
+        self
+    }
+
+    /// Sets how span durations are displayed.
+    ///
+    /// By default, [`DurationFormat::Auto`] is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::{DurationFormat, Pretty};
+    /// // Always display durations in milliseconds with 3 decimal places
+    /// let pretty = Pretty::new().with_duration_format(DurationFormat::Millis(3));
+    /// ```
+    /// ```log
+    /// INFO     my_span [ 1.234ms | 100.000% ]
+    /// ```
+    pub const fn with_duration_format(mut self, duration_format: DurationFormat) -> Self {
+        self.duration_format = duration_format;
+        self
+    }
+
+    /// Sets whether the timestamp of when each span was opened is displayed
+    /// after its duration.
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     my_span [ 1.23ms | 100.000% ] @ 2022-01-06T20:55:01.105085+00:00
+    /// ```
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub const fn with_span_start(mut self, span_start: bool) -> Self {
+        self.span_start = span_start;
+        self
+    }
+
+    /// Sets how the timestamp of each span and event is displayed.
+    ///
+    /// By default, [`Timestamps::Absolute`] is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::{Pretty, Timestamps};
+    /// // Show how long passed between each event and the one before it
+    /// let pretty = Pretty::new().with_timestamps(Timestamps::Delta);
+    /// ```
+    /// ```log
+    /// 2ca3e5a8-0d5f-4b4a-9d2c-0d1e5297f472            INFO     request [ 15.2ms | 100.000% ]
+    /// 2ca3e5a8-0d5f-4b4a-9d2c-0d1e5297f472 +42.0µs    INFO     ┝━ 💬 [info]: parsed
+    /// 2ca3e5a8-0d5f-4b4a-9d2c-0d1e5297f472 +12.3ms    INFO     ┝━ 💬 [info]: queried
+    /// 2ca3e5a8-0d5f-4b4a-9d2c-0d1e5297f472 +2.81ms    INFO     ┕━ 💬 [info]: responded
+    /// ```
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub const fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Sets whether levels are colored using ANSI escape codes.
+    ///
+    /// The names of [failed][crate::tree::TreeSpan::is_failed] spans are
+    /// colored red as well.
+    ///
+    /// To detect whether output should be colored, see
+    /// [`Ansi`][crate::formatter::Ansi].
+    pub const fn with_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Sets whether a footer with the [`Latency`] of processing each tree is
+    /// written after it.
+    ///
+    /// Latencies differ between runs, so the footer isn't written in
+    /// [snapshot mode][Pretty::with_snapshot].
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     my_span [ 1.23ms | 100.000% ]
+    /// latency [ queued 12.3µs | formatted 8.20µs ]
+    /// ```
+    pub const fn with_latency(mut self, latency: bool) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets whether the [self time][TreeSpan::duration_self] of spans with
+    /// child spans is displayed after their duration.
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     request [ 4.40ms | 530µs self | 12.045% / 100.000% ]
+    /// INFO     ┕━ query [ 3.87ms | 87.955% ]
+    /// ```
+    pub const fn with_self_time(mut self, self_time: bool) -> Self {
+        self.self_time = self_time;
+        self
+    }
+
+    /// Sets the most output written for each tree, or `None` for no limit.
+    ///
+    /// Spans and events that don't fit within the budget are omitted, along
+    /// with everything after them, and replaced by a trailer counting how
+    /// many were omitted at each level. The trailer itself isn't counted
+    /// towards the budget.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::{OutputBudget, Pretty};
+    /// let pretty = Pretty::new().with_budget(Some(OutputBudget::Lines(3)));
+    /// ```
+    /// ```log
+    /// INFO     import [ 1.05s | 0.125% / 100.000% ]
+    /// INFO     ┝━ 💬 [info]: importing 10000 rows
+    /// DEBUG    ┝━ 🐛 [debug]: imported row 1
+    /// ... 10000 omitted [ WARN: 1 | DEBUG: 9999 ]
+    /// ```
+    pub const fn with_budget(mut self, budget: Option<OutputBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Sets whether output is deterministic, omitting [`Uuid`]s, timestamps,
+    /// and span timings. This is useful for comparing output against
+    /// snapshots in tests.
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     my_span
+    /// INFO     ┕━ 💬 [info]: the same every time
+    /// ```
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    pub const fn with_snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// Sets the order that the children of each span are displayed in.
+    ///
+    /// By default, [`ChildOrder::Chronological`] is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::{ChildOrder, Pretty};
+    /// // Show the slowest child of each span first
+    /// let pretty = Pretty::new().with_child_order(ChildOrder::LongestFirst);
+    /// ```
+    /// ```log
+    /// INFO     request [ 9.12ms | 1.206% / 100.000% ]
+    /// INFO     ┝━ query [ 8.01ms | 87.829% ]
+    /// INFO     ┝━ auth [ 1.00ms | 10.965% ]
+    /// INFO     ┕━ 💬 [info]: done
+    /// ```
+    pub const fn with_child_order(mut self, child_order: ChildOrder) -> Self {
+        self.child_order = child_order;
+        self
+    }
+
+    /// Sets whether chains of spans that each have a single child span are
+    /// collapsed onto one line.
+    ///
+    /// The line shows the path of span names, the duration of the outermost
+    /// span, and the children of the innermost span.
+    ///
+    /// # Examples
+    ///
+    /// ```log
+    /// INFO     http > auth > handler [ 2.31ms | 12.001% / 100.000% ]
+    /// INFO     ┕━ 💬 [info]: handled request
+    /// ```
+    pub const fn with_collapse(mut self, collapse: bool) -> Self {
+        self.collapse = collapse;
+        self
+    }
+
+    /// Sets the level that spans must be at least as severe as to be shown
+    /// with their children, or `None` to show every span in full.
+    ///
+    /// Less severe spans are folded onto one line, with badges counting the
+    /// events below them and how many of those are warnings or errors. This
+    /// gives an overview of large trees, while formatters like [`Json`] still
+    /// write everything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// let pretty = Pretty::new().with_fold_below(Some(Level::INFO));
+    /// ```
+    /// ```log
+    /// INFO     request [ 9.12ms | 86.842% / 100.000% ]
+    /// DEBUG    ┝━ ▸ db_query [ 1.20ms | 14 events, 1 WARN ]
+    /// INFO     ┕━ 💬 [info]: done
+    /// ```
+    ///
+    /// [`Json`]: crate::formatter::json::Json
+    pub const fn with_fold_below(mut self, level: Option<Level>) -> Self {
+        self.fold_below = level;
+        self
+    }
+
+    /// Sets the column that span timings end at, so they line up in a
+    /// right-aligned column, or `None` to write them right after the span
+    /// name.
+    ///
+    /// Spans whose name reaches past the column are followed by a single
+    /// space instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// let pretty = Pretty::new().with_duration_column(Some(60));
+    /// ```
+    /// ```log
+    /// INFO     request              [ 9.12ms | 1.206% / 100.000% ]
+    /// INFO     ┝━ query                       [ 8.01ms | 87.829% ]
+    /// INFO     ┝━ auth                        [ 1.00ms | 10.965% ]
+    /// INFO     ┕━ 💬 [info]: done
+    /// ```
+    pub const fn with_duration_column(mut self, column: Option<usize>) -> Self {
+        self.duration_column = column;
+        self
+    }
+
+    /// Sets how the icons of events are displayed.
+    ///
+    /// By default, [`Icons::Emoji`] is used. See [`Icons`] for details.
+    pub const fn with_icons(mut self, icons: Icons) -> Self {
+        self.icons = icons;
+        self
+    }
+
+    /// Sets how many sets of scratch buffers are kept for reuse by later
+    /// trees, which is how many threads can format at once without
+    /// allocating them.
+    ///
+    /// Formatting a tree needs buffers for its lines, span names, and
+    /// timings. Reusing them saves allocating and growing them for every
+    /// tree. With `0`, buffers are allocated for each tree and freed after.
+    ///
+    /// By default, 4 sets are kept.
+    pub const fn with_pool_size(mut self, size: usize) -> Self {
+        self.pool.size = size;
+        self
+    }
+
+    /// Sets the most bytes that a pooled buffer can hold on to, after which
+    /// it's freed instead of kept, so one huge tree doesn't pin its memory.
+    ///
+    /// By default, buffers keep up to 64 KiB.
+    pub const fn with_pool_capacity(mut self, max_capacity: usize) -> Self {
+        self.pool.max_capacity = max_capacity;
+        self
+    }
+}
+
+/// The order that the [`Pretty`] formatter displays the children of a span in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildOrder {
+    /// In the order they occurred.
+    Chronological,
+    /// Spans first, from longest to shortest, followed by events in the order
+    /// they occurred.
+    LongestFirst,
+    /// Grouped by level, from most to least severe, and in the order they
+    /// occurred within each level.
+    ByLevel,
+}
+
+impl ChildOrder {
+    /// Reorders the children of every span in `tree`.
+    pub(crate) fn apply(self, tree: &mut Tree) {
+        let span = match &mut tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => return,
+        };
+
+        // Sorts are stable, so ties stay in chronological order
+        match self {
+            ChildOrder::Chronological => return,
+            ChildOrder::LongestFirst => span.children.sort_by_key(|child| match &child.kind {
+                TreeKind::Span(span) => (false, Reverse(span.duration_total)),
+                TreeKind::Event(_) => (true, Reverse(Duration::ZERO)),
+            }),
+            ChildOrder::ByLevel => span.children.sort_by_key(|child| child.attrs.level),
+        }
+
+        for child in span.children.iter_mut() {
+            self.apply(child);
+        }
+    }
+}
+
+/// How timestamps are displayed by the [`Pretty`] formatter.
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamps {
+    /// When each span was opened or event occurred, in RFC 3339 format.
+    Absolute,
+    /// How long after its previous sibling each span was opened or event
+    /// occurred, like `+12.3ms`. The first child of a span is measured from
+    /// when the span was opened, and root spans and events show no delta.
+    ///
+    /// Deltas follow the displayed order of children, so they can be
+    /// negative with a [`ChildOrder`] other than [`ChildOrder::Chronological`].
+    Delta,
+    /// The absolute timestamp followed by the delta.
+    Both,
+}
+
+/// The most output the [`Pretty`] formatter writes for each tree.
+///
+/// See [`Pretty::with_budget`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputBudget {
+    /// At most this many lines.
+    Lines(usize),
+    /// At most this many bytes.
+    Bytes(usize),
+}
+
+impl OutputBudget {
+    fn limit(self) -> usize {
+        match self {
+            OutputBudget::Lines(limit) | OutputBudget::Bytes(limit) => limit,
+        }
+    }
+}
+
+/// Where a span or event starts in the formatted output of a tree.
+struct Row {
+    start: usize,
+    level: Level,
+}
+
+/// How much of the [`OutputBudget`] the tree being formatted has used, and
+/// what didn't fit in it.
+#[derive(Default)]
+struct Spent {
+    /// The output counted so far, which ends where `counted` does.
+    used: usize,
+    counted: usize,
+    /// The row being formatted, which is removed if it goes over the budget.
+    last: Option<Row>,
+    /// Whether the budget ran out, so that the rest of the tree is omitted.
+    exhausted: bool,
+    /// The omitted rows of each level, indexed by [`level_index`].
+    omitted: [usize; 5],
+}
+
+impl Spent {
+    fn new(start: usize) -> Self {
+        Spent {
+            counted: start,
+            ..Spent::default()
+        }
+    }
+
+    /// Counts the output written since the last call, and removes the last
+    /// row if it didn't fit. Returns whether the budget is left for more rows.
+    fn check(&mut self, budget: OutputBudget, writer: &mut Vec<u8>) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        self.used += match budget {
+            OutputBudget::Lines(_) => writer[self.counted..]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count(),
+            OutputBudget::Bytes(_) => writer.len() - self.counted,
+        };
+        self.counted = writer.len();
+        if self.used <= budget.limit() {
+            return true;
+        }
+        if let Some(last) = self.last.take() {
+            writer.truncate(last.start);
+            self.omit(last.level);
+        }
+        self.exhausted = true;
+        false
+    }
+
+    fn omit(&mut self, level: Level) {
+        self.omitted[level_index(level)] += 1;
+    }
+
+    /// Writes a trailer counting the omitted rows, if there are any.
+    fn finish(&self, writer: &mut Vec<u8>) -> io::Result<()> {
+        let total = self.omitted.iter().sum::<usize>();
+        if total == 0 {
+            return Ok(());
+        }
+
+        write!(writer, "... {} omitted [ ", total)?;
+        let counts = LEVELS.iter().filter_map(|&level| {
+            let count = self.omitted[level_index(level)];
+            (count > 0).then_some((level, count))
+        });
+        for (idx, (level, count)) in counts.enumerate() {
+            if idx > 0 {
+                write!(writer, " | ")?;
+            }
+            write!(writer, "{}: {}", level, count)?;
+        }
+        writeln!(writer, " ]")
+    }
+}
+
+/// Buffers used while formatting a tree, which are cleared and reused by
+/// later trees.
+#[derive(Default)]
+struct Scratch {
+    indent: Vec<Edge>,
+    spent: Spent,
+    line: Vec<u8>,
+    name: String,
+    timing: String,
+}
+
+impl Reuse for Scratch {
+    fn capacity(&self) -> usize {
+        self.indent.capacity() * mem::size_of::<Edge>()
+            + self.line.capacity()
+            + self.name.capacity()
+            + self.timing.capacity()
+    }
+
+    fn clear(&mut self) {
+        self.indent.clear();
+        self.line.clear();
+        self.name.clear();
+        self.timing.clear();
+    }
+}
+
+/// How durations are displayed by the [`Pretty`] formatter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// Scale to the most readable unit, with 3 significant figures.
+    Auto,
+    /// Always display in milliseconds, with the given number of decimals.
+    Millis(usize),
+    /// Always display in microseconds, with the given number of decimals.
+    Micros(usize),
+    /// Always display in seconds, with the given number of decimals.
+    Secs(usize),
+}
+
+impl Default for Pretty {
+    fn default() -> Self {
+        Pretty::new()
     }
 }
 
 impl Formatter for Pretty {
-    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
-        let mut indent = Vec::with_capacity(0);
+    fn fmt(&self, mut tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        let mut scratch = self.pool.take();
+        let mut indent = mem::take(&mut scratch.indent);
+        scratch.spent = Spent::new(writer.len());
+
+        self.child_order.apply(&mut tree);
+
+        self.format_tree(&tree, None, None, &mut indent, &mut scratch, writer)?;
+
+        if let Some(budget) = self.budget {
+            scratch.spent.check(budget, writer);
+            scratch.spent.finish(writer)?;
+        }
+        scratch.indent = indent;
+        self.pool.put(scratch);
+
+        // Annotations describe the whole tree, so they're kept even if the tree
+        // was truncated
+        for annotation in tree.annotations.iter() {
+            writeln!(writer, "NOTE     {}", annotation)?;
+        }
+        #[cfg(feature = "arena")]
+        crate::arena::recycle(tree);
+        Ok(())
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        if !self.latency || self.snapshot {
+            return Ok(());
+        }
+
+        let formatting = latency.formatting.as_nanos() as f64;
+        write!(writer, "latency [ ")?;
+        if let Some(queued) = latency.queued {
+            let queued = queued.as_nanos() as f64;
+            write!(
+                writer,
+                "queued {} | ",
+                DurationDisplay(queued, self.duration_format)
+            )?;
+        }
+        writeln!(
+            writer,
+            "formatted {} ]",
+            DurationDisplay(formatting, self.duration_format)
+        )
+    }
 
-        format_tree(&tree, None, &mut indent, writer)
+    fn set_ansi(&mut self, ansi: bool) {
+        self.ansi = ansi;
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        self.icons = icons;
     }
 }
 
@@ -71,27 +673,95 @@ impl Edge {
             Self::Turn => "┕━ ",
         }
     }
+
+    /// The edge drawn on continuation lines of a wrapped line.
+    fn continuation(&self) -> Self {
+        match self {
+            Self::Null | Self::Turn => Self::Null,
+            Self::Line | Self::Fork => Self::Line,
+        }
+    }
 }
 
-fn format_attrs(attrs: &TreeAttrs, writer: &mut Vec<u8>) -> io::Result<()> {
-    #[cfg(feature = "uuid")]
-    write!(writer, "{} ", attrs.uuid)?;
+impl Pretty {
+    pub(crate) fn format_attrs(&self, attrs: &TreeAttrs, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.format_attrs_after(attrs, None, writer)
+    }
 
-    #[cfg(feature = "chrono")]
-    write!(writer, "{:<32} ", attrs.timestamp.to_rfc3339())?;
+    /// Formats an event on a single line, outside of its tree.
+    pub(crate) fn format_flat_event(
+        &self,
+        attrs: &TreeAttrs,
+        event: &TreeEvent,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        self.format_attrs(attrs, writer)?;
+        format_event(event, attrs.level, self.icons, writer)
+    }
+
+    /// Formats `attrs`, measuring deltas from the `previous` sibling or
+    /// parent.
+    fn format_attrs_after(
+        &self,
+        attrs: &TreeAttrs,
+        previous: Option<&TreeAttrs>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        #[cfg(not(feature = "chrono"))]
+        let _ = previous;
+
+        if !self.snapshot {
+            #[cfg(feature = "uuid")]
+            write!(writer, "{} ", attrs.uuid)?;
+
+            #[cfg(feature = "chrono")]
+            if self.timestamps != Timestamps::Delta {
+                write!(writer, "{:<32} ", attrs.timestamp.to_rfc3339())?;
+            }
+
+            #[cfg(feature = "chrono")]
+            if self.timestamps != Timestamps::Absolute {
+                let delta = previous.map(|previous| {
+                    let delta = attrs.timestamp - previous.timestamp;
+                    let nanos = delta.num_nanoseconds().unwrap_or(i64::MAX);
+                    let sign = if nanos < 0 { '-' } else { '+' };
+                    let nanos = nanos.unsigned_abs() as f64;
+                    format!("{}{}", sign, DurationDisplay(nanos, self.duration_format))
+                });
+                write!(writer, "{:<10} ", delta.unwrap_or_default())?;
+            }
+        }
+
+        format_level(attrs.level, self.ansi, writer)
+    }
+}
 
-    write!(writer, "{:<8} ", attrs.level)
+/// Writes `level` padded to a fixed width, colored if `ansi` is set.
+pub(crate) fn format_level(level: Level, ansi: bool, writer: &mut Vec<u8>) -> io::Result<()> {
+    if ansi {
+        let color = match level {
+            Level::TRACE => "35",
+            Level::DEBUG => "34",
+            Level::INFO => "32",
+            Level::WARN => "33",
+            Level::ERROR => "31",
+        };
+        write!(writer, "\x1b[{}m{:<8}\x1b[0m ", color, level)
+    } else {
+        write!(writer, "{:<8} ", level)
+    }
 }
 
-fn format_indent(indent: &mut Vec<Edge>, writer: &mut Vec<u8>) -> io::Result<()> {
+fn format_indent(indent: &[Edge], writer: &mut Vec<u8>) -> io::Result<()> {
     indent
         .iter()
         .try_for_each(|edge| writer.write_all(edge.repr().as_bytes()))
 }
 
-fn format_event(event: &TreeEvent, level: Level, writer: &mut Vec<u8>) -> io::Result<()> {
-    let (message, icon) = match event.tag {
-        Some(TagData { message, icon }) => (message, icon),
+/// Returns the tag message and icon of an event, falling back to its level.
+pub(crate) fn tag_and_icon(event: &TreeEvent, level: Level) -> (&'static str, char) {
+    match event.tag {
+        Some(TagData { message, icon, .. }) => (message, icon),
         None => match level {
             Level::TRACE => ("trace", TRACE_ICON),
             Level::DEBUG => ("debug", DEBUG_ICON),
@@ -99,101 +769,569 @@ fn format_event(event: &TreeEvent, level: Level, writer: &mut Vec<u8>) -> io::Re
             Level::WARN => ("warn", WARN_ICON),
             Level::ERROR => ("error", ERROR_ICON),
         },
-    };
+    }
+}
+
+/// Displays the icon of an event followed by its tag in brackets, like
+/// `💬 [info]`.
+pub(crate) struct TagDisplay<'a> {
+    pub(crate) event: &'a TreeEvent,
+    pub(crate) level: Level,
+    pub(crate) icons: Icons,
+}
+
+impl fmt::Display for TagDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, icon) = tag_and_icon(self.event, self.level);
+        match self.icons {
+            Icons::Emoji => write!(f, "{} ", icon)?,
+            Icons::Text if self.event.tag.is_some() => {
+                let marker = message
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric())
+                    .take(3)
+                    .flat_map(char::to_uppercase)
+                    .collect::<String>();
+                write!(f, "[{:<3}] ", marker)?
+            }
+            Icons::Text => {
+                let marker = match self.level {
+                    Level::TRACE => "TRC",
+                    Level::DEBUG => "DBG",
+                    Level::INFO => "INF",
+                    Level::WARN => "WRN",
+                    Level::ERROR => "ERR",
+                };
+                write!(f, "[{}] ", marker)?
+            }
+            Icons::Off => {}
+        }
+        write!(f, "[{}]", message)
+    }
+}
 
-    write!(writer, "{} [{}]: {}", icon, message, event.message)?;
+pub(crate) fn format_event(
+    event: &TreeEvent,
+    level: Level,
+    icons: Icons,
+    writer: &mut Vec<u8>,
+) -> io::Result<()> {
+    let tag = TagDisplay {
+        event,
+        level,
+        icons,
+    };
+    write!(writer, "{}: {}", tag, event.message)?;
 
-    for KeyValue { key, value } in event.fields.iter() {
+    for KeyValue { key, value, .. } in event.fields.iter() {
         write!(writer, " | {}: {}", key, value)?;
     }
 
     writeln!(writer)
 }
 
-fn format_span(
-    span: &TreeSpan,
-    duration_root: Option<f64>,
-    indent: &mut Vec<Edge>,
-    writer: &mut Vec<u8>,
-) -> io::Result<()> {
-    let duration_total = span.duration_total.as_nanos() as f64;
-    let duration_nested = span.duration_nested.as_nanos() as u64;
-    let duration_root = duration_root.unwrap_or(duration_total);
-    let load_total = 100.0 * duration_total / duration_root;
+/// Returns the annotations written at the end of the line of `tree`, which
+/// are all of them unless it's the root, whose annotations are written after
+/// the whole tree.
+fn nested_annotations(tree: &Tree, duration_root: Option<f64>) -> &[Annotation] {
+    match duration_root {
+        Some(_) => &tree.annotations,
+        None => &[],
+    }
+}
+
+/// Writes the annotations of a nested span or event at the end of its line.
+fn format_annotations(annotations: &[Annotation], writer: &mut Vec<u8>) -> io::Result<()> {
+    for annotation in annotations {
+        write!(writer, " | {}", annotation)?;
+    }
+    Ok(())
+}
+
+/// Returns how many columns `line` takes up, ignoring ANSI escape codes.
+fn display_width(line: &[u8]) -> usize {
+    let mut width = 0;
+    let mut escaped = false;
+    for c in String::from_utf8_lossy(line).chars() {
+        match c {
+            '\x1b' => escaped = true,
+            'm' if escaped => escaped = false,
+            _ if escaped => {}
+            c => width += char_width(c),
+        }
+    }
+    width
+}
+
+/// Returns how many columns `c` takes up in a terminal: `0` for combining
+/// and zero-width characters, `2` for wide characters like CJK and emoji,
+/// and `1` otherwise.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F => {
+            0
+        }
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
 
-    write!(
-        writer,
-        "{} [ {} | ",
-        span.name,
-        DurationDisplay(duration_total)
-    )?;
+/// Returns the width of the terminal that stdout or stderr is connected to.
+#[cfg(unix)]
+fn terminal_width() -> Option<usize> {
+    [libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .iter()
+        .find_map(|&fd| {
+            // SAFETY: zeroed `winsize`s are valid, and `TIOCGWINSZ` only
+            // writes to the one it's given
+            let mut size: libc::winsize = unsafe { mem::zeroed() };
+            let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+            (result == 0 && size.ws_col > 0).then(|| usize::from(size.ws_col))
+        })
+}
 
-    if duration_nested > 0 {
-        let load_direct = 100.0 * (duration_total - duration_nested as f64) / duration_root;
-        write!(writer, "{:.3}% / ", load_direct)?;
+#[cfg(not(unix))]
+fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Returns the only child of `span` if it's a span that's collapsed into it.
+fn collapsible(span: &TreeSpan) -> Option<(&TreeAttrs, &TreeSpan)> {
+    match span.children.as_slice() {
+        // Annotated spans aren't collapsed, since their notes would be lost
+        [Tree {
+            attrs,
+            kind: TreeKind::Span(child),
+            annotations,
+            ..
+        }] if annotations.is_empty() => Some((attrs, child)),
+        _ => None,
     }
+}
+
+/// Splits `line` after at most `width` columns, preferring to break on
+/// whitespace. Returns the head and the remaining tail.
+fn split_line(line: &str, width: usize) -> (&str, &str) {
+    let mut used = 0;
+    let hard = line.char_indices().find(|&(idx, c)| {
+        used += char_width(c);
+        // At least one character is kept, so that wrapping makes progress
+        used > width.max(1) && idx > 0
+    });
+    let hard = match hard {
+        Some((idx, _)) => idx,
+        None => return (line, ""),
+    };
+    let split = match line[..hard].rfind(' ') {
+        Some(idx) if idx > 0 => idx,
+        _ => hard,
+    };
+    (&line[..split], line[split..].trim_start_matches(' '))
+}
 
-    writeln!(writer, "{:.3}% ]", load_total)?;
+impl Pretty {
+    fn format_span(
+        &self,
+        tree: &Tree,
+        span: &TreeSpan,
+        duration_root: Option<f64>,
+        indent: &mut Vec<Edge>,
+        scratch: &mut Scratch,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let attrs = &tree.attrs;
+        let annotations = nested_annotations(tree, duration_root);
+        let duration_total = span.duration_total.as_nanos() as f64;
+        let duration_root = duration_root.unwrap_or(duration_total);
+        let load_total = 100.0 * duration_total / duration_root;
 
-    if let Some((last, remaining)) = span.children.split_last() {
-        match indent.last_mut() {
-            Some(edge @ Edge::Turn) => *edge = Edge::Null,
-            Some(edge @ Edge::Fork) => *edge = Edge::Line,
-            _ => {}
+        if self.fold_below.is_some_and(|level| attrs.level > level) {
+            return self.format_folded(span, duration_total, annotations, writer);
         }
 
-        indent.push(Edge::Fork);
+        let mut span = span;
+        let mut innermost = attrs;
+        // A failure anywhere inside of the collapsed spans fails the first
+        let failed = span.is_failed();
+        let mut name = mem::take(&mut scratch.name);
+        name.clear();
+        name.push_str(span.name);
+        if self.collapse {
+            while let Some((child_attrs, child)) = collapsible(span) {
+                span = child;
+                innermost = child_attrs;
+                name.push_str(" > ");
+                name.push_str(span.name);
+            }
+        }
+        // Time spent in the collapsed spans themselves counts as direct
+        let duration_nested = span.duration_nested.as_nanos() as u64;
+
+        // Failed spans are red
+        let (color, reset) = if self.ansi && failed {
+            ("\x1b[31m", "\x1b[0m")
+        } else {
+            ("", "")
+        };
+
+        if self.snapshot {
+            write!(writer, "{}{}{}", color, name, reset)?;
+            scratch.name = name;
+            format_annotations(annotations, writer)?;
+            writeln!(writer)?;
+            return self.format_children(innermost, span, duration_root, indent, scratch, writer);
+        }
+
+        let mut timing = mem::take(&mut scratch.timing);
+        timing.clear();
+        let _ = write!(
+            timing,
+            "[ {} | ",
+            DurationDisplay(duration_total, self.duration_format)
+        );
+
+        if duration_nested > 0 && self.self_time {
+            let duration_self = duration_total - duration_nested as f64;
+            let _ = write!(
+                timing,
+                "{} self | ",
+                DurationDisplay(duration_self, self.duration_format)
+            );
+        }
+
+        if duration_nested > 0 {
+            let load_direct = 100.0 * (duration_total - duration_nested as f64) / duration_root;
+            let _ = write!(timing, "{:.3}% / ", load_direct);
+        }
+
+        let _ = write!(timing, "{:.3}% ]", load_total);
+
+        match self.duration_column {
+            Some(column) => {
+                let line_start = writer
+                    .iter()
+                    .rposition(|&byte| byte == b'\n')
+                    .map_or(0, |idx| idx + 1);
+                let used = display_width(&writer[line_start..]) + display_width(name.as_bytes());
+                let padding = column
+                    .saturating_sub(used + display_width(timing.as_bytes()))
+                    .max(1);
+                write!(
+                    writer,
+                    "{}{}{}{:padding$}{}",
+                    color,
+                    name,
+                    reset,
+                    "",
+                    timing,
+                    padding = padding
+                )?;
+            }
+            None => write!(writer, "{}{}{} {}", color, name, reset, timing)?,
+        }
+        // Give the buffers back for the spans below this one
+        scratch.name = name;
+        scratch.timing = timing;
+
+        #[cfg(feature = "chrono")]
+        if self.span_start {
+            write!(writer, " @ {}", attrs.timestamp.to_rfc3339())?;
+        }
+
+        format_annotations(annotations, writer)?;
+        writeln!(writer)?;
+
+        self.format_children(innermost, span, duration_root, indent, scratch, writer)
+    }
+
+    /// Writes a span on one line, with badges counting the events below it
+    /// instead of its children.
+    fn format_folded(
+        &self,
+        span: &TreeSpan,
+        duration_total: f64,
+        annotations: &[Annotation],
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let mut counts = EventCounts::default();
+        for child in span.children.iter() {
+            let _ = child.walk(&mut counts);
+        }
+        let total = counts.total();
+
+        write!(writer, "▸ {} [ ", span.name)?;
+        if !self.snapshot {
+            let duration = DurationDisplay(duration_total, self.duration_format);
+            write!(writer, "{} | ", duration)?;
+        }
+        match total {
+            1 => write!(writer, "1 event")?,
+            total => write!(writer, "{} events", total)?,
+        }
+        for level in [Level::ERROR, Level::WARN] {
+            let count = counts.level(level);
+            if count > 0 {
+                write!(writer, ", {} {}", count, level)?;
+            }
+        }
+        write!(writer, " ]")?;
+
+        format_annotations(annotations, writer)?;
+        writeln!(writer)
+    }
+
+    /// Counts the rows that formatting `tree` would write as omitted.
+    fn omit(&self, tree: &Tree, spent: &mut Spent) {
+        spent.omit(tree.attrs.level);
+        let mut span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => return,
+        };
+        if self
+            .fold_below
+            .is_some_and(|level| tree.attrs.level > level)
+        {
+            return;
+        }
+        if self.collapse {
+            while let Some((_, child)) = collapsible(span) {
+                span = child;
+            }
+        }
+        for child in span.children.iter() {
+            self.omit(child, spent);
+        }
+    }
+
+    fn format_children(
+        &self,
+        attrs: &TreeAttrs,
+        span: &TreeSpan,
+        duration_root: f64,
+        indent: &mut Vec<Edge>,
+        scratch: &mut Scratch,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        if let Some((last, remaining)) = span.children.split_last() {
+            match indent.last_mut() {
+                Some(edge @ Edge::Turn) => *edge = Edge::Null,
+                Some(edge @ Edge::Fork) => *edge = Edge::Line,
+                _ => {}
+            }
+
+            indent.push(Edge::Fork);
+
+            let mut previous = attrs;
+            for tree in remaining {
+                if let Some(edge) = indent.last_mut() {
+                    *edge = Edge::Turn;
+                }
+                let duration_root = Some(duration_root);
+                self.format_tree(tree, Some(previous), duration_root, indent, scratch, writer)?;
+                previous = &tree.attrs;
+            }
 
-        for tree in remaining {
             if let Some(edge) = indent.last_mut() {
                 *edge = Edge::Turn;
             }
-            format_tree(tree, Some(duration_root), indent, writer)?;
+            let duration_root = Some(duration_root);
+            self.format_tree(last, Some(previous), duration_root, indent, scratch, writer)?;
+
+            indent.pop();
+        }
+
+        Ok(())
+    }
+
+    fn format_tree(
+        &self,
+        tree: &Tree,
+        previous: Option<&TreeAttrs>,
+        duration_root: Option<f64>,
+        indent: &mut Vec<Edge>,
+        scratch: &mut Scratch,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let start = writer.len();
+        if let Some(budget) = self.budget {
+            // Rows that don't fit are counted without formatting them
+            if !scratch.spent.check(budget, writer) {
+                self.omit(tree, &mut scratch.spent);
+                return Ok(());
+            }
+            scratch.spent.last = Some(Row {
+                start,
+                level: tree.attrs.level,
+            });
         }
 
-        if let Some(edge) = indent.last_mut() {
-            *edge = Edge::Turn;
+        self.format_attrs_after(&tree.attrs, previous, writer)?;
+
+        let attrs_width = display_width(&writer[start..]);
+
+        format_indent(indent, writer)?;
+
+        match &tree.kind {
+            TreeKind::Event(event) => {
+                let mut line = mem::take(&mut scratch.line);
+                line.clear();
+                format_event(event, tree.attrs.level, self.icons, &mut line)?;
+                let annotations = nested_annotations(tree, duration_root);
+                if !annotations.is_empty() {
+                    line.pop();
+                    format_annotations(annotations, &mut line)?;
+                    writeln!(line)?;
+                }
+                match self.width {
+                    Some(width) => {
+                        let tag = TagDisplay {
+                            event,
+                            level: tree.attrs.level,
+                            icons: self.icons,
+                        };
+                        // Continuation lines line up with the start of the message
+                        let name = &mut scratch.name;
+                        name.clear();
+                        let _ = write!(name, "{}: ", tag);
+                        let tag_width = display_width(name.as_bytes());
+                        let prefix = Prefix {
+                            attrs_width,
+                            indent,
+                            tag_width,
+                        };
+                        format_wrapped(&line, &prefix, width, writer)?
+                    }
+                    None => writer.extend_from_slice(&line),
+                }
+                scratch.line = line;
+
+                #[cfg(feature = "tracing-error")]
+                if let Some(frames) = &event.span_trace {
+                    self.format_span_trace(&tree.attrs, frames, indent, writer)?;
+                }
+
+                Ok(())
+            }
+            TreeKind::Span(span) => {
+                self.format_span(tree, span, duration_root, indent, scratch, writer)
+            }
+        }
+    }
+
+    /// Formats the frames of a span trace as children of the event that
+    /// recorded it.
+    #[cfg(feature = "tracing-error")]
+    fn format_span_trace(
+        &self,
+        attrs: &TreeAttrs,
+        frames: &[SpanTraceFrame],
+        indent: &[Edge],
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let mut indent = indent.iter().map(Edge::continuation).collect::<Vec<_>>();
+        indent.push(Edge::Fork);
+
+        for (idx, frame) in frames.iter().enumerate() {
+            if idx + 1 == frames.len() {
+                if let Some(edge) = indent.last_mut() {
+                    *edge = Edge::Turn;
+                }
+            }
+
+            self.format_attrs(attrs, writer)?;
+            format_indent(&indent, writer)?;
+            write!(writer, "{}::{}", frame.target, frame.name)?;
+            if !frame.fields.is_empty() {
+                write!(writer, "{{{}}}", frame.fields)?;
+            }
+            if let (Some(file), Some(line)) = (frame.file, frame.line) {
+                write!(writer, " at {}:{}", file, line)?;
+            }
+            writeln!(writer)?;
         }
-        format_tree(last, Some(duration_root), indent, writer)?;
 
-        indent.pop();
+        Ok(())
     }
+}
 
-    Ok(())
+/// What's written to the left of the message of an event.
+struct Prefix<'a> {
+    /// The width of the attributes, like the level.
+    attrs_width: usize,
+    /// The tree guide lines.
+    indent: &'a [Edge],
+    /// The width of the icon and tag before the message.
+    tag_width: usize,
 }
 
-fn format_tree(
-    tree: &Tree,
-    duration_root: Option<f64>,
-    indent: &mut Vec<Edge>,
+fn format_wrapped(
+    line: &[u8],
+    prefix: &Prefix<'_>,
+    width: usize,
     writer: &mut Vec<u8>,
 ) -> io::Result<()> {
-    format_attrs(&tree.attrs, writer)?;
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches('\n');
+    let prefix_width = prefix.attrs_width + 3 * prefix.indent.len();
+    let available = width.saturating_sub(prefix_width + prefix.tag_width);
 
-    format_indent(indent, writer)?;
+    let (first, mut rest) = split_line(line, width.saturating_sub(prefix_width));
+    writeln!(writer, "{}", first)?;
 
-    match &tree.kind {
-        TreeKind::Event(event) => format_event(event, tree.attrs.level, writer),
-        TreeKind::Span(span) => format_span(span, duration_root, indent, writer),
+    while !rest.is_empty() {
+        let (piece, tail) = split_line(rest, available);
+        write!(writer, "{:width$}", "", width = prefix.attrs_width)?;
+        for edge in prefix.indent {
+            writer.write_all(edge.continuation().repr().as_bytes())?;
+        }
+        writeln!(writer, "{:width$}{}", "", piece, width = prefix.tag_width)?;
+        rest = tail;
     }
+
+    Ok(())
 }
 
-struct DurationDisplay(f64);
+pub(crate) struct DurationDisplay(pub(crate) f64, pub(crate) DurationFormat);
 
-// Taken from chrono
 impl fmt::Display for DurationDisplay {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut t = self.0;
-        for unit in ["ns", "µs", "ms", "s"] {
-            if t < 10.0 {
-                return write!(f, "{:.2}{}", t, unit);
-            } else if t < 100.0 {
-                return write!(f, "{:.1}{}", t, unit);
-            } else if t < 1000.0 {
-                return write!(f, "{:.0}{}", t, unit);
-            }
-            t /= 1000.0;
+        let nanos = self.0;
+        match self.1 {
+            DurationFormat::Auto => auto_duration(nanos, f),
+            DurationFormat::Micros(decimals) => write!(f, "{:.*}µs", decimals, nanos / 1e3),
+            DurationFormat::Millis(decimals) => write!(f, "{:.*}ms", decimals, nanos / 1e6),
+            DurationFormat::Secs(decimals) => write!(f, "{:.*}s", decimals, nanos / 1e9),
+        }
+    }
+}
+
+// Taken from chrono
+fn auto_duration(nanos: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut t = nanos;
+    for unit in ["ns", "µs", "ms", "s"] {
+        if t < 10.0 {
+            return write!(f, "{:.2}{}", t, unit);
+        } else if t < 100.0 {
+            return write!(f, "{:.1}{}", t, unit);
+        } else if t < 1000.0 {
+            return write!(f, "{:.0}{}", t, unit);
         }
-        write!(f, "{:.0}s", t * 1000.0)
+        t /= 1000.0;
     }
+    write!(f, "{:.0}s", t * 1000.0)
 }
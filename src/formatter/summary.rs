@@ -0,0 +1,136 @@
+//! A [`Formatter`] that formats each tree as a single summary line.
+//!
+//! See [`Summary`] for more details.
+
+use crate::formatter::pretty::{self, DurationDisplay, DurationFormat};
+use crate::formatter::Formatter;
+use crate::layer::{Tree, TreeKind};
+use std::io::{self, Write};
+
+/// Format logs as one line per tree, with the name and duration of the root
+/// span, how many events it contains of each level, the tags of its events,
+/// and its ID.
+///
+/// This is meant for the console of services whose full trees are written
+/// somewhere else, like a file, with a [`Tee`] of two processors. Trees that
+/// are a single event are summarized by their message.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{pretty::Pretty, summary::Summary};
+/// # use tracing_forest::Processor;
+/// let console = tracing_forest::blocking(Summary::new(), std::io::stderr);
+/// let file = tracing_forest::blocking(Pretty::new(), std::io::sink);
+///
+/// let layer = console.tee(file).into_layer();
+/// ```
+/// ```log
+/// INFO     request [ 7.47ms ] | events: 1 WARN, 3 INFO | tags: security.access | id: 9b9c7ad5-138e-4a3c-8e2f-0b1e1e6c7255
+/// ```
+///
+/// [`Tee`]: crate::processor::tee::Tee
+pub struct Summary {
+    duration_format: DurationFormat,
+    ansi: bool,
+    #[cfg(feature = "uuid")]
+    id: bool,
+    #[doc(hidden)]
+    _priv: (),
+}
+
+impl Summary {
+    /// Constructs a new [`Summary`] formatter.
+    pub const fn new() -> Self {
+        Summary {
+            duration_format: DurationFormat::Auto,
+            ansi: false,
+            #[cfg(feature = "uuid")]
+            id: true,
+            _priv: (),
+        }
+    }
+
+    /// Sets how the durations of root spans are displayed.
+    pub const fn with_duration_format(mut self, duration_format: DurationFormat) -> Self {
+        self.duration_format = duration_format;
+        self
+    }
+
+    /// Sets whether levels are colored using ANSI escape codes.
+    ///
+    /// To detect whether output should be colored, see
+    /// [`Ansi`][crate::formatter::Ansi].
+    pub const fn with_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Sets whether the [`Uuid`] of each tree is displayed.
+    ///
+    /// By default, it's displayed, so the full tree can be found elsewhere.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub const fn with_id(mut self, id: bool) -> Self {
+        self.id = id;
+        self
+    }
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Summary::new()
+    }
+}
+
+impl Formatter for Summary {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        pretty::format_level(tree.attrs.level, self.ansi, writer)?;
+        match &tree.kind {
+            TreeKind::Event(event) => write!(writer, "{}", event.message)?,
+            TreeKind::Span(span) => {
+                let duration = span.duration_total.as_nanos() as f64;
+                write!(
+                    writer,
+                    "{} [ {} ]",
+                    span.name,
+                    DurationDisplay(duration, self.duration_format)
+                )?;
+            }
+        }
+
+        let counts = tree.event_counts();
+        write!(writer, " | events: ")?;
+        let mut levels = counts.levels();
+        match levels.next() {
+            Some((level, count)) => {
+                write!(writer, "{} {}", count, level)?;
+                for (level, count) in levels {
+                    write!(writer, ", {} {}", count, level)?;
+                }
+            }
+            None => write!(writer, "0")?,
+        }
+
+        let mut tags = counts.tags().iter().map(|(tag, _)| tag);
+        if let Some(tag) = tags.next() {
+            write!(writer, " | tags: {}", tag)?;
+            for tag in tags {
+                write!(writer, ", {}", tag)?;
+            }
+        }
+
+        #[cfg(feature = "uuid")]
+        if self.id {
+            write!(writer, " | id: {}", tree.attrs.uuid)?;
+        }
+
+        writeln!(writer)
+    }
+
+    fn set_ansi(&mut self, ansi: bool) {
+        self.ansi = ansi;
+    }
+}
@@ -0,0 +1,165 @@
+//! A [`Formatter`] that can be replaced at runtime.
+//!
+//! See [`Switchable`] for more details.
+
+use crate::formatter::{Formatter, Icons};
+use crate::layer::Tree;
+use crate::processor::Latency;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// A [`Formatter`] that passes trees to another formatter, which can be
+/// replaced at runtime with a [`SwitchHandle`].
+///
+/// This lets a program flip its output without restarting, like switching
+/// from [`Pretty`] to [`Json`] when a remote debugging session attaches or a
+/// signal is received. Each tree is formatted along with its latency by
+/// whichever formatter was current when it started, so trees that are being
+/// formatted while the formatter is replaced finish with the old one, and
+/// replacing it doesn't wait for them.
+///
+/// Settings applied by [`LayerBuilder::set_ansi`] and
+/// [`LayerBuilder::icons`] are remembered, and applied to every formatter
+/// that's switched to.
+///
+/// To initialize a new [`Switchable`], see [`Switchable::new`] or
+/// [`LayerBuilder::switchable`].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+/// # use tracing_forest::formatter::switch::Switchable;
+/// # use tracing_forest::{blocking, Processor};
+/// let (formatter, handle) = Switchable::new(Pretty::new());
+/// let processor = blocking(formatter, std::io::stdout);
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info!("written as a tree");
+///     handle.set(Json::new(true));
+///     tracing::info!("written as JSON");
+/// });
+/// ```
+///
+/// [`Pretty`]: crate::formatter::pretty::Pretty
+/// [`Json`]: crate::formatter::json::Json
+/// [`LayerBuilder::set_ansi`]: crate::builder::LayerBuilder::set_ansi
+/// [`LayerBuilder::icons`]: crate::builder::LayerBuilder::icons
+/// [`LayerBuilder::switchable`]: crate::builder::LayerBuilder::switchable
+pub struct Switchable {
+    current: Arc<RwLock<Current>>,
+}
+
+struct Current {
+    formatter: Arc<dyn Formatter + Send + Sync>,
+    ansi: Option<bool>,
+    icons: Option<Icons>,
+}
+
+/// A handle for replacing the formatter of a [`Switchable`].
+///
+/// Handles can be cloned and sent to other threads.
+#[derive(Clone)]
+pub struct SwitchHandle {
+    current: Arc<RwLock<Current>>,
+}
+
+impl Switchable {
+    /// Construct a new [`Switchable`] that formats trees with `formatter`
+    /// until it's replaced using the returned [`SwitchHandle`].
+    pub fn new<F>(formatter: F) -> (Self, SwitchHandle)
+    where
+        F: 'static + Formatter + Send + Sync,
+    {
+        let current = Arc::new(RwLock::new(Current {
+            formatter: Arc::new(formatter),
+            ansi: None,
+            icons: None,
+        }));
+        let handle = SwitchHandle {
+            current: current.clone(),
+        };
+        (Switchable { current }, handle)
+    }
+}
+
+impl SwitchHandle {
+    /// Format trees with `formatter` from now on.
+    pub fn set<F>(&self, mut formatter: F)
+    where
+        F: 'static + Formatter + Send + Sync,
+    {
+        #[allow(clippy::expect_used)]
+        let mut current = self.current.write().expect("switchable formatter poisoned");
+        if let Some(ansi) = current.ansi {
+            formatter.set_ansi(ansi);
+        }
+        if let Some(icons) = current.icons {
+            formatter.set_icons(icons);
+        }
+        current.formatter = Arc::new(formatter);
+    }
+}
+
+impl Switchable {
+    /// Returns the current formatter, without keeping it locked while it's
+    /// used.
+    fn current(&self) -> Arc<dyn Formatter + Send + Sync> {
+        #[allow(clippy::expect_used)]
+        let current = self.current.read().expect("switchable formatter poisoned");
+        current.formatter.clone()
+    }
+}
+
+impl fmt::Debug for Switchable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Switchable").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for SwitchHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwitchHandle").finish_non_exhaustive()
+    }
+}
+
+impl Formatter for Switchable {
+    fn fmt(&self, tree: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.current().fmt(tree, writer)
+    }
+
+    fn fmt_latency(&self, latency: &Latency, writer: &mut Vec<u8>) -> io::Result<()> {
+        self.current().fmt_latency(latency, writer)
+    }
+
+    fn fmt_with_latency(
+        &self,
+        tree: Tree,
+        queued: Option<Duration>,
+        writer: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        self.current().fmt_with_latency(tree, queued, writer)
+    }
+
+    fn set_ansi(&mut self, ansi: bool) {
+        #[allow(clippy::expect_used)]
+        let mut current = self.current.write().expect("switchable formatter poisoned");
+        current.ansi = Some(ansi);
+        // Trees being formatted keep their formatter, so this waits for the
+        // next one that's switched to
+        if let Some(formatter) = Arc::get_mut(&mut current.formatter) {
+            formatter.set_ansi(ansi);
+        }
+    }
+
+    fn set_icons(&mut self, icons: Icons) {
+        #[allow(clippy::expect_used)]
+        let mut current = self.current.write().expect("switchable formatter poisoned");
+        current.icons = Some(icons);
+        if let Some(formatter) = Arc::get_mut(&mut current.formatter) {
+            formatter.set_icons(icons);
+        }
+    }
+}
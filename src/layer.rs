@@ -2,79 +2,177 @@
 //!
 //! Implementation details in this module aren't important, unless you're
 //! implementing your own [`Processor`] or [`Formatter`].
-//! 
-//! # Quick reference
-//! 
-//! Trace data is stored in the layer as a tree, where spans represent internal 
-//! nodes and events represent leafs.
-//! 
-//! * [`Tree`]: A node in the trace tree.
-//! * [`TreeAttrs`]: Common data used by spans and events, like a [`Uuid`] if 
-//! the `uuid` feature is enabled, a timestamp if the `timestamp` feature is 
-//! enabled, and a [`Level`].
-//! * [`TreeKind`]: Contains either a [`TreeSpan`] or a [`TreeEvent`].
-//! * [`TreeSpan`]: Data unique to span traces, including durations and other 
-//! [`Tree`] nodes.
-//! * [`TreeEvent`]: Data unique to event traces, like tags.
+//!
+//! The tree types are defined in the [`tree`][crate::tree] module, and are
+//! re-exported here.
 //!
 //! [`Formatter`]: crate::formatter::Formatter
 
+#[cfg(feature = "chrono")]
+use crate::clock;
+use crate::error::{self, ForestError};
 use crate::fail;
-use crate::processor::Processor;
-#[cfg(feature = "json")]
-use crate::ser;
-use crate::tag::{NoTag, Tag, TagData, TagParser};
+use crate::formatter::pretty::Pretty;
+use crate::processor::{self, sample, Processor};
+use crate::tag::{NoTag, Tag, TagData, TagParser, TagRegistry, TAG_KEY};
+#[cfg(feature = "tracing-error")]
+pub use crate::tree::SpanTraceFrame;
+use crate::tree::{self, Fields};
+pub use crate::tree::{
+    Annotation, EventMetadata, KeyValue, Tree, TreeAttrs, TreeEvent, TreeKind, TreeSpan,
+    TreeVisitor,
+};
+#[cfg(feature = "uuid")]
+use crate::uuid::UuidVersion;
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
-#[cfg(feature = "json")]
-use serde::Serialize;
-#[cfg(feature = "smallvec")]
-use smallvec::SmallVec;
+use std::any::TypeId;
+use std::cell::Cell;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{self, Poll};
 use std::time::{Duration, Instant};
 use std::{borrow::Cow, fmt};
 use tracing::field::{Field, Visit};
+#[cfg(feature = "sync")]
+use tracing::instrument::Instrument;
+use tracing::level_filters::LevelFilter;
 use tracing::span::{Attributes, Record};
-use tracing::{Event, Id, Level, Metadata, Subscriber};
+use tracing::{Dispatch, Event, Id, Level, Metadata, Subscriber};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
 use tracing_subscriber::layer::Layered;
+use tracing_subscriber::registry::SpanRef;
 use tracing_subscriber::Registry;
 use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
 #[cfg(feature = "uuid")]
 use uuid::Uuid;
 
-#[cfg(feature = "smallvec")]
-pub(crate) type Fields = SmallVec<[KeyValue; 3]>;
-#[cfg(not(feature = "smallvec"))]
-pub(crate) type Fields = Vec<KeyValue>;
-
-#[doc(hidden)]
-#[derive(Debug)]
-pub struct KeyValue {
-    pub key: &'static str,
-    pub value: String,
-}
-
 #[cfg(feature = "uuid")]
 const DEFAULT_EVENT_UUID: Uuid = Uuid::nil();
 
-pub(crate) const TAG_KEY: &'static str = "__event_tag";
+/// The span field that excludes a span and its subtree from trees.
+pub(crate) const SKIP_KEY: &str = "__forest_skip";
+
+/// The span field that excludes a span and its subtree from trees when
+/// recorded as `true`, like with [`skip_span!`].
+///
+/// The field must be recorded when the span is created, and isn't shown in
+/// trees. Other layers of the subscriber still see the span and everything
+/// inside of it.
+///
+/// # Examples
+///
+/// ```
+/// tracing::info_span!("poll", forest.skip = true).in_scope(|| {
+///     tracing::trace!("excluded from trees");
+/// });
+/// ```
+///
+/// [`skip_span!`]: crate::skip_span
+pub const SKIP_FIELD: &str = "forest.skip";
+
+/// The event field that records the format string of the event's message,
+/// like with [`templated!`].
+///
+/// The field is stored as the event's [`template`] instead of as a field.
+///
+/// # Examples
+///
+/// ```
+/// # let delay = 250;
+/// tracing::warn!(message.template = "retrying in {}ms", "retrying in {}ms", delay);
+/// ```
+///
+/// [`templated!`]: crate::templated
+/// [`template`]: crate::layer::TreeEvent::template
+pub const TEMPLATE_FIELD: &str = "message.template";
 
 /// The main type provided by this crate.
-/// 
+///
 /// See the [top-level documentation] for details on how to use.
-/// 
+///
 /// [top-level documentation]: crate
 pub struct TreeLayer<P> {
     processor: P,
     tag_parser: TagParser,
+    live: Option<BoxMakeWriter>,
+    live_formatter: Pretty,
+    max_level: LevelFilter,
+    verbose_level: LevelFilter,
+    verbose: Arc<AtomicBool>,
+    sample_rate: f64,
+    sampled: AtomicU64,
+    field_rules: FieldRules,
+    transforms: Vec<fn(Tree) -> Tree>,
+    retroactive_verbosity: bool,
+    #[cfg(feature = "chrono")]
+    coarse_clock: Option<clock::Clock>,
+    #[cfg(feature = "chrono")]
+    wall_clock: Option<fn() -> DateTime<Utc>>,
+    #[cfg(feature = "uuid")]
+    new_uuid: fn() -> Uuid,
+    #[cfg(feature = "uuid")]
+    lazy_uuids: bool,
+    #[cfg(feature = "uuid")]
+    id_field: Option<&'static str>,
+    submit: Submit,
 }
 
+/// Points to the processor of a [`TreeLayer`] inside of a type-erased
+/// [`Dispatch`], since the type of the processor isn't known there.
+struct Submit(fn(&Dispatch, Tree));
+
 impl<P: Processor> TreeLayer<P> {
     /// Create a new `TreeLayer` from a [`Processor`].
     pub fn new(processor: P) -> Self {
         TreeLayer {
             processor,
-            tag_parser: NoTag::from_field,
+            tag_parser: TagParser::of::<NoTag>(),
+            live: None,
+            live_formatter: Pretty::new(),
+            max_level: LevelFilter::TRACE,
+            verbose_level: LevelFilter::TRACE,
+            verbose: Arc::new(AtomicBool::new(false)),
+            sample_rate: 1.0,
+            sampled: AtomicU64::new(0),
+            field_rules: FieldRules::new(),
+            transforms: Vec::new(),
+            retroactive_verbosity: false,
+            #[cfg(feature = "chrono")]
+            coarse_clock: None,
+            #[cfg(feature = "chrono")]
+            wall_clock: None,
+            #[cfg(feature = "uuid")]
+            new_uuid: Uuid::new_v4,
+            #[cfg(feature = "uuid")]
+            lazy_uuids: false,
+            #[cfg(feature = "uuid")]
+            id_field: None,
+            submit: Submit(Self::submit),
+        }
+    }
+
+    fn submit(dispatch: &Dispatch, tree: Tree) {
+        if let Some(layer) = dispatch.downcast_ref::<Self>() {
+            layer.process(tree);
+        }
+    }
+
+    /// Sends a finished tree to the processor.
+    fn process(&self, mut tree: Tree) {
+        tree.closed = Some(Instant::now());
+        if self.retroactive_verbosity && tree.most_severe_level() > Level::WARN {
+            match &mut tree.kind {
+                TreeKind::Event(_) if tree.attrs.level >= Level::DEBUG => return,
+                TreeKind::Event(_) => {}
+                TreeKind::Span(span) => drop_verbose_events(span),
+            }
         }
+        self.processor
+            .process(processor::transform(&self.transforms, tree))
     }
 
     /// Compose the `TreeLayer` onto a [`Registry`].
@@ -84,142 +182,934 @@ impl<P: Processor> TreeLayer<P> {
 
     /// Set the accepted [`Tag`] type of the `TreeLayer`.
     pub fn tag<T: Tag>(mut self) -> Self {
-        self.tag_parser = T::from_field;
+        self.tag_parser.set::<T>();
+        self
+    }
+
+    /// Tag events by the prefixes of their targets in `registry`, when they
+    /// aren't tagged by an `__event_tag` field or the [`Tag`] type.
+    ///
+    /// See [`TagRegistry`] for more details.
+    pub fn tag_registry(mut self, registry: TagRegistry) -> Self {
+        self.tag_parser.registry = Some(Arc::new(registry));
+        self
+    }
+
+    pub(crate) fn tag_parser(mut self, tag_parser: TagParser) -> Self {
+        self.tag_parser = tag_parser;
+        self
+    }
+
+    /// Set the most verbose [`Level`] of spans and events that are collected.
+    ///
+    /// This only filters what the [`TreeLayer`] collects, so other layers of
+    /// the subscriber still see everything. Spans that aren't collected are
+    /// left out of trees, and what happens inside of them is collected as if
+    /// it happened in their closest collected ancestor.
+    ///
+    /// By default, all levels are collected.
+    pub fn max_level(mut self, max_level: impl Into<LevelFilter>) -> Self {
+        self.max_level = max_level.into();
+        self
+    }
+
+    /// Set the most verbose [`Level`] of spans and events that are collected
+    /// while verbosity is raised with a [`VerbosityHandle`].
+    ///
+    /// By default, all levels are collected while it's raised.
+    pub fn verbose_level(mut self, verbose_level: impl Into<LevelFilter>) -> Self {
+        self.verbose_level = verbose_level.into();
+        self
+    }
+
+    /// Returns a handle for raising the verbosity of the `TreeLayer` from its
+    /// [maximum level] to its [verbose level] at runtime, like while
+    /// debugging a live service.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let layer = blocking(Pretty::new(), std::io::stdout)
+    ///     .into_layer()
+    ///     .max_level(Level::INFO);
+    /// let verbosity = layer.verbosity_handle();
+    ///
+    /// tracing::subscriber::with_default(layer.into_subscriber(), || {
+    ///     tracing::debug!("ignored");
+    ///     verbosity.set_verbose(true);
+    ///     tracing::debug!("collected");
+    /// });
+    /// ```
+    ///
+    /// [maximum level]: TreeLayer::max_level
+    /// [verbose level]: TreeLayer::verbose_level
+    pub fn verbosity_handle(&self) -> VerbosityHandle {
+        VerbosityHandle {
+            verbose: self.verbose.clone(),
+        }
+    }
+
+    /// Set the fraction of trees that are processed, between `0.0` and `1.0`.
+    ///
+    /// Trees are sampled evenly, so a rate of `0.25` processes every fourth
+    /// tree. Live events are written regardless. By default, every tree is
+    /// processed.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the [`FieldRules`] that rename and drop fields of spans and events
+    /// as they're collected.
+    ///
+    /// By default, fields are kept as they're recorded.
+    pub fn field_rules(mut self, rules: FieldRules) -> Self {
+        self.field_rules = rules;
+        self
+    }
+
+    /// Apply `transform` to every tree before it's sent to the processor.
+    ///
+    /// Transforms can enrich trees, like adding computed fields, reclassify
+    /// their levels, or merge their children, without writing a processor.
+    /// They run on the thread that finished the tree, in the order they're
+    /// added. To run a transform where trees are taken off of the queue
+    /// instead, like on the task of an async processor, see
+    /// [`LayerBuilder::set_transform`] or [`Transformed`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::layer::Tree;
+    /// fn errors_are_warnings(mut tree: Tree) -> Tree {
+    ///     if tree.attrs.level == Level::ERROR {
+    ///         tree.attrs.level = Level::WARN;
+    ///     }
+    ///     tree
+    /// }
+    ///
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .transform(errors_are_warnings)
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    ///
+    /// [`LayerBuilder::set_transform`]: crate::builder::LayerBuilder::set_transform
+    /// [`Transformed`]: crate::formatter::Transformed
+    pub fn transform(mut self, transform: fn(Tree) -> Tree) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// Set whether `TRACE` and `DEBUG` events are only kept in trees that
+    /// contain a `WARN` or `ERROR`.
+    ///
+    /// Verbose events are still collected into every tree, but once a tree is
+    /// finished, they're dropped unless something in it went wrong, in which
+    /// case the full detail leading up to the problem is processed. This is
+    /// "retroactive verbosity": requests that succeed are logged at `INFO`,
+    /// and requests that fail are logged at `TRACE`. Verbose spans are kept
+    /// either way, and verbose events outside of spans are always dropped.
+    ///
+    /// This needs the [maximum level] to include `DEBUG`, or `TRACE`, which
+    /// it does by default. By default, every event is processed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .retroactive_verbosity(true)
+    ///         .into_subscriber()
+    /// });
+    ///
+    /// tracing::info_span!("ok").in_scope(|| {
+    ///     tracing::debug!("dropped, since nothing went wrong");
+    /// });
+    /// tracing::info_span!("failed").in_scope(|| {
+    ///     tracing::debug!("kept, since it helps explain the error");
+    ///     tracing::error!("request failed");
+    /// });
+    /// ```
+    ///
+    /// [maximum level]: TreeLayer::max_level
+    pub fn retroactive_verbosity(mut self, retroactive: bool) -> Self {
+        self.retroactive_verbosity = retroactive;
+        self
+    }
+
+    /// Set whether trees are timestamped with a coarse clock, instead of
+    /// reading the system clock for each of them.
+    ///
+    /// The coarse clock is a cached time that a background thread updates
+    /// every millisecond, so timestamps can lag behind by a few milliseconds,
+    /// and trees opened within the same millisecond have the same
+    /// timestamp. This is only worth it on hot paths where reading the clock
+    /// shows up in profiles.
+    ///
+    /// The timestamps inside of a tree are derived from the timestamp of its
+    /// root, see [`wall_clock`]. With the coarse clock, the time since the
+    /// root is also read from a coarse monotonic clock that the same thread
+    /// updates, so logging an event doesn't read any clock, and the events
+    /// inside of a tree are timestamped to the millisecond. Durations of spans
+    /// are still measured precisely.
+    ///
+    /// The thread is shared by all layers with coarse timestamps. It's started
+    /// when the first of them enables this, and stops once all of them are
+    /// dropped or have disabled it again. By default, the system clock is
+    /// read.
+    ///
+    /// [`wall_clock`]: TreeLayer::wall_clock
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn coarse_timestamps(mut self, coarse: bool) -> Self {
+        self.coarse_clock = match self.coarse_clock.take() {
+            Some(clock) if coarse => Some(clock),
+            None if coarse => Some(clock::Clock::acquire()),
+            _ => None,
+        };
+        self
+    }
+
+    /// Set the function that reads the wall clock for timestamps, instead of
+    /// the system clock.
+    ///
+    /// The wall clock is only read once per tree, when its root is opened or
+    /// logged. The timestamps of the spans and events inside of it are the
+    /// timestamp of the root plus the time since then, measured by the
+    /// monotonic clock, like durations are. This way, offsets and durations
+    /// within a tree are never negative, even if the wall clock is stepped by
+    /// NTP or jumps forward after the machine was suspended.
+    ///
+    /// This takes precedence over [`coarse_timestamps`], and is useful to
+    /// test how trees look when the clock jumps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// use chrono::{DateTime, Utc};
+    ///
+    /// fn epoch() -> DateTime<Utc> {
+    ///     DateTime::UNIX_EPOCH
+    /// }
+    ///
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .wall_clock(epoch)
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    ///
+    /// [`coarse_timestamps`]: TreeLayer::coarse_timestamps
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    pub fn wall_clock(mut self, now: fn() -> DateTime<Utc>) -> Self {
+        self.wall_clock = Some(now);
+        self
+    }
+
+    #[cfg(feature = "chrono")]
+    fn now(&self) -> DateTime<Utc> {
+        match self.wall_clock {
+            Some(now) => now(),
+            None if self.coarse_clock.is_some() => clock::now(),
+            None => Utc::now(),
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    fn anchor(&self) -> Anchor {
+        Anchor::new(self.now(), self.coarse_clock.is_some())
+    }
+
+    /// Returns whether the next tree should be processed.
+    fn sample(&self) -> bool {
+        sample::sampled(&self.sampled, self.sample_rate)
+    }
+
+    /// Set the version of [`Uuid`] generated for root spans that aren't given
+    /// one explicitly.
+    ///
+    /// By default, [`UuidVersion::V4`] is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor, UuidVersion};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .uuid_version(UuidVersion::V7)
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn uuid_version(mut self, version: UuidVersion) -> Self {
+        self.new_uuid = version.generator();
+        self
+    }
+
+    /// Set whether the [`Uuid`]s of root spans are only generated once they're
+    /// requested with [`id`], instead of when each root span is opened.
+    ///
+    /// Generating a random [`Uuid`] is a significant part of the cost of
+    /// collecting a small tree, so this is useful when IDs are only read for
+    /// some requests, or not at all. Trees whose ID was never requested get
+    /// one when they're closed, before they're sent to the processor, so
+    /// every processed tree still has its own. This moves the cost out of
+    /// opening root spans, and skips it for trees that are [sampled] out.
+    /// Live events written before the ID was requested have the nil [`Uuid`].
+    ///
+    /// By default, IDs are generated eagerly.
+    ///
+    /// [`id`]: crate::id
+    /// [sampled]: TreeLayer::sample_rate
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn lazy_uuids(mut self, lazy: bool) -> Self {
+        self.lazy_uuids = lazy;
+        self
+    }
+
+    /// Use the value of the `name` field of root spans as the [`Uuid`] of
+    /// their trees, so code that already records a request or trace ID gets
+    /// trees with the same ID without any changes.
+    ///
+    /// Values that are [`Uuid`]s are used as is, and any other value is
+    /// hashed into a [`Uuid`], so the same value always gives the same ID.
+    /// The field must be recorded when the span is created, and is still
+    /// displayed like any other field. Root spans without it get a generated
+    /// [`Uuid`] as usual, and an ID given with the `uuid` argument of
+    /// [`info_span!`] and friends takes precedence over it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .id_field("trace_id")
+    ///         .into_subscriber()
+    /// });
+    ///
+    /// tracing::info_span!("request", trace_id = "7a1f9c").in_scope(|| {
+    ///     tracing::info!("handled");
+    /// });
+    /// ```
+    ///
+    /// [`info_span!`]: tracing::info_span
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+    pub fn id_field(mut self, name: &'static str) -> Self {
+        self.id_field = Some(name);
+        self
+    }
+
+    /// Additionally write every event to `make_writer` as soon as it occurs,
+    /// while still collecting and processing trees as usual.
+    ///
+    /// Live events are written on a single line, prefixed with the [`Uuid`]
+    /// of the tree they belong to if the `uuid` feature is enabled.
+    ///
+    /// Events logged with the field `immediate = true` are written to this
+    /// writer even if live mode isn't enabled, in which case they're written
+    /// to stdout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .live(std::io::stderr)
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    pub fn live<W>(mut self, make_writer: W) -> Self
+    where
+        W: 'static + for<'a> MakeWriter<'a> + Send + Sync,
+    {
+        self.live = Some(BoxMakeWriter::new(make_writer));
+        self
+    }
+
+    /// Set the [`Pretty`] formatter that live and immediate events are written
+    /// with, like to change their icons or color them.
+    ///
+    /// Only the settings for a single line apply, like [`Pretty::with_ansi`]
+    /// and [`Pretty::with_icons`]. The [`builder`] passes on its
+    /// [`set_ansi`] and [`icons`] settings. By default, events are written
+    /// with [`Pretty::new`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// use tracing_forest::formatter::Icons;
+    ///
+    /// let _guard = tracing::subscriber::set_default({
+    ///     blocking(Pretty::new(), std::io::stdout)
+    ///         .into_layer()
+    ///         .live(std::io::stderr)
+    ///         .live_formatter(Pretty::new().with_icons(Icons::Text))
+    ///         .into_subscriber()
+    /// });
+    /// ```
+    ///
+    /// [`builder`]: crate::builder
+    /// [`set_ansi`]: crate::builder::LayerBuilder::set_ansi
+    /// [`icons`]: crate::builder::LayerBuilder::icons
+    pub fn live_formatter(mut self, formatter: Pretty) -> Self {
+        self.live_formatter = formatter;
         self
     }
 }
 
-impl<P: Processor> From<P> for TreeLayer<P> {
-    fn from(processor: P) -> Self {
-        TreeLayer::new(processor)
+/// A handle for raising the verbosity of a [`TreeLayer`] at runtime,
+/// returned by [`TreeLayer::verbosity_handle`].
+///
+/// Handles can be cloned and sent to other threads.
+#[derive(Debug, Clone)]
+pub struct VerbosityHandle {
+    verbose: Arc<AtomicBool>,
+}
+
+impl VerbosityHandle {
+    /// Set whether the layer collects spans and events up to its verbose
+    /// level instead of its maximum level.
+    pub fn set_verbose(&self, verbose: bool) {
+        self.verbose.store(verbose, Ordering::Relaxed);
+    }
+
+    /// Toggle whether verbosity is raised, returning whether it now is.
+    pub fn toggle(&self) -> bool {
+        !self.verbose.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Returns whether verbosity is raised.
+    pub fn is_verbose(&self) -> bool {
+        self.verbose.load(Ordering::Relaxed)
     }
 }
 
-/// A node of a log tree.
-#[derive(Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-pub struct Tree {
-    /// Shared fields associated with both spans and events.
-    #[cfg_attr(feature = "json", serde(flatten))]
-    pub attrs: TreeAttrs,
-    /// Fields specific to either a span or an event.
-    pub kind: TreeKind,
-}
-
-impl Tree {
-    /// Create a new `Tree`.
-    fn new(attrs: TreeAttrs, kind: impl Into<TreeKind>) -> Self {
-        Tree {
-            attrs,
-            kind: kind.into(),
+/// A handle for sending trees that were built by hand to the processor of
+/// the current [`TreeLayer`], as if they had been collected from `tracing`.
+///
+/// This lets tools that aggregate data out-of-band, like by reconstructing
+/// traces from another protocol, share the same processor as live trace data.
+/// Submitted trees are processed immediately, and aren't affected by
+/// [sampling].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::layer::TreeHandle;
+/// # use tracing_forest::tree::Tree;
+/// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+/// let _guard = tracing::subscriber::set_default({
+///     blocking(Pretty::new(), std::io::stdout)
+///         .into_layer()
+///         .into_subscriber()
+/// });
+///
+/// let handle = TreeHandle::current().expect("no TreeLayer in the current subscriber");
+///
+/// let mut tree = Tree::root("replayed request");
+/// tree.add_child(Tree::event(Level::INFO, "received").with_field("bytes", "512"));
+/// handle.submit(tree);
+/// ```
+///
+/// [sampling]: TreeLayer::sample_rate
+#[derive(Clone)]
+pub struct TreeHandle {
+    dispatch: Dispatch,
+}
+
+impl TreeHandle {
+    /// Returns a handle to the [`TreeLayer`] of the current default
+    /// subscriber, or `None` if it doesn't have one.
+    pub fn current() -> Option<Self> {
+        tracing::dispatcher::get_default(|dispatch| {
+            dispatch.downcast_ref::<Submit>().map(|_| TreeHandle {
+                dispatch: dispatch.clone(),
+            })
+        })
+    }
+
+    /// Send a tree to the processor of the [`TreeLayer`].
+    pub fn submit(&self, tree: Tree) {
+        if let Some(submit) = self.dispatch.downcast_ref::<Submit>() {
+            (submit.0)(&self.dispatch, tree);
         }
     }
 }
 
-/// The shared attributes of both spans and events within a [`Tree`].
-#[derive(Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-pub struct TreeAttrs {
-    /// The ID that this trace data is associated with.
-    #[cfg(feature = "uuid")]
-    pub uuid: Uuid,
-    /// When the trace data was collected.
-    #[cfg(feature = "chrono")]
-    #[cfg_attr(feature = "json", serde(serialize_with = "ser::timestamp"))]
-    pub timestamp: DateTime<Utc>,
-    /// Level the trace data was collected with.
-    #[cfg_attr(feature = "json", serde(serialize_with = "ser::level"))]
-    pub level: Level,
+/// Returns the closest span to `span` that's collected by a [`TreeLayer`],
+/// starting with `span` itself.
+pub(crate) fn collected<'a, R>(span: SpanRef<'a, R>) -> Option<SpanRef<'a, R>>
+where
+    R: LookupSpan<'a>,
+{
+    span.scope()
+        .find(|span| span.extensions().get::<TreeSpanOpened>().is_some())
 }
 
-/// The kind of log, either a [`TreeEvent`] or a [`TreeSpan`].
-#[derive(Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-pub enum TreeKind {
-    Event(TreeEvent),
-    Span(TreeSpan),
+/// Returns what `span` passes on to its descendants, from the closest span to
+/// it that's either collected by a [`TreeLayer`], or filtered out by its
+/// level.
+fn inherited<'a, R>(span: &SpanRef<'a, R>) -> Inherited
+where
+    R: LookupSpan<'a>,
+{
+    span.scope()
+        .find_map(|span| {
+            let extensions = span.extensions();
+            match extensions.get::<TreeSpanOpened>() {
+                Some(opened) => Some(Inherited {
+                    tag: opened.tag,
+                    skip: opened.skip,
+                }),
+                None => extensions
+                    .get::<TreeSpanFiltered>()
+                    .map(|filtered| filtered.0),
+            }
+        })
+        .unwrap_or_default()
 }
 
-/// Information unique to logged events.
-#[derive(Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-pub struct TreeEvent {
-    /// An optional tag that the event was collected with.
-    pub tag: Option<TagData>,
-    /// The message associated with the event.
-    pub message: Cow<'static, str>,
-    /// Key-value data.
-    #[cfg_attr(feature = "json", serde(serialize_with = "ser::fields"))]
-    pub fields: Fields,
+/// Runs `f` on the tree being built for `span`, or returns `None` if `span`
+/// isn't collected by a [`TreeLayer`].
+///
+/// Spans are only found in subscribers built on a [`Registry`].
+fn with_opened<R>(span: &tracing::Span, f: impl FnOnce(&mut TreeSpanOpened) -> R) -> Option<R> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(id)?;
+        let mut extensions = span.extensions_mut();
+        extensions.get_mut::<TreeSpanOpened>().map(f)
+    })
+    .flatten()
 }
 
-impl From<TreeEvent> for TreeKind {
-    fn from(event: TreeEvent) -> Self {
-        TreeKind::Event(event)
+/// Adds `tree` as the last child of `span`, or processes it with the
+/// current [`TreeLayer`] if `span` isn't collected by one.
+///
+/// The tree takes on the [`Uuid`] of `span`.
+#[cfg(feature = "json")]
+pub(crate) fn graft(span: &tracing::Span, tree: Tree) {
+    let mut tree = Some(tree);
+
+    with_opened(span, |opened| {
+        let tree = match tree.take() {
+            Some(tree) if !opened.skip => tree,
+            _ => return,
+        };
+
+        #[cfg(feature = "uuid")]
+        let tree = tree.with_uuid(opened.uuid());
+        match tree.kind {
+            TreeKind::Span(child) => opened.log_span(tree.attrs, child),
+            TreeKind::Event(child) => opened.log_event(tree.attrs, child),
+        }
+    });
+
+    if let (Some(tree), Some(handle)) = (tree, TreeHandle::current()) {
+        handle.submit(tree);
     }
 }
 
-impl From<TreeSpan> for TreeKind {
-    fn from(span: TreeSpan) -> Self {
-        TreeKind::Span(span)
+/// Adds a field to every event inside the current span, including events in
+/// child spans, without repeating it at each callsite.
+///
+/// Fields are added when the span closes, to events that don't already have a
+/// field with the same key, so fields of inner spans and of the events
+/// themselves take precedence, and inheriting the same key again replaces its
+/// value. Values are recorded with their `Debug`
+/// representation, like other fields. This has no effect outside of a span
+/// collected by a [`TreeLayer`].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::{blocking, formatter::json::Json, Processor};
+/// # tracing::subscriber::with_default(blocking(Json::new(true), std::io::stdout).into_layer().into_subscriber(), || {
+/// tracing::info_span!("request").in_scope(|| {
+///     tracing_forest::inherit("user_id", 42);
+///
+///     tracing::info_span!("db").in_scope(|| {
+///         tracing::info!("query");
+///     });
+///     tracing::info!(user_id = 7, "impersonating");
+/// });
+/// # });
+/// ```
+/// ```json
+/// {"level":"INFO","kind":{"Span":{"name":"request",...,"children":[
+///     {"level":"INFO","kind":{"Span":{"name":"db",...,"children":[
+///         {"level":"INFO","kind":{"Event":{"tag":null,"message":"query","fields":{"user_id":"42"}}}}
+///     ]}}},
+///     {"level":"INFO","kind":{"Event":{"tag":null,"message":"impersonating","fields":{"user_id":"7"}}}}
+/// ]}}}
+/// ```
+pub fn inherit(key: &'static str, value: impl fmt::Debug) {
+    let value = format!("{:?}", value);
+    with_opened(&tracing::Span::current(), |opened| {
+        match opened.inherited.iter_mut().find(|kv| kv.key == key) {
+            Some(kv) => kv.value = value,
+            None => opened.inherited.push(KeyValue::new(key, value)),
+        }
+    });
+}
+
+/// Runs `f` without collecting anything it logs into trees.
+///
+/// Spans opened and events logged inside of `f` are excluded, along with
+/// everything inside of those spans, like with the `skip_tree` argument of
+/// [`#[instrument]`][crate::instrument]. This is useful for wrapping chatty
+/// third-party calls that would otherwise flood a tree. Other layers of the
+/// subscriber still see the logs. Only the current thread is suppressed, so
+/// work that `f` hands off to other threads or tasks is still collected.
+///
+/// For futures, see [`suppress_future`]. To exclude a span wherever it's
+/// entered, record the [`SKIP_FIELD`] on it, or create it with
+/// [`skip_span!`][crate::skip_span].
+///
+/// # Examples
+///
+/// ```
+/// # #[tracing_forest::main]
+/// # fn main() {
+/// # fn handshake() {}
+/// tracing::info_span!("connect").in_scope(|| {
+///     let _ = tracing_forest::suppress(|| {
+///         tracing::debug!("exchanging 400 handshake frames...");
+///         handshake()
+///     });
+///     tracing::info!("connected");
+/// });
+/// # }
+/// ```
+/// ```log
+/// INFO     connect [ 7.12µs | 100.000% ]
+/// INFO     ┕━ 💬 [info]: connected
+/// ```
+pub fn suppress<R>(f: impl FnOnce() -> R) -> R {
+    let _suppressing = Suppressing::new();
+    f()
+}
+
+/// Polls `future` without collecting anything it logs into trees.
+///
+/// See [`suppress`] for more details.
+///
+/// # Examples
+///
+/// ```
+/// # async fn handshake() {}
+/// # async {
+/// tracing_forest::suppress_future(handshake()).await;
+/// # };
+/// ```
+pub fn suppress_future<F: Future>(future: F) -> Suppressed<F> {
+    Suppressed {
+        future: Box::pin(future),
     }
 }
 
-/// Information unique to logged spans.
+/// A future that doesn't collect anything it logs into trees, returned by
+/// [`suppress_future`].
 #[derive(Debug)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-pub struct TreeSpan {
-    /// The name of the span.
-    pub name: &'static str,
-    #[cfg_attr(
-        feature = "json",
-        serde(rename = "nanos_total", serialize_with = "ser::nanos")
-    )]
-    /// The duration that the span was entered for.
-    pub duration_total: Duration,
-    #[cfg_attr(
-        feature = "json",
-        serde(rename = "nanos_nested", serialize_with = "ser::nanos")
-    )]
-    /// The duration that child spans of this span were entered for.
-    pub duration_nested: Duration,
-    /// Spans and events that occurred inside of this span.
-    pub children: Vec<Tree>,
+pub struct Suppressed<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Suppressed<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<F::Output> {
+        let _suppressing = Suppressing::new();
+        self.future.as_mut().poll(cx)
+    }
+}
+
+std::thread_local! {
+    /// How many calls to [`suppress`] the current thread is inside of.
+    static SUPPRESSED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Keeps the current thread suppressed until it's dropped, even if the
+/// suppressed code panics.
+struct Suppressing(());
+
+impl Suppressing {
+    fn new() -> Self {
+        SUPPRESSED.with(|depth| depth.set(depth.get() + 1));
+        Suppressing(())
+    }
+}
+
+impl Drop for Suppressing {
+    fn drop(&mut self) {
+        SUPPRESSED.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Returns whether the current thread is inside of [`suppress`].
+fn suppressed() -> bool {
+    // Threads that are exiting can't be suppressed anymore
+    SUPPRESSED
+        .try_with(|depth| depth.get() > 0)
+        .unwrap_or(false)
+}
+
+/// Spawns `future` on the current tokio runtime as part of the current span's
+/// tree.
+///
+/// Tasks spawned with [`tokio::spawn`] don't inherit the span that spawned
+/// them, so their events and spans become separate root trees. This spawns
+/// the task inside of the current span instead, so they become its children
+/// and take on its [`Uuid`]. The task also keeps the current subscriber.
+///
+/// The span stays open until the task finishes, so its tree is only
+/// processed once both the span and the task are done. The task starts with
+/// a copy of the current task's [baggage].
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+///
+/// # Examples
+///
+/// ```
+/// # #[tracing_forest::main]
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # async fn refresh_cache() {}
+/// async {
+///     let refresh = tracing_forest::spawn_in_tree(async {
+///         tracing::info!("refreshing cache");
+///         refresh_cache().await;
+///     });
+///     tracing::info!("responded");
+///     refresh.await.unwrap();
+/// }
+/// .instrument(tracing::info_span!("request"))
+/// .await;
+/// # }
+/// # use tracing::Instrument;
+/// ```
+///
+/// [`Uuid`]: ::uuid::Uuid
+/// [baggage]: crate::baggage
+#[cfg(feature = "sync")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+pub fn spawn_in_tree<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    use tracing::instrument::WithSubscriber;
+
+    tokio::spawn(
+        crate::baggage::scope(future)
+            .instrument(tracing::Span::current())
+            .with_current_subscriber(),
+    )
+}
+
+impl fmt::Debug for TreeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TreeHandle").finish_non_exhaustive()
+    }
+}
+
+impl<P: Processor> From<P> for TreeLayer<P> {
+    fn from(processor: P) -> Self {
+        TreeLayer::new(processor)
+    }
+}
+
+#[cfg(feature = "tracing-error")]
+impl SpanTraceFrame {
+    fn collect(span_trace: &tracing_error::SpanTrace) -> Vec<Self> {
+        let mut frames = Vec::new();
+        span_trace.with_spans(|metadata, fields| {
+            frames.push(SpanTraceFrame {
+                target: metadata.target(),
+                name: metadata.name(),
+                fields: fields.to_string(),
+                file: metadata.file(),
+                line: metadata.line(),
+            });
+            true
+        });
+        frames
+    }
+}
+
+/// The tag and skip flag a span passes on to its descendants.
+#[derive(Clone, Copy, Default)]
+struct Inherited {
+    tag: Option<TagData>,
+    skip: bool,
+}
+
+/// Marks a span that isn't collected because of its level, so that its
+/// descendants still inherit its tag, and are still skipped with it.
+struct TreeSpanFiltered(Inherited);
+
+impl TreeSpanFiltered {
+    fn open(attrs: &Attributes, tag_parser: &TagParser, parent: Inherited) -> Self {
+        struct FilteredVisitor {
+            tag: Option<TagData>,
+            from_field: fn(u64) -> TagData,
+            skip: bool,
+        }
+
+        impl Visit for FilteredVisitor {
+            fn record_bool(&mut self, field: &Field, value: bool) {
+                if let SKIP_KEY | SKIP_FIELD = field.name() {
+                    self.skip = value;
+                }
+            }
+
+            fn record_u64(&mut self, field: &Field, value: u64) {
+                if field.name() == TAG_KEY {
+                    self.tag = Some((self.from_field)(value));
+                }
+            }
+
+            fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+        }
+
+        let mut visitor = FilteredVisitor {
+            tag: None,
+            from_field: tag_parser.from_field,
+            skip: false,
+        };
+        attrs.record(&mut visitor);
+
+        TreeSpanFiltered(Inherited {
+            tag: visitor.tag.or(parent.tag),
+            skip: visitor.skip || parent.skip,
+        })
+    }
 }
 
 pub(crate) struct TreeSpanOpened {
     attrs: TreeAttrs,
     span: TreeSpan,
     start: Instant,
+    tag: Option<TagData>,
+    skip: bool,
+    inherited: Fields,
+    #[cfg(feature = "chrono")]
+    anchor: Anchor,
+    /// Generates the [`Uuid`] of a root span once it's requested.
+    #[cfg(feature = "uuid")]
+    lazy_uuid: Option<fn() -> Uuid>,
+}
+
+/// The wall clock reading of the root of a tree, and the monotonic time it was
+/// read at, which the timestamps of its descendants are derived from.
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy)]
+struct Anchor {
+    timestamp: DateTime<Utc>,
+    monotonic: Duration,
+    /// Whether the monotonic time is read from the coarse clock.
+    coarse: bool,
+}
+
+#[cfg(feature = "chrono")]
+impl Anchor {
+    fn new(timestamp: DateTime<Utc>, coarse: bool) -> Self {
+        Anchor {
+            timestamp,
+            monotonic: clock::monotonic(coarse),
+            coarse,
+        }
+    }
+
+    /// Returns the timestamp of the anchor plus the monotonic time since it
+    /// was taken, which never goes backwards, unlike the wall clock.
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = clock::monotonic(self.coarse).saturating_sub(self.monotonic);
+        let elapsed =
+            chrono::Duration::from_std(elapsed).unwrap_or_else(|_| chrono::Duration::zero());
+        self.timestamp + elapsed
+    }
 }
 
 impl TreeSpanOpened {
-    fn open<S>(attrs: &Attributes, ctx: &Context<S>) -> Self
+    fn open<S>(
+        attrs: &Attributes,
+        ctx: &Context<S>,
+        tag_parser: &TagParser,
+        #[cfg(feature = "chrono")] anchor: impl FnOnce() -> Anchor,
+        #[cfg(feature = "uuid")] new_uuid: fn() -> Uuid,
+        #[cfg(feature = "uuid")] lazy_uuids: bool,
+        #[cfg(feature = "uuid")] id_field: Option<&'static str>,
+    ) -> Self
     where
         S: Subscriber + for<'a> LookupSpan<'a>,
     {
-        #[cfg(not(feature = "uuid"))]
-        let _ = ctx;
-
         struct SpanVisitor {
+            #[cfg(feature = "uuid")]
+            uuid: Option<Uuid>,
             #[cfg(feature = "uuid")]
             uuid_lsb: Option<u64>,
             #[cfg(feature = "uuid")]
             uuid_msb: Option<u64>,
+            #[cfg(feature = "uuid")]
+            id_field: Option<&'static str>,
+            #[cfg(feature = "uuid")]
+            id: Option<Uuid>,
+            tag: Option<TagData>,
+            from_field: fn(u64) -> TagData,
+            skip: bool,
+            fields: Fields,
         }
 
         impl SpanVisitor {
-            fn new() -> Self {
+            fn new(from_field: fn(u64) -> TagData) -> Self {
                 SpanVisitor {
+                    #[cfg(feature = "uuid")]
+                    uuid: None,
                     #[cfg(feature = "uuid")]
                     uuid_lsb: None,
                     #[cfg(feature = "uuid")]
                     uuid_msb: None,
+                    #[cfg(feature = "uuid")]
+                    id_field: None,
+                    #[cfg(feature = "uuid")]
+                    id: None,
+                    tag: None,
+                    from_field,
+                    skip: false,
+                    fields: Fields::new(),
                 }
             }
 
             #[cfg(feature = "uuid")]
             fn get_uuid(&self) -> Option<Uuid> {
+                if self.uuid.is_some() {
+                    return self.uuid;
+                }
                 match (self.uuid_msb, self.uuid_lsb) {
                     (Some(msb), Some(lsb)) => Some(crate::uuid::from_u64_pair(msb, lsb)),
                     (None, None) => None,
@@ -234,53 +1124,157 @@ impl TreeSpanOpened {
         }
 
         impl Visit for SpanVisitor {
+            fn record_bool(&mut self, field: &Field, value: bool) {
+                match field.name() {
+                    SKIP_KEY | SKIP_FIELD => self.skip = value,
+                    _ => self.record_debug(field, &value),
+                }
+            }
+
             fn record_u64(&mut self, field: &Field, value: u64) {
                 match field.name() {
                     #[cfg(feature = "uuid")]
                     "__uuid_lsb" => self.uuid_lsb = Some(value),
                     #[cfg(feature = "uuid")]
                     "__uuid_msb" => self.uuid_msb = Some(value),
+                    TAG_KEY => self.tag = Some((self.from_field)(value)),
+                    _ => self.record_debug(field, &value),
+                }
+            }
+
+            fn record_u128(&mut self, field: &Field, value: u128) {
+                match field.name() {
+                    #[cfg(feature = "uuid")]
+                    "__uuid" => self.uuid = Some(Uuid::from_u128(value)),
                     _ => self.record_debug(field, &value),
                 }
             }
 
-            fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {
-                // do nothing
+            fn record_str(&mut self, field: &Field, value: &str) {
+                #[cfg(feature = "uuid")]
+                if self.id_field == Some(field.name()) {
+                    // Strings are hashed as they are, without the quotes and
+                    // escapes that `Debug` adds
+                    self.id = Some(crate::uuid::from_field_value(value));
+                    return record_span_field(&mut self.fields, field, &value);
+                }
+                self.record_debug(field, &value)
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                record_span_field(&mut self.fields, field, value);
+                #[cfg(feature = "uuid")]
+                if self.id_field == Some(field.name()) {
+                    let value = format!("{:?}", value);
+                    self.id = Some(crate::uuid::from_field_value(&value));
+                }
+            }
+
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+                insert_span_field(&mut self.fields, structured_field(field, value));
             }
         }
 
-        let mut visitor = SpanVisitor::new();
+        let mut visitor = SpanVisitor::new(tag_parser.from_field);
+        #[cfg(feature = "uuid")]
+        {
+            visitor.id_field = id_field;
+        }
 
         attrs.record(&mut visitor);
 
+        // Tags and skipping are inherited from the parent span, even if it
+        // isn't collected
+        let inherited = ctx
+            .lookup_current()
+            .as_ref()
+            .map(inherited)
+            .unwrap_or_default();
+        let parent = ctx.lookup_current().and_then(collected);
+        let parent = parent.as_ref().map(|parent| parent.extensions());
+        let parent = parent.as_ref().map(|extensions| {
+            extensions
+                .get::<TreeSpanOpened>()
+                .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
+        });
+
         #[cfg(feature = "uuid")]
-        let uuid = match visitor.get_uuid() {
-            Some(uuid) => uuid,
-            None => match ctx.lookup_current() {
-                Some(parent) => parent
-                    .extensions()
-                    .get::<TreeSpanOpened>()
-                    .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
-                    .uuid(),
-                None => Uuid::new_v4(),
+        let (uuid, lazy_uuid) = match visitor.get_uuid() {
+            Some(uuid) => (uuid, None),
+            None => match (parent, visitor.id) {
+                (Some(parent), _) => (parent.uuid(), None),
+                (None, Some(id)) => (id, None),
+                (None, None) if lazy_uuids => (Uuid::nil(), Some(new_uuid)),
+                (None, None) => (new_uuid(), None),
             },
         };
 
+        // Only roots read the wall clock, see `TreeLayer::wall_clock`
+        #[cfg(feature = "chrono")]
+        let (anchor, timestamp) = match parent {
+            Some(parent) => (parent.anchor, parent.anchor.now()),
+            None => {
+                let anchor = anchor();
+                (anchor, anchor.timestamp)
+            }
+        };
+
         TreeSpanOpened {
             attrs: TreeAttrs {
                 #[cfg(feature = "chrono")]
-                timestamp: Utc::now(),
+                timestamp,
                 #[cfg(feature = "uuid")]
                 uuid,
                 level: *attrs.metadata().level(),
             },
             span: TreeSpan {
                 name: attrs.metadata().name(),
+                fields: visitor.fields,
+                #[cfg(feature = "arena")]
+                children: crate::arena::children(),
+                #[cfg(not(feature = "arena"))]
                 children: Vec::new(),
                 duration_nested: Duration::ZERO,
                 duration_total: Duration::ZERO,
+                failed: false,
             },
             start: Instant::now(),
+            tag: visitor.tag.or(inherited.tag),
+            skip: visitor.skip || inherited.skip,
+            inherited: Fields::new(),
+            #[cfg(feature = "chrono")]
+            anchor,
+            #[cfg(feature = "uuid")]
+            lazy_uuid,
+        }
+    }
+
+    fn record(&mut self, values: &Record, rules: &FieldRules) {
+        struct RecordVisitor<'a>(&'a mut Fields);
+
+        impl Visit for RecordVisitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                record_span_field(self.0, field, value);
+            }
+
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+                insert_span_field(self.0, structured_field(field, value));
+            }
+        }
+
+        if rules.is_empty() {
+            values.record(&mut RecordVisitor(&mut self.span.fields));
+            self.span.failed |= tree::has_error(&self.span.fields);
+            return;
+        }
+        let mut recorded = Fields::new();
+        values.record(&mut RecordVisitor(&mut recorded));
+        self.span.failed |= tree::has_error(&recorded);
+        rules.apply(&mut recorded);
+        for kv in recorded {
+            insert_span_field(&mut self.span.fields, kv);
         }
     }
 
@@ -292,7 +1286,14 @@ impl TreeSpanOpened {
         self.span.duration_total += self.start.elapsed();
     }
 
-    fn close(self) -> (TreeAttrs, TreeSpan) {
+    fn close(mut self) -> (TreeAttrs, TreeSpan) {
+        if !self.inherited.is_empty() {
+            for child in self.span.children.iter_mut() {
+                inherit_fields(child, &self.inherited);
+            }
+        }
+        // Children are closed first, so their failures are already known
+        self.span.update_failed();
         (self.attrs, self.span)
     }
 
@@ -315,6 +1316,214 @@ impl TreeSpanOpened {
     pub fn uuid(&self) -> Uuid {
         self.attrs.uuid
     }
+
+    /// Returns the value of the field named `key`.
+    #[cfg(feature = "uuid")]
+    pub(crate) fn field(&self, key: &str) -> Option<&str> {
+        self.span
+            .fields
+            .iter()
+            .find(|kv| kv.key == key)
+            .map(|kv| kv.value.as_str())
+    }
+
+    /// Returns the [`Uuid`] of the span, generating it first if it's lazy.
+    #[cfg(feature = "uuid")]
+    pub fn generate_uuid(&mut self) -> Uuid {
+        if let Some(new_uuid) = self.lazy_uuid.take() {
+            self.attrs.uuid = new_uuid();
+        }
+        self.attrs.uuid
+    }
+
+    /// Set the [`Uuid`] of the span, if it doesn't have one yet.
+    #[cfg(feature = "uuid")]
+    pub fn fill_uuid(&mut self, uuid: Uuid) {
+        if self.attrs.uuid.is_nil() {
+            self.attrs.uuid = uuid;
+        }
+    }
+}
+
+/// Rules that rename and drop the fields of spans and events as they're
+/// collected, so output conforms to a logging schema without changing every
+/// callsite.
+///
+/// Rules are checked in the order they're added, and the first one matching
+/// a field applies. Fields inherited with [`inherit`] and the message of an
+/// event aren't affected.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::layer::FieldRules;
+/// let _guard = tracing::subscriber::set_default({
+///     tracing_forest::builder()
+///         .field_rules(
+///             FieldRules::new()
+///                 .rename("err", "error.message")
+///                 .drop("private.*"),
+///         )
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+///
+/// // Logged with the field `error.message`, and without `private.email`
+/// tracing::warn!(err = "timed out", private.email = "user@example.com", "retrying");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FieldRules {
+    rules: Vec<FieldRule>,
+}
+
+#[derive(Debug, Clone)]
+enum FieldRule {
+    Rename(&'static str, &'static str),
+    Drop(&'static str),
+}
+
+impl FieldRules {
+    /// Construct a new [`FieldRules`] without any rules.
+    pub fn new() -> Self {
+        FieldRules::default()
+    }
+
+    /// Rename fields with the key `from` to `to`.
+    pub fn rename(mut self, from: &'static str, to: &'static str) -> Self {
+        self.rules.push(FieldRule::Rename(from, to));
+        self
+    }
+
+    /// Drop fields whose key matches `pattern`, where `*` matches any
+    /// sequence of characters and `?` matches any single character.
+    pub fn drop(mut self, pattern: &'static str) -> Self {
+        self.rules.push(FieldRule::Drop(pattern));
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the key that a field with the key `key` is kept under, or
+    /// `None` if it's dropped.
+    fn key(&self, key: &'static str) -> Option<&'static str> {
+        for rule in self.rules.iter() {
+            match *rule {
+                FieldRule::Rename(from, to) if from == key => return Some(to),
+                FieldRule::Drop(pattern) if crate::capture::glob(pattern, key) => return None,
+                _ => {}
+            }
+        }
+        Some(key)
+    }
+
+    fn apply(&self, fields: &mut Fields) {
+        if self.is_empty() {
+            return;
+        }
+        let mut i = 0;
+        while i < fields.len() {
+            match self.key(fields[i].key) {
+                Some(key) => {
+                    fields[i].key = key;
+                    i += 1;
+                }
+                None => {
+                    fields.remove(i);
+                }
+            }
+        }
+    }
+}
+
+/// Records a field of a span, replacing an earlier value with the same key.
+///
+/// The reserved fields used to configure spans aren't recorded.
+fn record_span_field(fields: &mut Fields, field: &Field, value: &dyn fmt::Debug) {
+    insert_span_field(fields, KeyValue::new(field.name(), debug_string(value)));
+}
+
+/// Formats the value of a field, into a string from the [arena] if it's
+/// enabled.
+///
+/// [arena]: crate::arena
+fn debug_string(value: &dyn fmt::Debug) -> String {
+    #[cfg(feature = "arena")]
+    {
+        use std::fmt::Write as _;
+
+        let mut string = crate::arena::string();
+        let _ = write!(string, "{:?}", value);
+        string
+    }
+    #[cfg(not(feature = "arena"))]
+    format!("{:?}", value)
+}
+
+fn insert_span_field(fields: &mut Fields, field: KeyValue) {
+    if let "__uuid_lsb" | "__uuid_msb" | TAG_KEY | SKIP_KEY | SKIP_FIELD = field.key {
+        return;
+    }
+    match fields.iter_mut().find(|kv| kv.key == field.key) {
+        Some(kv) => *kv = field,
+        None => fields.push(field),
+    }
+}
+
+/// Captures a field recorded with [`valuable`], keeping its structure for
+/// JSON output.
+///
+/// [`valuable`]: https://docs.rs/valuable
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+fn structured_field(field: &Field, value: valuable::Value<'_>) -> KeyValue {
+    let mut kv = KeyValue::new(field.name(), format!("{:?}", value));
+    kv.structured = serde_json::to_value(valuable_serde::Serializable::new(value)).ok();
+    kv
+}
+
+/// Sets the [`Uuid`] of every span and event in `tree` that was collected
+/// before the lazy [`Uuid`] of its root was generated.
+#[cfg(feature = "uuid")]
+fn fill_uuid(tree: &mut Tree, uuid: Uuid) {
+    if tree.attrs.uuid.is_nil() {
+        tree.attrs.uuid = uuid;
+    }
+    if let TreeKind::Span(span) = &mut tree.kind {
+        for child in span.children.iter_mut() {
+            fill_uuid(child, uuid);
+        }
+    }
+}
+
+/// Removes the `TRACE` and `DEBUG` events from `span` and its children.
+fn drop_verbose_events(span: &mut TreeSpan) {
+    span.children.retain_mut(|child| match &mut child.kind {
+        TreeKind::Event(_) => child.attrs.level < Level::DEBUG,
+        TreeKind::Span(span) => {
+            drop_verbose_events(span);
+            true
+        }
+    });
+}
+
+/// Adds the inherited fields to every event in `tree` that doesn't already
+/// have a field with the same key.
+fn inherit_fields(tree: &mut Tree, inherited: &Fields) {
+    match &mut tree.kind {
+        TreeKind::Event(event) => {
+            for kv in inherited.iter() {
+                if !event.fields.iter().any(|field| field.key == kv.key) {
+                    event.fields.push(kv.clone());
+                }
+            }
+        }
+        TreeKind::Span(span) => {
+            for child in span.children.iter_mut() {
+                inherit_fields(child, inherited);
+            }
+        }
+    }
 }
 
 impl<P: Processor> TreeLayer<P> {
@@ -323,18 +1532,24 @@ impl<P: Processor> TreeLayer<P> {
             immediate: bool,
             tag: Option<TagData>,
             message: Cow<'static, str>,
+            template: Option<Cow<'static, str>>,
             fields: Fields,
-            tag_parser: TagParser,
+            from_field: fn(u64) -> TagData,
+            #[cfg(feature = "tracing-error")]
+            span_trace: Option<Vec<SpanTraceFrame>>,
         }
 
         impl EventVisitor {
-            fn new(tag_parser: TagParser) -> Self {
+            fn new(from_field: fn(u64) -> TagData) -> Self {
                 EventVisitor {
                     immediate: false,
                     tag: None,
                     message: Cow::from("<no message>"),
+                    template: None,
                     fields: Fields::new(),
-                    tag_parser,
+                    from_field,
+                    #[cfg(feature = "tracing-error")]
+                    span_trace: None,
                 }
             }
         }
@@ -353,44 +1568,111 @@ impl<P: Processor> TreeLayer<P> {
                         if self.tag.is_some() {
                             fail::multiple_tags_on_event();
                         }
-                        self.tag = Some((self.tag_parser)(value));
+                        self.tag = Some((self.from_field)(value));
                     }
                     _ => self.record_debug(field, &value),
                 }
             }
 
+            fn record_str(&mut self, field: &Field, value: &str) {
+                match field.name() {
+                    TEMPLATE_FIELD => self.template = Some(Cow::from(value.to_string())),
+                    _ => self.record_debug(field, &value),
+                }
+            }
+
             fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
-                let value = format!("{:?}", value);
+                let value = debug_string(value);
                 match field.name() {
                     // Only the first "message" is the message
                     "message" if matches!(self.message, Cow::Borrowed(_)) => {
                         self.message = Cow::from(value)
                     }
-                    key => self.fields.push(KeyValue { key, value }),
+                    key => self.fields.push(KeyValue::new(key, value)),
+                }
+            }
+
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+                self.fields.push(structured_field(field, value));
+            }
+
+            #[cfg(feature = "tracing-error")]
+            fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+                use tracing_error::ExtractSpanTrace;
+
+                // The span trace may be attached to any error in the chain
+                let mut error = Some(value);
+                while let (None, Some(err)) = (&self.span_trace, error) {
+                    self.span_trace = err.span_trace().map(SpanTraceFrame::collect);
+                    error = err.source();
                 }
+                self.record_debug(field, &format_args!("{}", value));
             }
         }
 
-        let mut visitor = EventVisitor::new(self.tag_parser);
+        let mut visitor = EventVisitor::new(self.tag_parser.from_field);
 
         event.record(&mut visitor);
+        #[cfg(feature = "sync")]
+        crate::baggage::attach(&mut visitor.fields);
+        let has_error = tree::has_error(&visitor.fields);
+        self.field_rules.apply(&mut visitor.fields);
 
         let tree_event = TreeEvent {
-            tag: visitor.tag,
+            tag: visitor.tag.or_else(|| self.tag_parser.parse_event(event)),
             message: visitor.message,
+            template: visitor.template,
             fields: visitor.fields,
+            target: event.metadata().target(),
+            file: event.metadata().file(),
+            line: event.metadata().line(),
+            metadata: Some(event.metadata().into()),
+            #[cfg(feature = "tracing-error")]
+            span_trace: visitor.span_trace,
+            has_error,
         };
 
         let tree_attrs = TreeAttrs {
             #[cfg(feature = "uuid")]
             uuid: DEFAULT_EVENT_UUID,
+            // Set by `on_event`, once it's known whether the event is a root
             #[cfg(feature = "chrono")]
-            timestamp: Utc::now(),
+            timestamp: DateTime::UNIX_EPOCH,
             level: *event.metadata().level(),
         };
 
         (tree_attrs, tree_event, visitor.immediate)
     }
+
+    /// Returns whether spans and events at `level` are collected, at the
+    /// current verbosity.
+    fn collects(&self, level: &Level) -> bool {
+        let max_level = match self.verbose.load(Ordering::Relaxed) {
+            true => self.verbose_level,
+            false => self.max_level,
+        };
+        max_level >= *level
+    }
+
+    fn write_live(&self, attrs: &TreeAttrs, event: &TreeEvent) {
+        let mut buf = Vec::with_capacity(0);
+
+        let formatted = self
+            .live_formatter
+            .format_flat_event(attrs, event, &mut buf);
+        if let Err(err) = formatted {
+            return error::report(ForestError::Format(err));
+        }
+
+        let written = match &self.live {
+            Some(make_writer) => make_writer.make_writer().write_all(&buf[..]),
+            None => std::io::stdout().write_all(&buf[..]),
+        };
+        if let Err(err) = written {
+            error::report(ForestError::Write(err));
+        }
+    }
 }
 
 impl<P, S> Layer<S> for TreeLayer<P>
@@ -410,70 +1692,167 @@ where
     fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
         let span = ctx.span(id).unwrap_or_else(fail::span_not_in_context);
 
-        let opened = TreeSpanOpened::open(attrs, &ctx);
+        if !self.collects(attrs.metadata().level()) {
+            let parent = ctx
+                .lookup_current()
+                .as_ref()
+                .map(inherited)
+                .unwrap_or_default();
+            let mut filtered = TreeSpanFiltered::open(attrs, &self.tag_parser, parent);
+            filtered.0.skip |= suppressed();
+            span.extensions_mut().insert(filtered);
+            return;
+        }
+
+        let mut opened = TreeSpanOpened::open(
+            attrs,
+            &ctx,
+            &self.tag_parser,
+            #[cfg(feature = "chrono")]
+            || self.anchor(),
+            #[cfg(feature = "uuid")]
+            self.new_uuid,
+            #[cfg(feature = "uuid")]
+            self.lazy_uuids,
+            #[cfg(feature = "uuid")]
+            self.id_field,
+        );
+
+        opened.span.failed = tree::has_error(&opened.span.fields);
+        self.field_rules.apply(&mut opened.span.fields);
+        // Spans opened while suppressed are skipped wherever they're entered
+        opened.skip |= suppressed();
 
         let mut extensions = span.extensions_mut();
 
         extensions.insert(opened);
     }
 
-    fn on_record(&self, _span: &Id, _values: &Record, _ctx: Context<S>) {}
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        let span = ctx.span(id).unwrap_or_else(fail::span_not_in_context);
+        if let Some(opened) = span.extensions_mut().get_mut::<TreeSpanOpened>() {
+            opened.record(values, &self.field_rules);
+        };
+    }
 
     fn on_follows_from(&self, _span: &Id, _follows: &Id, _ctx: Context<S>) {}
 
     fn on_event(&self, event: &Event, ctx: Context<S>) {
-        let (tree_attrs, tree_event, immediate) = self.parse_event(event);
+        if !self.collects(event.metadata().level()) || suppressed() {
+            return;
+        }
+
+        // Events inside skipped spans aren't parsed at all
+        let span = ctx.event_span(event);
+        let inherited = span.as_ref().map(inherited).unwrap_or_default();
+        if inherited.skip {
+            return;
+        }
 
-        if immediate {
-            todo!("print to console as a blocking operation");
+        #[allow(unused_mut)]
+        let (mut tree_attrs, mut tree_event, immediate) = self.parse_event(event);
+        if tree_event.tag.is_none() {
+            tree_event.tag = inherited.tag;
         }
 
-        match ctx.event_span(event) {
+        let parent = span.and_then(collected);
+        #[cfg(feature = "chrono")]
+        if parent.is_none() {
+            tree_attrs.timestamp = self.now();
+        }
+
+        if let Some(parent) = &parent {
+            let extensions = parent.extensions();
+            let opened = extensions
+                .get::<TreeSpanOpened>()
+                .unwrap_or_else(fail::tree_span_opened_not_in_extensions);
+
+            #[cfg(feature = "chrono")]
+            {
+                tree_attrs.timestamp = opened.anchor.now();
+            }
+            #[cfg(feature = "uuid")]
+            {
+                tree_attrs.uuid = opened.uuid();
+            }
+        }
+
+        if immediate || self.live.is_some() {
+            self.write_live(&tree_attrs, &tree_event);
+        }
+
+        match parent {
             Some(parent) => parent
                 .extensions_mut()
                 .get_mut::<TreeSpanOpened>()
                 .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
                 .log_event(tree_attrs, tree_event),
-            None => self.processor.process(Tree::new(tree_attrs, tree_event)),
+            None => {
+                if self.sample() {
+                    self.process(Tree::new(tree_attrs, tree_event))
+                }
+            }
         }
     }
 
     fn on_enter(&self, id: &Id, ctx: Context<S>) {
-        ctx.span(id)
-            .unwrap_or_else(fail::span_not_in_context)
-            .extensions_mut()
-            .get_mut::<TreeSpanOpened>()
-            .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
-            .enter();
+        let span = ctx.span(id).unwrap_or_else(fail::span_not_in_context);
+        if let Some(opened) = span.extensions_mut().get_mut::<TreeSpanOpened>() {
+            opened.enter();
+        };
     }
 
     fn on_exit(&self, id: &Id, ctx: Context<S>) {
-        ctx.span(id)
-            .unwrap_or_else(fail::span_not_in_context)
-            .extensions_mut()
-            .get_mut::<TreeSpanOpened>()
-            .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
-            .exit();
+        let span = ctx.span(id).unwrap_or_else(fail::span_not_in_context);
+        if let Some(opened) = span.extensions_mut().get_mut::<TreeSpanOpened>() {
+            opened.exit();
+        };
     }
 
     fn on_close(&self, id: Id, ctx: Context<S>) {
         let span = ctx.span(&id).unwrap_or_else(fail::span_not_in_context);
 
-        let (tree_attrs, tree_span) = span
-            .extensions_mut()
-            .remove::<TreeSpanOpened>()
-            .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
-            .close();
+        // Spans above the max level when they were opened aren't collected
+        let opened = match span.extensions_mut().remove::<TreeSpanOpened>() {
+            Some(opened) if !opened.skip => opened,
+            _ => return,
+        };
+        #[cfg(feature = "uuid")]
+        let lazy_uuid = opened.lazy_uuid;
+        let (tree_attrs, tree_span) = opened.close();
 
-        match span.parent() {
+        match span.parent().and_then(collected) {
             Some(parent) => parent
                 .extensions_mut()
                 .get_mut::<TreeSpanOpened>()
                 .unwrap_or_else(fail::tree_span_opened_not_in_extensions)
                 .log_span(tree_attrs, tree_span),
-            None => self.processor.process(Tree::new(tree_attrs, tree_span)),
+            None => {
+                if self.sample() {
+                    #[allow(unused_mut)]
+                    let mut tree = Tree::new(tree_attrs, tree_span);
+                    // IDs that were never requested are generated on the way
+                    // out, so trees that are sampled out never pay for them
+                    #[cfg(feature = "uuid")]
+                    if self.lazy_uuids {
+                        let uuid = lazy_uuid.map_or(tree.attrs.uuid, |new_uuid| new_uuid());
+                        fill_uuid(&mut tree, uuid);
+                    }
+                    self.process(tree)
+                }
+            }
         }
     }
 
     fn on_id_change(&self, _old: &Id, _new: &Id, _ctx: Context<S>) {}
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const Self as *const ())
+        } else if id == TypeId::of::<Submit>() {
+            Some(&self.submit as *const Submit as *const ())
+        } else {
+            None
+        }
+    }
 }
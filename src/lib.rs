@@ -40,6 +40,14 @@
 //! }
 //! ```
 //!
+//! Without the attribute, call one of the `init` functions, like
+//! [`init_pretty`], [`init_pretty_stderr`], `init_json`, or [`init_test`],
+//! and keep the returned guard in scope:
+//! ```
+//! let _guard = tracing_forest::init_pretty();
+//! tracing::info!("Hello, world!");
+//! ```
+//!
 //! # Contextual Coherence in action
 //!
 //! This example contains two counters, one for evens and another for odds.
@@ -157,7 +165,8 @@
 //! To retreive the [`Uuid`] of the current span, use the [`id`] function.
 //! To set the [`Uuid`] of a new span, use [`uuid_span!`], or the shorthand
 //! versions, [`uuid_trace_span!`], [`uuid_debug_span!`], [`uuid_info_span!`],
-//! [`uuid_warn_span!`], or [`uuid_error_span!`].
+//! [`uuid_warn_span!`], or [`uuid_error_span!`]. To continue the tree of
+//! another service, see the [`propagation`] module.
 //!
 //! ## Example
 //!
@@ -192,45 +201,147 @@
 //!
 //! `tracing-forest` uses feature flags to reduce dependencies in your code.
 //!
+//! * `std` (default): Enables collecting and processing trees. Without it,
+//!   only the [`tree`] data model and [tag] types are available, which only
+//!   require `alloc`.
 //! * `full`: Enables all features listed below.
 //! * `uuid`: Enables spans to carry operation IDs.
 //! * `chrono`: Enables timestamps on trace data.
-//! * `smallvec`: Enables some performance optimizations.
-//! * `sync`: Enables the [`AsyncProcessor`] type.
-//! * `json`: Enables JSON formatting for logs.
+//! * `smallvec`: Enables some performance optimizations, like storing up to
+//!   3 fields of each span and event without allocating.
+//! * `smallvec-large`: Stores up to 8 fields of each span and event without
+//!   allocating, at the cost of larger trees. This isn't part of `full`.
+//! * `arena`: Reuses the allocations of processed trees to collect the next
+//!   ones, through a bounded [arena]. This isn't part of `full`.
+//! * `sync`: Enables the [`AsyncProcessor`] type and task-local [`baggage`].
+//! * `json`: Enables JSON formatting for logs, [grafting] JSON trees from
+//!   child processes, and [replaying] recorded trees.
+//! * `config`: Enables loading a [`ForestConfig`] from a file.
+//! * `env-filter`: Enables filtering [captured] trees with an `EnvFilter`.
+//! * `valuable`: Enables writing fields recorded with [`valuable`] as nested
+//!   JSON arrays and maps. Like `tracing`'s own support, this also requires
+//!   building with `RUSTFLAGS="--cfg tracing_unstable"`, and isn't part of
+//!   `full`.
+//! * `indicatif`: Enables the [`ProgressWriter`] type, for writing without
+//!   corrupting progress bars.
+//! * `signals`: Enables running [actions on Unix signals], like dumping
+//!   recent trees or reopening log files. It has no effect on other
+//!   platforms.
+//! * `json-schema`: Enables generating a [JSON Schema] for versioned JSON
+//!   output.
+//! * `cbor`: Enables [CBOR formatting] for logs, and replaying recorded CBOR
+//!   trees.
+//! * `tracing-error`: Enables rendering [`SpanTrace`]s attached to errors.
+//! * `sqlite`: Enables the [`SqliteProcessor`] type.
+//! * `postgres` and `clickhouse`: Enable the [`Postgres`] and [`ClickHouse`]
+//!   sinks for [`BulkProcessor`]s.
+//! * `seq`: Enables the [`Seq`] sink for [`BulkProcessor`]s.
+//! * `honeycomb`: Enables the [`Honeycomb`] transport for sending trees to
+//!   Honeycomb.
+//! * `datadog`: Enables the [`Datadog`] transport for sending trees to
+//!   Datadog as traces or logs.
 //! * `derive`: Enables [`#[derive(Tag)]`][derive] for making custom [tag] types.
-//! * `attributes`: Enables the [`#[tracing_forest::test]`][attr_test] and
-//! [`#[tracing_forest::main]`][attr_main] attributes.
+//! * `attributes`: Enables the [`#[tracing_forest::test]`][attr_test],
+//!   [`#[tracing_forest::main]`][attr_main], and
+//!   [`#[tracing_forest::instrument]`][attr_instrument] attributes.
 //!
 //! [`Uuid`]: ::uuid::Uuid
+//! [arena]: crate::arena
 //! [`AsyncProcessor`]: crate::processor::sync::AsyncProcessor
+//! [`baggage`]: crate::baggage
+//! [`SqliteProcessor`]: crate::processor::sqlite::SqliteProcessor
+//! [JSON Schema]: crate::formatter::json::schema::json_schema
+//! [CBOR formatting]: crate::formatter::cbor::Cbor
+//! [captured]: crate::capture::Capture::set_filter
+//! [grafting]: crate::bridge
+//! [replaying]: crate::replay()
+//! [`ForestConfig`]: crate::config::ForestConfig
+//! [`ProgressWriter`]: crate::writer::ProgressWriter
+//! [actions on Unix signals]: crate::signals::Signals
+//! [`Postgres`]: crate::processor::bulk::Postgres
+//! [`ClickHouse`]: crate::processor::bulk::ClickHouse
+//! [`Seq`]: crate::processor::bulk::Seq
+//! [`Honeycomb`]: crate::processor::honeycomb::Honeycomb
+//! [`Datadog`]: crate::processor::datadog::Datadog
+//! [`BulkProcessor`]: crate::processor::bulk::BulkProcessor
+//! [`SpanTrace`]: https://docs.rs/tracing-error/0.2/tracing_error/struct.SpanTrace.html
+//! [`valuable`]: https://docs.rs/valuable
 //! [derive]: tracing_forest_macros::Tag
 //! [attr_test]: tracing_forest_macros::test
 //! [attr_main]: tracing_forest_macros::main
+//! [attr_instrument]: tracing_forest_macros::instrument
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "arena")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arena")))]
+pub mod arena;
+#[cfg(feature = "sync")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+pub mod baggage;
+#[cfg(all(feature = "std", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod bridge;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "config")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
 pub mod formatter;
+#[cfg(feature = "std")]
 pub mod layer;
+#[cfg(feature = "std")]
 pub mod processor;
+#[cfg(all(feature = "std", feature = "uuid"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+pub mod propagation;
+#[cfg(all(feature = "std", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod replay;
+#[cfg(all(feature = "signals", unix))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "signals", unix))))]
+pub mod signals;
 pub mod tag;
+pub mod tree;
+#[cfg(feature = "std")]
+pub mod writer;
 #[doc(hidden)]
 #[macro_use]
 mod cfg;
+#[cfg(feature = "std")]
+mod artifact;
+#[cfg(all(feature = "std", feature = "chrono"))]
+mod clock;
 #[doc(hidden)]
 #[cfg(feature = "json")]
 mod ser;
-#[cfg(feature = "uuid")]
+#[cfg(all(feature = "std", feature = "uuid"))]
 mod uuid;
+#[cfg(feature = "std")]
 #[macro_use]
 mod macros;
 pub(crate) mod fail;
 
 // Items that are required for macros but not intended for public API
+#[cfg(feature = "std")]
 #[doc(hidden)]
 pub mod private {
-    pub use crate::tag::{unrecognized_tag_id, TagData};
+    pub use crate::artifact::{Artifact, ArtifactWriter};
+    pub use crate::capture::assert_matches;
+    pub use crate::processor::summary::ReportOnDrop;
+    pub use crate::tag::{target_matches, unrecognized_tag_id, TagData};
     #[cfg(feature = "uuid")]
-    pub use crate::uuid::into_u64_pair;
+    pub use crate::uuid::{into_u128, into_u64_pair};
+    pub use tracing::instrument;
     pub use tracing::subscriber::set_default;
+    pub use tracing::{Event, Level};
     pub use tracing_subscriber::{fmt::TestWriter, Layer, Registry};
     pub const TRACE_ICON: char = '📍';
     pub const DEBUG_ICON: char = '🐛';
@@ -252,16 +363,38 @@ pub mod private {
 // *   [ ] crate-wide docs
 // *   [ ] proc macros
 
-pub use crate::layer::TreeLayer;
+#[cfg(all(feature = "std", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use crate::builder::init_json;
+#[cfg(feature = "std")]
+pub use crate::builder::{builder, init_pretty, init_pretty_stderr, init_test};
+#[cfg(feature = "std")]
+pub use crate::capture::capture;
+#[cfg(feature = "std")]
+pub use crate::error::set_error_handler;
+#[cfg(feature = "sync")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+pub use crate::layer::spawn_in_tree;
+#[cfg(feature = "std")]
+pub use crate::layer::{inherit, suppress, suppress_future, TreeLayer};
+#[cfg(feature = "std")]
 pub use crate::processor::blocking::blocking;
 #[cfg(feature = "sync")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
 pub use crate::processor::sync::async_spawn;
+#[cfg(feature = "std")]
+pub use crate::processor::worker::{worker, ForestGuard};
+#[cfg(feature = "std")]
 pub use crate::processor::Processor;
+#[cfg(all(feature = "std", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use crate::replay::replay;
 pub use crate::tag::Tag;
-#[cfg(feature = "uuid")]
+#[cfg(feature = "std")]
+pub use crate::tag::TagRegistry;
+#[cfg(all(feature = "std", feature = "uuid"))]
 #[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
-pub use crate::uuid::id;
+pub use crate::uuid::{id, UuidVersion};
 
 /// Derive macro generating an implementation of the [`Tag`] trait.
 ///
@@ -305,6 +438,34 @@ pub use crate::uuid::id;
 ///     SecurityBreach,
 /// }
 /// ```
+/// Tags are compared by their [`Severity`][crate::tag::Severity], which
+/// defaults to that of the level keyword, or `info` for custom icons. To set it
+/// explicitly, add a `severity` argument after the message:
+/// ```
+/// # use tracing_forest::Tag;
+/// #[derive(Tag)]
+/// enum MyTag {
+///     #[tag(custom('🤯'): "security.breach", severity = critical)]
+///     SecurityBreach,
+/// }
+/// ```
+/// Events without an `__event_tag` field can also be tagged by their target
+/// and level, with `target` and `level` arguments. An event matches a `target`
+/// if its target is that module or inside of it, and matches a `level` if it
+/// has exactly that level. When several variants match, the first one is
+/// used:
+/// ```
+/// # use tracing_forest::Tag;
+/// #[derive(Tag)]
+/// enum MyTag {
+///     #[tag(custom('🔐'): "security.critical", target = "app::security", level = "error")]
+///     SecurityCritical,
+///     #[tag(custom('🔓'): "security.access", target = "app::security")]
+///     SecurityAccess,
+/// }
+/// ```
+/// This replaces writing a custom macro for each variant, since every
+/// `tracing::error!` in `app::security` is tagged as `security.critical`.
 ///
 /// # Examples
 ///
@@ -379,6 +540,24 @@ pub use tracing_forest_macros::Tag;
 /// }
 /// ```
 ///
+/// ### Artifacts of failed tests
+///
+/// With the `artifact` argument, logs aren't printed. Instead, if the test
+/// fails, they are written to `target/forest/<test path>.log`, with the `::`
+/// between modules replaced by `-`, and the path is printed. Passing tests
+/// write nothing, so CI keeps detailed traces of failures without flooding
+/// the console.
+///
+/// ```
+/// #[tracing_forest::test(artifact)]
+/// fn test_checkout() {
+///     tracing::info!("only kept if the test fails");
+/// }
+/// ```
+/// ```log
+/// tracing-forest: trees written to target/forest/my_crate-tests-test_checkout.log
+/// ```
+///
 /// ### Using with Tokio runtime
 ///
 /// The attribute can also be proceeded by the [`#[tokio::test]`][tokio::test]
@@ -442,6 +621,30 @@ pub use tracing_forest_macros::test;
 /// }
 /// ```
 ///
+/// ### Summarizing the run
+///
+/// With the `summary` argument, a summary tree is written when `main`
+/// returns, counting events by level and tag, and showing the total runtime
+/// and the slowest spans. See [`Summarize`] for details.
+///
+/// ```
+/// #[tracing_forest::main(summary)]
+/// fn main() {
+///     tracing::info_span!("import").in_scope(|| {
+///         tracing::warn!("skipped a row");
+///     });
+/// }
+/// ```
+/// ```log
+/// INFO     import [ 10.4µs | 100.000% ]
+/// WARN     ┕━ 🚧 [warn]: skipped a row
+/// INFO     run summary [ 52.1µs | 100.000% ]
+/// INFO     ┝━ 💬 [info]: events by level | WARN: 1
+/// INFO     ┕━ import [ 10.4µs | 19.962% ]
+/// ```
+///
+/// [`Summarize`]: crate::processor::summary::Summarize
+///
 /// ### Using with Tokio runtime
 ///
 /// The attribute can also be proceeded by the [`#[tokio::main]`][tokio::main]
@@ -458,3 +661,43 @@ pub use tracing_forest_macros::test;
 /// ```
 #[cfg(feature = "attributes")]
 pub use tracing_forest_macros::main;
+
+/// Instruments a function to create and enter a span every time it's called,
+/// with options for how the span is collected into trees.
+///
+/// This behaves like [`#[tracing::instrument]`][tracing::instrument], and
+/// accepts all of its arguments, along with:
+/// * `tag = "MyTag::Variant"`: Tags every event in the span and its children
+///   that isn't already tagged. Child spans can override the tag with their
+///   own.
+/// * `uuid = expr`: Sets the [`Uuid`] of the span from an expression, which
+///   can refer to the function's arguments. Requires the `uuid` feature.
+/// * `skip_tree`: Excludes the span and everything inside of it from trees,
+///   while other layers still see it.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::Tag;
+/// # use uuid::Uuid;
+/// #[derive(Tag)]
+/// enum MyTag {
+///     #[tag(info: "request.info")]
+///     RequestInfo,
+/// }
+///
+/// #[tracing_forest::instrument(tag = "MyTag::RequestInfo", uuid = request_id, skip(body))]
+/// fn handle(request_id: Uuid, body: &[u8]) {
+///     tracing::info!(len = body.len(), "received");
+///     encode(body);
+/// }
+///
+/// #[tracing_forest::instrument(skip_tree, skip_all)]
+/// fn encode(body: &[u8]) {
+///     tracing::trace!("this is never part of a tree");
+/// }
+/// ```
+///
+/// [`Uuid`]: ::uuid::Uuid
+#[cfg(feature = "attributes")]
+pub use tracing_forest_macros::instrument;
@@ -80,3 +80,199 @@ macro_rules! uuid_error_span {
         ::tracing_forest::uuid_error_span!($uuid, $name,)
     };
 }
+
+/// Creates a new root [`Span`] that continues the tree of the service that
+/// sent `carrier`, like the headers of a request.
+///
+/// The span adopts the [`Uuid`] written into the carrier by
+/// [`propagation::inject`], and records the calling path in the
+/// `remote.path` field. If the carrier has no valid ID, a span with a new ID
+/// is created instead. The remaining arguments are the same as
+/// [`uuid_span!`]'s.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use tracing::Level;
+/// # use tracing_forest::remote_span;
+/// let mut headers = HashMap::new();
+/// headers.insert("x-forest-id".to_string(), uuid::Uuid::new_v4().to_string());
+///
+/// let span = remote_span!(headers, Level::INFO, "charge", amount = 42);
+/// let _enter = span.enter();
+/// ```
+///
+/// [`Span`]: tracing::Span
+/// [`Uuid`]: uuid::Uuid
+/// [`propagation::inject`]: crate::propagation::inject
+#[macro_export]
+macro_rules! remote_span {
+    ($carrier:expr, $lvl:expr, $name:expr, $( $fields:tt )*) => {
+        match ::tracing_forest::propagation::extract(&$carrier) {
+            ::core::option::Option::Some(remote) => ::tracing_forest::uuid_span!(
+                remote.id,
+                $lvl,
+                $name,
+                remote.path = %remote.path,
+                $( $fields )*
+            ),
+            ::core::option::Option::None => ::tracing::span!($lvl, $name, $( $fields )*),
+        }
+    };
+    ($carrier:expr, $lvl:expr, $name:expr) => {
+        ::tracing_forest::remote_span!($carrier, $lvl, $name,)
+    };
+}
+
+/// Creates a new [`Span`] that's excluded from trees, along with everything
+/// inside of it.
+///
+/// The span records the [`SKIP_FIELD`] as `true`, and otherwise takes the
+/// same arguments as [`tracing`]s [`span!`] macro. Other layers of the
+/// subscriber still see the span and its events.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::skip_span;
+/// let span = skip_span!(Level::DEBUG, "heartbeat", peer = 7);
+/// span.in_scope(|| {
+///     tracing::debug!("excluded from trees");
+/// });
+/// ```
+///
+/// [`Span`]: tracing::Span
+/// [`SKIP_FIELD`]: crate::layer::SKIP_FIELD
+/// [`span!`]: tracing::span!
+#[macro_export]
+macro_rules! skip_span {
+    ($lvl:expr, $name:expr, $( $fields:tt )*) => {
+        ::tracing::span!($lvl, $name, forest.skip = true, $( $fields )*)
+    };
+    ($lvl:expr, $name:expr) => {
+        ::tracing_forest::skip_span!($lvl, $name,)
+    };
+}
+
+/// Logs an event whose message is rendered from a format string, and
+/// records the format string as the event's [`template`].
+///
+/// This takes a level, a format string, and its arguments, like
+/// [`tracing`]s [`event!`] macro. To log an event with fields and a template,
+/// record the [`TEMPLATE_FIELD`] yourself.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::templated;
+/// # let delay = 250;
+/// templated!(Level::WARN, "retrying in {}ms", delay);
+/// ```
+///
+/// [`template`]: crate::layer::TreeEvent::template
+/// [`event!`]: tracing::event!
+/// [`TEMPLATE_FIELD`]: crate::layer::TEMPLATE_FIELD
+#[macro_export]
+macro_rules! templated {
+    ($lvl:expr, $template:literal $(, $args:expr )* $(,)?) => {
+        ::tracing::event!($lvl, message.template = $template, $template $(, $args )*)
+    };
+}
+
+/// Asserts that a captured [`Tree`] matches a pattern, panicking with both
+/// trees outlined and the path to the first difference if it doesn't.
+///
+/// A pattern is either:
+/// * `span "name" [children, ..]`, optionally with a level, as in
+///   `span INFO "name" [..]`. The brackets can be omitted for spans without
+///   children.
+/// * `event LEVEL "message"`, optionally followed by fields that must be
+///   present, as in `event INFO "message" (key = "value")`.
+/// * `..`, which matches any remaining children of a span.
+///
+/// Span names, messages, and field values are globs, where `*` matches any
+/// sequence of characters and `?` matches any single character. Fields are
+/// compared to the field's value without surrounding quotes.
+///
+/// See [`capture`] for capturing trees to assert against.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::assert_tree;
+/// let trees = tracing_forest::capture().run(|| {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::debug_span!("db").in_scope(|| {
+///             tracing::warn!(rows = 0, "empty result");
+///         });
+///         tracing::info!("done");
+///     });
+/// });
+///
+/// assert_tree!(trees[0], span INFO "request" [
+///     span "db" [
+///         event WARN "empty*" (rows = "0"),
+///     ],
+///     ..
+/// ]);
+/// ```
+///
+/// [`Tree`]: crate::layer::Tree
+/// [`capture`]: crate::capture::capture
+#[macro_export]
+macro_rules! assert_tree {
+    ($tree:expr, $( $pattern:tt )+) => {
+        ::tracing_forest::private::assert_matches(
+            &$tree,
+            &::tracing_forest::__tree_pattern!($( $pattern )+),
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tree_pattern {
+    (..) => {
+        ::tracing_forest::capture::Pattern::Rest
+    };
+    (span $name:literal $( [ $( $children:tt )* ] )?) => {
+        ::tracing_forest::capture::Pattern::Span {
+            level: None,
+            name: $name,
+            children: ::tracing_forest::__tree_pattern!(@children [] [] $( $( $children )* )?),
+        }
+    };
+    (span $lvl:ident $name:literal $( [ $( $children:tt )* ] )?) => {
+        ::tracing_forest::capture::Pattern::Span {
+            level: Some(::tracing::Level::$lvl),
+            name: $name,
+            children: ::tracing_forest::__tree_pattern!(@children [] [] $( $( $children )* )?),
+        }
+    };
+    (event $lvl:ident $message:literal $( ( $( $key:ident = $value:literal ),* $(,)? ) )?) => {
+        ::tracing_forest::capture::Pattern::Event {
+            level: ::tracing::Level::$lvl,
+            message: $message,
+            fields: vec![$( $( (stringify!($key), $value) ),* )?],
+        }
+    };
+    // Split children on top-level commas, then build each one
+    (@children [$( $done:tt )*] []) => {
+        vec![$( ::tracing_forest::__tree_pattern!(@child $done) ),*]
+    };
+    (@children [$( $done:tt )*] [$( $cur:tt )+]) => {
+        ::tracing_forest::__tree_pattern!(@children [$( $done )* { $( $cur )+ }] [])
+    };
+    (@children [$( $done:tt )*] [$( $cur:tt )*] , $( $rest:tt )*) => {
+        ::tracing_forest::__tree_pattern!(@children [$( $done )* { $( $cur )* }] [] $( $rest )*)
+    };
+    (@children [$( $done:tt )*] [$( $cur:tt )*] $next:tt $( $rest:tt )*) => {
+        ::tracing_forest::__tree_pattern!(@children [$( $done )*] [$( $cur )* $next] $( $rest )*)
+    };
+    (@child { $( $pattern:tt )* }) => {
+        ::tracing_forest::__tree_pattern!($( $pattern )*)
+    };
+}
@@ -0,0 +1,171 @@
+//! A [`Processor`] that notifies a callback when trees match a predicate.
+//!
+//! See [`Alert`] for more details.
+
+use crate::layer::{Tree, TreeKind};
+use crate::processor::Processor;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A [`Processor`] that invokes a callback for [`Tree`]s matching a predicate,
+/// and then forwards every tree to another processor.
+///
+/// Notifications are deduplicated by a key computed from each tree, so the
+/// same alert is sent at most once per dedup window, and rate limited across
+/// all keys so that an error storm doesn't cause a notification storm. The
+/// callback also receives how many matching trees with the same key were
+/// suppressed since the key was last notified.
+///
+/// The callback runs on the thread that processes the tree, so it should hand
+/// off slow work, like posting to a webhook, to another thread or task.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tracing::Level;
+/// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+/// # use tracing_forest::processor::alert::Alert;
+/// let processor = Alert::new(
+///     blocking(Pretty::new(), std::io::stdout),
+///     |tree| tree.attrs.level == Level::ERROR,
+///     |tree, suppressed| {
+///         eprintln!("ALERT ({} suppressed): {:?}", suppressed, tree.attrs.level);
+///     },
+/// )
+/// .dedup_window(Duration::from_secs(300))
+/// .rate_limit(10, Duration::from_secs(60));
+///
+/// let _guard = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+/// ```
+pub struct Alert<P, F, C> {
+    processor: P,
+    predicate: F,
+    notify: C,
+    key: fn(&Tree) -> String,
+    dedup_window: Duration,
+    rate_limit: Option<(usize, Duration)>,
+    state: Mutex<AlertState>,
+}
+
+#[derive(Default)]
+struct AlertState {
+    /// When each key was last notified, and how many duplicates were
+    /// suppressed since.
+    keys: HashMap<String, (Instant, usize)>,
+    /// How many notifications for each key were dropped by the rate limit.
+    ///
+    /// These are kept apart from `keys` so that a rate limited key isn't also
+    /// treated as recently notified and suppressed again by deduplication.
+    limited: HashMap<String, usize>,
+    /// When recent notifications were sent, for rate limiting.
+    sent: VecDeque<Instant>,
+}
+
+impl<P, F, C> Alert<P, F, C>
+where
+    P: Processor,
+    F: 'static + Fn(&Tree) -> bool,
+    C: 'static + Fn(&Tree, usize),
+{
+    /// Construct a new [`Alert`] that calls `notify` for trees matching
+    /// `predicate` before forwarding all trees to `processor`.
+    ///
+    /// By default, trees are keyed by [`default_key`], duplicates are
+    /// suppressed for one minute, and notifications aren't rate limited.
+    pub fn new(processor: P, predicate: F, notify: C) -> Self {
+        Alert {
+            processor,
+            predicate,
+            notify,
+            key: default_key,
+            dedup_window: Duration::from_secs(60),
+            rate_limit: None,
+            state: Mutex::new(AlertState::default()),
+        }
+    }
+
+    /// Set the function used to compute the deduplication key of a tree.
+    pub fn key(mut self, key: fn(&Tree) -> String) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Set how long notifications for the same key are suppressed after one
+    /// is sent.
+    pub fn dedup_window(mut self, dedup_window: Duration) -> Self {
+        self.dedup_window = dedup_window;
+        self
+    }
+
+    /// Send at most `max` notifications in any period of length `per`.
+    pub fn rate_limit(mut self, max: usize, per: Duration) -> Self {
+        self.rate_limit = Some((max, per));
+        self
+    }
+
+    /// Returns the number of suppressed duplicates if a notification should
+    /// be sent for `key` now.
+    fn should_notify(&self, key: String) -> Option<usize> {
+        let now = Instant::now();
+        #[allow(clippy::expect_used)]
+        let mut state = self.state.lock().expect("alert state poisoned");
+        let AlertState {
+            keys,
+            limited,
+            sent,
+        } = &mut *state;
+
+        let window = self.dedup_window;
+        keys.retain(|_, (last, suppressed)| now.duration_since(*last) < window || *suppressed > 0);
+
+        if let Some((last, suppressed)) = keys.get_mut(&key) {
+            if now.duration_since(*last) < window {
+                *suppressed += 1;
+                return None;
+            }
+        }
+
+        if let Some((max, per)) = self.rate_limit {
+            while sent.front().is_some_and(|t| now.duration_since(*t) >= per) {
+                sent.pop_front();
+            }
+            if sent.len() >= max {
+                *limited.entry(key).or_insert(0) += 1;
+                return None;
+            }
+            sent.push_back(now);
+        }
+
+        let limited = limited.remove(&key).unwrap_or(0);
+        let deduped = keys.insert(key, (now, 0)).map_or(0, |(_, n)| n);
+        Some(deduped + limited)
+    }
+}
+
+impl<P, F, C> Processor for Alert<P, F, C>
+where
+    P: Processor,
+    F: 'static + Fn(&Tree) -> bool,
+    C: 'static + Fn(&Tree, usize),
+{
+    fn process(&self, tree: Tree) {
+        if (self.predicate)(&tree) {
+            if let Some(suppressed) = self.should_notify((self.key)(&tree)) {
+                (self.notify)(&tree, suppressed);
+            }
+        }
+
+        self.processor.process(tree);
+    }
+}
+
+/// The default deduplication key of a tree: the name of the root span, or the
+/// message if the tree is a single event.
+pub fn default_key(tree: &Tree) -> String {
+    match &tree.kind {
+        TreeKind::Span(span) => span.name.to_string(),
+        TreeKind::Event(event) => event.message.to_string(),
+    }
+}
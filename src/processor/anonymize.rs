@@ -0,0 +1,183 @@
+//! A [`Processor`] that hides the values recorded in trees.
+//!
+//! See [`Anonymize`] for more details.
+
+use crate::layer::{KeyValue, Tree, TreeKind};
+use crate::processor::Processor;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// What masked values are replaced with.
+pub const MASK: &str = "***";
+
+/// A [`Processor`] that masks or hashes every recorded value of a tree
+/// before forwarding it to another processor, so trees can be attached to
+/// public bug reports without leaking data.
+///
+/// Field values of spans and events, event messages, and the fields of
+/// [span traces] are replaced. Field keys, span names, levels, tags,
+/// durations, timestamps, and the shape of the tree are kept, along with
+/// targets, source locations, and message templates, which describe the code
+/// rather than the data. Annotations added by processors are also kept.
+///
+/// By default, values are replaced with [`MASK`]. With [`hash`], they're
+/// replaced with a short hash like `#d3bb10e8`, so equal values can still be
+/// told apart from different ones. Hashes aren't cryptographic, and values
+/// with few possibilities, like booleans or small numbers, can be guessed
+/// from them.
+///
+/// ```log
+/// INFO     login [ 17.4µs | 100.000% ]
+/// INFO     ┕━ 💬 [info]: #d3bb10e8 | attempt: #6a9c37d7
+/// ```
+///
+/// To initialize a new [`Anonymize`], see [`Processor::anonymize`]. To
+/// anonymize trees in a formatter instead, use [`mask`] or [`hash`][fn@hash]
+/// as a transform.
+///
+/// [span traces]: crate::layer::TreeEvent::span_trace
+/// [`hash`]: Anonymize::hash
+pub struct Anonymize<P> {
+    processor: P,
+    hash: bool,
+    keep_messages: bool,
+}
+
+impl<P> Anonymize<P> {
+    pub(crate) fn new(processor: P) -> Self {
+        Anonymize {
+            processor,
+            hash: false,
+            keep_messages: false,
+        }
+    }
+
+    /// Set whether values are replaced with a hash instead of [`MASK`].
+    pub fn hash(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Set whether event messages are kept.
+    ///
+    /// Messages are replaced by default, since they're often formatted with
+    /// the same data that fields hold. Keep them if every message in the
+    /// application is a literal.
+    pub fn keep_messages(mut self, keep_messages: bool) -> Self {
+        self.keep_messages = keep_messages;
+        self
+    }
+}
+
+impl<P: Processor> Processor for Anonymize<P> {
+    fn process(&self, mut tree: Tree) {
+        let replace = if self.hash { hash_value } else { mask_value };
+        anonymize(&mut tree, replace, self.keep_messages);
+        self.processor.process(tree);
+    }
+}
+
+/// Replaces every recorded value of `tree` with [`MASK`], like [`Anonymize`]
+/// does with its default settings.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{pretty::Pretty, Transformed};
+/// # use tracing_forest::processor::anonymize;
+/// let formatter = Transformed::new(Pretty::new(), anonymize::mask);
+/// ```
+pub fn mask(mut tree: Tree) -> Tree {
+    anonymize(&mut tree, mask_value, false);
+    tree
+}
+
+/// Replaces every recorded value of `tree` with a short hash of it, like
+/// [`Anonymize`] does with [`hash`] set.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::processor::anonymize;
+/// let _guard = tracing::subscriber::set_default({
+///     tracing_forest::builder()
+///         .set_transform(anonymize::hash)
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+/// ```
+///
+/// [`hash`]: Anonymize::hash
+pub fn hash(mut tree: Tree) -> Tree {
+    anonymize(&mut tree, hash_value, false);
+    tree
+}
+
+fn mask_value(_: &str) -> String {
+    MASK.to_string()
+}
+
+fn hash_value(value: &str) -> String {
+    // `DefaultHasher::new` always uses the same keys, so equal values have
+    // equal hashes across trees and runs
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("#{:08x}", hasher.finish() as u32)
+}
+
+fn anonymize(tree: &mut Tree, replace: fn(&str) -> String, keep_messages: bool) {
+    match &mut tree.kind {
+        TreeKind::Span(span) => {
+            for kv in span.fields.iter_mut() {
+                anonymize_field(kv, replace);
+            }
+            for child in span.children.iter_mut() {
+                anonymize(child, replace, keep_messages);
+            }
+        }
+        TreeKind::Event(event) => {
+            if !keep_messages {
+                event.message = replace(&event.message).into();
+            }
+            for kv in event.fields.iter_mut() {
+                anonymize_field(kv, replace);
+            }
+            #[cfg(feature = "tracing-error")]
+            for frame in event.span_trace.iter_mut().flatten() {
+                if !frame.fields.is_empty() {
+                    frame.fields = replace(&frame.fields);
+                }
+            }
+        }
+    }
+}
+
+fn anonymize_field(kv: &mut KeyValue, replace: fn(&str) -> String) {
+    kv.value = replace(&kv.value);
+    #[cfg(feature = "valuable")]
+    if let Some(structured) = &mut kv.structured {
+        anonymize_structured(structured, replace);
+    }
+}
+
+/// Replaces the values nested in `value`, keeping the keys of maps.
+#[cfg(feature = "valuable")]
+fn anonymize_structured(value: &mut serde_json::Value, replace: fn(&str) -> String) {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                anonymize_structured(value, replace);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                anonymize_structured(value, replace);
+            }
+        }
+        Value::String(string) => *string = replace(string),
+        Value::Null => {}
+        other => *other = Value::String(replace(&other.to_string())),
+    }
+}
@@ -2,19 +2,34 @@
 //!
 //! See [`BlockingProcessor`] for more details.
 
+use crate::error::{self, ForestError};
 use crate::formatter::Formatter;
 use crate::layer::Tree;
-use crate::processor::Processor;
+use crate::processor::buffers::Buffers;
+use crate::processor::{transform, Processor};
 use std::io::Write;
 use tracing_subscriber::fmt::MakeWriter;
 
 /// A [`Processor`] that blocks the current thread to format and write logs on
 /// arrival.
 ///
+/// Trees are formatted into buffers that are reused once they've been
+/// written, so processing a tree doesn't allocate a new one.
+///
 /// To initialize a new [`BlockingProcessor`], see [`blocking`].
 pub struct BlockingProcessor<F, W> {
     formatter: F,
     make_writer: W,
+    buffers: Buffers,
+    transforms: Vec<fn(Tree) -> Tree>,
+}
+
+impl<F, W> BlockingProcessor<F, W> {
+    /// Applies `transforms` to every tree before it's formatted.
+    pub(crate) fn with_transforms(mut self, transforms: Vec<fn(Tree) -> Tree>) -> Self {
+        self.transforms = transforms;
+        self
+    }
 }
 
 impl<F, W> Processor for BlockingProcessor<F, W>
@@ -23,12 +38,16 @@ where
     W: 'static + for<'a> MakeWriter<'a>,
 {
     fn process(&self, tree: Tree) {
-        let mut buf = Vec::with_capacity(0);
+        let tree = transform(&self.transforms, tree);
+        let mut buf = self.buffers.take();
 
-        #[allow(clippy::expect_used)]
-        self.formatter.fmt(tree, &mut buf).expect("formatting failed");
-        #[allow(clippy::unwrap_used)]
-        self.make_writer.make_writer().write_all(&buf[..]).unwrap();
+        if let Err(err) = self.formatter.fmt_with_latency(tree, None, &mut buf) {
+            return error::report(ForestError::Format(err));
+        }
+        if let Err(err) = self.make_writer.make_writer().write_all(&buf[..]) {
+            error::report(ForestError::Write(err));
+        }
+        self.buffers.put(buf);
     }
 }
 
@@ -62,5 +81,7 @@ where
     BlockingProcessor {
         formatter,
         make_writer,
+        buffers: Buffers::default(),
+        transforms: Vec::new(),
     }
 }
@@ -0,0 +1,92 @@
+//! Output buffers that processors reuse between trees.
+
+use std::sync::Mutex;
+
+/// How many buffers a [`Buffers`] pool keeps for reuse.
+const SIZE: usize = 4;
+
+/// The most bytes a reused buffer can hold on to, after which it's freed
+/// instead, so one huge tree doesn't pin its memory.
+const MAX_CAPACITY: usize = 64 * 1024;
+
+/// A buffer that can be cleared and kept in a [`Buffers`] pool.
+pub(crate) trait Reuse: Default {
+    /// The bytes held on to by the buffer.
+    fn capacity(&self) -> usize;
+
+    /// Empties the buffer, keeping its allocation.
+    fn clear(&mut self);
+}
+
+impl Reuse for Vec<u8> {
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+}
+
+/// A pool of output buffers for processors that format trees on whichever
+/// thread closed them, so each tree doesn't allocate a new buffer.
+#[derive(Debug)]
+pub(crate) struct Buffers<T = Vec<u8>> {
+    pool: Mutex<Vec<T>>,
+    /// How many buffers are kept. With `0`, every buffer is freed after use.
+    pub(crate) size: usize,
+    /// The most bytes a kept buffer can hold on to.
+    pub(crate) max_capacity: usize,
+}
+
+impl<T> Buffers<T> {
+    /// Constructs an empty pool with the default limits.
+    pub(crate) const fn new() -> Self {
+        Buffers {
+            pool: Mutex::new(Vec::new()),
+            size: SIZE,
+            max_capacity: MAX_CAPACITY,
+        }
+    }
+}
+
+impl<T> Default for Buffers<T> {
+    fn default() -> Self {
+        Buffers::new()
+    }
+}
+
+impl<T: Reuse> Buffers<T> {
+    /// Takes an empty buffer from the pool, or allocates one if it's empty.
+    pub(crate) fn take(&self) -> T {
+        if self.size == 0 {
+            return T::default();
+        }
+        #[allow(clippy::expect_used)]
+        let mut pool = self.pool.lock().expect("buffer pool poisoned");
+        pool.pop().unwrap_or_default()
+    }
+
+    /// Returns `buf` to the pool once its contents have been written.
+    pub(crate) fn put(&self, mut buf: T) {
+        if self.size == 0 || buf.capacity() > self.max_capacity {
+            return;
+        }
+        buf.clear();
+        #[allow(clippy::expect_used)]
+        let mut pool = self.pool.lock().expect("buffer pool poisoned");
+        if pool.len() < self.size {
+            pool.push(buf);
+        }
+    }
+}
+
+/// Clears `buf` for the next tree of a processing thread, or frees it if it
+/// grew too large.
+pub(crate) fn reuse(buf: &mut Vec<u8>) {
+    if buf.capacity() > MAX_CAPACITY {
+        *buf = Vec::new();
+    } else {
+        buf.clear();
+    }
+}
@@ -0,0 +1,569 @@
+//! A [`Processor`] that inserts flattened events into a database in batches.
+//!
+//! See [`BulkProcessor`] for more details.
+
+use crate::layer::{Tree, TreeKind};
+use crate::processor::net::{Deliver, Outbox};
+use crate::processor::Processor;
+use std::error::Error;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The error type returned by [`BulkSink`]s.
+pub type BulkError = Box<dyn Error + Send + Sync>;
+
+/// An event flattened into a single row, along with the spans it occurred in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRow {
+    /// The ID of the root span, if the `uuid` feature is enabled and the event
+    /// occurred in a span.
+    pub uuid: Option<String>,
+    /// When the event occurred, in RFC 3339 format, if the `chrono` feature
+    /// is enabled.
+    pub timestamp: Option<String>,
+    /// The level of the event.
+    pub level: &'static str,
+    /// The names of the spans the event occurred in, from the root, joined
+    /// with ` > `.
+    pub span_path: String,
+    /// The tag of the event, if any.
+    pub tag: Option<&'static str>,
+    /// The message of the event.
+    pub message: String,
+    /// The format string that the message was rendered from, if it was
+    /// recorded.
+    pub template: Option<String>,
+    /// The fields of the event, as they're displayed by the other formatters.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl EventRow {
+    /// Flattens every event in a tree into rows, in the order they occurred.
+    pub fn flatten(tree: &Tree) -> Vec<EventRow> {
+        let mut rows = Vec::new();
+        let mut path = Vec::new();
+        flatten_into(tree, uuid(tree), &mut path, &mut rows);
+        rows
+    }
+
+    /// Returns the fields as a JSON object, mapping keys to values.
+    #[cfg(feature = "json")]
+    pub fn fields_json(&self) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), serde_json::Value::from(value.as_str())))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::Value::Object(fields).to_string()
+    }
+}
+
+fn flatten_into(
+    tree: &Tree,
+    uuid: Option<String>,
+    path: &mut Vec<&'static str>,
+    rows: &mut Vec<EventRow>,
+) {
+    match &tree.kind {
+        TreeKind::Span(span) => {
+            path.push(span.name);
+            for child in span.children.iter() {
+                flatten_into(child, uuid.clone(), path, rows);
+            }
+            path.pop();
+        }
+        TreeKind::Event(event) => rows.push(EventRow {
+            uuid,
+            timestamp: timestamp(tree),
+            level: tree.attrs.level.as_str(),
+            span_path: path.join(" > "),
+            tag: event.tag.map(|tag| tag.message),
+            message: event.message.to_string(),
+            template: event.template.as_deref().map(str::to_string),
+            fields: event
+                .fields
+                .iter()
+                .map(|kv| (kv.key, kv.value.clone()))
+                .collect(),
+        }),
+    }
+}
+
+fn uuid(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "uuid")]
+    return match tree.kind {
+        TreeKind::Event(_) => None,
+        TreeKind::Span(_) => Some(tree.attrs.uuid.to_string()),
+    };
+
+    #[cfg(not(feature = "uuid"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+fn timestamp(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "chrono")]
+    return Some(tree.attrs.timestamp.to_rfc3339());
+
+    #[cfg(not(feature = "chrono"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+/// A destination that [`BulkProcessor`]s insert batches of rows into.
+///
+/// This is implemented for [`Postgres`], [`ClickHouse`], and [`Seq`] when
+/// their features are enabled, and can be implemented for other databases.
+pub trait BulkSink: Send + 'static {
+    /// Insert a batch of rows.
+    ///
+    /// If this returns an error, none of the rows are considered inserted,
+    /// and the batch is retried later.
+    fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError>;
+}
+
+/// A [`Processor`] that flattens the events of [`Tree`]s into [`EventRow`]s
+/// and inserts them into a [`BulkSink`] in batches.
+///
+/// Rows are queued for a background thread, which inserts them once there
+/// are at least [`batch_size`] of them, the [`flush_interval`] elapses, or
+/// [`flush`] is called or the processor is dropped. A slow database never
+/// blocks the instrumented code.
+///
+/// Rows are sent the same way as the trees of a [`Resilient`] processor:
+/// when an insert fails, the rows are kept and no inserts are attempted
+/// until a backoff has elapsed, which doubles after every consecutive failure
+/// up to a maximum. If more than [`max_pending`] rows are waiting, the
+/// oldest are discarded, and the number discarded is available from
+/// [`dropped`].
+///
+/// To initialize a new [`BulkProcessor`], see [`bulk`].
+///
+/// [`batch_size`]: BulkProcessor::batch_size
+/// [`flush_interval`]: BulkProcessor::flush_interval
+/// [`flush`]: BulkProcessor::flush
+/// [`max_pending`]: BulkProcessor::max_pending
+/// [`dropped`]: BulkProcessor::dropped
+/// [`Resilient`]: crate::processor::net::Resilient
+pub struct BulkProcessor<S: BulkSink> {
+    outbox: Outbox<Rows<S>>,
+}
+
+/// Inserts the rows sent by the thread of a [`BulkProcessor`] into its sink.
+struct Rows<S>(Mutex<S>);
+
+impl<S: BulkSink> Deliver for Rows<S> {
+    type Record = EventRow;
+    type Connection = ();
+
+    fn connect(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn send_batch(&self, _: &mut (), rows: &[EventRow]) -> io::Result<()> {
+        #[allow(clippy::expect_used)]
+        let mut sink = self.0.lock().expect("bulk processor poisoned");
+        sink.insert(rows).map_err(io::Error::other)
+    }
+
+    fn spill(&self, _: &EventRow) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Initialize a new [`BulkProcessor`] inserting rows into `sink`, and spawn
+/// its thread.
+///
+/// ## Panics
+///
+/// Panics if the thread can't be spawned.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::processor::bulk::{bulk, BulkError, BulkSink, EventRow};
+/// # use tracing_forest::Processor;
+/// struct Stdout;
+///
+/// impl BulkSink for Stdout {
+///     fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError> {
+///         rows.iter().for_each(|row| println!("{:?}", row));
+///         Ok(())
+///     }
+/// }
+///
+/// let processor = bulk(Stdout).batch_size(100);
+/// ```
+pub fn bulk<S: BulkSink>(sink: S) -> BulkProcessor<S> {
+    let outbox = Outbox::new(Rows(Mutex::new(sink)), "tracing-forest-bulk");
+    outbox.batch_size(500);
+    outbox.batch_delay(None);
+    outbox.queue_capacity(100_000);
+    BulkProcessor { outbox }
+}
+
+impl<S: BulkSink> BulkProcessor<S> {
+    /// Set how many rows are queued before they're inserted.
+    ///
+    /// By default, rows are inserted in batches of 500.
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        self.outbox.batch_size(batch_size);
+        self
+    }
+
+    /// Set the most rows that are queued while inserts are failing,
+    /// after which the oldest rows are discarded.
+    ///
+    /// By default, at most 100,000 rows are queued.
+    pub fn max_pending(self, max_pending: usize) -> Self {
+        self.outbox.queue_capacity(max_pending);
+        self
+    }
+
+    /// Set how long to wait after the first failed insert, and the most to
+    /// wait after consecutive failures.
+    ///
+    /// By default, the backoff starts at 100ms and is at most 30s.
+    pub fn backoff(self, initial: Duration, max: Duration) -> Self {
+        self.outbox.initial_backoff(initial);
+        self.outbox.max_backoff(max);
+        self
+    }
+
+    /// Insert queued rows at most `interval` after the first of them was
+    /// queued, even if there are fewer than a batch of them, so rows don't
+    /// wait for the next tree while little is being logged.
+    ///
+    /// By default, rows are only inserted once there's a batch of them.
+    pub fn flush_interval(self, interval: Duration) -> Self {
+        self.outbox.batch_delay(Some(interval));
+        self
+    }
+
+    /// Returns how many rows have been discarded because too many were
+    /// queued while inserts were failing.
+    pub fn dropped(&self) -> u64 {
+        self.outbox.stats().dropped
+    }
+
+    /// Insert all queued rows now, ignoring any backoff, and wait until the
+    /// thread has tried to.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the insert failed, in which case rows remain
+    /// queued.
+    pub fn flush(&self) -> Result<(), BulkError> {
+        Ok(self.outbox.flush()?)
+    }
+}
+
+impl<S: BulkSink> Processor for BulkProcessor<S> {
+    fn process(&self, tree: Tree) {
+        self.outbox.push(EventRow::flatten(&tree));
+    }
+}
+
+/// A [`BulkSink`] that inserts rows into a PostgreSQL table.
+///
+/// The table must have the following columns, and can have others with
+/// defaults:
+///
+/// ```sql
+/// CREATE TABLE forest_events (
+///     uuid UUID,
+///     timestamp TIMESTAMPTZ,
+///     level TEXT NOT NULL,
+///     span_path TEXT NOT NULL,
+///     tag TEXT,
+///     message TEXT NOT NULL,
+///     fields JSONB NOT NULL
+/// );
+/// ```
+///
+/// Each batch is inserted in a single transaction, with one multi-row
+/// `INSERT` for every 1000 rows.
+#[cfg(feature = "postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postgres")))]
+pub struct Postgres {
+    client: postgres::Client,
+    table: String,
+}
+
+/// The most rows inserted by one statement, which keeps the number of
+/// parameters well under the limit of 65535.
+#[cfg(feature = "postgres")]
+const POSTGRES_ROWS: usize = 1000;
+
+#[cfg(feature = "postgres")]
+impl Postgres {
+    /// Construct a new [`Postgres`] sink inserting into `table` with an
+    /// already connected client.
+    ///
+    /// The table name is inserted into the query as is, so it must be a
+    /// trusted identifier.
+    pub fn new(client: postgres::Client, table: &str) -> Self {
+        Postgres {
+            client,
+            table: table.to_string(),
+        }
+    }
+}
+
+/// Returns an `INSERT` of `rows` rows into `table`, with a tuple of
+/// parameters each.
+#[cfg(feature = "postgres")]
+fn postgres_query(table: &str, rows: usize) -> String {
+    let mut query = format!(
+        "INSERT INTO {} (uuid, timestamp, level, span_path, tag, message, fields) VALUES ",
+        table
+    );
+    for row in 0..rows {
+        let n = row * 7;
+        if row > 0 {
+            query.push_str(", ");
+        }
+        query.push_str(&format!(
+            "(${}::text::uuid, ${}::text::timestamptz, ${}, ${}, ${}, ${}, ${}::text::jsonb)",
+            n + 1,
+            n + 2,
+            n + 3,
+            n + 4,
+            n + 5,
+            n + 6,
+            n + 7
+        ));
+    }
+    query
+}
+
+#[cfg(feature = "postgres")]
+impl BulkSink for Postgres {
+    fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError> {
+        use postgres::types::ToSql;
+
+        let mut tx = self.client.transaction()?;
+        for chunk in rows.chunks(POSTGRES_ROWS) {
+            let fields = chunk.iter().map(EventRow::fields_json).collect::<Vec<_>>();
+            let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(chunk.len() * 7);
+            for (row, fields) in chunk.iter().zip(&fields) {
+                params.extend_from_slice(&[
+                    &row.uuid,
+                    &row.timestamp,
+                    &row.level,
+                    &row.span_path,
+                    &row.tag,
+                    &row.message,
+                    fields,
+                ]);
+            }
+            tx.execute(postgres_query(&self.table, chunk.len()).as_str(), &params)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// A [`BulkSink`] that inserts rows into a ClickHouse table over its HTTP
+/// interface.
+///
+/// The table must have the following columns:
+///
+/// ```sql
+/// CREATE TABLE forest_events (
+///     uuid Nullable(UUID),
+///     timestamp Nullable(DateTime64(9)),
+///     level LowCardinality(String),
+///     span_path String,
+///     tag Nullable(String),
+///     message String,
+///     fields Map(String, String)
+/// ) ENGINE = MergeTree ORDER BY tuple();
+/// ```
+///
+/// Each batch is sent as a single `INSERT` in the `JSONEachRow` format. Only
+/// plain HTTP is supported unless a TLS feature of `ureq` is enabled.
+#[cfg(feature = "clickhouse")]
+#[cfg_attr(docsrs, doc(cfg(feature = "clickhouse")))]
+pub struct ClickHouse {
+    agent: ureq::Agent,
+    url: String,
+    query: String,
+    credentials: Option<(String, String)>,
+}
+
+#[cfg(feature = "clickhouse")]
+impl ClickHouse {
+    /// Construct a new [`ClickHouse`] sink inserting into `table` on the
+    /// server at `url`, like `http://localhost:8123`.
+    ///
+    /// The table name is inserted into the query as is, so it must be a
+    /// trusted identifier.
+    pub fn new(url: &str, table: &str) -> Self {
+        ClickHouse {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+            query: format!("INSERT INTO {} FORMAT JSONEachRow", table),
+            credentials: None,
+        }
+    }
+
+    /// Set the user and password to authenticate with.
+    pub fn credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+}
+
+#[cfg(feature = "clickhouse")]
+impl BulkSink for ClickHouse {
+    fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError> {
+        let mut body = String::new();
+        for row in rows {
+            let fields = row
+                .fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), serde_json::Value::from(value.as_str())))
+                .collect::<serde_json::Map<_, _>>();
+            let row = serde_json::json!({
+                "uuid": row.uuid,
+                "timestamp": row.timestamp,
+                "level": row.level,
+                "span_path": row.span_path,
+                "tag": row.tag,
+                "message": row.message,
+                "fields": fields,
+            });
+            body.push_str(&row.to_string());
+            body.push('\n');
+        }
+
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .query("query", &self.query)
+            .query("date_time_input_format", "best_effort");
+        if let Some((user, password)) = &self.credentials {
+            request = request
+                .set("X-ClickHouse-User", user)
+                .set("X-ClickHouse-Key", password);
+        }
+        request.send_string(&body)?;
+        Ok(())
+    }
+}
+
+/// A [`BulkSink`] that posts rows to a [Seq] server as events in the compact
+/// log event format (CLEF).
+///
+/// Each event has its timestamp, level, message, and template, if it was
+/// recorded, along with its fields and the following properties:
+///
+/// * `span_path`: the names of the spans the event occurred in.
+/// * `tag`: the tag of the event, if any.
+/// * `uuid`: the ID of the root span, if the `uuid` feature is enabled.
+///
+/// Fields with the same names as these properties are dropped, and fields
+/// starting with `@` are escaped as `@@`, as CLEF requires. Each batch is
+/// sent in a single request. Only plain HTTP is supported unless a TLS
+/// feature of `ureq` is enabled.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::processor::bulk::{bulk, Seq};
+/// let processor = bulk(Seq::new("http://localhost:5341").api_key("pXxdq1x9Zz9gP4Fb"));
+/// ```
+///
+/// [Seq]: https://datalust.co/seq
+#[cfg(feature = "seq")]
+#[cfg_attr(docsrs, doc(cfg(feature = "seq")))]
+pub struct Seq {
+    agent: ureq::Agent,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[cfg(feature = "seq")]
+impl Seq {
+    /// Construct a new [`Seq`] sink posting to the server at `url`, like
+    /// `http://localhost:5341`.
+    pub fn new(url: &str) -> Self {
+        Seq {
+            agent: ureq::Agent::new(),
+            url: format!("{}/api/events/raw", url.trim_end_matches('/')),
+            api_key: None,
+        }
+    }
+
+    /// Set the API key to authenticate with.
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+}
+
+#[cfg(feature = "seq")]
+fn clef(row: &EventRow) -> serde_json::Value {
+    use serde_json::{Map, Value};
+
+    let level = match row.level {
+        "TRACE" => "Verbose",
+        "DEBUG" => "Debug",
+        "INFO" => "Information",
+        "WARN" => "Warning",
+        _ => "Error",
+    };
+
+    let mut event = Map::new();
+    event.insert("@t".into(), row.timestamp.clone().into());
+    event.insert("@l".into(), level.into());
+    event.insert("@m".into(), row.message.as_str().into());
+    if let Some(template) = &row.template {
+        event.insert("@mt".into(), template.as_str().into());
+    }
+    event.insert("span_path".into(), row.span_path.as_str().into());
+    if let Some(tag) = row.tag {
+        event.insert("tag".into(), tag.into());
+    }
+    if let Some(uuid) = &row.uuid {
+        event.insert("uuid".into(), uuid.as_str().into());
+    }
+    for (key, value) in row.fields.iter() {
+        let key = if key.starts_with('@') {
+            format!("@{}", key)
+        } else {
+            key.to_string()
+        };
+        event
+            .entry(key)
+            .or_insert_with(|| Value::from(value.as_str()));
+    }
+    Value::Object(event)
+}
+
+#[cfg(feature = "seq")]
+impl BulkSink for Seq {
+    fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError> {
+        let mut body = String::new();
+        for row in rows {
+            body.push_str(&clef(row).to_string());
+            body.push('\n');
+        }
+
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/vnd.serilog.clef");
+        if let Some(api_key) = &self.api_key {
+            request = request.set("X-Seq-ApiKey", api_key);
+        }
+        request.send_string(&body)?;
+        Ok(())
+    }
+}
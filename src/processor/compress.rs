@@ -0,0 +1,149 @@
+//! A [`Processor`] that folds repeated subtrees into a single representative.
+//!
+//! See [`Compress`] for more details.
+
+use crate::formatter::pretty::{DurationDisplay, DurationFormat};
+use crate::layer::{Tree, TreeKind};
+use crate::processor::Processor;
+use std::time::Duration;
+
+/// A [`Processor`] that folds runs of structurally identical children of a
+/// span into one representative before forwarding trees to another
+/// processor.
+///
+/// Batch jobs often produce thousands of identical subtrees, like a span per
+/// processed item. Consecutive siblings are identical when they have the same
+/// kind, level, and name or message, events have the same tag and field
+/// names, and spans have identical children. Field values and durations
+/// aren't compared.
+///
+/// Only the first subtree of each run is kept, annotated with how many times
+/// it was repeated and, for spans, the minimum, average, and maximum
+/// duration of the run:
+///
+/// ```log
+/// INFO     batch [ 1.52s | 0.013% / 100.000% ]
+/// INFO     ┕━ item [ 1.50ms | 0.099% ] | repeated: 1000 | min: 1.21ms | avg: 1.52ms | max: 9.87ms
+/// ```
+///
+/// The durations of the kept span aren't changed, and the nested duration of
+/// its parent still includes the whole run.
+///
+/// To initialize a new [`Compress`], see [`Processor::compress`]. To compress
+/// trees in a formatter instead, use [`compress`] as a transform.
+pub struct Compress<P> {
+    processor: P,
+    min_repeats: usize,
+}
+
+impl<P> Compress<P> {
+    pub(crate) fn new(processor: P) -> Self {
+        Compress {
+            processor,
+            min_repeats: 2,
+        }
+    }
+
+    /// Set the shortest run of identical subtrees that is folded.
+    ///
+    /// By default, any subtree that repeats at least twice is folded.
+    pub fn min_repeats(mut self, min_repeats: usize) -> Self {
+        self.min_repeats = min_repeats.max(2);
+        self
+    }
+}
+
+impl<P: Processor> Processor for Compress<P> {
+    fn process(&self, mut tree: Tree) {
+        fold(&mut tree, self.min_repeats);
+        self.processor.process(tree);
+    }
+}
+
+/// Folds repeated subtrees of `tree`, like [`Compress`] does with its default
+/// settings.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{pretty::Pretty, Transformed};
+/// # use tracing_forest::processor::compress::compress;
+/// let formatter = Transformed::new(Pretty::new(), compress);
+/// ```
+pub fn compress(mut tree: Tree) -> Tree {
+    fold(&mut tree, 2);
+    tree
+}
+
+fn fold(tree: &mut Tree, min_repeats: usize) {
+    let span = match &mut tree.kind {
+        TreeKind::Span(span) => span,
+        TreeKind::Event(_) => return,
+    };
+
+    let mut children = Vec::with_capacity(span.children.len());
+    let mut remaining = std::mem::take(&mut span.children).into_iter().peekable();
+    while let Some(first) = remaining.next() {
+        let mut run = vec![first];
+        while let Some(next) = remaining.next_if(|next| same_shape(&run[0], next)) {
+            run.push(next);
+        }
+
+        if run.len() >= min_repeats {
+            let durations = run.iter().map(duration).collect::<Vec<_>>();
+            run.truncate(1);
+            let first = &mut run[0];
+            first.annotate_field("repeated", durations.len());
+            if let TreeKind::Span(_) = first.kind {
+                let min = durations.iter().min().copied().unwrap_or_default();
+                let max = durations.iter().max().copied().unwrap_or_default();
+                let avg = durations.iter().sum::<Duration>() / durations.len() as u32;
+                for (key, duration) in [("min", min), ("avg", avg), ("max", max)] {
+                    let duration = duration.as_nanos() as f64;
+                    first.annotate_field(key, DurationDisplay(duration, DurationFormat::Auto));
+                }
+            }
+        }
+
+        // Runs are found before folding, so repeats inside of subtrees that
+        // differ are still told apart
+        for mut child in run {
+            fold(&mut child, min_repeats);
+            children.push(child);
+        }
+    }
+    span.children = children;
+}
+
+fn duration(tree: &Tree) -> Duration {
+    match &tree.kind {
+        TreeKind::Span(span) => span.duration_total,
+        TreeKind::Event(_) => Duration::ZERO,
+    }
+}
+
+fn same_shape(a: &Tree, b: &Tree) -> bool {
+    if a.attrs.level != b.attrs.level {
+        return false;
+    }
+    match (&a.kind, &b.kind) {
+        (TreeKind::Event(a), TreeKind::Event(b)) => {
+            a.message == b.message
+                && a.tag == b.tag
+                && a.fields.len() == b.fields.len()
+                && a.fields
+                    .iter()
+                    .zip(b.fields.iter())
+                    .all(|(a, b)| a.key == b.key)
+        }
+        (TreeKind::Span(a), TreeKind::Span(b)) => {
+            a.name == b.name
+                && a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(b.children.iter())
+                    .all(|(a, b)| same_shape(a, b))
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,362 @@
+//! A [`Transport`] that sends trees to Datadog as traces or logs.
+//!
+//! See [`Datadog`] for more details.
+//!
+//! [`Transport`]: crate::processor::net::Transport
+
+use crate::layer::{Tree, TreeKind, TreeSpan};
+use crate::processor::net::Transport;
+use serde_json::{json, Map, Value};
+use std::io;
+use std::time::Duration;
+use tracing::Level;
+
+/// Where a [`Datadog`] transport sends trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Intake {
+    Traces,
+    Logs,
+}
+
+/// A [`Transport`] that sends [`Tree`]s to Datadog, either as APM traces
+/// through the Datadog Agent, or as logs to the log intake.
+///
+/// With [`Datadog::agent`], every span of a tree becomes a Datadog span of
+/// the same trace, whose ID is taken from the [`Uuid`] of the root span. The
+/// span's fields and the messages of the events logged directly inside of it
+/// are recorded in its `meta`, and spans that are at the `ERROR` level or
+/// directly contain an `ERROR` event are marked as errors. Events logged
+/// outside of any span aren't sent.
+///
+/// With [`Datadog::logs`], every event of a tree becomes a log with the
+/// `dd.trace_id` and `dd.span_id` of the span it occurred in, so logs can be
+/// correlated with traces sent by the agent.
+///
+/// The service, environment, and version given to the transport are
+/// attached to every span and log. The resource of a span is its name,
+/// unless it has the field given to [`resource_field`]. Only plain HTTP is
+/// supported unless a TLS feature of `ureq` is enabled, which the default
+/// log intake URL requires.
+///
+/// The traces or logs of up to 50 trees are sent in each request, waiting up
+/// to a second after a tree is queued for others to join it, which can be
+/// changed with [`Resilient::batch`].
+///
+/// Wrap a [`Datadog`] in a [`Resilient`] processor to send trees with it.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::processor::datadog::Datadog;
+/// # use tracing_forest::processor::net::Resilient;
+/// # use tracing_forest::Processor;
+/// let datadog = Datadog::agent("http://localhost:8126")
+///     .service("checkout")
+///     .env("production")
+///     .version("1.4.2")
+///     .resource_field("route");
+///
+/// let layer = Resilient::new(datadog).into_layer();
+/// ```
+///
+/// [`Transport`]: crate::processor::net::Transport
+/// [`Uuid`]: ::uuid::Uuid
+/// [`resource_field`]: Datadog::resource_field
+/// [`Resilient`]: crate::processor::net::Resilient
+/// [`Resilient::batch`]: crate::processor::net::Resilient::batch
+pub struct Datadog {
+    agent: ureq::Agent,
+    intake: Intake,
+    url: String,
+    api_key: Option<String>,
+    service: String,
+    env: Option<String>,
+    version: Option<String>,
+    resource_field: Option<&'static str>,
+}
+
+impl Datadog {
+    /// Construct a new [`Datadog`] transport sending traces to the Datadog
+    /// Agent at `url`, like `http://localhost:8126`.
+    pub fn agent(url: &str) -> Self {
+        Datadog::new(Intake::Traces, url, None)
+    }
+
+    /// Construct a new [`Datadog`] transport sending logs to the log intake,
+    /// authenticated with `api_key`.
+    ///
+    /// By default, logs are sent to `https://http-intake.logs.datadoghq.com`,
+    /// which can be changed for other Datadog sites with [`api_url`].
+    ///
+    /// [`api_url`]: Datadog::api_url
+    pub fn logs(api_key: &str) -> Self {
+        Datadog::new(
+            Intake::Logs,
+            "https://http-intake.logs.datadoghq.com",
+            Some(api_key.to_string()),
+        )
+    }
+
+    fn new(intake: Intake, url: &str, api_key: Option<String>) -> Self {
+        Datadog {
+            agent: ureq::Agent::new(),
+            intake,
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            service: "unknown_service".to_string(),
+            env: None,
+            version: None,
+            resource_field: None,
+        }
+    }
+
+    /// Set the URL that traces or logs are sent to.
+    pub fn api_url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set the service that spans and logs belong to.
+    ///
+    /// By default, the service is `unknown_service`.
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = service.to_string();
+        self
+    }
+
+    /// Set the environment tag, like `production`.
+    pub fn env(mut self, env: &str) -> Self {
+        self.env = Some(env.to_string());
+        self
+    }
+
+    /// Set the version tag of the service.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Use the value of the `key` field of a span as its resource, like a
+    /// route or a query, instead of its name.
+    pub fn resource_field(mut self, key: &'static str) -> Self {
+        self.resource_field = Some(key);
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        match self.intake {
+            Intake::Traces => format!("{}/v0.3/traces", self.url),
+            Intake::Logs => format!("{}/api/v2/logs", self.url),
+        }
+    }
+
+    /// Returns the elements of a request body for `tree`, which are its trace
+    /// or its logs.
+    fn elements(&self, tree: &Tree) -> Vec<Value> {
+        let trace_id = trace_id(tree);
+        let mut next_id = 1;
+        let mut items = Vec::new();
+        self.flatten(tree, trace_id, 0, &mut next_id, &mut items);
+        match self.intake {
+            // The agent takes a list of traces, each a list of spans
+            Intake::Traces if !items.is_empty() => vec![Value::Array(items)],
+            Intake::Traces | Intake::Logs => items,
+        }
+    }
+
+    fn submit(&self, agent: &ureq::Agent, records: &[&[u8]]) -> io::Result<()> {
+        let mut body = vec![b'['];
+        for record in records.iter().filter(|record| !record.is_empty()) {
+            if body.len() > 1 {
+                body.push(b',');
+            }
+            body.extend_from_slice(record);
+        }
+        if body.len() == 1 {
+            // None of the trees had anything to send
+            return Ok(());
+        }
+        body.push(b']');
+
+        let request = match self.intake {
+            Intake::Traces => agent.put(&self.endpoint()),
+            Intake::Logs => agent.post(&self.endpoint()),
+        };
+        let mut request = request.set("Content-Type", "application/json");
+        if let Some(api_key) = &self.api_key {
+            request = request.set("DD-API-KEY", api_key);
+        }
+        request.send_bytes(&body).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn flatten(
+        &self,
+        tree: &Tree,
+        trace_id: u64,
+        parent_id: u64,
+        next_id: &mut u64,
+        items: &mut Vec<Value>,
+    ) {
+        let span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => {
+                if self.intake == Intake::Logs {
+                    items.push(self.log(tree, trace_id, parent_id));
+                }
+                return;
+            }
+        };
+
+        // Span IDs only need to be unique within a trace
+        let span_id = trace_id.rotate_left(32).wrapping_add(*next_id).max(1);
+        *next_id += 1;
+        if self.intake == Intake::Traces {
+            items.push(self.span(tree, span, trace_id, span_id, parent_id));
+        }
+        for child in span.children.iter() {
+            self.flatten(child, trace_id, span_id, next_id, items);
+        }
+    }
+
+    fn span(
+        &self,
+        tree: &Tree,
+        span: &TreeSpan,
+        trace_id: u64,
+        span_id: u64,
+        parent_id: u64,
+    ) -> Value {
+        let mut meta = self.tags();
+        for kv in span.fields.iter() {
+            meta.entry(kv.key)
+                .or_insert_with(|| kv.value.as_str().into());
+        }
+
+        let mut error = tree.attrs.level == Level::ERROR;
+        let events = span.children.iter().filter_map(|child| match &child.kind {
+            TreeKind::Event(event) => Some((child.attrs.level, event)),
+            TreeKind::Span(_) => None,
+        });
+        for (idx, (level, event)) in events.enumerate() {
+            error |= level == Level::ERROR;
+            meta.insert(
+                format!("events.{}", idx),
+                format!("{} {}", level, event.message).into(),
+            );
+        }
+
+        let resource = self
+            .resource_field
+            .and_then(|key| span.fields.iter().find(|kv| kv.key == key))
+            .map_or(span.name, |kv| kv.value.trim_matches('"'));
+
+        json!({
+            "trace_id": trace_id,
+            "span_id": span_id,
+            "parent_id": parent_id,
+            "name": span.name,
+            "resource": resource,
+            "service": self.service,
+            "type": "custom",
+            "start": tree.attrs.timestamp.timestamp_nanos_opt().unwrap_or(0),
+            "duration": span.duration_total.as_nanos() as u64,
+            "error": error as u8,
+            "meta": meta,
+        })
+    }
+
+    fn log(&self, tree: &Tree, trace_id: u64, span_id: u64) -> Value {
+        let mut log = Map::new();
+        if let TreeKind::Event(event) = &tree.kind {
+            log.insert("message".into(), event.message.as_ref().into());
+            log.insert("logger.name".into(), event.target.into());
+            if let Some(tag) = event.tag {
+                log.insert("tag".into(), tag.message.into());
+            }
+            for kv in event.fields.iter() {
+                log.insert(kv.key.into(), kv.value.as_str().into());
+            }
+        }
+
+        let status = match tree.attrs.level {
+            Level::TRACE | Level::DEBUG => "debug",
+            Level::INFO => "info",
+            Level::WARN => "warning",
+            Level::ERROR => "error",
+        };
+        log.insert("status".into(), status.into());
+        log.insert("service".into(), self.service.as_str().into());
+        log.insert("ddsource".into(), "rust".into());
+        log.insert(
+            "timestamp".into(),
+            tree.attrs.timestamp.timestamp_millis().into(),
+        );
+        let tags = self
+            .tags()
+            .into_iter()
+            .filter_map(|(key, value)| Some(format!("{}:{}", key, value.as_str()?)))
+            .collect::<Vec<_>>();
+        if !tags.is_empty() {
+            log.insert("ddtags".into(), tags.join(",").into());
+        }
+        if span_id != 0 {
+            log.insert("dd.trace_id".into(), trace_id.to_string().into());
+            log.insert("dd.span_id".into(), span_id.to_string().into());
+        }
+        Value::Object(log)
+    }
+
+    fn tags(&self) -> Map<String, Value> {
+        let mut tags = Map::new();
+        if let Some(env) = &self.env {
+            tags.insert("env".into(), env.as_str().into());
+        }
+        if let Some(version) = &self.version {
+            tags.insert("version".into(), version.as_str().into());
+        }
+        tags
+    }
+}
+
+/// Returns the lower 64 bits of the root's [`Uuid`], since Datadog trace IDs
+/// are 64 bits.
+///
+/// [`Uuid`]: ::uuid::Uuid
+fn trace_id(tree: &Tree) -> u64 {
+    (tree.attrs.uuid.as_u128() as u64).max(1)
+}
+
+impl Transport for Datadog {
+    type Connection = ureq::Agent;
+
+    fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+        // Elements are separated by commas, so the records of a batch can be
+        // joined into one array, and trees with nothing to send leave the
+        // record empty
+        for (i, element) in self.elements(&tree).iter().enumerate() {
+            if i > 0 {
+                record.push(b',');
+            }
+            serde_json::to_writer(&mut *record, element)?;
+        }
+        Ok(())
+    }
+
+    fn connect(&self) -> io::Result<ureq::Agent> {
+        Ok(self.agent.clone())
+    }
+
+    fn send(&self, agent: &mut ureq::Agent, record: &[u8]) -> io::Result<()> {
+        self.submit(agent, &[record])
+    }
+
+    fn send_batch(&self, agent: &mut ureq::Agent, records: &[Vec<u8>]) -> io::Result<()> {
+        let records = records.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        self.submit(agent, &records)
+    }
+
+    fn batch(&self) -> (usize, Duration) {
+        (50, Duration::from_secs(1))
+    }
+}
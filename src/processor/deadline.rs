@@ -0,0 +1,62 @@
+//! A [`Processor`] that drops trees once they're too old to be worth
+//! processing.
+//!
+//! See [`Deadline`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A [`Processor`] that drops [`Tree`]s older than a maximum age by the time
+/// they're processed, and forwards the rest to another processor.
+///
+/// This is meant for live-monitoring sinks, like a dashboard or an alerting
+/// pipeline, that would rather catch up on fresh trees than work through a
+/// backlog of stale ones, like after a collector was down for a while and a
+/// [`worker`] queue filled up. Archival sinks should keep everything, so only
+/// wrap the live sink, and [`tee`] trees to both.
+///
+/// The age of a tree is measured by the monotonic clock from when its root
+/// was [closed], so long-running spans aren't dropped as soon as they close,
+/// and trees aren't dropped or kept because the wall clock was stepped. Trees
+/// that weren't collected by a [`TreeLayer`] don't know when they were
+/// closed, so they're never dropped.
+///
+/// To initialize a new [`Deadline`], see [`Processor::deadline`].
+///
+/// [`worker`]: crate::processor::worker
+/// [`tee`]: Processor::tee
+/// [closed]: Tree::closed
+/// [`TreeLayer`]: crate::layer::TreeLayer
+pub struct Deadline<P> {
+    processor: P,
+    max_age: Duration,
+    dropped: AtomicU64,
+}
+
+impl<P> Deadline<P> {
+    pub(crate) fn new(processor: P, max_age: Duration) -> Self {
+        Deadline {
+            processor,
+            max_age,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns how many trees were dropped for being too old.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<P: Processor> Processor for Deadline<P> {
+    fn process(&self, tree: Tree) {
+        let age = tree.closed().map(|closed| closed.elapsed());
+        if age.is_some_and(|age| age > self.max_age) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.processor.process(tree);
+    }
+}
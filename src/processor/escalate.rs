@@ -0,0 +1,142 @@
+//! A [`Processor`] that raises the level of trees matching escalation rules.
+//!
+//! See [`Escalate`] for more details.
+
+use crate::capture::glob;
+use crate::layer::{Tree, TreeKind};
+use crate::processor::Processor;
+use std::fmt;
+use std::time::Duration;
+use tracing::Level;
+
+/// Rules for raising the effective level of a [`Tree`].
+///
+/// Each rule checks the whole tree, and the most severe level of all
+/// matching rules becomes the level of the root. Rules never lower the level
+/// of a tree.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tracing::Level;
+/// # use tracing_forest::processor::escalate::Rules;
+/// let rules = Rules::new()
+///     .slower_than(Duration::from_secs(5), Level::WARN)
+///     .tag("security.*", Level::ERROR);
+/// ```
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+type CustomRule = Box<dyn Fn(&Tree) -> Option<Level> + Send + Sync>;
+
+enum Rule {
+    SlowerThan(Duration, Level),
+    Tag(String, Level),
+    Custom(CustomRule),
+}
+
+impl Rules {
+    /// Construct a new [`Rules`] without any rules.
+    pub fn new() -> Self {
+        Rules::default()
+    }
+
+    /// Escalate trees containing a span that was entered for longer than
+    /// `duration` to `level`.
+    pub fn slower_than(mut self, duration: Duration, level: Level) -> Self {
+        self.rules.push(Rule::SlowerThan(duration, level));
+        self
+    }
+
+    /// Escalate trees containing an event with a tag whose message matches
+    /// `pattern` to `level`.
+    ///
+    /// Patterns are globs, where `*` matches any sequence of characters and
+    /// `?` matches any single character.
+    pub fn tag(mut self, pattern: impl Into<String>, level: Level) -> Self {
+        self.rules.push(Rule::Tag(pattern.into(), level));
+        self
+    }
+
+    /// Escalate trees to the level returned by `rule`, if any.
+    pub fn rule<F>(mut self, rule: F) -> Self
+    where
+        F: 'static + Fn(&Tree) -> Option<Level> + Send + Sync,
+    {
+        self.rules.push(Rule::Custom(Box::new(rule)));
+        self
+    }
+
+    /// Returns the most severe level of all rules matching `tree`, or `None`
+    /// if no rules match.
+    pub fn level(&self, tree: &Tree) -> Option<Level> {
+        self.rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::SlowerThan(duration, level) => slower_than(tree, *duration).then_some(*level),
+                Rule::Tag(pattern, level) => {
+                    let mut tags = tree.tags().into_iter();
+                    tags.any(|tag| glob(pattern, tag.message)).then_some(*level)
+                }
+                Rule::Custom(rule) => rule(tree),
+            })
+            // Less verbose levels compare as smaller
+            .min()
+    }
+}
+
+impl fmt::Debug for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rules")
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+fn slower_than(tree: &Tree, duration: Duration) -> bool {
+    match &tree.kind {
+        TreeKind::Span(span) => {
+            span.duration_total > duration
+                || span
+                    .children
+                    .iter()
+                    .any(|child| slower_than(child, duration))
+        }
+        TreeKind::Event(_) => false,
+    }
+}
+
+/// A [`Processor`] that raises the level of [`Tree`]s matching escalation
+/// [`Rules`] before forwarding them to another processor.
+///
+/// Since the level of the root changes, this affects processors that filter
+/// by level, and how formatters render the root, like the color of the root
+/// line in [`Pretty`] output.
+///
+/// To initialize a new [`Escalate`], see [`Processor::escalate`].
+///
+/// [`Pretty`]: crate::formatter::pretty::Pretty
+pub struct Escalate<P> {
+    processor: P,
+    rules: Rules,
+}
+
+impl<P> Escalate<P> {
+    pub(crate) fn new(processor: P, rules: Rules) -> Self {
+        Escalate { processor, rules }
+    }
+}
+
+impl<P: Processor> Processor for Escalate<P> {
+    fn process(&self, mut tree: Tree) {
+        if let Some(level) = self.rules.level(&tree) {
+            if level < tree.attrs.level {
+                tree.attrs.level = level;
+            }
+        }
+        self.processor.process(tree);
+    }
+}
@@ -0,0 +1,36 @@
+//! A [`Processor`] that only processes trees matching a predicate.
+//!
+//! See [`Filter`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+
+/// A [`Processor`] that forwards [`Tree`]s matching a predicate to another
+/// processor, and drops the rest.
+///
+/// To initialize a new [`Filter`], see [`Processor::filter`].
+pub struct Filter<P, F> {
+    processor: P,
+    predicate: F,
+}
+
+impl<P, F> Filter<P, F> {
+    pub(crate) fn new(processor: P, predicate: F) -> Self {
+        Filter {
+            processor,
+            predicate,
+        }
+    }
+}
+
+impl<P, F> Processor for Filter<P, F>
+where
+    P: Processor,
+    F: 'static + Fn(&Tree) -> bool,
+{
+    fn process(&self, tree: Tree) {
+        if (self.predicate)(&tree) {
+            self.processor.process(tree);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! A [`Transport`] that sends trees to Honeycomb as events.
+//!
+//! See [`Honeycomb`] for more details.
+//!
+//! [`Transport`]: crate::processor::net::Transport
+
+use crate::layer::{Tree, TreeKind};
+use crate::processor::net::Transport;
+use serde_json::{json, Map, Value};
+use std::io;
+use std::time::Duration;
+
+/// A [`Transport`] that flattens [`Tree`]s into [Honeycomb] events and sends
+/// them to the batch API.
+///
+/// The events of up to 50 trees are sent in each request, waiting up to 100
+/// milliseconds after a tree is queued for others to join it, which can be
+/// changed with [`Resilient::batch`].
+///
+/// Every span becomes an event with its name, level, fields, and
+/// `duration_ms`, along with `trace.trace_id`, `trace.span_id`, and
+/// `trace.parent_id`, so Honeycomb can display the tree as a trace. The
+/// trace ID is the [`Uuid`] of the root span. Events logged inside of spans
+/// become span events of the span they occurred in, and events logged
+/// outside of any span become plain events.
+///
+/// Fields with the same names as these are dropped. Only plain HTTP is
+/// supported unless a TLS feature of `ureq` is enabled, which the default
+/// API URL requires.
+///
+/// Wrap a [`Honeycomb`] in a [`Resilient`] processor to send trees with it.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::processor::honeycomb::Honeycomb;
+/// # use tracing_forest::processor::net::Resilient;
+/// # use tracing_forest::Processor;
+/// let honeycomb = Honeycomb::new("hcaik_01hx", "checkout")
+///     .service_name("checkout-api")
+///     .sample_rate(0.1);
+///
+/// let layer = Resilient::new(honeycomb).into_layer().sample_rate(0.1);
+/// ```
+///
+/// [Honeycomb]: https://www.honeycomb.io
+/// [`Transport`]: crate::processor::net::Transport
+/// [`Uuid`]: ::uuid::Uuid
+/// [`Resilient`]: crate::processor::net::Resilient
+/// [`Resilient::batch`]: crate::processor::net::Resilient::batch
+pub struct Honeycomb {
+    agent: ureq::Agent,
+    api_key: String,
+    api_url: String,
+    dataset: String,
+    service_name: Option<String>,
+    sample_rate: u64,
+}
+
+impl Honeycomb {
+    /// Construct a new [`Honeycomb`] transport sending events to `dataset`,
+    /// authenticated with `api_key`.
+    ///
+    /// By default, events are sent to `https://api.honeycomb.io`.
+    pub fn new(api_key: &str, dataset: &str) -> Self {
+        Honeycomb {
+            agent: ureq::Agent::new(),
+            api_key: api_key.to_string(),
+            api_url: "https://api.honeycomb.io".to_string(),
+            dataset: dataset.to_string(),
+            service_name: None,
+            sample_rate: 1,
+        }
+    }
+
+    /// Set the URL of the API, like the address of a proxy.
+    pub fn api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set the `service.name` recorded in every event.
+    pub fn service_name(mut self, service_name: &str) -> Self {
+        self.service_name = Some(service_name.to_string());
+        self
+    }
+
+    /// Set the rate that trees are sampled at before they're sent, so that
+    /// Honeycomb counts each event as the ones that were dropped.
+    ///
+    /// This should be the rate given to [`TreeLayer::sample_rate`] or
+    /// [`Processor::sample`]. A rate of `0.1` is reported as a `samplerate`
+    /// of `10`.
+    ///
+    /// [`TreeLayer::sample_rate`]: crate::layer::TreeLayer::sample_rate
+    /// [`Processor::sample`]: crate::Processor::sample
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = if rate > 0.0 {
+            (1.0 / rate.min(1.0)).round() as u64
+        } else {
+            1
+        };
+        self
+    }
+
+    fn events(&self, tree: &Tree) -> Vec<Value> {
+        let mut batch = Vec::new();
+        match &tree.kind {
+            TreeKind::Span(_) => {
+                let trace = tree.attrs.uuid.to_simple().to_string();
+                let mut next_id = 0;
+                self.flatten(tree, &trace, None, &mut next_id, &mut batch);
+            }
+            TreeKind::Event(_) => batch.push(self.event(tree, Map::new())),
+        }
+        batch
+    }
+
+    fn flatten(
+        &self,
+        tree: &Tree,
+        trace: &str,
+        parent: Option<&str>,
+        next_id: &mut u64,
+        batch: &mut Vec<Value>,
+    ) {
+        let mut data = Map::new();
+        data.insert("trace.trace_id".into(), trace.into());
+
+        let span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => {
+                data.insert("trace.parent_id".into(), parent.into());
+                data.insert("meta.annotation_type".into(), "span_event".into());
+                return batch.push(self.event(tree, data));
+            }
+        };
+
+        let id = format!("{:016x}", *next_id);
+        *next_id += 1;
+        data.insert("trace.span_id".into(), id.as_str().into());
+        if let Some(parent) = parent {
+            data.insert("trace.parent_id".into(), parent.into());
+        }
+        data.insert("name".into(), span.name.into());
+        data.insert(
+            "duration_ms".into(),
+            (span.duration_total.as_secs_f64() * 1000.0).into(),
+        );
+        data.insert("level".into(), tree.attrs.level.as_str().into());
+        for kv in span.fields.iter() {
+            data.entry(kv.key)
+                .or_insert_with(|| kv.value.as_str().into());
+        }
+        batch.push(self.entry(tree, data));
+
+        for child in span.children.iter() {
+            self.flatten(child, trace, Some(&id), next_id, batch);
+        }
+    }
+
+    fn event(&self, tree: &Tree, mut data: Map<String, Value>) -> Value {
+        if let TreeKind::Event(event) = &tree.kind {
+            data.insert("name".into(), event.message.as_ref().into());
+            data.insert("level".into(), tree.attrs.level.as_str().into());
+            if let Some(tag) = event.tag {
+                data.insert("tag".into(), tag.message.into());
+            }
+            for kv in event.fields.iter() {
+                data.entry(kv.key)
+                    .or_insert_with(|| kv.value.as_str().into());
+            }
+        }
+        self.entry(tree, data)
+    }
+
+    fn entry(&self, tree: &Tree, mut data: Map<String, Value>) -> Value {
+        if let Some(service_name) = &self.service_name {
+            data.insert("service.name".into(), service_name.as_str().into());
+        }
+        json!({
+            "time": tree.attrs.timestamp.to_rfc3339(),
+            "samplerate": self.sample_rate,
+            "data": data,
+        })
+    }
+
+    fn post(&self, agent: &ureq::Agent, records: &[&[u8]]) -> io::Result<()> {
+        let mut body = vec![b'['];
+        for (i, record) in records.iter().enumerate() {
+            if i > 0 {
+                body.push(b',');
+            }
+            body.extend_from_slice(record);
+        }
+        body.push(b']');
+
+        agent
+            .post(&format!("{}/1/batch/{}", self.api_url, self.dataset))
+            .set("X-Honeycomb-Team", &self.api_key)
+            .set("Content-Type", "application/json")
+            .send_bytes(&body)
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl Transport for Honeycomb {
+    type Connection = ureq::Agent;
+
+    fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+        // Events are separated by commas, so the records of a batch can be
+        // joined into one array
+        for (i, event) in self.events(&tree).iter().enumerate() {
+            if i > 0 {
+                record.push(b',');
+            }
+            serde_json::to_writer(&mut *record, event)?;
+        }
+        Ok(())
+    }
+
+    fn connect(&self) -> io::Result<ureq::Agent> {
+        Ok(self.agent.clone())
+    }
+
+    fn send(&self, agent: &mut ureq::Agent, record: &[u8]) -> io::Result<()> {
+        self.post(agent, &[record])
+    }
+
+    fn send_batch(&self, agent: &mut ureq::Agent, records: &[Vec<u8>]) -> io::Result<()> {
+        let records = records.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        self.post(agent, &records)
+    }
+
+    fn batch(&self) -> (usize, Duration) {
+        (50, Duration::from_millis(100))
+    }
+}
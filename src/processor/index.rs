@@ -0,0 +1,461 @@
+//! A [`Processor`] that writes logs to a file along with an index of where
+//! each tree is.
+//!
+//! See [`IndexedProcessor`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::formatter::Formatter;
+use crate::layer::{Tree, TreeKind};
+use crate::processor::buffers::Buffers;
+use crate::processor::Processor;
+use crate::writer;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::Level;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+/// The header line of CSV indexes.
+pub const CSV_HEADER: &str = "id,root,start,duration_ns,level,offset,len";
+
+/// The format of the index written by an [`IndexedProcessor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// Comma-separated values, starting with the [`CSV_HEADER`]. Fields that
+    /// contain commas or quotes are quoted, with quotes doubled.
+    Csv,
+    /// One JSON object per line, with the same keys as the CSV columns.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    JsonLines,
+}
+
+/// Where a tree is in a file written by an [`IndexedProcessor`], and what it
+/// is, from one line of the index.
+///
+/// The columns of the index are:
+///
+/// * `id`: The [`Uuid`] of the tree, or empty without the `uuid` feature.
+/// * `root`: The name of the root span, or the message of an event, with
+///   newlines replaced by spaces.
+/// * `start`: When the tree started, in RFC 3339, or empty without the
+///   `chrono` feature.
+/// * `duration_ns`: The duration of the root span in nanoseconds, or `0` for
+///   an event.
+/// * `level`: The most severe level in the tree, like `WARN`.
+/// * `offset` and `len`: The range of bytes of the formatted tree in the
+///   file.
+///
+/// [`Uuid`]: ::uuid::Uuid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The ID of the tree.
+    #[cfg(feature = "uuid")]
+    pub id: Uuid,
+    /// The name of the root span, or the message of an event.
+    pub root: String,
+    /// When the tree started.
+    #[cfg(feature = "chrono")]
+    pub start: DateTime<Utc>,
+    /// The duration of the root span.
+    pub duration: Duration,
+    /// The most severe level in the tree.
+    pub level: Level,
+    /// Where the tree starts in the file.
+    pub offset: u64,
+    /// How many bytes the tree takes up in the file.
+    pub len: u64,
+}
+
+impl IndexEntry {
+    fn new(tree: &Tree, offset: u64, len: u64) -> Self {
+        let (root, duration) = match &tree.kind {
+            TreeKind::Span(span) => (span.name, span.duration_total),
+            TreeKind::Event(event) => (event.message.as_ref(), Duration::ZERO),
+        };
+        // Every entry must fit on one line
+        let root = root.replace(['\n', '\r'], " ");
+        IndexEntry {
+            #[cfg(feature = "uuid")]
+            id: tree.attrs.uuid,
+            root,
+            #[cfg(feature = "chrono")]
+            start: tree.attrs.timestamp,
+            duration,
+            level: tree.most_severe_level(),
+            offset,
+            len,
+        }
+    }
+
+    /// Writes this entry as one line of an index.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if writing to `writer` fails.
+    pub fn write<W: Write>(&self, format: IndexFormat, mut writer: W) -> io::Result<()> {
+        #[cfg(feature = "uuid")]
+        let id = self.id.to_string();
+        #[cfg(not(feature = "uuid"))]
+        let id = String::new();
+        #[cfg(feature = "chrono")]
+        let start = self.start.to_rfc3339();
+        #[cfg(not(feature = "chrono"))]
+        let start = String::new();
+
+        match format {
+            IndexFormat::Csv => writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                id,
+                csv_escape(&self.root),
+                start,
+                self.duration.as_nanos(),
+                self.level,
+                self.offset,
+                self.len
+            ),
+            #[cfg(feature = "json")]
+            IndexFormat::JsonLines => {
+                let line = serde_json::json!({
+                    "id": Some(id).filter(|id| !id.is_empty()),
+                    "root": self.root,
+                    "start": Some(start).filter(|start| !start.is_empty()),
+                    "duration_ns": self.duration.as_nanos() as u64,
+                    "level": self.level.as_str(),
+                    "offset": self.offset,
+                    "len": self.len,
+                });
+                writeln!(writer, "{}", line)
+            }
+        }
+    }
+
+    /// Parses one line of an index.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error of kind [`InvalidData`] if the line isn't an entry in
+    /// `format`, like the [`CSV_HEADER`].
+    ///
+    /// [`InvalidData`]: io::ErrorKind::InvalidData
+    pub fn parse(line: &str, format: IndexFormat) -> io::Result<Self> {
+        let columns = match format {
+            IndexFormat::Csv => csv_split(line)?,
+            #[cfg(feature = "json")]
+            IndexFormat::JsonLines => json_columns(line)?,
+        };
+        let [_id, root, _start, duration, level, offset, len]: [String; 7] = columns
+            .try_into()
+            .map_err(|_| invalid("wrong number of columns"))?;
+
+        Ok(IndexEntry {
+            #[cfg(feature = "uuid")]
+            id: Uuid::parse_str(&_id).map_err(|_| invalid("invalid id"))?,
+            root,
+            #[cfg(feature = "chrono")]
+            start: DateTime::parse_from_rfc3339(&_start)
+                .map_err(|_| invalid("invalid start"))?
+                .with_timezone(&Utc),
+            duration: Duration::from_nanos(
+                duration.parse().map_err(|_| invalid("invalid duration"))?,
+            ),
+            level: level.parse().map_err(|_| invalid("invalid level"))?,
+            offset: offset.parse().map_err(|_| invalid("invalid offset"))?,
+            len: len.parse().map_err(|_| invalid("invalid len"))?,
+        })
+    }
+
+    /// Reads the formatted tree that this entry points to from `file`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if seeking or reading from `file` fails, like if it
+    /// was rotated since the index was written.
+    pub fn read_tree<R: Read + Seek>(&self, file: &mut R) -> io::Result<Vec<u8>> {
+        let mut tree = vec![0; self.len as usize];
+        file.seek(SeekFrom::Start(self.offset))?;
+        file.read_exact(&mut tree)?;
+        Ok(tree)
+    }
+}
+
+/// Reads the entries of an index, skipping the [`CSV_HEADER`] and empty
+/// lines.
+///
+/// ## Examples
+///
+/// ```
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// # use tracing_forest::processor::index::{read_index, IndexFormat};
+/// # fn find(path: &std::path::Path) -> std::io::Result<()> {
+/// let index = BufReader::new(File::open(path.with_extension("idx"))?);
+/// let mut log = File::open(path)?;
+///
+/// for entry in read_index(index, IndexFormat::Csv) {
+///     let entry = entry?;
+///     if entry.root == "checkout" && entry.level == tracing::Level::ERROR {
+///         let tree = entry.read_tree(&mut log)?;
+///         println!("{}", String::from_utf8_lossy(&tree));
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_index<R: BufRead>(
+    reader: R,
+    format: IndexFormat,
+) -> impl Iterator<Item = io::Result<IndexEntry>> {
+    reader.lines().filter_map(move |line| match line {
+        Ok(line) if line.is_empty() || line == CSV_HEADER => None,
+        Ok(line) => Some(IndexEntry::parse(&line, format)),
+        Err(err) => Some(Err(err)),
+    })
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_split(line: &str) -> io::Result<Vec<String>> {
+    let mut columns = Vec::new();
+    let mut column = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                column.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => columns.push(mem::take(&mut column)),
+            c => column.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid("unterminated quote"));
+    }
+    columns.push(column);
+    Ok(columns)
+}
+
+#[cfg(feature = "json")]
+fn json_columns(line: &str) -> io::Result<Vec<String>> {
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    let keys = CSV_HEADER.split(',');
+    Ok(keys
+        .map(|key| match &value[key] {
+            serde_json::Value::String(string) => string.clone(),
+            serde_json::Value::Null => String::new(),
+            other => other.to_string(),
+        })
+        .collect())
+}
+
+/// A [`Processor`] that appends formatted [`Tree`]s to a file, and a line
+/// describing each tree to an index file, so that tools can find a tree in a
+/// large file without reading all of it.
+///
+/// Each line of the index is an [`IndexEntry`] with the ID, root name, start,
+/// duration, and most severe level of a tree, and the range of bytes it was
+/// written to. Read an index with [`read_index`].
+///
+/// The file isn't rotated by default. With [`rotate`], the file and its
+/// index are rotated together, like a [`RotatingFile`], so `app.idx.1` is
+/// the index of `app.log.1`.
+///
+/// If writing a tree fails partway, the offsets of later trees are taken
+/// from the size of the file, so they still point to where they were
+/// written.
+///
+/// To initialize a new [`IndexedProcessor`], see [`indexed`].
+///
+/// [`rotate`]: IndexedProcessor::rotate
+/// [`RotatingFile`]: crate::writer::RotatingFile
+pub struct IndexedProcessor<F> {
+    formatter: F,
+    format: IndexFormat,
+    log_path: PathBuf,
+    index_path: PathBuf,
+    max_bytes: Option<u64>,
+    keep: usize,
+    files: Mutex<Files>,
+    buffers: Buffers,
+}
+
+struct Files {
+    log: File,
+    log_len: u64,
+    index: File,
+    index_len: u64,
+}
+
+impl<F> IndexedProcessor<F> {
+    /// Set the format of the index.
+    ///
+    /// By default, the index is [`IndexFormat::Csv`].
+    pub fn format(mut self, format: IndexFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Rotate the file and its index once the file grows past `max_bytes`.
+    ///
+    /// Like a [`RotatingFile`], trees are never split across files, and 5 old
+    /// files are kept by default.
+    ///
+    /// [`RotatingFile`]: crate::writer::RotatingFile
+    pub fn rotate(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set how many old files and indexes are kept once they're
+    /// [rotated](IndexedProcessor::rotate). With `0`, both are truncated
+    /// instead of renamed.
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    fn rotate_files(&self, files: &mut Files) -> io::Result<()> {
+        let rotated = writer::rotate(&self.log_path, &mut files.log, self.keep)
+            .and_then(|_| writer::rotate(&self.index_path, &mut files.index, self.keep));
+        // Either file may not have been rotated
+        files.resync();
+        rotated
+    }
+}
+
+impl Files {
+    /// Takes the lengths of the files from the file system, after a write or
+    /// rotation that may have failed partway.
+    fn resync(&mut self) {
+        if let Ok(metadata) = self.log.metadata() {
+            self.log_len = metadata.len();
+        }
+        if let Ok(metadata) = self.index.metadata() {
+            self.index_len = metadata.len();
+        }
+    }
+}
+
+impl<F: 'static + Formatter> Processor for IndexedProcessor<F> {
+    fn process(&self, tree: Tree) {
+        let mut buf = self.buffers.take();
+        // Formatting consumes the tree
+        let mut entry = IndexEntry::new(&tree, 0, 0);
+
+        if let Err(err) = self.formatter.fmt(tree, &mut buf) {
+            return error::report(ForestError::Format(err));
+        }
+
+        #[allow(clippy::expect_used)]
+        let mut files = self.files.lock().expect("indexed files poisoned");
+
+        if let Some(max_bytes) = self.max_bytes {
+            if files.log_len > 0 && files.log_len + buf.len() as u64 > max_bytes {
+                if let Err(err) = self.rotate_files(&mut files) {
+                    error::report(ForestError::Write(err));
+                }
+            }
+        }
+
+        if let Err(err) = files.log.write_all(&buf) {
+            files.resync();
+            return error::report(ForestError::Write(err));
+        }
+        entry.offset = files.log_len;
+        entry.len = buf.len() as u64;
+        files.log_len += entry.len;
+        self.buffers.put(buf);
+
+        let mut line = Vec::new();
+        if files.index_len == 0 && self.format == IndexFormat::Csv {
+            line.extend_from_slice(CSV_HEADER.as_bytes());
+            line.push(b'\n');
+        }
+        let written = entry
+            .write(self.format, &mut line)
+            .and_then(|_| files.index.write_all(&line));
+        match written {
+            Ok(()) => files.index_len += line.len() as u64,
+            Err(error) => {
+                let len = files.index_len;
+                files.resync();
+                // The next entry would be appended to a partial line otherwise
+                if files.index_len > len && files.index.write_all(b"\n").is_ok() {
+                    files.index_len += 1;
+                }
+                error::report(ForestError::Processor {
+                    processor: "index",
+                    error,
+                })
+            }
+        }
+    }
+}
+
+/// Initialize a new [`IndexedProcessor`] appending trees to the file at `path`
+/// and their index to the file at `index_path`, creating them if they don't
+/// exist.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{formatter::pretty::Pretty, processor::index::indexed, Processor};
+/// # let dir = std::env::temp_dir().join("tracing-forest-index-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let processor = indexed(Pretty::new(), dir.join("app.log"), dir.join("app.idx"))
+///     .expect("failed to open log files");
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info_span!("checkout").in_scope(|| tracing::info!("paid"));
+/// });
+/// ```
+///
+/// ## Errors
+///
+/// Returns an error if either file cannot be opened.
+pub fn indexed<F, P, Q>(formatter: F, path: P, index_path: Q) -> io::Result<IndexedProcessor<F>>
+where
+    F: 'static + Formatter + Send,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let log = writer::open(path.as_ref())?;
+    let index = writer::open(index_path.as_ref())?;
+
+    Ok(IndexedProcessor {
+        formatter,
+        format: IndexFormat::Csv,
+        log_path: path.as_ref().to_path_buf(),
+        index_path: index_path.as_ref().to_path_buf(),
+        max_bytes: None,
+        keep: 5,
+        files: Mutex::new(Files {
+            log_len: log.metadata()?.len(),
+            log,
+            index_len: index.metadata()?.len(),
+            index,
+        }),
+        buffers: Buffers::default(),
+    })
+}
@@ -0,0 +1,158 @@
+//! A [`Processor`] that sends trees to processors bound to ranges of levels.
+//!
+//! See [`Levels`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::fmt;
+use tracing::Level;
+
+type BoxProcessor = Box<dyn Processor + Send + Sync>;
+
+type Hook = Box<dyn Fn(&Tree) + Send + Sync>;
+
+/// A [`Processor`] that forwards each [`Tree`] to every processor bound to a
+/// range of levels containing it, such as writing `TRACE` and `DEBUG` trees
+/// to a file, `INFO` trees to stdout, and `WARN` and `ERROR` trees to stderr.
+///
+/// A tree is routed by its [most severe level][Tree::most_severe_level], so a
+/// request that logged an error is handled together with other errors. Trees
+/// matching several ranges are sent to each of their processors in the order
+/// they were bound, and trees matching no range are sent to the fallback
+/// processor, if there is one.
+///
+/// To build a [`TreeLayer`] from a [`Levels`] using the formatter and writer
+/// of a builder as the fallback, see [`LayerBuilder::levels_layer`].
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+/// # use tracing_forest::processor::levels::Levels;
+/// let processor = Levels::new()
+///     .bind(Level::TRACE, Level::DEBUG, blocking(Pretty::new(), std::io::sink))
+///     .bind(Level::INFO, Level::INFO, blocking(Pretty::new(), std::io::stdout))
+///     .bind(Level::WARN, Level::ERROR, blocking(Pretty::new(), std::io::stderr))
+///     .hook(Level::ERROR, Level::ERROR, |tree| {
+///         eprintln!("ALERT: {:?}", tree.attrs.level);
+///     });
+///
+/// let _guard = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+/// ```
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+/// [`LayerBuilder::levels_layer`]: crate::builder::LayerBuilder::levels_layer
+#[derive(Default)]
+pub struct Levels {
+    routes: Vec<Route>,
+    fallback: Option<BoxProcessor>,
+}
+
+struct Route {
+    most_verbose: Level,
+    least_verbose: Level,
+    target: Target,
+}
+
+enum Target {
+    Processor(BoxProcessor),
+    Hook(Hook),
+}
+
+impl Levels {
+    /// Construct a new [`Levels`] with no bound processors, which drops every
+    /// tree.
+    pub fn new() -> Self {
+        Levels::default()
+    }
+
+    /// Send trees from `from` to `to` inclusive to `processor`.
+    ///
+    /// The bounds can be given in either order, so
+    /// `bind(Level::TRACE, Level::DEBUG, ..)` and
+    /// `bind(Level::DEBUG, Level::TRACE, ..)` are the same.
+    pub fn bind<P>(self, from: Level, to: Level, processor: P) -> Self
+    where
+        P: Processor + Send + Sync,
+    {
+        self.route(from, to, Target::Processor(Box::new(processor)))
+    }
+
+    /// Call `hook` with trees from `from` to `to` inclusive, such as to raise
+    /// an alert, in addition to any processors they're sent to.
+    ///
+    /// Hooks don't count as processors for deciding whether a tree is sent
+    /// to the fallback processor.
+    pub fn hook<F>(self, from: Level, to: Level, hook: F) -> Self
+    where
+        F: 'static + Fn(&Tree) + Send + Sync,
+    {
+        self.route(from, to, Target::Hook(Box::new(hook)))
+    }
+
+    /// Send trees that match no bound processor to `processor`.
+    pub fn fallback<P>(mut self, processor: P) -> Self
+    where
+        P: Processor + Send + Sync,
+    {
+        self.fallback = Some(Box::new(processor));
+        self
+    }
+
+    fn route(mut self, from: Level, to: Level, target: Target) -> Self {
+        self.routes.push(Route {
+            most_verbose: from.max(to),
+            least_verbose: from.min(to),
+            target,
+        });
+        self
+    }
+}
+
+impl fmt::Debug for Levels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Levels")
+            .field("routes", &self.routes.len())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl Route {
+    fn contains(&self, level: Level) -> bool {
+        self.least_verbose <= level && level <= self.most_verbose
+    }
+}
+
+impl Processor for Levels {
+    fn process(&self, tree: Tree) {
+        let level = tree.most_severe_level();
+        let processors: Vec<&BoxProcessor> = self
+            .routes
+            .iter()
+            .filter(|route| route.contains(level))
+            .filter_map(|route| match &route.target {
+                Target::Processor(processor) => Some(processor),
+                Target::Hook(hook) => {
+                    hook(&tree);
+                    None
+                }
+            })
+            .collect();
+
+        match processors.split_last() {
+            None => {
+                if let Some(fallback) = &self.fallback {
+                    fallback.process(tree);
+                }
+            }
+            Some((last, rest)) => {
+                for processor in rest {
+                    processor.process(tree.clone());
+                }
+                last.process(tree);
+            }
+        }
+    }
+}
@@ -3,12 +3,88 @@
 //! See [`Processor`] for more details.
 
 use crate::layer::{Tree, TreeLayer};
+use crate::processor::anonymize::Anonymize;
+use crate::processor::compress::Compress;
+use crate::processor::deadline::Deadline;
+use crate::processor::escalate::{Escalate, Rules};
+use crate::processor::filter::Filter;
+use crate::processor::pause::{Pausable, PauseHandle, PausePolicy};
+use crate::processor::route::Route;
+use crate::processor::sample::{AdaptiveSample, Sample};
+use crate::processor::sink::Map;
+use crate::processor::summary::{Summarize, SummaryHandle};
+use crate::processor::tee::Tee;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod alert;
+
+pub mod anonymize;
 
 pub mod blocking;
 
+pub(crate) mod buffers;
+
+pub mod bulk;
+
+pub mod compress;
+
+#[cfg(feature = "datadog")]
+pub mod datadog;
+
+pub mod deadline;
+
+pub mod escalate;
+
+pub mod filter;
+
+#[cfg(feature = "honeycomb")]
+pub mod honeycomb;
+
+pub mod index;
+
+pub mod levels;
+
+pub mod net;
+
+pub mod pause;
+
+pub mod per_tree;
+
+pub mod recent;
+
+pub mod route;
+
+pub mod sample;
+
+pub mod sink;
+
+pub mod summary;
+
+pub mod tee;
+
+pub mod tenant;
+
+#[cfg(unix)]
+pub mod socket;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub mod wal;
+
+pub mod worker;
+
 #[cfg(feature = "sync")]
 pub mod sync;
 
+/// Applies `transforms` to `tree` in the order they were added.
+pub(crate) fn transform(transforms: &[fn(Tree) -> Tree], tree: Tree) -> Tree {
+    transforms
+        .iter()
+        .fold(tree, |tree, transform| transform(tree))
+}
+
 /// A type that can process [trace trees][crate::layer::Tree].
 ///
 /// `Processor`s are responsible for both formatting and writing logs to their
@@ -17,9 +93,34 @@ pub mod sync;
 /// [`tracing_subscriber::fmt::MakeWriter`], and [`std::io::Write`].
 ///
 /// This trait is already implemented for
-/// [`BlockingProcessor`][blocking::BlockingProcessor] and
+/// [`BlockingProcessor`][blocking::BlockingProcessor],
+/// [`WalProcessor`][wal::WalProcessor], and
 /// [`AsyncProcessor`][sync::AsyncProcessor].
-pub trait Processor: 'static + Sized {
+///
+/// To convert trees into custom records for another destination without
+/// implementing this trait, see [`sink::map`].
+///
+/// # Choosing a processor at runtime
+///
+/// `Processor` is object safe, and is implemented for [`Box`] and [`Arc`] of
+/// any processor, so processors can be selected from configuration without
+/// the choice leaking into the [`TreeLayer`]'s type.
+///
+/// ```
+/// # use tracing_forest::{blocking, Processor};
+/// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+/// fn processor(format: &str) -> Box<dyn Processor + Send + Sync> {
+///     match format {
+///         "json" => Box::new(blocking(Json::new(true), std::io::stdout)),
+///         _ => Box::new(blocking(Pretty::new(), std::io::stdout)),
+///     }
+/// }
+///
+/// let _guard = tracing::subscriber::set_default({
+///     processor("json").into_layer().into_subscriber()
+/// });
+/// ```
+pub trait Processor: 'static {
     /// Converts the [`Processor`] into a [`TreeLayer`].
     ///
     /// This is the same as `TreeLayer::new(processor)`.
@@ -34,10 +135,309 @@ pub trait Processor: 'static + Sized {
     ///         .into_subscriber()
     /// });
     /// ```
-    fn into_layer(self) -> TreeLayer<Self> {
+    fn into_layer(self) -> TreeLayer<Self>
+    where
+        Self: Sized,
+    {
         TreeLayer::new(self)
     }
 
+    /// Only process [`Tree`]s that match a predicate, dropping the rest.
+    ///
+    /// ## Examples
+    ///
+    /// Only write trees containing events tagged as critical:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::tag::Severity;
+    /// let processor = blocking(Pretty::new(), std::io::stderr)
+    ///     .filter(|tree| tree.max_tag_severity() >= Some(Severity::Critical));
+    /// ```
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: 'static + Fn(&Tree) -> bool,
+    {
+        Filter::new(self, predicate)
+    }
+
+    /// Convert each [`Tree`] into a record with `f`, to be sent to a [`Sink`]
+    /// once the tree is processed.
+    ///
+    /// See [`sink::map`] for details.
+    ///
+    /// ## Examples
+    ///
+    /// Pretty print trees to stdout, and send the names of root spans to a
+    /// channel:
+    ///
+    /// ```
+    /// # use std::sync::mpsc;
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::layer::{Tree, TreeKind};
+    /// fn name(tree: &Tree) -> Option<&'static str> {
+    ///     match &tree.kind {
+    ///         TreeKind::Span(span) => Some(span.name),
+    ///         TreeKind::Event(_) => None,
+    ///     }
+    /// }
+    ///
+    /// let (sender, receiver) = mpsc::channel();
+    /// let processor = blocking(Pretty::new(), std::io::stdout).map(name).sink(sender);
+    /// ```
+    ///
+    /// [`Sink`]: sink::Sink
+    fn map<F, T>(self, f: F) -> Map<F, T, Self>
+    where
+        Self: Sized,
+        F: 'static + Fn(&Tree) -> T,
+    {
+        Map::new(self, f)
+    }
+
+    /// Send [`Tree`]s that match a predicate to another processor instead.
+    ///
+    /// ## Examples
+    ///
+    /// Write trees with `audit.*` tags as JSON to stderr, and pretty print
+    /// everything else to stdout:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let processor = blocking(Pretty::new(), std::io::stdout).route(
+    ///     |tree| tree.tags().iter().any(|tag| tag.message.starts_with("audit.")),
+    ///     blocking(Json::new(true), std::io::stderr),
+    /// );
+    /// ```
+    fn route<F, Q>(self, predicate: F, processor: Q) -> Route<Self, F, Q>
+    where
+        Self: Sized,
+        F: 'static + Fn(&Tree) -> bool,
+        Q: Processor,
+    {
+        Route::new(self, predicate, processor)
+    }
+
+    /// Only process a fraction of [`Tree`]s, between `0.0` and `1.0`, dropping
+    /// the rest.
+    ///
+    /// See [`Sample`] for details.
+    ///
+    /// ## Examples
+    ///
+    /// Write every tree to stdout, and one in ten of them to a collector:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let processor = blocking(Pretty::new(), std::io::stdout)
+    ///     .tee(blocking(Json::new(true), std::io::stderr).sample(0.1));
+    /// ```
+    fn sample(self, rate: f64) -> Sample<Self>
+    where
+        Self: Sized,
+    {
+        Sample::new(self, rate)
+    }
+
+    /// Only process a fraction of [`Tree`]s, like [`sample`], but process
+    /// every tree for `window` after a tree with an error.
+    ///
+    /// See [`AdaptiveSample`] for details.
+    ///
+    /// ## Examples
+    ///
+    /// Write one in a hundred trees to a collector, and every tree for a
+    /// minute after an error:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::json::Json;
+    /// let processor = blocking(Json::new(true), std::io::stderr)
+    ///     .sample_adaptive(0.01, Duration::from_secs(60));
+    /// ```
+    ///
+    /// [`sample`]: Processor::sample
+    fn sample_adaptive(self, rate: f64, window: Duration) -> AdaptiveSample<Self>
+    where
+        Self: Sized,
+    {
+        AdaptiveSample::new(self, rate, window)
+    }
+
+    /// Send every [`Tree`] to another processor as well.
+    ///
+    /// Each tree is processed by `self` first, and then a copy of it is
+    /// processed by `processor`.
+    ///
+    /// ## Examples
+    ///
+    /// Pretty print trees to stdout, and write them as JSON to stderr:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let processor = blocking(Pretty::new(), std::io::stdout)
+    ///     .tee(blocking(Json::new(true), std::io::stderr));
+    /// ```
+    fn tee<Q>(self, processor: Q) -> Tee<Self, Q>
+    where
+        Self: Sized,
+        Q: Processor,
+    {
+        Tee::new(self, processor)
+    }
+
+    /// Drop [`Tree`]s that are older than `max_age` by the time they're
+    /// processed.
+    ///
+    /// See [`Deadline`] for details.
+    ///
+    /// ## Examples
+    ///
+    /// Archive every tree, but only send a dashboard trees from the last
+    /// minute, so it catches up quickly after an outage:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_forest::{blocking, Processor};
+    /// # use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    /// let processor = blocking(Json::new(true), std::io::stdout).tee(
+    ///     blocking(Pretty::new(), std::io::stderr).deadline(Duration::from_secs(60)),
+    /// );
+    /// ```
+    fn deadline(self, max_age: Duration) -> Deadline<Self>
+    where
+        Self: Sized,
+    {
+        Deadline::new(self, max_age)
+    }
+
+    /// Raise the level of [`Tree`]s matching escalation [`Rules`], so later
+    /// processors and formatters treat them as more severe.
+    ///
+    /// ## Examples
+    ///
+    /// Treat slow requests as warnings, and security events as errors:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing::Level;
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::processor::escalate::Rules;
+    /// let processor = blocking(Pretty::new().with_ansi(true), std::io::stdout).escalate(
+    ///     Rules::new()
+    ///         .slower_than(Duration::from_secs(5), Level::WARN)
+    ///         .tag("security.*", Level::ERROR),
+    /// );
+    /// ```
+    fn escalate(self, rules: Rules) -> Escalate<Self>
+    where
+        Self: Sized,
+    {
+        Escalate::new(self, rules)
+    }
+
+    /// Fold runs of structurally identical subtrees into a single
+    /// representative, annotated with how many times it repeated.
+    ///
+    /// ## Examples
+    ///
+    /// Keep one of each kind of item span in the logs of a batch job:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let processor = blocking(Pretty::new(), std::io::stdout).compress();
+    ///
+    /// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+    ///     tracing::info_span!("batch").in_scope(|| {
+    ///         for item in 0..1000 {
+    ///             tracing::info_span!("item", item).in_scope(|| tracing::debug!("processed"));
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    fn compress(self) -> Compress<Self>
+    where
+        Self: Sized,
+    {
+        Compress::new(self)
+    }
+
+    /// Mask every recorded value of a tree, keeping its keys, levels,
+    /// durations, and shape, so it can be shared publicly.
+    ///
+    /// ## Examples
+    ///
+    /// Write trees that can be attached to a bug report, with equal values
+    /// hashed alike:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let processor = blocking(Pretty::new(), std::io::stdout).anonymize().hash(true);
+    ///
+    /// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+    ///     tracing::info_span!("login", user = "alice").in_scope(|| {
+    ///         tracing::info!(attempt = 2, "logged in as alice");
+    ///     });
+    /// });
+    /// ```
+    fn anonymize(self) -> Anonymize<Self>
+    where
+        Self: Sized,
+    {
+        Anonymize::new(self)
+    }
+
+    /// Allow processing to be paused and resumed at runtime with the returned
+    /// [`PauseHandle`], handling trees according to `policy` while paused.
+    ///
+    /// ## Examples
+    ///
+    /// Hold back logs while a progress bar is drawn:
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::processor::pause::PausePolicy;
+    /// let (processor, handle) = blocking(Pretty::new(), std::io::stdout)
+    ///     .pausable(PausePolicy::Buffer(1024));
+    ///
+    /// handle.pause();
+    /// // draw the progress bar...
+    /// handle.resume();
+    /// ```
+    fn pausable(self, policy: PausePolicy) -> (Pausable<Self>, PauseHandle)
+    where
+        Self: Sized + Send + Sync,
+    {
+        Pausable::new(self, policy)
+    }
+
+    /// Record statistics about every [`Tree`] that's processed, so that a
+    /// summary of the run can be processed with the returned
+    /// [`SummaryHandle`] when it ends.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// let (processor, summary) = blocking(Pretty::new(), std::io::stdout).summarize();
+    ///
+    /// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+    ///     tracing::info_span!("job").in_scope(|| tracing::info!("done"));
+    /// });
+    /// summary.report();
+    /// ```
+    fn summarize(self) -> (Summarize<Self>, SummaryHandle)
+    where
+        Self: Sized + Send + Sync,
+    {
+        Summarize::new(self)
+    }
+
     /// Processes the [`Tree`] of logs. Implementors of this trait are free to
     /// define what this means, such as:
     /// * Writing to a stdout or a file
@@ -46,3 +446,30 @@ pub trait Processor: 'static + Sized {
     /// * Ignoring
     fn process(&self, tree: Tree);
 }
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    fn process(&self, tree: Tree) {
+        self.as_ref().process(tree)
+    }
+}
+
+impl<P: Processor + ?Sized> Processor for Arc<P> {
+    fn process(&self, tree: Tree) {
+        self.as_ref().process(tree)
+    }
+}
+
+/// How long the logging pipeline spent on a [`Tree`], for detecting when
+/// logging itself is a bottleneck.
+///
+/// Processors measure this for each tree and pass it to
+/// [`Formatter::fmt_latency`][crate::formatter::Formatter::fmt_latency], which
+/// formatters can use to include it in their output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    /// How long the tree waited to be processed after it was sent to a
+    /// processing task, or `None` if it wasn't queued.
+    pub queued: Option<Duration>,
+    /// How long formatting the tree took.
+    pub formatting: Duration,
+}
@@ -0,0 +1,660 @@
+//! A [`Processor`] base that reliably sends trees to a network collector.
+//!
+//! See [`Resilient`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How a [`Resilient`] processor encodes trees and delivers them to a
+/// collector, such as a syslog server, Loki, a GELF input, or an OTLP
+/// endpoint.
+///
+/// Implementations only need to handle the happy path: [`Resilient`] decides
+/// when to connect, retries failed sends, and counts what was dropped.
+///
+/// Trees are encoded on the thread that closed them, and connections are
+/// opened and used on the background thread of the processor.
+pub trait Transport {
+    /// An open connection to the collector.
+    type Connection;
+
+    /// Encode `tree` as one record, appending it to `record`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the tree can't be encoded, which drops it.
+    fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Open a new connection to the collector.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the collector is unavailable.
+    fn connect(&self) -> io::Result<Self::Connection>;
+
+    /// Send an encoded record over `conn`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the record couldn't be delivered, which closes the
+    /// connection and queues the record to be retried.
+    fn send(&self, conn: &mut Self::Connection, record: &[u8]) -> io::Result<()>;
+
+    /// Send several encoded records over `conn` at once, like in a single
+    /// request to a batch API.
+    ///
+    /// By default, the records are sent one at a time with [`send`].
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the records couldn't be delivered, which closes
+    /// the connection and queues all of them to be retried, so ones that were
+    /// delivered before the failure are sent again.
+    ///
+    /// [`send`]: Transport::send
+    fn send_batch(&self, conn: &mut Self::Connection, records: &[Vec<u8>]) -> io::Result<()> {
+        records
+            .iter()
+            .try_for_each(|record| self.send(conn, record))
+    }
+
+    /// Returns the most records to send in one [`send_batch`], and how long
+    /// to wait for a batch to fill up after a record is queued, unless they
+    /// are set with [`Resilient::batch`].
+    ///
+    /// By default, records are sent one at a time as soon as they're queued.
+    ///
+    /// [`send_batch`]: Transport::send_batch
+    fn batch(&self) -> (usize, Duration) {
+        (1, Duration::ZERO)
+    }
+
+    /// Keep `record` somewhere else, like a file, instead of discarding it
+    /// because the retry queue is full or the processor stopped before it was
+    /// sent. Returns whether the record was kept.
+    ///
+    /// By default, records aren't kept.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the record couldn't be kept, which drops it.
+    fn spill(&self, record: &[u8]) -> io::Result<bool> {
+        let _ = record;
+        Ok(false)
+    }
+}
+
+/// Counters describing the deliveries of a [`Resilient`] processor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetStats {
+    /// How many records have been delivered.
+    pub sent: u64,
+    /// How many records have been discarded, because they couldn't be encoded
+    /// or the retry queue was full.
+    pub dropped: u64,
+    /// How many records have been kept by [`Transport::spill`] instead of
+    /// being discarded.
+    pub spilled: u64,
+    /// How many connections have been opened.
+    pub connects: u64,
+    /// How many records are waiting to be sent.
+    pub queued: usize,
+}
+
+/// A [`Processor`] that sends each [`Tree`] to a collector with a
+/// [`Transport`], handling the reliability concerns shared by network
+/// processors.
+///
+/// Trees are encoded on the thread that closed them and queued for a
+/// background thread, which connects to the collector and sends them, so a
+/// slow or unavailable collector never blocks the program. Records are sent
+/// in [batches] once enough of them are queued, or the first one has waited
+/// long enough for others to join it. When connecting
+/// or sending fails, the connection is closed and the thread waits before
+/// reconnecting, doubling the delay after each failed attempt up to the
+/// [maximum backoff]. Trees processed in the meantime are kept in the bounded
+/// [retry queue], and are sent in order once the thread reconnects. Once the
+/// queue is full, the oldest records are [spilled] or discarded, so a long
+/// outage keeps the most recent trees.
+///
+/// The thread runs until the processor is dropped, which tries once more to
+/// send the queued records and waits for it. What happened to records is
+/// available from [`stats`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::{self, Write};
+/// # use std::net::TcpStream;
+/// # use std::time::Duration;
+/// # use tracing_forest::formatter::{json::Json, Formatter};
+/// # use tracing_forest::layer::Tree;
+/// # use tracing_forest::processor::net::{Resilient, Transport};
+/// # use tracing_forest::Processor;
+/// struct Tcp {
+///     addr: &'static str,
+/// }
+///
+/// impl Transport for Tcp {
+///     type Connection = TcpStream;
+///
+///     fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+///         Json::new(true).fmt(tree, record)
+///     }
+///
+///     fn connect(&self) -> io::Result<TcpStream> {
+///         TcpStream::connect(self.addr)
+///     }
+///
+///     fn send(&self, conn: &mut TcpStream, record: &[u8]) -> io::Result<()> {
+///         conn.write_all(record)
+///     }
+/// }
+///
+/// let processor = Resilient::new(Tcp { addr: "127.0.0.1:5170" })
+///     .initial_backoff(Duration::from_millis(100))
+///     .max_backoff(Duration::from_secs(30))
+///     .queue_capacity(1024);
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info!("this log is queued until the collector is up");
+/// });
+/// ```
+///
+/// [batches]: Resilient::batch
+/// [maximum backoff]: Resilient::max_backoff
+/// [retry queue]: Resilient::queue_capacity
+/// [spilled]: Transport::spill
+/// [`stats`]: Resilient::stats
+pub struct Resilient<T: Transport> {
+    outbox: Outbox<T>,
+}
+
+/// How the thread of an [`Outbox`] delivers records, which is what a
+/// [`Transport`] does after encoding trees, for records of any type.
+pub(crate) trait Deliver {
+    /// What's queued for each tree.
+    type Record;
+    /// An open connection to the destination.
+    type Connection;
+
+    /// See [`Transport::connect`].
+    fn connect(&self) -> io::Result<Self::Connection>;
+
+    /// See [`Transport::send_batch`].
+    fn send_batch(&self, conn: &mut Self::Connection, records: &[Self::Record]) -> io::Result<()>;
+
+    /// See [`Transport::spill`].
+    fn spill(&self, record: &Self::Record) -> io::Result<bool>;
+}
+
+impl<T: Transport> Deliver for T {
+    type Record = Vec<u8>;
+    type Connection = T::Connection;
+
+    fn connect(&self) -> io::Result<T::Connection> {
+        Transport::connect(self)
+    }
+
+    fn send_batch(&self, conn: &mut T::Connection, records: &[Vec<u8>]) -> io::Result<()> {
+        Transport::send_batch(self, conn, records)
+    }
+
+    fn spill(&self, record: &Vec<u8>) -> io::Result<bool> {
+        Transport::spill(self, record)
+    }
+}
+
+/// The queue and background thread of a processor that delivers records
+/// with a [`Deliver`], retrying with backoff and batching them the way that
+/// [`Resilient`] describes.
+pub(crate) struct Outbox<D: Deliver> {
+    shared: Arc<Shared<D>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Shared<D: Deliver> {
+    deliver: D,
+    state: Mutex<State<D::Record>>,
+    /// Wakes the thread when there's something to send.
+    wake: Condvar,
+    /// Wakes callers of [`Outbox::flush`] once the thread has tried.
+    attempted: Condvar,
+}
+
+struct State<R> {
+    queue: VecDeque<R>,
+    queue_capacity: usize,
+    /// How many records the thread took from the queue to send.
+    sending: usize,
+    retry_at: Option<Instant>,
+    initial_backoff: Duration,
+    backoff: Duration,
+    max_backoff: Duration,
+    batch_size: usize,
+    /// Without a delay, records wait for a full batch or a flush.
+    batch_delay: Option<Duration>,
+    /// When the queue last went from empty to holding a record.
+    since: Option<Instant>,
+    /// How many flushes were requested, and how many the thread has tried.
+    flushes: u64,
+    flushed: u64,
+    /// Why the last attempt failed, for [`Outbox::flush`].
+    failure: Option<(io::ErrorKind, String)>,
+    stopping: bool,
+    stats: NetStats,
+}
+
+impl<T> Resilient<T>
+where
+    T: 'static + Transport + Send + Sync,
+{
+    /// Construct a new [`Resilient`] processor sending trees with `transport`,
+    /// and spawn its thread.
+    ///
+    /// By default, the processor waits 100 milliseconds after the first
+    /// failure, at most 30 seconds between attempts, and queues up to 1024
+    /// records.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the thread can't be spawned.
+    pub fn new(transport: T) -> Self {
+        let (batch_size, batch_delay) = transport.batch();
+        let outbox = Outbox::new(transport, "tracing-forest-net");
+        outbox.batch_size(batch_size);
+        outbox.batch_delay(Some(batch_delay));
+        Resilient { outbox }
+    }
+}
+
+impl<T: Transport> Resilient<T> {
+    /// Set how long to wait before reconnecting after the first failure.
+    pub fn initial_backoff(self, backoff: Duration) -> Self {
+        self.outbox.initial_backoff(backoff);
+        self
+    }
+
+    /// Set the longest wait between reconnection attempts.
+    pub fn max_backoff(self, backoff: Duration) -> Self {
+        self.outbox.max_backoff(backoff);
+        self
+    }
+
+    /// Set how many records are kept waiting to be sent while the collector
+    /// is slow or unavailable. With `0`, only the newest record waits for the
+    /// thread, and records that can't be sent are discarded.
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        self.outbox.queue_capacity(capacity);
+        self
+    }
+
+    /// Send up to `size` records at a time with [`Transport::send_batch`],
+    /// waiting up to `delay` after a record is queued for the batch to fill
+    /// up.
+    ///
+    /// By default, the [batch size] of the transport is used.
+    ///
+    /// [batch size]: Transport::batch
+    pub fn batch(self, size: usize, delay: Duration) -> Self {
+        self.outbox.batch_size(size);
+        self.outbox.batch_delay(Some(delay));
+        self
+    }
+
+    /// Returns the [`Transport`] of the processor.
+    pub fn transport(&self) -> &T {
+        &self.outbox.shared.deliver
+    }
+
+    /// Returns what has happened to the records of the processor so far.
+    pub fn stats(&self) -> NetStats {
+        self.outbox.stats()
+    }
+
+    /// Send every queued record now, even while backing off, and wait until
+    /// the thread has tried to.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if connecting or sending failed, in which case the
+    /// records that weren't sent remain queued.
+    pub fn flush(&self) -> io::Result<()> {
+        self.outbox.flush()
+    }
+}
+
+impl<D> Outbox<D>
+where
+    D: 'static + Deliver + Send + Sync,
+    D::Record: Send,
+{
+    /// Construct a new [`Outbox`] delivering records with `deliver`, and spawn
+    /// its thread named `name`.
+    ///
+    /// By default, the thread waits 100 milliseconds after the first failure,
+    /// at most 30 seconds between attempts, queues up to 1024 records, and
+    /// sends them one at a time as soon as they're queued.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the thread can't be spawned.
+    pub(crate) fn new(deliver: D, name: &str) -> Self {
+        let initial_backoff = Duration::from_millis(100);
+        let shared = Arc::new(Shared {
+            deliver,
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                queue_capacity: 1024,
+                sending: 0,
+                retry_at: None,
+                initial_backoff,
+                backoff: initial_backoff,
+                max_backoff: Duration::from_secs(30),
+                batch_size: 1,
+                batch_delay: Some(Duration::ZERO),
+                since: None,
+                flushes: 0,
+                flushed: 0,
+                failure: None,
+                stopping: false,
+                stats: NetStats::default(),
+            }),
+            wake: Condvar::new(),
+            attempted: Condvar::new(),
+        });
+
+        let worker = shared.clone();
+        #[allow(clippy::expect_used)]
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || worker.work())
+            .expect("failed to spawn the network thread");
+
+        Outbox {
+            shared,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl<D: Deliver> Outbox<D> {
+    pub(crate) fn initial_backoff(&self, backoff: Duration) {
+        let mut state = self.shared.lock();
+        state.initial_backoff = backoff;
+        state.backoff = backoff;
+    }
+
+    pub(crate) fn max_backoff(&self, backoff: Duration) {
+        self.shared.lock().max_backoff = backoff;
+    }
+
+    pub(crate) fn queue_capacity(&self, capacity: usize) {
+        self.shared.lock().queue_capacity = capacity;
+    }
+
+    pub(crate) fn batch_size(&self, size: usize) {
+        self.shared.lock().batch_size = size.max(1);
+    }
+
+    /// Set how long records wait for a batch to fill up, or with `None`, to
+    /// wait until it's full or flushed.
+    pub(crate) fn batch_delay(&self, delay: Option<Duration>) {
+        self.shared.lock().batch_delay = delay;
+    }
+
+    pub(crate) fn stats(&self) -> NetStats {
+        let state = self.shared.lock();
+        NetStats {
+            queued: state.queue.len() + state.sending,
+            ..state.stats
+        }
+    }
+
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        state.flushes += 1;
+        let flush = state.flushes;
+        self.shared.wake.notify_one();
+
+        while state.flushed < flush {
+            #[allow(clippy::expect_used)]
+            let next = self.shared.attempted.wait(state);
+            state = next.expect("network processor poisoned");
+        }
+        match &state.failure {
+            Some((kind, message)) => Err(io::Error::new(*kind, message.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues `records` for the thread, discarding the oldest records past
+    /// the capacity of the queue.
+    pub(crate) fn push(&self, records: impl IntoIterator<Item = D::Record>) {
+        let mut state = self.shared.lock();
+        let queued = state.queue.len();
+        state.queue.extend(records);
+        if state.queue.len() == queued {
+            return;
+        }
+        if queued == 0 {
+            state.since = Some(Instant::now());
+        }
+        // The newest record always waits for the thread, even with no queue
+        let capacity = state.queue_capacity.max(1);
+        let overflow = overflow(&mut state, capacity);
+        drop(state);
+
+        self.shared.wake.notify_one();
+        self.shared.discard(overflow);
+    }
+
+    /// Counts a record that couldn't be queued as dropped.
+    pub(crate) fn reject(&self) {
+        self.shared.lock().stats.dropped += 1;
+    }
+}
+
+impl<D: Deliver> Shared<D> {
+    fn lock(&self) -> MutexGuard<'_, State<D::Record>> {
+        #[allow(clippy::expect_used)]
+        self.state.lock().expect("network processor poisoned")
+    }
+
+    /// Waits for records to send, and tries to send them until the processor
+    /// is dropped.
+    fn work(&self) {
+        let mut conn = None;
+        let mut state = self.lock();
+        loop {
+            let flushes = state.flushes;
+            let full = state.queue.len() >= state.batch_size;
+            let waiting = !full && state.batch_delay.is_none();
+            let filling = match state.batch_delay {
+                Some(delay) if !full => state.since.map(|since| since + delay),
+                _ => None,
+            };
+            let due_at = state.retry_at.max(filling);
+            let due = !waiting && due_at.is_none_or(|at| Instant::now() >= at);
+            let ready = !state.queue.is_empty() && due;
+            if !ready && state.flushed == flushes && !state.stopping {
+                let timeout = due_at
+                    .filter(|_| !state.queue.is_empty() && !waiting)
+                    .map(|at| at.saturating_duration_since(Instant::now()));
+                #[allow(clippy::expect_used)]
+                {
+                    state = match timeout {
+                        Some(timeout) => {
+                            let (state, _) = self
+                                .wake
+                                .wait_timeout(state, timeout)
+                                .expect("network processor poisoned");
+                            state
+                        }
+                        None => self.wake.wait(state).expect("network processor poisoned"),
+                    };
+                }
+                continue;
+            }
+
+            // Flushing or stopping tries right away, even while backing off
+            if !state.queue.is_empty() {
+                let count = state.queue.len();
+                drop(state);
+                let sent = self.send(&mut conn, count);
+                state = self.lock();
+                let overflow = attempted(&mut state, sent);
+                if !overflow.is_empty() {
+                    drop(state);
+                    self.discard(overflow);
+                    state = self.lock();
+                }
+            } else {
+                state.failure = None;
+            }
+            state.flushed = flushes;
+            self.attempted.notify_all();
+
+            if state.stopping {
+                break;
+            }
+        }
+
+        let remaining = state.queue.drain(..).collect();
+        drop(state);
+        self.discard(remaining);
+    }
+
+    /// Sends the first `count` records of the queue in batches, connecting
+    /// first if there's no connection.
+    fn send(&self, conn: &mut Option<D::Connection>, mut count: usize) -> io::Result<()> {
+        while count > 0 {
+            let records = {
+                let mut state = self.lock();
+                let size = state.batch_size.min(count).min(state.queue.len());
+                if size == 0 {
+                    break;
+                }
+                state.sending = size;
+                state.queue.drain(..size).collect::<Vec<_>>()
+            };
+
+            let sent = self
+                .connection(conn)
+                .and_then(|conn| self.deliver.send_batch(conn, &records));
+
+            let mut state = self.lock();
+            state.sending = 0;
+            if let Err(err) = sent {
+                // Records are retried in order after reconnecting
+                records
+                    .into_iter()
+                    .rev()
+                    .for_each(|record| state.queue.push_front(record));
+                *conn = None;
+                return Err(err);
+            }
+            state.stats.sent += records.len() as u64;
+            count -= records.len();
+        }
+        Ok(())
+    }
+
+    fn connection<'c>(
+        &self,
+        conn: &'c mut Option<D::Connection>,
+    ) -> io::Result<&'c mut D::Connection> {
+        match conn {
+            Some(conn) => Ok(conn),
+            None => {
+                let opened = self.deliver.connect()?;
+                self.lock().stats.connects += 1;
+                Ok(conn.insert(opened))
+            }
+        }
+    }
+
+    /// Spills records with the transport, or discards them.
+    fn discard(&self, records: Vec<D::Record>) {
+        if records.is_empty() {
+            return;
+        }
+
+        let (mut spilled, mut dropped) = (0, 0);
+        for record in records {
+            match self.deliver.spill(&record) {
+                Ok(true) => spilled += 1,
+                Ok(false) => dropped += 1,
+                Err(err) => {
+                    dropped += 1;
+                    error::report(ForestError::Processor {
+                        processor: "network",
+                        error: err,
+                    });
+                }
+            }
+        }
+
+        let mut state = self.lock();
+        state.stats.spilled += spilled;
+        state.stats.dropped += dropped;
+    }
+}
+
+/// Updates the backoff after an attempt, and removes the records past the
+/// capacity of the queue, which a failed attempt returned to it.
+fn attempted<R>(state: &mut State<R>, result: io::Result<()>) -> Vec<R> {
+    match &result {
+        // Only a connection that delivered everything resets the backoff
+        Ok(()) => {
+            state.retry_at = None;
+            state.backoff = state.initial_backoff;
+        }
+        Err(_) => {
+            state.retry_at = Some(Instant::now() + state.backoff);
+            state.backoff = (state.backoff * 2).min(state.max_backoff);
+        }
+    }
+    state.failure = result.err().map(|err| (err.kind(), err.to_string()));
+
+    let capacity = state.queue_capacity;
+    overflow(state, capacity)
+}
+
+/// Removes the oldest records past `capacity` from the queue.
+fn overflow<R>(state: &mut State<R>, capacity: usize) -> Vec<R> {
+    let excess = state.queue.len().saturating_sub(capacity);
+    state.queue.drain(..excess).collect()
+}
+
+impl<T> Processor for Resilient<T>
+where
+    T: 'static + Transport + Send + Sync,
+{
+    fn process(&self, tree: Tree) {
+        let mut record = Vec::new();
+
+        if let Err(err) = self.outbox.shared.deliver.encode(tree, &mut record) {
+            self.outbox.reject();
+            return error::report(ForestError::Format(err));
+        }
+
+        self.outbox.push(Some(record));
+    }
+}
+
+impl<D: Deliver> Drop for Outbox<D> {
+    fn drop(&mut self) {
+        self.shared.lock().stopping = true;
+        self.shared.wake.notify_one();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! A [`Processor`] whose output can be paused and resumed at runtime.
+//!
+//! See [`Pausable`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// What a [`Pausable`] processor does with trees while it's paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// Buffer up to the given number of trees, discarding the oldest once
+    /// it's full, and process them in order when resumed.
+    Buffer(usize),
+    /// Discard trees.
+    Drop,
+}
+
+/// A [`Processor`] that can be paused and resumed with a [`PauseHandle`], so
+/// interactive programs can suspend log output while drawing prompts or
+/// progress bars.
+///
+/// To initialize a new [`Pausable`], see [`Processor::pausable`].
+pub struct Pausable<P> {
+    shared: Arc<Shared<P>>,
+}
+
+struct Shared<P> {
+    processor: P,
+    policy: PausePolicy,
+    state: Mutex<State>,
+}
+
+struct State {
+    paused: bool,
+    buffered: VecDeque<Tree>,
+    dropped: u64,
+}
+
+/// A handle for pausing and resuming a [`Pausable`] processor.
+///
+/// Handles can be cloned and sent to other threads.
+#[derive(Clone)]
+pub struct PauseHandle {
+    control: Arc<dyn Control + Send + Sync>,
+}
+
+trait Control {
+    fn set_paused(&self, paused: bool);
+
+    fn is_paused(&self) -> bool;
+
+    fn dropped(&self) -> u64;
+}
+
+impl<P> Pausable<P>
+where
+    P: Processor + Send + Sync,
+{
+    pub(crate) fn new(processor: P, policy: PausePolicy) -> (Self, PauseHandle) {
+        let shared = Arc::new(Shared {
+            processor,
+            policy,
+            state: Mutex::new(State {
+                paused: false,
+                buffered: VecDeque::new(),
+                dropped: 0,
+            }),
+        });
+        let handle = PauseHandle {
+            control: shared.clone(),
+        };
+        (Pausable { shared }, handle)
+    }
+}
+
+impl<P: Processor> Control for Shared<P> {
+    fn set_paused(&self, paused: bool) {
+        #[allow(clippy::expect_used)]
+        let mut state = self.state.lock().expect("pausable processor poisoned");
+        state.paused = paused;
+
+        // Trees are flushed while holding the lock, so they stay in order with
+        // trees that arrive in the meantime
+        if !paused {
+            while let Some(tree) = state.buffered.pop_front() {
+                self.processor.process(tree);
+            }
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        #[allow(clippy::expect_used)]
+        self.state
+            .lock()
+            .expect("pausable processor poisoned")
+            .paused
+    }
+
+    fn dropped(&self) -> u64 {
+        #[allow(clippy::expect_used)]
+        self.state
+            .lock()
+            .expect("pausable processor poisoned")
+            .dropped
+    }
+}
+
+impl PauseHandle {
+    /// Pause processing trees.
+    pub fn pause(&self) {
+        self.control.set_paused(true);
+    }
+
+    /// Resume processing trees, first processing any that were buffered while
+    /// paused.
+    pub fn resume(&self) {
+        self.control.set_paused(false);
+    }
+
+    /// Returns whether processing is paused.
+    pub fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    /// Returns how many trees have been discarded while paused.
+    pub fn dropped(&self) -> u64 {
+        self.control.dropped()
+    }
+
+    /// Pause processing while running `f`, and resume afterwards.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+    /// # use tracing_forest::processor::pause::PausePolicy;
+    /// let (processor, handle) = blocking(Pretty::new(), std::io::stdout)
+    ///     .pausable(PausePolicy::Buffer(1024));
+    ///
+    /// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+    ///     let _answer = handle.paused(|| {
+    ///         tracing::info!("this is written after the prompt");
+    ///         "yes"
+    ///     });
+    /// });
+    /// ```
+    pub fn paused<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Resume<'a>(&'a PauseHandle);
+
+        impl Drop for Resume<'_> {
+            fn drop(&mut self) {
+                self.0.resume();
+            }
+        }
+
+        self.pause();
+        let _resume = Resume(self);
+        f()
+    }
+}
+
+impl<P: Processor> Processor for Pausable<P> {
+    fn process(&self, tree: Tree) {
+        #[allow(clippy::expect_used)]
+        let mut state = self
+            .shared
+            .state
+            .lock()
+            .expect("pausable processor poisoned");
+
+        if !state.paused {
+            drop(state);
+            return self.shared.processor.process(tree);
+        }
+
+        match self.shared.policy {
+            PausePolicy::Buffer(capacity) => {
+                if state.buffered.len() >= capacity {
+                    state.dropped += 1;
+                    if state.buffered.pop_front().is_none() {
+                        // A capacity of zero buffers nothing
+                        return;
+                    }
+                }
+                state.buffered.push_back(tree);
+            }
+            PausePolicy::Drop => state.dropped += 1,
+        }
+    }
+}
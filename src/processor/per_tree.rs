@@ -0,0 +1,208 @@
+//! A [`Processor`] that writes each tree to its own file.
+//!
+//! See [`PerTreeFile`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::formatter::Formatter;
+use crate::layer::Tree;
+use crate::processor::buffers::Buffers;
+use crate::processor::Processor;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A [`Processor`] that writes each [`Tree`] to a new file in a directory,
+/// named by its timestamp and [`Uuid`], like
+/// `20261014T142106.543584Z-81278707-13bc-4bd1-a2df-f11c1940755e.log`.
+///
+/// This is handy for debugging specific requests, since the trace of a single
+/// request can be found by its ID and attached to a ticket. The timestamp is
+/// only included with the `chrono` feature, and the [`Uuid`] with the `uuid`
+/// feature. If a file with the same name already exists, a counter is appended.
+///
+/// With [`max_files`] or [`max_age`], older files in the directory are
+/// removed after each tree is written. Only files with the processor's
+/// [`extension`] are considered, so the directory should be dedicated to it.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tracing_forest::formatter::pretty::Pretty;
+/// # use tracing_forest::processor::per_tree::PerTreeFile;
+/// # use tracing_forest::Processor;
+/// let dir = std::env::temp_dir().join("tracing-forest-per-tree-doc");
+/// let processor = PerTreeFile::new(&dir, Pretty::new())
+///     .expect("failed to create the directory")
+///     .max_files(100)
+///     .max_age(Duration::from_secs(24 * 60 * 60));
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info_span!("request").in_scope(|| {
+///         tracing::info!("written to its own file");
+///     });
+/// });
+/// ```
+///
+/// [`Uuid`]: ::uuid::Uuid
+/// [`max_files`]: PerTreeFile::max_files
+/// [`max_age`]: PerTreeFile::max_age
+/// [`extension`]: PerTreeFile::extension
+#[derive(Debug)]
+pub struct PerTreeFile<F> {
+    dir: PathBuf,
+    formatter: F,
+    extension: &'static str,
+    max_files: Option<usize>,
+    max_age: Option<Duration>,
+    buffers: Buffers,
+}
+
+impl<F: Formatter> PerTreeFile<F> {
+    /// Construct a new [`PerTreeFile`] that formats trees with `formatter`
+    /// and writes them to files in `dir`, creating it if it doesn't exist.
+    ///
+    /// By default, files have the `log` extension and are kept forever.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new<P: AsRef<Path>>(dir: P, formatter: F) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        Ok(PerTreeFile {
+            dir,
+            formatter,
+            extension: "log",
+            max_files: None,
+            max_age: None,
+            buffers: Buffers::default(),
+        })
+    }
+
+    /// Set the extension of the files, like `json`.
+    pub fn extension(mut self, extension: &'static str) -> Self {
+        self.extension = extension;
+        self
+    }
+
+    /// Keep at most `max_files` files in the directory, removing the oldest
+    /// ones first.
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Remove files that were last modified more than `max_age` ago.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns the directory that files are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn write(&self, name: &str, buf: &[u8]) -> io::Result<()> {
+        let mut suffix = 0;
+        loop {
+            let path = if suffix == 0 {
+                self.dir.join(format!("{}.{}", name, self.extension))
+            } else {
+                self.dir
+                    .join(format!("{}-{}.{}", name, suffix, self.extension))
+            };
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => return file.write_all(buf),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => suffix += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files.is_none() && self.max_age.is_none() {
+            return Ok(());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == self.extension) {
+                let metadata = entry.metadata()?;
+                if metadata.is_file() {
+                    files.push((metadata.modified()?, path));
+                }
+            }
+        }
+        // Newest first
+        files.sort_by(|a, b| b.cmp(a));
+
+        let now = SystemTime::now();
+        for (idx, (modified, path)) in files.into_iter().enumerate() {
+            let too_many = self.max_files.is_some_and(|max| idx >= max);
+            let too_old = self
+                .max_age
+                .is_some_and(|max_age| now.duration_since(modified).is_ok_and(|age| age > max_age));
+            if too_many || too_old {
+                match fs::remove_file(path) {
+                    // Removed by another processor sharing the directory
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn file_name(tree: &Tree) -> String {
+    #[cfg(feature = "chrono")]
+    let name = tree
+        .attrs
+        .timestamp
+        .format("%Y%m%dT%H%M%S%.6fZ")
+        .to_string();
+    #[cfg(not(feature = "chrono"))]
+    let name = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros()
+        .to_string();
+
+    #[cfg(feature = "uuid")]
+    let name = format!("{}-{}", name, tree.attrs.uuid);
+    #[cfg(not(any(feature = "chrono", feature = "uuid")))]
+    let _ = tree;
+
+    name
+}
+
+impl<F> Processor for PerTreeFile<F>
+where
+    F: 'static + Formatter,
+{
+    fn process(&self, tree: Tree) {
+        let mut buf = self.buffers.take();
+        let name = file_name(&tree);
+
+        if let Err(err) = self.formatter.fmt(tree, &mut buf) {
+            return error::report(ForestError::Format(err));
+        }
+        let written = self.write(&name, &buf);
+        self.buffers.put(buf);
+        if let Err(err) = written {
+            return error::report(ForestError::Write(err));
+        }
+        if let Err(error) = self.prune() {
+            error::report(ForestError::Processor {
+                processor: "per-tree file",
+                error,
+            });
+        }
+    }
+}
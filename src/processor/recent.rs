@@ -0,0 +1,106 @@
+//! A [`Processor`] that keeps the most recent trees in memory.
+//!
+//! See [`RecentTrees`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A [`Processor`] that stores the last `capacity` [`Tree`]s in a ring buffer,
+/// optionally discarding trees older than a maximum age.
+///
+/// `RecentTrees` is cheap to clone, and all clones share the same buffer, so
+/// one clone can be given to a [`TreeLayer`] while another is kept as a handle
+/// for taking snapshots, like from a debug HTTP endpoint.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::{processor::recent::RecentTrees, Processor};
+/// let recent = RecentTrees::new(100);
+///
+/// tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+///     tracing::info_span!("request").in_scope(|| tracing::info!("handled"));
+/// });
+///
+/// assert_eq!(recent.snapshot().len(), 1);
+/// ```
+///
+/// [`TreeLayer`]: crate::layer::TreeLayer
+#[derive(Clone)]
+pub struct RecentTrees {
+    inner: Arc<Mutex<Ring>>,
+}
+
+struct Ring {
+    capacity: usize,
+    max_age: Option<Duration>,
+    trees: VecDeque<(Instant, Arc<Tree>)>,
+}
+
+impl Ring {
+    fn prune(&mut self, now: Instant) {
+        if let Some(max_age) = self.max_age {
+            while let Some((received, _)) = self.trees.front() {
+                if now.duration_since(*received) < max_age {
+                    break;
+                }
+                self.trees.pop_front();
+            }
+        }
+    }
+}
+
+impl RecentTrees {
+    /// Construct a new [`RecentTrees`] that holds at most `capacity` trees.
+    pub fn new(capacity: usize) -> Self {
+        RecentTrees {
+            inner: Arc::new(Mutex::new(Ring {
+                capacity,
+                max_age: None,
+                trees: VecDeque::with_capacity(capacity),
+            })),
+        }
+    }
+
+    /// Discard trees that were processed more than `max_age` ago.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        self.lock().max_age = Some(max_age);
+        self
+    }
+
+    /// Returns the stored trees, from oldest to newest.
+    pub fn snapshot(&self) -> Vec<Arc<Tree>> {
+        let mut ring = self.lock();
+        ring.prune(Instant::now());
+        ring.trees.iter().map(|(_, tree)| tree.clone()).collect()
+    }
+
+    /// Removes all stored trees.
+    pub fn clear(&self) {
+        self.lock().trees.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Ring> {
+        #[allow(clippy::expect_used)]
+        self.inner.lock().expect("recent trees poisoned")
+    }
+}
+
+impl Processor for RecentTrees {
+    fn process(&self, tree: Tree) {
+        let now = Instant::now();
+        let mut ring = self.lock();
+
+        ring.prune(now);
+        if ring.capacity == 0 {
+            return;
+        }
+        if ring.trees.len() == ring.capacity {
+            ring.trees.pop_front();
+        }
+        ring.trees.push_back((now, Arc::new(tree)));
+    }
+}
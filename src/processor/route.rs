@@ -0,0 +1,45 @@
+//! A [`Processor`] that sends trees to one of two processors.
+//!
+//! See [`Route`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+
+/// A [`Processor`] that forwards [`Tree`]s matching a predicate to one
+/// processor, and the rest to another.
+///
+/// Since each processor has its own formatter and writer, this can render
+/// certain trees differently or send them to a separate sink, such as writing
+/// audit trails as JSON to a file while everything else is pretty printed.
+///
+/// To initialize a new [`Route`], see [`Processor::route`].
+pub struct Route<P, F, Q> {
+    processor: P,
+    predicate: F,
+    matched: Q,
+}
+
+impl<P, F, Q> Route<P, F, Q> {
+    pub(crate) fn new(processor: P, predicate: F, matched: Q) -> Self {
+        Route {
+            processor,
+            predicate,
+            matched,
+        }
+    }
+}
+
+impl<P, F, Q> Processor for Route<P, F, Q>
+where
+    P: Processor,
+    F: 'static + Fn(&Tree) -> bool,
+    Q: Processor,
+{
+    fn process(&self, tree: Tree) {
+        if (self.predicate)(&tree) {
+            self.matched.process(tree);
+        } else {
+            self.processor.process(tree);
+        }
+    }
+}
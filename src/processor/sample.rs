@@ -0,0 +1,124 @@
+//! A [`Processor`] that only processes a fraction of trees.
+//!
+//! See [`Sample`] and [`AdaptiveSample`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::Level;
+
+/// A [`Processor`] that forwards a fraction of [`Tree`]s to another processor,
+/// and drops the rest.
+///
+/// Trees are sampled evenly, so a rate of `0.25` forwards every fourth tree,
+/// the same as [`TreeLayer::sample_rate`]. Unlike the layer's rate, this can
+/// sample only some of the trees in a pipeline, like the ones sent to one
+/// side of a [`Tee`].
+///
+/// To initialize a new [`Sample`], see [`Processor::sample`].
+///
+/// [`TreeLayer::sample_rate`]: crate::layer::TreeLayer::sample_rate
+/// [`Tee`]: crate::processor::tee::Tee
+pub struct Sample<P> {
+    processor: P,
+    rate: f64,
+    seen: AtomicU64,
+}
+
+impl<P> Sample<P> {
+    pub(crate) fn new(processor: P, rate: f64) -> Self {
+        Sample {
+            processor,
+            rate: rate.clamp(0.0, 1.0),
+            seen: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Returns whether the next of the trees counted by `seen` should be kept
+/// to keep `rate` of them.
+pub(crate) fn sampled(seen: &AtomicU64, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    let n = seen.fetch_add(1, Ordering::Relaxed) as f64;
+    ((n + 1.0) * rate).floor() > (n * rate).floor()
+}
+
+impl<P: Processor> Processor for Sample<P> {
+    fn process(&self, tree: Tree) {
+        if sampled(&self.seen, self.rate) {
+            self.processor.process(tree);
+        }
+    }
+}
+
+/// A [`Processor`] that forwards a fraction of [`Tree`]s to another
+/// processor, and a larger fraction for a while after a tree with an error.
+///
+/// A tree is an error if its most severe level is `ERROR`. Error trees are
+/// always forwarded, and start a window during which trees are sampled at
+/// the boosted rate instead, which is `1.0` by default. Each error restarts
+/// the window. This captures the context around incidents, like the other
+/// requests failing at the same time, while keeping few trees otherwise.
+///
+/// To initialize a new [`AdaptiveSample`], see [`Processor::sample_adaptive`].
+pub struct AdaptiveSample<P> {
+    processor: P,
+    rate: f64,
+    boosted_rate: f64,
+    window: Duration,
+    created: Instant,
+    /// Nanoseconds after `created` that the window ends, or 0 if no error
+    /// was seen yet.
+    boosted_until: AtomicU64,
+    seen: AtomicU64,
+}
+
+impl<P> AdaptiveSample<P> {
+    pub(crate) fn new(processor: P, rate: f64, window: Duration) -> Self {
+        AdaptiveSample {
+            processor,
+            rate: rate.clamp(0.0, 1.0),
+            boosted_rate: 1.0,
+            window,
+            created: Instant::now(),
+            boosted_until: AtomicU64::new(0),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the rate that trees are sampled at after an error.
+    pub fn boosted_rate(mut self, rate: f64) -> Self {
+        self.boosted_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns whether trees are being sampled at the boosted rate.
+    pub fn is_boosted(&self) -> bool {
+        self.elapsed() < self.boosted_until.load(Ordering::Relaxed)
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.created.elapsed().as_nanos() as u64
+    }
+}
+
+impl<P: Processor> Processor for AdaptiveSample<P> {
+    fn process(&self, tree: Tree) {
+        if tree.most_severe_level() == Level::ERROR {
+            let until = self.elapsed().saturating_add(self.window.as_nanos() as u64);
+            self.boosted_until.fetch_max(until, Ordering::Relaxed);
+            return self.processor.process(tree);
+        }
+        let rate = if self.is_boosted() {
+            self.boosted_rate
+        } else {
+            self.rate
+        };
+        if sampled(&self.seen, rate) {
+            self.processor.process(tree);
+        }
+    }
+}
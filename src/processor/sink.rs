@@ -0,0 +1,184 @@
+//! A [`Processor`] that converts trees into custom records for a [`Sink`].
+//!
+//! See [`map`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::layer::Tree;
+use crate::processor::Processor;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::mpsc;
+
+/// A destination for records of type `T`, like a message queue producer or
+/// a channel to another part of the application.
+///
+/// This is implemented for closures returning an [`io::Result`], and for
+/// channel senders.
+pub trait Sink<T>: 'static {
+    /// Deliver `record`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the record couldn't be delivered, which is passed
+    /// to the [error handler] and drops the record.
+    ///
+    /// [error handler]: crate::set_error_handler
+    fn send(&self, record: T) -> io::Result<()>;
+}
+
+impl<T, F> Sink<T> for F
+where
+    F: 'static + Fn(T) -> io::Result<()>,
+{
+    fn send(&self, record: T) -> io::Result<()> {
+        self(record)
+    }
+}
+
+impl<T: 'static + Send> Sink<T> for mpsc::Sender<T> {
+    fn send(&self, record: T) -> io::Result<()> {
+        mpsc::Sender::send(self, record).map_err(|_| closed())
+    }
+}
+
+impl<T: 'static + Send> Sink<T> for mpsc::SyncSender<T> {
+    fn send(&self, record: T) -> io::Result<()> {
+        mpsc::SyncSender::send(self, record).map_err(|_| closed())
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: 'static + Send> Sink<T> for tokio::sync::mpsc::UnboundedSender<T> {
+    fn send(&self, record: T) -> io::Result<()> {
+        tokio::sync::mpsc::UnboundedSender::send(self, record).map_err(|_| closed())
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the receiver was dropped")
+}
+
+/// Start building a [`Processor`] that converts each [`Tree`] into a record
+/// with `f`, which is then sent to a [`Sink`].
+///
+/// This is an alternative to implementing [`Processor`] for forwarding trees
+/// to destinations that expect their own record types, like protobuf
+/// messages or domain events. Records that the sink fails to deliver are
+/// reported to the [error handler].
+///
+/// To keep processing trees as well, like writing them to stdout, see
+/// [`Processor::map`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::mpsc;
+/// # use tracing_forest::layer::{Tree, TreeKind};
+/// # use tracing_forest::processor::sink::map;
+/// # use tracing_forest::Processor;
+/// struct Request {
+///     name: &'static str,
+///     millis: u128,
+/// }
+///
+/// fn request(tree: &Tree) -> Option<Request> {
+///     match &tree.kind {
+///         TreeKind::Span(span) => Some(Request {
+///             name: span.name,
+///             millis: span.duration_total.as_millis(),
+///         }),
+///         TreeKind::Event(_) => None,
+///     }
+/// }
+///
+/// let (sender, receiver) = mpsc::channel();
+/// let processor = map(request).sink(sender);
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info_span!("checkout").in_scope(|| tracing::info!("paid"));
+/// });
+///
+/// let request = receiver.recv().unwrap().unwrap();
+/// assert_eq!(request.name, "checkout");
+/// ```
+///
+/// [error handler]: crate::set_error_handler
+pub fn map<F, T>(f: F) -> Map<F, T>
+where
+    F: 'static + Fn(&Tree) -> T,
+{
+    Map::new(Discard, f)
+}
+
+/// A [`Processor`] that drops every [`Tree`], which trees converted by
+/// [`map`] are passed to once they're converted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Discard;
+
+impl Processor for Discard {
+    fn process(&self, tree: Tree) {
+        let _ = tree;
+    }
+}
+
+/// Converts [`Tree`]s into records, waiting for a [`Sink`] to send them to.
+///
+/// To initialize a new [`Map`], see [`map`] or [`Processor::map`].
+pub struct Map<F, T, P = Discard> {
+    processor: P,
+    f: F,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<F, T, P> Map<F, T, P>
+where
+    F: 'static + Fn(&Tree) -> T,
+{
+    pub(crate) fn new(processor: P, f: F) -> Self {
+        Map {
+            processor,
+            f,
+            _record: PhantomData,
+        }
+    }
+
+    /// Send the converted records to `sink`, completing the [`Processor`].
+    pub fn sink<S: Sink<T>>(self, sink: S) -> Mapped<F, S, T, P> {
+        Mapped {
+            processor: self.processor,
+            f: self.f,
+            sink,
+            _record: PhantomData,
+        }
+    }
+}
+
+/// A [`Processor`] that converts each [`Tree`] into a record and sends it to
+/// a [`Sink`], after passing the tree on to another processor.
+///
+/// To initialize a new [`Mapped`], see [`map`] or [`Processor::map`].
+pub struct Mapped<F, S, T, P = Discard> {
+    processor: P,
+    f: F,
+    sink: S,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<F, S, T, P> Processor for Mapped<F, S, T, P>
+where
+    F: 'static + Fn(&Tree) -> T,
+    S: Sink<T>,
+    T: 'static,
+    P: Processor,
+{
+    fn process(&self, tree: Tree) {
+        let record = (self.f)(&tree);
+        self.processor.process(tree);
+        if let Err(error) = self.sink.send(record) {
+            error::report(ForestError::Processor {
+                processor: "sink",
+                error,
+            });
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! A [`Processor`] that sends logs to a collector over a Unix domain socket.
+//!
+//! See [`SocketProcessor`] for more details.
+
+use crate::formatter::Formatter;
+use crate::layer::Tree;
+use crate::processor::net::{Resilient, Transport};
+use crate::processor::Processor;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The type of Unix domain socket a [`SocketProcessor`] connects to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    /// A `SOCK_STREAM` socket, where records are sent back to back.
+    Stream,
+    /// A `SOCK_DGRAM` socket, where each record is sent as one datagram.
+    Datagram,
+}
+
+/// A [`Processor`] that sends each formatted [`Tree`] to a Unix domain socket,
+/// so a sidecar collector can pick it up.
+///
+/// Each tree is sent as a record, which is a little-endian `u32` length
+/// followed by that many bytes of formatted output. Datagram sockets carry one
+/// record per datagram, so trees larger than the socket's maximum datagram
+/// size can't be sent.
+///
+/// Records are sent by a [`Resilient`] processor, so the socket is connected
+/// and written to on a background thread. When the socket is unavailable,
+/// the thread reconnects after the [reconnect delay] has elapsed, and trees
+/// processed in the meantime wait in the [queue]. Trees that overflow the
+/// queue, or are still queued when the processor is dropped, are appended to
+/// the [spill file], if there is one, and are sent in order before the next
+/// record once the socket is available. Otherwise, they are discarded, and
+/// the number discarded is available from [`dropped`].
+///
+/// If a write to a stream socket fails partway through a record, the
+/// connection is closed and the record is sent again after reconnecting, so
+/// the collector should discard a truncated record at the end of a
+/// connection.
+///
+/// To initialize a new [`SocketProcessor`], see [`unix_stream`] and
+/// [`unix_datagram`].
+///
+/// [reconnect delay]: SocketProcessor::reconnect_delay
+/// [queue]: SocketProcessor::queue_capacity
+/// [spill file]: SocketProcessor::spill
+/// [`dropped`]: SocketProcessor::dropped
+pub struct SocketProcessor<F: Formatter> {
+    net: Resilient<Socket<F>>,
+}
+
+/// The [`Transport`] of a [`SocketProcessor`].
+struct Socket<F> {
+    formatter: F,
+    path: PathBuf,
+    kind: SocketKind,
+    options: Mutex<Options>,
+}
+
+struct Options {
+    write_timeout: Duration,
+    spill: Option<File>,
+    /// Whether the spill file has records that weren't sent.
+    spilled: bool,
+}
+
+enum Conn {
+    Stream(UnixStream),
+    Datagram(UnixDatagram),
+}
+
+impl Conn {
+    fn send(&mut self, record: &[u8]) -> io::Result<()> {
+        match self {
+            Conn::Stream(stream) => stream.write_all(record),
+            Conn::Datagram(socket) => socket.send(record).map(drop),
+        }
+    }
+}
+
+impl<F> SocketProcessor<F>
+where
+    F: 'static + Formatter + Send + Sync,
+{
+    /// Set how long to wait after the socket becomes unavailable before
+    /// reconnecting.
+    ///
+    /// By default, the processor waits one second between attempts.
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.net = self.net.initial_backoff(delay).max_backoff(delay);
+        self
+    }
+
+    /// Set how long a send can block before the socket is considered
+    /// unavailable.
+    ///
+    /// By default, sends time out after one second.
+    pub fn write_timeout(self, timeout: Duration) -> Self {
+        self.net.transport().options().write_timeout = timeout;
+        self
+    }
+
+    /// Set how many trees wait to be sent while the socket is slow or
+    /// unavailable, before they're spilled or discarded.
+    ///
+    /// By default, up to 1024 trees are queued.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.net = self.net.queue_capacity(capacity);
+        self
+    }
+
+    /// Spill trees to the file at `path` when they can't be queued.
+    ///
+    /// The file uses the same record format as the socket. If it already
+    /// contains records from a previous run, they are sent once the
+    /// processor connects.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn spill<P: AsRef<Path>>(self, path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut options = self.net.transport().options();
+        options.spilled = file.metadata()?.len() > 0;
+        options.spill = Some(file);
+        drop(options);
+        Ok(self)
+    }
+
+    /// Returns how many trees have been discarded because the socket was
+    /// unavailable and there was no spill file, or the spill file couldn't be
+    /// written.
+    pub fn dropped(&self) -> u64 {
+        self.net.stats().dropped
+    }
+
+    /// Send every queued tree now, and wait until the thread has tried to.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the socket is unavailable, in which case the trees
+    /// that weren't sent remain queued.
+    pub fn flush(&self) -> io::Result<()> {
+        self.net.flush()
+    }
+}
+
+impl<F> Socket<F> {
+    fn options(&self) -> MutexGuard<'_, Options> {
+        #[allow(clippy::expect_used)]
+        self.options.lock().expect("socket processor poisoned")
+    }
+}
+
+impl Options {
+    /// Sends the records in the spill file, keeping any that couldn't be sent.
+    fn drain_spill(&mut self, conn: &mut Conn) -> io::Result<()> {
+        let spill = match &mut self.spill {
+            Some(spill) if self.spilled => spill,
+            _ => return Ok(()),
+        };
+
+        let mut contents = Vec::new();
+        spill.seek(SeekFrom::Start(0))?;
+        spill.read_to_end(&mut contents)?;
+
+        let mut rest = &contents[..];
+        let mut result = Ok(());
+        while rest.len() >= 4 {
+            let len = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if rest.len() < 4 + len {
+                // Torn write, the record was never spilled
+                rest = &[];
+                break;
+            }
+            let (record, tail) = rest.split_at(4 + len);
+            if let Err(err) = conn.send(record) {
+                result = Err(err);
+                break;
+            }
+            rest = tail;
+        }
+
+        spill.set_len(0)?;
+        spill.seek(SeekFrom::Start(0))?;
+        spill.write_all(rest)?;
+        self.spilled = !rest.is_empty();
+        result
+    }
+}
+
+impl<F: Formatter> Transport for Socket<F> {
+    type Connection = Conn;
+
+    fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+        record.extend_from_slice(&[0; 4]);
+        self.formatter.fmt(tree, record)?;
+        let len = u32::try_from(record.len() - 4)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        record[..4].copy_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+
+    fn connect(&self) -> io::Result<Conn> {
+        let write_timeout = self.options().write_timeout;
+        let timeout = Some(write_timeout).filter(|timeout| !timeout.is_zero());
+        match self.kind {
+            SocketKind::Stream => {
+                let stream = UnixStream::connect(&self.path)?;
+                stream.set_write_timeout(timeout)?;
+                Ok(Conn::Stream(stream))
+            }
+            SocketKind::Datagram => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(&self.path)?;
+                socket.set_write_timeout(timeout)?;
+                Ok(Conn::Datagram(socket))
+            }
+        }
+    }
+
+    fn send(&self, conn: &mut Conn, record: &[u8]) -> io::Result<()> {
+        // Spilled records are older than the queued ones, so they go first
+        self.options().drain_spill(conn)?;
+        conn.send(record)
+    }
+
+    fn spill(&self, record: &[u8]) -> io::Result<bool> {
+        let mut options = self.options();
+        let spill = match &mut options.spill {
+            Some(spill) => spill,
+            None => return Ok(false),
+        };
+        spill.seek(SeekFrom::End(0))?;
+        spill.write_all(record)?;
+        options.spilled = true;
+        Ok(true)
+    }
+}
+
+impl<F> Processor for SocketProcessor<F>
+where
+    F: 'static + Formatter + Send + Sync,
+{
+    fn process(&self, tree: Tree) {
+        self.net.process(tree);
+    }
+}
+
+fn socket<F>(formatter: F, path: &Path, kind: SocketKind) -> SocketProcessor<F>
+where
+    F: 'static + Formatter + Send + Sync,
+{
+    let socket = Socket {
+        formatter,
+        path: path.to_path_buf(),
+        kind,
+        options: Mutex::new(Options {
+            write_timeout: Duration::from_secs(1),
+            spill: None,
+            spilled: false,
+        }),
+    };
+    let delay = Duration::from_secs(1);
+    SocketProcessor {
+        net: Resilient::new(socket)
+            .initial_backoff(delay)
+            .max_backoff(delay),
+    }
+}
+
+/// Initialize a new [`SocketProcessor`] sending trees to the stream socket at
+/// `path`.
+///
+/// The socket is connected when the first tree is processed, so the
+/// collector doesn't need to be running yet.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{formatter::pretty::Pretty, processor::socket::unix_stream, Processor};
+/// # let dir = std::env::temp_dir().join("tracing-forest-socket-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let processor = unix_stream(Pretty::new(), dir.join("collector.sock"))
+///     .spill(dir.join("spill.log"))
+///     .expect("failed to open spill file");
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info!("this log is sent once the collector is up");
+/// });
+/// ```
+pub fn unix_stream<F, P>(formatter: F, path: P) -> SocketProcessor<F>
+where
+    F: 'static + Formatter + Send + Sync,
+    P: AsRef<Path>,
+{
+    socket(formatter, path.as_ref(), SocketKind::Stream)
+}
+
+/// Initialize a new [`SocketProcessor`] sending trees to the datagram socket
+/// at `path`.
+///
+/// The socket is connected when the first tree is processed, so the
+/// collector doesn't need to be running yet.
+pub fn unix_datagram<F, P>(formatter: F, path: P) -> SocketProcessor<F>
+where
+    F: 'static + Formatter + Send + Sync,
+    P: AsRef<Path>,
+{
+    socket(formatter, path.as_ref(), SocketKind::Datagram)
+}
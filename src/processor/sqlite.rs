@@ -0,0 +1,305 @@
+//! A [`Processor`] that stores logs in a SQLite database.
+//!
+//! See [`SqliteProcessor`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::layer::{KeyValue, Tree, TreeKind};
+use crate::processor::Processor;
+use rusqlite::{params, Connection, Transaction};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS trees (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT,
+    timestamp TEXT,
+    level TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS spans (
+    id INTEGER PRIMARY KEY,
+    tree_id INTEGER NOT NULL REFERENCES trees(id),
+    parent_id INTEGER REFERENCES spans(id),
+    name TEXT NOT NULL,
+    level TEXT NOT NULL,
+    uuid TEXT,
+    timestamp TEXT,
+    duration_ms REAL NOT NULL,
+    nested_ms REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    tree_id INTEGER NOT NULL REFERENCES trees(id),
+    span_id INTEGER REFERENCES spans(id),
+    level TEXT NOT NULL,
+    tag TEXT,
+    message TEXT NOT NULL,
+    timestamp TEXT
+);
+CREATE TABLE IF NOT EXISTS fields (
+    event_id INTEGER NOT NULL REFERENCES events(id),
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+";
+
+/// A [`Processor`] that inserts [`Tree`]s into a SQLite database, so local
+/// debugging sessions can be queried with SQL.
+///
+/// Trees are buffered and inserted in batches, each in a single transaction.
+/// A batch is inserted once it reaches the [batch size], once its oldest tree
+/// is older than the [maximum delay] when another tree arrives, when
+/// [`flush`] is called, or when the processor is dropped. The database is
+/// opened in WAL mode, so it can be queried while logs are being inserted.
+///
+/// To initialize a new [`SqliteProcessor`], see [`sqlite`].
+///
+/// ## Schema
+///
+/// ```sql
+/// CREATE TABLE trees (id, uuid, timestamp, level);
+/// CREATE TABLE spans (id, tree_id, parent_id, name, level, uuid, timestamp, duration_ms, nested_ms);
+/// CREATE TABLE events (id, tree_id, span_id, level, tag, message, timestamp);
+/// CREATE TABLE fields (event_id, key, value);
+/// ```
+///
+/// `parent_id` and `span_id` are `NULL` for root spans and events outside of
+/// spans. `uuid` and `timestamp` are `NULL` without the `uuid` and `chrono`
+/// features. Field values are stored as they're displayed by the other
+/// formatters, so strings include their quotes.
+///
+/// ## Examples
+///
+/// ```sql
+/// SELECT name, duration_ms FROM spans WHERE duration_ms > 100 ORDER BY duration_ms DESC;
+///
+/// SELECT events.message FROM events
+/// JOIN fields ON fields.event_id = events.id
+/// WHERE fields.key = 'user' AND fields.value = '"alice"';
+/// ```
+///
+/// [batch size]: SqliteProcessor::batch_size
+/// [maximum delay]: SqliteProcessor::max_delay
+/// [`flush`]: SqliteProcessor::flush
+pub struct SqliteProcessor {
+    state: Mutex<State>,
+    batch_size: usize,
+    max_delay: Duration,
+}
+
+struct State {
+    conn: Connection,
+    pending: Vec<Tree>,
+    oldest: Option<Instant>,
+}
+
+impl SqliteProcessor {
+    /// Set how many trees are buffered before they're inserted.
+    ///
+    /// By default, trees are inserted in batches of 64.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set how long trees can be buffered before they're inserted, even if
+    /// the batch isn't full.
+    ///
+    /// This is only checked when a tree is processed. By default, trees are
+    /// buffered for at most one second.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Insert all buffered trees.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the trees couldn't be inserted, in which case they
+    /// remain buffered.
+    pub fn flush(&self) -> rusqlite::Result<()> {
+        #[allow(clippy::expect_used)]
+        let mut state = self.state.lock().expect("sqlite processor poisoned");
+        state.flush()
+    }
+}
+
+impl State {
+    fn flush(&mut self) -> rusqlite::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction()?;
+        for tree in self.pending.iter() {
+            insert_tree(&tx, tree)?;
+        }
+        tx.commit()?;
+
+        self.pending.clear();
+        self.oldest = None;
+        Ok(())
+    }
+}
+
+impl Processor for SqliteProcessor {
+    fn process(&self, tree: Tree) {
+        #[allow(clippy::expect_used)]
+        let mut state = self.state.lock().expect("sqlite processor poisoned");
+
+        let oldest = *state.oldest.get_or_insert_with(Instant::now);
+        state.pending.push(tree);
+
+        if state.pending.len() >= self.batch_size || oldest.elapsed() >= self.max_delay {
+            if let Err(err) = state.flush() {
+                error::report(ForestError::Processor {
+                    processor: "sqlite",
+                    error: io::Error::other(err),
+                });
+            }
+        }
+    }
+}
+
+impl Drop for SqliteProcessor {
+    fn drop(&mut self) {
+        if let Ok(state) = self.state.get_mut() {
+            // Panicking in drop would abort, so the remaining trees are lost
+            let _ = state.flush();
+        }
+    }
+}
+
+fn insert_tree(tx: &Transaction, tree: &Tree) -> rusqlite::Result<()> {
+    tx.prepare_cached("INSERT INTO trees (uuid, timestamp, level) VALUES (?1, ?2, ?3)")?
+        .execute(params![
+            uuid(tree),
+            timestamp(tree),
+            tree.attrs.level.as_str()
+        ])?;
+    let tree_id = tx.last_insert_rowid();
+    insert_node(tx, tree_id, None, tree)
+}
+
+fn insert_node(
+    tx: &Transaction,
+    tree_id: i64,
+    parent_id: Option<i64>,
+    tree: &Tree,
+) -> rusqlite::Result<()> {
+    let level = tree.attrs.level.as_str();
+
+    match &tree.kind {
+        TreeKind::Span(span) => {
+            tx.prepare_cached(
+                "INSERT INTO spans (tree_id, parent_id, name, level, uuid, timestamp, duration_ms, nested_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?
+            .execute(params![
+                tree_id,
+                parent_id,
+                span.name,
+                level,
+                uuid(tree),
+                timestamp(tree),
+                span.duration_total.as_secs_f64() * 1000.0,
+                span.duration_nested.as_secs_f64() * 1000.0,
+            ])?;
+            let span_id = tx.last_insert_rowid();
+
+            for child in span.children.iter() {
+                insert_node(tx, tree_id, Some(span_id), child)?;
+            }
+        }
+        TreeKind::Event(event) => {
+            tx.prepare_cached(
+                "INSERT INTO events (tree_id, span_id, level, tag, message, timestamp)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                tree_id,
+                parent_id,
+                level,
+                event.tag.map(|tag| tag.message),
+                event.message.as_ref(),
+                timestamp(tree),
+            ])?;
+            let event_id = tx.last_insert_rowid();
+
+            let mut insert_field =
+                tx.prepare_cached("INSERT INTO fields (event_id, key, value) VALUES (?1, ?2, ?3)")?;
+            for KeyValue { key, value, .. } in event.fields.iter() {
+                insert_field.execute(params![event_id, key, value])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn uuid(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "uuid")]
+    return match tree.kind {
+        // Events aren't assigned their own IDs
+        TreeKind::Event(_) => None,
+        TreeKind::Span(_) => Some(tree.attrs.uuid.to_string()),
+    };
+
+    #[cfg(not(feature = "uuid"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+fn timestamp(tree: &Tree) -> Option<String> {
+    #[cfg(feature = "chrono")]
+    return Some(tree.attrs.timestamp.to_rfc3339());
+
+    #[cfg(not(feature = "chrono"))]
+    {
+        let _ = tree;
+        None
+    }
+}
+
+/// Initialize a new [`SqliteProcessor`] storing trees in the database at
+/// `path`, creating it and its tables if they don't exist.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{processor::sqlite::sqlite, Processor};
+/// # let dir = std::env::temp_dir().join("tracing-forest-sqlite-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let processor = sqlite(dir.join("traces.db"))
+///     .expect("failed to open database")
+///     .batch_size(16);
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info!("this log can be queried with SQL");
+/// });
+/// ```
+///
+/// ## Errors
+///
+/// Returns an error if the database cannot be opened or initialized.
+pub fn sqlite<P: AsRef<Path>>(path: P) -> rusqlite::Result<SqliteProcessor> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.execute_batch(SCHEMA)?;
+
+    Ok(SqliteProcessor {
+        state: Mutex::new(State {
+            conn,
+            pending: Vec::new(),
+            oldest: None,
+        }),
+        batch_size: 64,
+        max_delay: Duration::from_secs(1),
+    })
+}
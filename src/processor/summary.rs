@@ -0,0 +1,176 @@
+//! A [`Processor`] that summarizes every tree it processes.
+//!
+//! See [`Summarize`] for more details.
+
+use crate::layer::{Tree, TreeKind};
+use crate::processor::Processor;
+use crate::tree::EventCounts;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Level;
+
+/// How many of the slowest spans a summary shows.
+const SLOWEST: usize = 5;
+
+/// A [`Processor`] that records statistics about the [`Tree`]s it forwards
+/// to another processor, so that a summary of the whole run can be processed
+/// when it ends.
+///
+/// The summary is a tree named `run summary`, whose duration is the time
+/// since the processor was created. It contains an event counting events by
+/// level, an event counting events by tag if any were tagged, and the slowest
+/// spans, which are displayed like this by the [`Pretty`] formatter:
+///
+/// ```log
+/// INFO     run summary [ 2.51s | 100.000% ]
+/// INFO     ┝━ 💬 [info]: events by level | ERROR: 1 | WARN: 3 | INFO: 120
+/// INFO     ┝━ 💬 [info]: events by tag | security.critical: 1
+/// INFO     ┝━ import [ 1.83s | 72.908% ]
+/// INFO     ┕━ export [ 402ms | 16.016% ]
+/// ```
+///
+/// This is used by `#[tracing_forest::main(summary)]`. To initialize a new
+/// [`Summarize`], see [`Processor::summarize`].
+///
+/// [`Pretty`]: crate::formatter::pretty::Pretty
+pub struct Summarize<P> {
+    shared: Arc<Shared<P>>,
+}
+
+struct Shared<P> {
+    processor: P,
+    started: Instant,
+    stats: Mutex<Stats>,
+}
+
+#[derive(Default)]
+struct Stats {
+    counts: EventCounts,
+    /// The slowest spans so far, from slowest to fastest.
+    slowest: Vec<(Duration, Level, &'static str)>,
+}
+
+/// A handle for processing the summary of a [`Summarize`] processor.
+///
+/// Dropping the handle doesn't process the summary, so the run can end
+/// without one.
+pub struct SummaryHandle {
+    report: Arc<dyn Report + Send + Sync>,
+}
+
+trait Report {
+    fn report(&self);
+}
+
+impl<P> Summarize<P>
+where
+    P: Processor + Send + Sync,
+{
+    pub(crate) fn new(processor: P) -> (Self, SummaryHandle) {
+        let shared = Arc::new(Shared {
+            processor,
+            started: Instant::now(),
+            stats: Mutex::new(Stats::default()),
+        });
+        let handle = SummaryHandle {
+            report: shared.clone(),
+        };
+        (Summarize { shared }, handle)
+    }
+}
+
+impl Stats {
+    fn record(&mut self, tree: &Tree) {
+        match &tree.kind {
+            TreeKind::Event(event) => {
+                self.counts.add(tree.attrs.level, event.tag);
+            }
+            TreeKind::Span(span) => {
+                let idx = self
+                    .slowest
+                    .partition_point(|(duration, ..)| *duration >= span.duration_total);
+                if idx < SLOWEST {
+                    self.slowest
+                        .insert(idx, (span.duration_total, tree.attrs.level, span.name));
+                    self.slowest.truncate(SLOWEST);
+                }
+                span.children.iter().for_each(|child| self.record(child));
+            }
+        }
+    }
+
+    fn tree(&self, runtime: Duration) -> Tree {
+        let mut levels = Tree::event(Level::INFO, "events by level");
+        // Levels are ordered from most to least severe
+        for (level, count) in self.counts.levels() {
+            levels = levels.with_field(level.as_str(), count.to_string());
+        }
+
+        let mut summary = Tree::root("run summary").with_duration(runtime);
+        summary.add_child(levels);
+
+        if !self.counts.tags().is_empty() {
+            let mut counts = self.counts.tags().to_vec();
+            counts.sort_unstable();
+            let mut tags = Tree::event(Level::INFO, "events by tag");
+            for (tag, count) in counts {
+                tags = tags.with_field(tag, count.to_string());
+            }
+            summary.add_child(tags);
+        }
+
+        for &(duration, level, name) in self.slowest.iter() {
+            summary.add_child(Tree::span(level, name).with_duration(duration));
+        }
+
+        // The slowest spans can be nested in each other, so they aren't
+        // counted as time spent in children of the summary
+        if let TreeKind::Span(span) = &mut summary.kind {
+            span.duration_nested = Duration::ZERO;
+        }
+        summary
+    }
+}
+
+impl<P: Processor> Report for Shared<P> {
+    fn report(&self) {
+        let summary = {
+            #[allow(clippy::expect_used)]
+            let stats = self.stats.lock().expect("summary poisoned");
+            stats.tree(self.started.elapsed())
+        };
+        self.processor.process(summary);
+    }
+}
+
+impl SummaryHandle {
+    /// Process the summary of every tree processed so far with the processor
+    /// that the [`Summarize`] forwards trees to.
+    pub fn report(self) {
+        self.report.report();
+    }
+}
+
+/// Reports a summary when dropped, so `#[tracing_forest::main(summary)]`
+/// reports it even if `main` returns early.
+#[doc(hidden)]
+pub struct ReportOnDrop(pub Option<SummaryHandle>);
+
+impl Drop for ReportOnDrop {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            handle.report();
+        }
+    }
+}
+
+impl<P: Processor> Processor for Summarize<P> {
+    fn process(&self, tree: Tree) {
+        {
+            #[allow(clippy::expect_used)]
+            let mut stats = self.shared.stats.lock().expect("summary poisoned");
+            stats.record(&tree);
+        }
+        self.shared.processor.process(tree);
+    }
+}
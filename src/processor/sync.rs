@@ -2,10 +2,14 @@
 //!
 //! See [`AsyncProcessor`] for more details.
 
+use crate::error::{self, ForestError};
 use crate::formatter::Formatter;
 use crate::layer::Tree;
-use crate::processor::Processor;
+use crate::processor::{buffers, Processor};
 use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tracing_subscriber::fmt::MakeWriter;
@@ -19,7 +23,7 @@ use tracing_subscriber::fmt::MakeWriter;
 /// In the case where a processing task has already been initialized,
 /// an [`AsyncProcessor`] can also be constructed manually from a
 /// [`tokio::sync::mpsc::UnboundedSender`]:
-/// 
+///
 /// ```
 /// # use tokio::sync::mpsc;
 /// # use tracing_forest::layer::{Tree, TreeLayer};
@@ -30,21 +34,59 @@ use tracing_subscriber::fmt::MakeWriter;
 /// }
 /// ```
 pub struct AsyncProcessor {
-    tx: mpsc::UnboundedSender<Tree>,
+    txs: Vec<Sender>,
+    next: AtomicUsize,
+}
+
+enum Sender {
+    /// Provided by the user, so the receiver doesn't expect a send time.
+    Untimed(mpsc::UnboundedSender<Tree>),
+    /// Connected to a spawned task, which measures the time spent queued.
+    Timed(mpsc::UnboundedSender<(Tree, Instant)>),
 }
 
 impl From<mpsc::UnboundedSender<Tree>> for AsyncProcessor {
     fn from(tx: mpsc::UnboundedSender<Tree>) -> Self {
-        AsyncProcessor { tx }
+        AsyncProcessor::sharded(vec![Sender::Untimed(tx)])
+    }
+}
+
+impl AsyncProcessor {
+    fn sharded(txs: Vec<Sender>) -> Self {
+        AsyncProcessor {
+            txs,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the index of the worker that processes `tree`.
+    fn shard(&self, tree: &Tree) -> usize {
+        // Trees without an ID, like ones with a lazy ID that was never
+        // requested, are spread evenly instead
+        #[cfg(feature = "uuid")]
+        let key = match tree.attrs.uuid.as_u128() as usize {
+            0 => self.next.fetch_add(1, Ordering::Relaxed),
+            key => key,
+        };
+        #[cfg(not(feature = "uuid"))]
+        let key = {
+            let _ = tree;
+            self.next.fetch_add(1, Ordering::Relaxed)
+        };
+        key % self.txs.len()
     }
 }
 
 impl Processor for AsyncProcessor {
     fn process(&self, tree: Tree) {
-        #[allow(clippy::expect_used)]
-        self.tx
-            .send(tree)
-            .expect("failed to send logs to processing thread");
+        let sent = match &self.txs[self.shard(&tree)] {
+            Sender::Untimed(tx) => tx.send(tree).map_err(|_| ()),
+            Sender::Timed(tx) => tx.send((tree, Instant::now())).map_err(|_| ()),
+        };
+
+        if sent.is_err() {
+            error::report(ForestError::ChannelClosed);
+        }
     }
 }
 
@@ -116,20 +158,119 @@ where
     F: 'static + Formatter + Send,
     W: 'static + for<'a> MakeWriter<'a> + Send,
 {
-    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (tx, rx) = mpsc::unbounded_channel();
+    let handle = tokio::spawn(work(rx, formatter, make_writer));
+    let processor = AsyncProcessor::sharded(vec![Sender::Timed(tx)]);
 
-    let handle = tokio::spawn(async move {
-        while let Some(tree) = rx.recv().await {
-            let mut buf = Vec::with_capacity(0);
+    (processor, handle)
+}
 
-            #[allow(clippy::expect_used)]
-            formatter.fmt(tree, &mut buf).expect("formatting failed");
-            #[allow(clippy::unwrap_used)]
-            make_writer.make_writer().write_all(&buf[..]).unwrap();
+/// Initialize a new [`AsyncProcessor`] that shards trees across `workers`
+/// spawned processing tasks, returning the processor and a [`JoinHandle`]
+/// that completes once every task has.
+///
+/// With a multi-threaded runtime, this lets formatting and writing keep up
+/// with event rates that a single task can't. Trees are sharded by their
+/// [`Uuid`], so trees with the same ID are processed by the same task in the
+/// order they were sent. Without the `uuid` feature, trees are distributed
+/// round-robin. Trees for different shards may be written in any order.
+///
+/// The gain depends on how many cores are available and how expensive the
+/// formatter is. To compare worker counts on a given machine, run
+/// `cargo bench --bench sharded`.
+///
+/// The same caveats about awaiting the handle as [`async_spawn`] apply.
+///
+/// [`Uuid`]: uuid::Uuid
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{formatter::pretty::Pretty, Processor};
+/// # use tracing_forest::processor::sync::async_spawn_sharded;
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let (processor, handle) = async_spawn_sharded(Pretty::new(), std::io::stdout, 4);
+///     let guard = tracing::subscriber::set_default({
+///         processor
+///             .into_layer()
+///             .into_subscriber()
+///     });
+///
+///     tracing::info!("processed by one of four tasks");
+///
+///     drop(guard);
+///     handle.await.unwrap();
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// Panics if `workers` is zero, or if called from **outside** of the Tokio
+/// runtime.
+pub fn async_spawn_sharded<F, W>(
+    formatter: F,
+    make_writer: W,
+    workers: usize,
+) -> (AsyncProcessor, JoinHandle<()>)
+where
+    F: 'static + Formatter + Send + Sync,
+    W: 'static + for<'a> MakeWriter<'a> + Send + Sync,
+{
+    assert!(workers > 0, "at least one worker is required");
+
+    let formatter = Arc::new(formatter);
+    let make_writer = Arc::new(make_writer);
+
+    let (txs, handles): (Vec<_>, Vec<_>) = (0..workers)
+        .map(|_| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let writer = SharedWriter(make_writer.clone());
+            let handle = tokio::spawn(work(rx, formatter.clone(), writer));
+            (Sender::Timed(tx), handle)
+        })
+        .unzip();
+
+    let handle = tokio::spawn(async move {
+        for handle in handles {
+            if let Err(err) = handle.await {
+                if err.is_panic() {
+                    std::panic::resume_unwind(err.into_panic());
+                }
+            }
         }
     });
 
-    let processor = AsyncProcessor::from(tx);
+    (AsyncProcessor::sharded(txs), handle)
+}
 
-    (processor, handle)
+async fn work<F, W>(mut rx: mpsc::UnboundedReceiver<(Tree, Instant)>, formatter: F, make_writer: W)
+where
+    F: Formatter,
+    W: for<'a> MakeWriter<'a>,
+{
+    // The buffer is reused for every tree
+    let mut buf = Vec::new();
+    while let Some((tree, sent)) = rx.recv().await {
+        buffers::reuse(&mut buf);
+
+        if let Err(err) = formatter.fmt_with_latency(tree, Some(sent.elapsed()), &mut buf) {
+            error::report(ForestError::Format(err));
+            continue;
+        }
+        if let Err(err) = make_writer.make_writer().write_all(&buf[..]) {
+            error::report(ForestError::Write(err));
+        }
+    }
+}
+
+/// A [`MakeWriter`] shared between workers.
+struct SharedWriter<W>(Arc<W>);
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for SharedWriter<W> {
+    type Writer = W::Writer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.0.make_writer()
+    }
 }
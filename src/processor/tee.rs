@@ -0,0 +1,36 @@
+//! A [`Processor`] that sends trees to two processors.
+//!
+//! See [`Tee`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+
+/// A [`Processor`] that forwards every [`Tree`] to one processor, and a copy
+/// of it to another.
+///
+/// This can write the same trees to several destinations, such as pretty
+/// printing them to stdout while sending them as JSON to a collector.
+///
+/// To initialize a new [`Tee`], see [`Processor::tee`].
+pub struct Tee<P, Q> {
+    processor: P,
+    copy: Q,
+}
+
+impl<P, Q> Tee<P, Q> {
+    pub(crate) fn new(processor: P, copy: Q) -> Self {
+        Tee { processor, copy }
+    }
+}
+
+impl<P, Q> Processor for Tee<P, Q>
+where
+    P: Processor,
+    Q: Processor,
+{
+    fn process(&self, tree: Tree) {
+        let copy = tree.clone();
+        self.processor.process(tree);
+        self.copy.process(copy);
+    }
+}
@@ -0,0 +1,193 @@
+//! A [`Processor`] that sends trees to a separate processor per tenant.
+//!
+//! See [`Tenants`] for more details.
+
+use crate::layer::Tree;
+use crate::processor::Processor;
+use crate::tree::unquote;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A [`Processor`] that routes each [`Tree`] by a field of its root span, like
+/// `tenant_id`, to a processor for that value.
+///
+/// Processors are created the first time a tenant logs a tree, and with an
+/// [idle timeout], dropped once the tenant hasn't logged anything for a while,
+/// which closes files or connections they own. Trees without the field are
+/// sent to the [untenanted] processor, if there is one, and dropped
+/// otherwise.
+///
+/// The field is matched against the value recorded on the root span, with
+/// strings unquoted, so `tenant_id = "acme"` and `tenant_id = %"acme"` are
+/// the same tenant, `acme`.
+///
+/// # Examples
+///
+/// Write each tenant's logs to its own file:
+///
+/// ```
+/// # use std::fs::File;
+/// # use std::sync::Mutex;
+/// # use std::time::Duration;
+/// # use tracing_forest::{blocking, formatter::json::Json, Processor};
+/// # use tracing_forest::processor::tenant::Tenants;
+/// let dir = std::env::temp_dir();
+/// let processor = Tenants::new("tenant_id", move |tenant: &str| {
+///     let file = File::create(dir.join(format!("{}.log", tenant))).unwrap();
+///     blocking(Json::new(true), Mutex::new(file))
+/// })
+/// .idle_timeout(Duration::from_secs(300))
+/// .untenanted(blocking(Json::new(true), std::io::stdout));
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info_span!("request", tenant_id = "acme").in_scope(|| {
+///         tracing::info!("written to acme.log");
+///     });
+/// });
+/// ```
+///
+/// [idle timeout]: Tenants::idle_timeout
+/// [untenanted]: Tenants::untenanted
+pub struct Tenants<F, P> {
+    field: &'static str,
+    make: F,
+    idle_timeout: Option<Duration>,
+    untenanted: Option<Box<dyn Processor + Send + Sync>>,
+    state: Mutex<State<P>>,
+}
+
+struct State<P> {
+    tenants: HashMap<String, Tenant<P>>,
+    swept: Instant,
+}
+
+struct Tenant<P> {
+    processor: Arc<P>,
+    last_used: Instant,
+}
+
+impl<F, P> Tenants<F, P>
+where
+    F: Fn(&str) -> P,
+{
+    /// Construct a new [`Tenants`] processor routing trees by the root span
+    /// field named `field`, calling `make` to create the processor of each
+    /// new tenant.
+    pub fn new(field: &'static str, make: F) -> Self {
+        Tenants {
+            field,
+            make,
+            idle_timeout: None,
+            untenanted: None,
+            state: Mutex::new(State {
+                tenants: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Drop the processors of tenants that haven't logged a tree for
+    /// `timeout`.
+    ///
+    /// Idle tenants are found while trees are processed, so a processor is
+    /// dropped between one and two timeouts after its last tree. A tenant
+    /// that logs again afterwards gets a new processor. By default, processors
+    /// are kept forever.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Send trees that don't have the field to `processor`, instead of
+    /// dropping them.
+    pub fn untenanted<Q>(mut self, processor: Q) -> Self
+    where
+        Q: Processor + Send + Sync,
+    {
+        self.untenanted = Some(Box::new(processor));
+        self
+    }
+
+    /// Returns the tenants that currently have a processor, sorted.
+    pub fn tenants(&self) -> Vec<String> {
+        #[allow(clippy::expect_used)]
+        let state = self.state.lock().expect("tenants poisoned");
+        let mut tenants = state.tenants.keys().cloned().collect::<Vec<_>>();
+        tenants.sort();
+        tenants
+    }
+
+    fn processor(&self, tenant: &str) -> Arc<P> {
+        let now = Instant::now();
+        // Processors are created and dropped outside of the lock, since they
+        // may open files or connections, and other tenants shouldn't wait
+        let mut idle = Vec::new();
+
+        {
+            #[allow(clippy::expect_used)]
+            let mut state = self.state.lock().expect("tenants poisoned");
+
+            if let Some(timeout) = self.idle_timeout {
+                if now.duration_since(state.swept) >= timeout {
+                    let names = state
+                        .tenants
+                        .iter()
+                        .filter(|(_, tenant)| now.duration_since(tenant.last_used) >= timeout)
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>();
+                    for name in names {
+                        idle.extend(state.tenants.remove(&name));
+                    }
+                    state.swept = now;
+                }
+            }
+
+            if let Some(tenant) = state.tenants.get_mut(tenant) {
+                tenant.last_used = now;
+                return tenant.processor.clone();
+            }
+        }
+        drop(idle);
+
+        let processor = Arc::new((self.make)(tenant));
+
+        #[allow(clippy::expect_used)]
+        let mut state = self.state.lock().expect("tenants poisoned");
+        // Another thread may have created a processor for the tenant while
+        // the lock wasn't held, in which case that one is kept, and this one
+        // is dropped once the lock is released
+        if let Some(existing) = state.tenants.get_mut(tenant) {
+            existing.last_used = now;
+            return existing.processor.clone();
+        }
+        let entry = Tenant {
+            processor: processor.clone(),
+            last_used: now,
+        };
+        state.tenants.insert(tenant.to_string(), entry);
+        processor
+    }
+}
+
+impl<F, P> Processor for Tenants<F, P>
+where
+    F: 'static + Fn(&str) -> P,
+    P: Processor,
+{
+    fn process(&self, tree: Tree) {
+        // The lock isn't held while processing, so tenants don't wait on each
+        // other
+        let processor = tree
+            .field(self.field)
+            .map(|tenant| self.processor(&unquote(tenant)));
+        match processor {
+            Some(processor) => processor.process(tree),
+            None => {
+                if let Some(untenanted) = &self.untenanted {
+                    untenanted.process(tree);
+                }
+            }
+        }
+    }
+}
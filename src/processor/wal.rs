@@ -0,0 +1,319 @@
+//! A [`Processor`] that journals logs to disk before queueing them to be
+//! written.
+//!
+//! See [`WalProcessor`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::formatter::Formatter;
+use crate::layer::Tree;
+use crate::processor::buffers::Buffers;
+use crate::processor::worker::ForestGuard;
+use crate::processor::Processor;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The size of the header of the log, which is the offset of the first
+/// record that hasn't been written yet.
+const HEADER: u64 = 8;
+
+/// How many bytes of written records the log keeps before it's compacted,
+/// while it can't be emptied because trees are still queued.
+const COMPACT_BYTES: u64 = 1024 * 1024;
+
+/// A [`Processor`] that appends each formatted [`Tree`] to a write-ahead log
+/// before queueing it to be written on a background thread, and removes it
+/// from the log once it has been written.
+///
+/// Trees are journaled on the thread that closed them, so a tree is in the
+/// log before it's queued. If the program crashes before the background
+/// thread has written a tree, the tree remains in the log and is flushed to
+/// the writer the next time a [`WalProcessor`] is opened on the same path.
+///
+/// Trees are written at least once, so ones that were written right before a
+/// crash may be written again. Once writing a tree fails, trees stay in the
+/// log until it's reopened, so nothing after the failure is lost.
+///
+/// The thread runs until the [`ForestGuard`] returned alongside the processor
+/// is dropped, which waits for every tree queued before then to be written.
+///
+/// To initialize a new [`WalProcessor`], see [`wal`].
+///
+/// ## Log format
+///
+/// The log starts with a little-endian `u64` offset of the first record that
+/// hasn't been written. Each record is a little-endian `u32` length followed
+/// by that many bytes of formatted output. A record that was only partially
+/// appended when a crash occurred is discarded during recovery, since it was
+/// never queued.
+pub struct WalProcessor<F> {
+    formatter: F,
+    log: Arc<Mutex<Log>>,
+    tx: mpsc::Sender<Message>,
+    /// Buffers are returned to the pool by the thread once they're written.
+    buffers: Arc<Buffers>,
+}
+
+/// A formatted tree and the offset of the end of its record, or `None` to
+/// stop the thread.
+type Message = Option<(Vec<u8>, u64)>;
+
+struct Log {
+    path: PathBuf,
+    file: File,
+    /// The offset of the first record in the file, which offsets are counted
+    /// from so they stay valid when the file is compacted.
+    base: u64,
+    /// The offset of the end of the last record.
+    len: u64,
+    /// Whether writing a tree failed, so records aren't removed anymore.
+    failed: bool,
+}
+
+impl Log {
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+
+    /// Returns where `offset` is in the file.
+    fn position(&self, offset: u64) -> u64 {
+        HEADER + offset - self.base
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<u64> {
+        let len = u32::try_from(record.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        let position = self.position(self.len);
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(record)?;
+        self.file.sync_data()?;
+        self.len += 4 + record.len() as u64;
+        Ok(self.len)
+    }
+
+    /// Removes the records up to `offset`, once they were written.
+    fn remove(&mut self, offset: u64) -> io::Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+        if offset == self.len {
+            self.base = offset;
+            self.file.set_len(HEADER)?;
+            return write_header(&mut self.file, HEADER);
+        }
+        if offset - self.base > COMPACT_BYTES {
+            return self.compact(offset);
+        }
+        // Losing this to a crash only writes the records again
+        let position = self.position(offset);
+        write_header(&mut self.file, position)
+    }
+
+    /// Replaces the file with one that only has the records after `offset`.
+    fn compact(&mut self, offset: u64) -> io::Result<()> {
+        let mut pending = Vec::new();
+        self.file.seek(SeekFrom::Start(self.position(offset)))?;
+        (&mut self.file)
+            .take(self.len - offset)
+            .read_to_end(&mut pending)?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&HEADER.to_le_bytes())?;
+        file.write_all(&pending)?;
+        file.sync_data()?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.file = Log::open(&self.path)?;
+        self.base = offset;
+        Ok(())
+    }
+}
+
+fn write_header(file: &mut File, position: u64) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&position.to_le_bytes())
+}
+
+/// Flushes all complete records in the log that weren't written to the
+/// writer, and then empties the log.
+fn recover<W>(file: &mut File, make_writer: &W) -> io::Result<()>
+where
+    W: for<'a> MakeWriter<'a>,
+{
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut contents)?;
+
+    let start = match contents.get(..HEADER as usize) {
+        Some(header) => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(header);
+            u64::from_le_bytes(bytes).max(HEADER)
+        }
+        None => HEADER,
+    };
+    let mut rest = contents.get(start as usize..).unwrap_or_default();
+
+    let mut writer = make_writer.make_writer();
+    while rest.len() >= 4 {
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if tail.len() < len {
+            // Torn write, the record was never queued
+            break;
+        }
+        let (record, tail) = tail.split_at(len);
+        writer.write_all(record)?;
+        rest = tail;
+    }
+    writer.flush()?;
+
+    file.set_len(HEADER)?;
+    write_header(file, HEADER)?;
+    file.sync_data()
+}
+
+impl<F> Processor for WalProcessor<F>
+where
+    F: 'static + Formatter,
+{
+    fn process(&self, tree: Tree) {
+        let mut buf = self.buffers.take();
+
+        if let Err(err) = self.formatter.fmt(tree, &mut buf) {
+            return error::report(ForestError::Format(err));
+        }
+
+        #[allow(clippy::expect_used)]
+        let mut log = self.log.lock().expect("write-ahead log poisoned");
+
+        match log.append(&buf) {
+            // Records are queued while the log is locked, so they're written
+            // in the order they were appended
+            Ok(end) => {
+                if self.tx.send(Some((buf, end))).is_err() {
+                    // The record stays in the log, so it's written on the next
+                    // start
+                    error::report(ForestError::ChannelClosed);
+                }
+            }
+            Err(err) => error::report(wal_error(err)),
+        }
+    }
+}
+
+fn wal_error(error: io::Error) -> ForestError {
+    ForestError::Processor {
+        processor: "write-ahead log",
+        error,
+    }
+}
+
+fn work<W>(rx: mpsc::Receiver<Message>, log: Arc<Mutex<Log>>, buffers: Arc<Buffers>, make_writer: W)
+where
+    W: for<'a> MakeWriter<'a>,
+{
+    while let Ok(Some((record, end))) = rx.recv() {
+        let written = make_writer.make_writer().write_all(&record);
+        buffers.put(record);
+
+        #[allow(clippy::expect_used)]
+        let mut log = log.lock().expect("write-ahead log poisoned");
+        if let Err(err) = written {
+            log.failed = true;
+            error::report(ForestError::Write(err));
+            continue;
+        }
+        if let Err(err) = log.remove(end) {
+            error::report(wal_error(err));
+        }
+    }
+
+    if let Err(err) = make_writer.make_writer().flush() {
+        error::report(ForestError::Write(err));
+    }
+}
+
+/// Initialize a new [`WalProcessor`] journaling to the file at `path` and
+/// spawn its thread, returning the processor and a [`ForestGuard`] that stops
+/// the thread once dropped.
+///
+/// If the file already contains trees that were not written before the last
+/// shutdown, they are written to `make_writer` before this function returns.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{formatter::pretty::Pretty, processor::wal::wal, Processor};
+/// # let dir = std::env::temp_dir().join("tracing-forest-wal-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let (processor, _guard) = wal(Pretty::new(), std::io::stdout, dir.join("forest.wal"))
+///     .expect("failed to open write-ahead log");
+///
+/// tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+///     tracing::info!("this log survives a crash before it's written");
+/// });
+/// ```
+///
+/// ## Errors
+///
+/// Returns an error if the file cannot be opened, or if recovering unwritten
+/// trees fails.
+///
+/// ## Panics
+///
+/// Panics if the thread can't be spawned.
+pub fn wal<F, W, P>(
+    formatter: F,
+    make_writer: W,
+    path: P,
+) -> io::Result<(WalProcessor<F>, ForestGuard)>
+where
+    F: 'static + Formatter + Send,
+    W: 'static + for<'a> MakeWriter<'a> + Send,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().to_path_buf();
+    let mut file = Log::open(&path)?;
+    recover(&mut file, &make_writer)?;
+
+    let log = Arc::new(Mutex::new(Log {
+        path,
+        file,
+        base: 0,
+        len: 0,
+        failed: false,
+    }));
+
+    let (tx, rx) = mpsc::channel();
+    let shared = log.clone();
+    let buffers = Arc::new(Buffers::default());
+    let returned = buffers.clone();
+    #[allow(clippy::expect_used)]
+    let handle = thread::Builder::new()
+        .name("tracing-forest-wal".to_string())
+        .spawn(move || work(rx, shared, returned, make_writer))
+        .expect("failed to spawn the write-ahead log thread");
+
+    let stop = tx.clone();
+    let guard = ForestGuard::worker(handle, move || stop.send(None).is_ok());
+    let processor = WalProcessor {
+        formatter,
+        log,
+        tx,
+        buffers,
+    };
+    Ok((processor, guard))
+}
@@ -0,0 +1,431 @@
+//! A [`Processor`] that formats and writes logs on a background thread.
+//!
+//! See [`WorkerProcessor`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::formatter::pretty::Pretty;
+use crate::formatter::Formatter;
+use crate::layer::{Tree, TreeKind};
+use crate::processor::{buffers, transform, Processor};
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
+use tracing::subscriber::DefaultGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// A [`Processor`] that sends logs to a background thread, which formats and
+/// writes them.
+///
+/// Unlike an [`AsyncProcessor`], this doesn't need a Tokio runtime, and
+/// unlike a [`BlockingProcessor`], the thread that closed a span doesn't wait
+/// for its tree to be written.
+///
+/// The thread runs until the [`ForestGuard`] returned alongside the processor
+/// is dropped, which waits for every tree sent before then to be written.
+/// Trees sent afterwards are discarded.
+///
+/// The queue of trees sent to the thread is unbounded, so a thread that can't
+/// keep up lets it grow without limit. To handle trees differently while the
+/// queue is long, see [`WorkerProcessor::degrade`].
+///
+/// To initialize a new [`WorkerProcessor`], see [`worker`], or [`spawn`] to
+/// run another processor on the thread.
+///
+/// [`AsyncProcessor`]: crate::processor::sync::AsyncProcessor
+/// [`BlockingProcessor`]: crate::processor::blocking::BlockingProcessor
+pub struct WorkerProcessor {
+    tx: mpsc::Sender<Message>,
+    queued: Arc<AtomicUsize>,
+    degrade: Option<(usize, Degrade)>,
+    degraded: AtomicBool,
+}
+
+/// A tree and when it was sent, or `None` to stop the thread.
+type Message = Option<(Tree, Instant)>;
+
+/// How a [`WorkerProcessor`] handles trees while its thread is overloaded.
+///
+/// See [`WorkerProcessor::degrade`] for details.
+pub enum Degrade {
+    /// Send only the root of each tree to the thread, annotated with how many
+    /// spans and events were dropped from below it.
+    Summary,
+    /// Write each event of a tree on its own line to a writer, right away on
+    /// the thread that closed the tree, instead of sending it to the thread.
+    ///
+    /// Events are written with the settings of the [`Pretty`] formatter for a
+    /// single line, like its icons and colors.
+    Flat(BoxMakeWriter, Pretty),
+}
+
+impl Degrade {
+    /// Construct a [`Degrade::Flat`] writing to `make_writer` with
+    /// [`Pretty::new`].
+    pub fn flat<W>(make_writer: W) -> Self
+    where
+        W: 'static + for<'a> MakeWriter<'a> + Send + Sync,
+    {
+        Degrade::flat_with(Pretty::new(), make_writer)
+    }
+
+    /// Construct a [`Degrade::Flat`] writing to `make_writer` with
+    /// `formatter`, usually configured like the formatter of the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// # use tracing_forest::formatter::Icons;
+    /// # use tracing_forest::processor::worker::Degrade;
+    /// let pretty = || Pretty::new().with_icons(Icons::Text);
+    /// let (processor, _guard) = tracing_forest::worker(pretty(), std::io::stdout);
+    /// let processor = processor.degrade(1024, Degrade::flat_with(pretty(), std::io::stderr));
+    /// ```
+    pub fn flat_with<W>(formatter: Pretty, make_writer: W) -> Self
+    where
+        W: 'static + for<'a> MakeWriter<'a> + Send + Sync,
+    {
+        Degrade::Flat(BoxMakeWriter::new(make_writer), formatter)
+    }
+}
+
+impl fmt::Debug for Degrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Degrade::Summary => f.write_str("Summary"),
+            Degrade::Flat(..) => f.write_str("Flat(..)"),
+        }
+    }
+}
+
+impl WorkerProcessor {
+    /// Handle trees with `degrade` instead of queueing them whole while more
+    /// than `threshold` trees are waiting to be processed by the thread.
+    ///
+    /// This keeps a burst of logging from ballooning memory, or a slow
+    /// writer from falling further and further behind, at the cost of some
+    /// detail. The processor recovers once the thread has caught up to half
+    /// of `threshold`, so it doesn't flap between modes at the threshold.
+    ///
+    /// By default, trees are always queued whole.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::formatter::pretty::Pretty;
+    /// # use tracing_forest::processor::worker::Degrade;
+    /// # use tracing_forest::Processor;
+    /// let (processor, _guard) = tracing_forest::worker(Pretty::new(), std::io::stdout);
+    /// let processor = processor.degrade(1024, Degrade::flat(std::io::stderr));
+    /// let _default = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+    /// ```
+    pub fn degrade(mut self, threshold: usize, degrade: Degrade) -> Self {
+        self.degrade = Some((threshold, degrade));
+        self
+    }
+
+    /// Returns how many trees are waiting to be processed by the thread.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether trees are currently being degraded, because the thread
+    /// is overloaded.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Returns the mode to handle a tree with, if the thread is overloaded.
+    fn overloaded(&self) -> Option<&Degrade> {
+        let (threshold, degrade) = self.degrade.as_ref()?;
+        let queued = self.queued();
+        let degraded = match self.degraded.load(Ordering::Relaxed) {
+            true => queued > threshold / 2,
+            false => queued > *threshold,
+        };
+        self.degraded.store(degraded, Ordering::Relaxed);
+        degraded.then_some(degrade)
+    }
+
+    fn send(&self, tree: Tree) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        if self.tx.send(Some((tree, Instant::now()))).is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            error::report(ForestError::ChannelClosed);
+        }
+    }
+}
+
+impl Processor for WorkerProcessor {
+    fn process(&self, tree: Tree) {
+        match self.overloaded() {
+            None => self.send(tree),
+            Some(Degrade::Summary) => self.send(summarize(tree)),
+            Some(Degrade::Flat(make_writer, pretty)) => write_flat(&tree, pretty, make_writer),
+        }
+    }
+}
+
+/// Drops the children of a root span, noting how many there were.
+fn summarize(mut tree: Tree) -> Tree {
+    let span = match &mut tree.kind {
+        TreeKind::Span(span) => span,
+        TreeKind::Event(_) => return tree,
+    };
+    let children = std::mem::take(&mut span.children);
+    let (mut spans, mut events) = (0, 0);
+    count(&children, &mut spans, &mut events);
+    if spans + events > 0 {
+        tree.annotate_field(
+            "overloaded",
+            format_args!("{} spans and {} events dropped", spans, events),
+        );
+    }
+    tree
+}
+
+fn count(trees: &[Tree], spans: &mut usize, events: &mut usize) {
+    for tree in trees {
+        match &tree.kind {
+            TreeKind::Span(span) => {
+                *spans += 1;
+                count(&span.children, spans, events);
+            }
+            TreeKind::Event(_) => *events += 1,
+        }
+    }
+}
+
+/// Writes every event of `tree` on its own line, like live events.
+fn write_flat(tree: &Tree, pretty: &Pretty, make_writer: &BoxMakeWriter) {
+    let mut buf = Vec::with_capacity(0);
+    if let Err(err) = format_flat(tree, pretty, &mut buf) {
+        return error::report(ForestError::Format(err));
+    }
+    if let Err(err) = make_writer.make_writer().write_all(&buf[..]) {
+        error::report(ForestError::Write(err));
+    }
+}
+
+fn format_flat(tree: &Tree, pretty: &Pretty, writer: &mut Vec<u8>) -> std::io::Result<()> {
+    match &tree.kind {
+        TreeKind::Event(event) => pretty.format_flat_event(&tree.attrs, event, writer),
+        TreeKind::Span(span) => span
+            .children
+            .iter()
+            .try_for_each(|child| format_flat(child, pretty, writer)),
+    }
+}
+
+/// Initialize a new [`WorkerProcessor`] and spawn its thread, returning the
+/// processor and a [`ForestGuard`] that stops the thread once dropped.
+///
+/// The guard should be kept until the program exits, usually by binding it
+/// in `main`, so the last trees are written before returning. Binding it to
+/// `_` drops it immediately, which discards all trees.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{formatter::pretty::Pretty, Processor};
+/// let (processor, _guard) = tracing_forest::worker(Pretty::new(), std::io::stdout);
+/// tracing::subscriber::set_global_default({
+///     processor
+///         .into_layer()
+///         .into_subscriber()
+/// }).unwrap();
+///
+/// tracing::info!("written before the program exits");
+/// ```
+///
+/// ## Panics
+///
+/// Panics if the thread can't be spawned.
+pub fn worker<F, W>(formatter: F, make_writer: W) -> (WorkerProcessor, ForestGuard)
+where
+    F: 'static + Formatter + Send,
+    W: 'static + for<'a> MakeWriter<'a> + Send,
+{
+    start(move |rx| work(rx, formatter, make_writer))
+}
+
+/// Initialize a new [`WorkerProcessor`] that sends trees to `processor` on
+/// its thread, returning the processor and a [`ForestGuard`] that stops the
+/// thread once dropped.
+///
+/// This moves a whole processing pipeline, like a stack of combinators or a
+/// processor that writes to the network, off of the threads being traced.
+/// See [`worker`] for details.
+///
+/// ## Examples
+///
+/// ```
+/// # use tracing_forest::{blocking, formatter::pretty::Pretty, Processor};
+/// # use tracing_forest::processor::worker;
+/// let pipeline = blocking(Pretty::new(), std::io::stdout).compress();
+/// let (processor, _guard) = worker::spawn(pipeline);
+/// let _default = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+/// ```
+///
+/// ## Panics
+///
+/// Panics if the thread can't be spawned.
+pub fn spawn<P>(processor: P) -> (WorkerProcessor, ForestGuard)
+where
+    P: Processor + Send,
+{
+    spawn_transformed(processor, Vec::new())
+}
+
+/// Like [`spawn`], but applies `transforms` to every tree on the thread,
+/// before it's sent to `processor`.
+pub(crate) fn spawn_transformed<P>(
+    processor: P,
+    transforms: Vec<fn(Tree) -> Tree>,
+) -> (WorkerProcessor, ForestGuard)
+where
+    P: Processor + Send,
+{
+    start(move |rx| {
+        while let Some((tree, _)) = rx.recv() {
+            processor.process(transform(&transforms, tree));
+        }
+    })
+}
+
+/// The receiving end of the queue, which keeps count of the trees in it.
+struct Receiver {
+    rx: mpsc::Receiver<Message>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl Receiver {
+    /// Returns the next tree and when it was sent, or `None` once the thread
+    /// should stop.
+    fn recv(&self) -> Option<(Tree, Instant)> {
+        let message = self.rx.recv().ok()??;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        Some(message)
+    }
+}
+
+fn start<F>(run: F) -> (WorkerProcessor, ForestGuard)
+where
+    F: 'static + FnOnce(Receiver) + Send,
+{
+    let (tx, rx) = mpsc::channel();
+    let queued = Arc::new(AtomicUsize::new(0));
+    let rx = Receiver {
+        rx,
+        queued: queued.clone(),
+    };
+
+    #[allow(clippy::expect_used)]
+    let handle = thread::Builder::new()
+        .name("tracing-forest".to_string())
+        .spawn(move || run(rx))
+        .expect("failed to spawn the worker thread");
+
+    let stop = tx.clone();
+    let guard = ForestGuard::worker(handle, move || stop.send(None).is_ok());
+
+    let processor = WorkerProcessor {
+        tx,
+        queued,
+        degrade: None,
+        degraded: AtomicBool::new(false),
+    };
+    (processor, guard)
+}
+
+fn work<F, W>(rx: Receiver, formatter: F, make_writer: W)
+where
+    F: Formatter,
+    W: for<'a> MakeWriter<'a>,
+{
+    // The buffer is reused for every tree
+    let mut buf = Vec::new();
+    while let Some((tree, sent)) = rx.recv() {
+        buffers::reuse(&mut buf);
+
+        if let Err(err) = formatter.fmt_with_latency(tree, Some(sent.elapsed()), &mut buf) {
+            error::report(ForestError::Format(err));
+            continue;
+        }
+        if let Err(err) = make_writer.make_writer().write_all(&buf[..]) {
+            error::report(ForestError::Write(err));
+        }
+    }
+
+    if let Err(err) = make_writer.make_writer().flush() {
+        error::report(ForestError::Write(err));
+    }
+}
+
+/// A guard that keeps logging running until it's dropped, like the one
+/// returned by [`worker`] or the `init` functions.
+///
+/// Dropping the guard of a [`WorkerProcessor`] waits for the trees already
+/// sent to it to be written and flushed, then stops its thread. Dropping the
+/// guard returned by [`init_test`] uninstalls its subscriber.
+///
+/// [`init_test`]: crate::builder::init_test
+#[must_use = "dropping the guard stops logging"]
+pub struct ForestGuard {
+    worker: Option<Worker>,
+    default: Option<DefaultGuard>,
+}
+
+struct Worker {
+    /// Tells the thread to stop, returning whether it's still running.
+    stop: Box<dyn FnOnce() -> bool + Send + Sync>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl ForestGuard {
+    pub(crate) fn scoped(default: DefaultGuard) -> Self {
+        ForestGuard {
+            worker: None,
+            default: Some(default),
+        }
+    }
+
+    /// Returns a guard that calls `stop` once dropped, and then waits for the
+    /// thread of `handle` to finish.
+    pub(crate) fn worker<F>(handle: thread::JoinHandle<()>, stop: F) -> Self
+    where
+        F: 'static + FnOnce() -> bool + Send + Sync,
+    {
+        ForestGuard {
+            worker: Some(Worker {
+                stop: Box::new(stop),
+                handle,
+            }),
+            default: None,
+        }
+    }
+}
+
+impl fmt::Debug for ForestGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForestGuard").finish_non_exhaustive()
+    }
+}
+
+impl Drop for ForestGuard {
+    fn drop(&mut self) {
+        drop(self.default.take());
+
+        if let Some(worker) = self.worker.take() {
+            // The thread has already stopped if it panicked, leaving nothing
+            // to wait for
+            if (worker.stop)() {
+                let _ = worker.handle.join();
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Stitch together the trees of services that call each other.
+//!
+//! A service making a request calls [`inject`] to write the [`Uuid`] of the
+//! current tree and the path of spans leading to the call into the request's
+//! headers. The service handling it opens its root span with [`remote_span!`],
+//! which adopts that [`Uuid`], so the trees of both services share an ID and
+//! can be found together.
+//!
+//! # Examples
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # use tracing::Level;
+//! # use tracing_subscriber::Registry;
+//! # #[tracing_forest::test]
+//! # fn test_propagation() {
+//! // In the calling service
+//! let mut headers = HashMap::new();
+//! let caller = tracing::info_span!("checkout").in_scope(|| {
+//!     tracing_forest::propagation::inject::<Registry>(&mut headers);
+//!     tracing_forest::id::<Registry>()
+//! });
+//!
+//! // In the called service
+//! tracing_forest::remote_span!(headers, Level::INFO, "charge").in_scope(|| {
+//!     assert_eq!(tracing_forest::id::<Registry>(), caller);
+//! });
+//! # }
+//! ```
+//!
+//! [`remote_span!`]: crate::remote_span
+
+use crate::layer::TreeSpanOpened;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use uuid::Uuid;
+
+/// The header carrying the [`Uuid`] of the calling tree.
+pub const ID_HEADER: &str = "x-forest-id";
+
+/// The header carrying the names of the spans leading to the call, from the
+/// root of the calling tree, separated by `PATH_SEPARATOR`.
+pub const PATH_HEADER: &str = "x-forest-path";
+
+/// Separates the span names in the [`PATH_HEADER`].
+pub const PATH_SEPARATOR: &str = " > ";
+
+/// The span field that [`remote_span!`] records the calling path in.
+///
+/// [`remote_span!`]: crate::remote_span
+pub const PATH_FIELD: &str = "remote.path";
+
+/// A map that propagation headers are written to and read from, like the
+/// headers of an HTTP request.
+///
+/// This is implemented for maps of strings. For the header types of HTTP
+/// libraries, implement it on a wrapper.
+pub trait Carrier {
+    /// Returns the value of the header `key`.
+    fn get(&self, key: &str) -> Option<&str>;
+
+    /// Sets the header `key` to `value`.
+    fn set(&mut self, key: &'static str, value: String);
+}
+
+impl<S: BuildHasher> Carrier for HashMap<String, String, S> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key).map(String::as_str)
+    }
+
+    fn set(&mut self, key: &'static str, value: String) {
+        self.insert(key.to_string(), value);
+    }
+}
+
+impl Carrier for BTreeMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        BTreeMap::get(self, key).map(String::as_str)
+    }
+
+    fn set(&mut self, key: &'static str, value: String) {
+        self.insert(key.to_string(), value);
+    }
+}
+
+impl<C: Carrier + ?Sized> Carrier for &mut C {
+    fn get(&self, key: &str) -> Option<&str> {
+        (**self).get(key)
+    }
+
+    fn set(&mut self, key: &'static str, value: String) {
+        (**self).set(key, value)
+    }
+}
+
+/// The context of a calling tree, read from propagation headers by
+/// [`extract`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// The [`Uuid`] of the calling tree.
+    pub id: Uuid,
+    /// The names of the spans leading to the call, separated by
+    /// [`PATH_SEPARATOR`], or an empty string if it wasn't sent.
+    pub path: String,
+}
+
+/// Writes the [`Uuid`] of the current tree and the path of spans leading to
+/// the current span into `carrier`.
+///
+/// If the root of the current tree was opened with [`remote_span!`], the
+/// path starts with the path of the service that called this one. Nothing is
+/// written outside of a span.
+///
+/// ## Panics
+///
+/// Panics in the same cases as [`id`], except outside of a span.
+///
+/// [`id`]: crate::id
+/// [`remote_span!`]: crate::remote_span
+pub fn inject<S>(mut carrier: impl Carrier)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if tracing::Span::current().is_none() {
+        return;
+    }
+    let id = crate::id::<S>();
+
+    let path = tracing::dispatcher::get_default(|dispatch| {
+        let subscriber = dispatch
+            .downcast_ref::<S>()
+            .unwrap_or_else(crate::fail::subscriber_not_found::<S>);
+        let current = subscriber.current_span();
+        let span = current
+            .id()
+            .and_then(|id| subscriber.span(id))
+            .unwrap_or_else(crate::fail::span_not_in_context);
+
+        let mut names = span
+            .scope()
+            .map(|span| span.name().to_string())
+            .collect::<Vec<_>>();
+        let remote = span.scope().last().and_then(|root| {
+            let extensions = root.extensions();
+            let opened = extensions.get::<TreeSpanOpened>()?;
+            opened.field(PATH_FIELD).map(str::to_string)
+        });
+        names.extend(remote.filter(|path| !path.is_empty()));
+        names.reverse();
+        names.join(PATH_SEPARATOR)
+    });
+
+    carrier.set(ID_HEADER, id.to_string());
+    carrier.set(PATH_HEADER, path);
+}
+
+/// Reads the context of the calling tree from `carrier`, or `None` if it
+/// doesn't have a valid [`ID_HEADER`].
+pub fn extract<C: Carrier + ?Sized>(carrier: &C) -> Option<Remote> {
+    let id = Uuid::parse_str(carrier.get(ID_HEADER)?.trim()).ok()?;
+    let path = carrier.get(PATH_HEADER).unwrap_or_default().to_string();
+    Some(Remote { id, path })
+}
@@ -0,0 +1,162 @@
+//! Feed recorded trees through a [`Processor`] again.
+//!
+//! See [`replay`] for more details.
+//!
+//! [`Processor`]: crate::processor::Processor
+
+use crate::bridge;
+use crate::processor::Processor;
+use std::io::{self, BufRead};
+
+/// How many trees [`replay`] sent to its processor, and which records it
+/// skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replayed {
+    /// The number of trees that were processed.
+    pub trees: usize,
+    /// The records that weren't serialized trees, like the output of code
+    /// that doesn't use `tracing`, or a line cut off by a crash, in the order
+    /// they were read.
+    pub skipped: Vec<Skipped>,
+}
+
+/// A record that [`replay`] skipped, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    /// The 1-based number of the record, which is its line number for
+    /// newline-delimited JSON, and its position in the sequence for CBOR.
+    pub record: usize,
+    /// Why the record couldn't be parsed as a tree.
+    pub error: String,
+}
+
+/// Read trees from `reader`, one per line, and send each of them to
+/// `processor` in the order they were written.
+///
+/// This re-renders or re-routes historical traces after changing
+/// configuration, like formatting a file of JSON trees with [`Pretty`], or
+/// loading it into a database that was added since. Trees must have been
+/// written by the [`Json`] formatter with the default schema, and keep the
+/// IDs and timestamps they were recorded with. See [`bridge::parse_tree`]
+/// for details.
+///
+/// Empty lines are ignored, and other lines that aren't trees are skipped,
+/// and listed with their line number and parse error. Trees aren't affected
+/// by the filtering or sampling of a [`TreeLayer`], since they don't go
+/// through one, so use combinators like [`Processor::filter`] to only replay
+/// some of them.
+///
+/// To replay trees written by the [`Cbor`] formatter, see [`replay_cbor`].
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails, after processing the
+/// trees read before then.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::pretty::Pretty;
+/// let recorded = r#"{"level":"WARN","kind":{"Event":{"tag":null,"message":"disk almost full","fields":{}}}}"#;
+///
+/// let pretty = tracing_forest::blocking(Pretty::new().with_snapshot(true), std::io::stdout);
+/// let replayed = tracing_forest::replay(recorded.as_bytes(), &pretty).unwrap();
+/// assert_eq!(replayed.trees, 1);
+/// ```
+///
+/// [`Pretty`]: crate::formatter::pretty::Pretty
+/// [`Json`]: crate::formatter::json::Json
+/// [`TreeLayer`]: crate::layer::TreeLayer
+/// [`Cbor`]: crate::formatter::cbor::Cbor
+pub fn replay<R, P>(reader: R, processor: &P) -> io::Result<Replayed>
+where
+    R: BufRead,
+    P: Processor + ?Sized,
+{
+    let mut replayed = Replayed::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match bridge::parse_tree(&line) {
+            Ok(tree) => {
+                processor.process(tree);
+                replayed.trees += 1;
+            }
+            Err(err) => replayed.skipped.push(Skipped {
+                record: index + 1,
+                error: err.to_string(),
+            }),
+        }
+    }
+    Ok(replayed)
+}
+
+/// Read trees written by the [`Cbor`] formatter from `reader`, and send each
+/// of them to `processor` in the order they were written.
+///
+/// This works like [`replay`], but for a CBOR sequence instead of lines of
+/// JSON. Data items that aren't trees are skipped and listed with their
+/// position in the sequence. Since data items aren't delimited, replaying
+/// stops at the first one that isn't well-formed, like one cut off by a
+/// crash, which is also listed as skipped.
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails, after processing the
+/// trees read before then.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::formatter::{cbor::Cbor, pretty::Pretty, Formatter};
+/// let trees = tracing_forest::capture().run(|| tracing::warn!("disk almost full"));
+/// let mut recorded = Vec::new();
+/// Cbor::new().fmt(trees[0].clone(), &mut recorded).unwrap();
+///
+/// let pretty = tracing_forest::blocking(Pretty::new().with_snapshot(true), std::io::stdout);
+/// let replayed = tracing_forest::replay::replay_cbor(&recorded[..], &pretty).unwrap();
+/// assert_eq!(replayed.trees, 1);
+/// ```
+///
+/// [`Cbor`]: crate::formatter::cbor::Cbor
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+pub fn replay_cbor<R, P>(mut reader: R, processor: &P) -> io::Result<Replayed>
+where
+    R: BufRead,
+    P: Processor + ?Sized,
+{
+    let mut replayed = Replayed::default();
+    let mut record = 0;
+    while !reader.fill_buf()?.is_empty() {
+        record += 1;
+        let skip = |error: String| Skipped { record, error };
+
+        // Items are decoded generically first, so an item that's well-formed
+        // but isn't a tree is skipped without losing track of the next one
+        let item = match ciborium::from_reader::<ciborium::Value, _>(&mut reader) {
+            Ok(item) => item,
+            Err(ciborium::de::Error::Io(err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                return Err(err);
+            }
+            Err(err) => {
+                replayed.skipped.push(skip(err.to_string()));
+                break;
+            }
+        };
+        let tree = item
+            .deserialized::<serde_json::Value>()
+            .map_err(|err| err.to_string())
+            .and_then(|value| bridge::parse_value(&value).map_err(|err| err.to_string()));
+        match tree {
+            Ok(tree) => {
+                processor.process(tree);
+                replayed.trees += 1;
+            }
+            Err(error) => replayed.skipped.push(skip(error)),
+        }
+    }
+    Ok(replayed)
+}
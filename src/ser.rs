@@ -1,8 +1,8 @@
-use crate::layer::{Fields, KeyValue};
+use crate::tree::{Annotation, Fields, TreeSpan};
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
-use serde::{ser::SerializeMap, Serializer};
-use std::time::Duration;
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Serialize, Serializer};
 use tracing::Level;
 
 #[cfg(feature = "chrono")]
@@ -17,14 +17,52 @@ pub(crate) fn level<S: Serializer>(level: &Level, serializer: S) -> Result<S::Ok
     serializer.serialize_str(level.as_str())
 }
 
-pub(crate) fn nanos<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
-    serializer.serialize_u128(duration.as_nanos())
-}
-
 pub(crate) fn fields<S: Serializer>(fields: &Fields, serializer: S) -> Result<S::Ok, S::Error> {
     let mut model = serializer.serialize_map(Some(fields.len()))?;
-    for KeyValue { key, value } in fields.iter() {
-        model.serialize_entry(key, value)?;
+    for kv in fields.iter() {
+        #[cfg(feature = "valuable")]
+        if let Some(structured) = &kv.structured {
+            model.serialize_entry(kv.key, structured)?;
+            continue;
+        }
+        model.serialize_entry(kv.key, &kv.value)?;
     }
     model.end()
 }
+
+/// Serializes [`Fields`] as a map, for fields that can't use [`fields`].
+struct FieldMap<'a>(&'a Fields);
+
+impl Serialize for FieldMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fields(self.0, serializer)
+    }
+}
+
+impl Serialize for TreeSpan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut model = serializer.serialize_struct("TreeSpan", 6)?;
+        model.serialize_field("name", self.name)?;
+        if !self.fields.is_empty() {
+            model.serialize_field("fields", &FieldMap(&self.fields))?;
+        }
+        model.serialize_field("nanos_total", &self.duration_total.as_nanos())?;
+        model.serialize_field("nanos_nested", &self.duration_nested.as_nanos())?;
+        model.serialize_field("nanos_self", &self.duration_self().as_nanos())?;
+        model.serialize_field("children", &self.children)?;
+        model.end()
+    }
+}
+
+impl Serialize for Annotation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Annotation::Note(note) => serializer.serialize_str(note),
+            Annotation::Field(key, value) => {
+                let mut model = serializer.serialize_map(Some(1))?;
+                model.serialize_entry(key, value)?;
+                model.end()
+            }
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Actions triggered by Unix signals, like dumping recent trees or reopening
+//! log files.
+//!
+//! See [`Signals`] for more details.
+
+use crate::error::{self, ForestError};
+use crate::formatter::Formatter;
+use crate::layer::VerbosityHandle;
+use crate::processor::recent::RecentTrees;
+use crate::writer::ReopenHandle;
+use libc::c_int;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use tracing_subscriber::fmt::MakeWriter;
+
+pub use libc::{SIGHUP, SIGUSR1, SIGUSR2};
+
+/// The write end of the pipe that the signal handler wakes the listening
+/// thread through, or `-1` if it wasn't created yet.
+///
+/// The pipe is created by the first [`Signals`] to listen and never closed,
+/// since a handler that's still running on another thread when the guard is
+/// dropped could otherwise write to whatever file reuses the descriptor.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// The read end of the pipe, or `-1` if it wasn't created yet.
+static READ: AtomicI32 = AtomicI32::new(-1);
+
+/// Whether a [`Signals`] is listening.
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+type Action = Box<dyn FnMut() + Send>;
+
+/// Runs actions when the process receives Unix signals.
+///
+/// The signal handler only wakes a thread named `tracing-forest-signals`,
+/// which runs the actions, so they can do anything, like formatting and
+/// writing trees. The usual actions have shortcuts:
+///
+/// * [`dump_recent`] writes the trees of a [`RecentTrees`] buffer, like on
+///   `SIGUSR1`, to see what a running service was doing.
+/// * [`reopen`] reopens a [`RotatingFile`], like on `SIGHUP` after it was
+///   moved by `logrotate`.
+/// * [`toggle_verbosity`] raises or restores the verbosity of a
+///   [`TreeLayer`], like on `SIGUSR2`.
+///
+/// Signals are handled until the [`SignalGuard`] returned by [`listen`] is
+/// dropped, which restores how they were handled before. Only one
+/// [`Signals`] can listen at a time.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing::Level;
+/// # use tracing_forest::formatter::pretty::Pretty;
+/// # use tracing_forest::processor::recent::RecentTrees;
+/// # use tracing_forest::signals::{Signals, SIGHUP, SIGUSR1, SIGUSR2};
+/// # use tracing_forest::writer::RotatingFile;
+/// # use tracing_forest::Processor;
+/// # let dir = std::env::temp_dir().join("tracing-forest-signals-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let file = RotatingFile::new(dir.join("app.log"), 10 * 1024 * 1024)
+///     .expect("failed to open log file");
+/// let reopen = file.reopen_handle();
+///
+/// let recent = RecentTrees::new(100);
+/// let layer = tracing_forest::blocking(Pretty::new(), file)
+///     .tee(recent.clone())
+///     .into_layer()
+///     .max_level(Level::INFO);
+///
+/// let _signals = Signals::new()
+///     .dump_recent(SIGUSR1, recent, Pretty::new(), std::io::stderr)
+///     .reopen(SIGHUP, reopen)
+///     .toggle_verbosity(SIGUSR2, layer.verbosity_handle())
+///     .listen()
+///     .expect("failed to handle signals");
+///
+/// let _guard = tracing::subscriber::set_default(layer.into_subscriber());
+/// ```
+///
+/// [`dump_recent`]: Signals::dump_recent
+/// [`reopen`]: Signals::reopen
+/// [`toggle_verbosity`]: Signals::toggle_verbosity
+/// [`listen`]: Signals::listen
+/// [`RotatingFile`]: crate::writer::RotatingFile
+/// [`TreeLayer`]: crate::layer::TreeLayer
+#[derive(Default)]
+pub struct Signals {
+    actions: Vec<(c_int, Action)>,
+}
+
+impl Signals {
+    /// Construct a new [`Signals`] without any actions.
+    pub fn new() -> Self {
+        Signals::default()
+    }
+
+    /// Run `action` whenever the process receives `signal`.
+    ///
+    /// Actions for the same signal run in the order they're added.
+    pub fn on<F>(mut self, signal: c_int, action: F) -> Self
+    where
+        F: 'static + FnMut() + Send,
+    {
+        self.actions.push((signal, Box::new(action)));
+        self
+    }
+
+    /// Format the trees in `recent` with `formatter` and write them to
+    /// `make_writer`, oldest first, whenever the process receives `signal`.
+    pub fn dump_recent<F, W>(
+        self,
+        signal: c_int,
+        recent: RecentTrees,
+        formatter: F,
+        make_writer: W,
+    ) -> Self
+    where
+        F: 'static + Formatter + Send,
+        W: 'static + for<'a> MakeWriter<'a> + Send,
+    {
+        self.on(signal, move || {
+            let mut buf = Vec::with_capacity(0);
+            for tree in recent.snapshot() {
+                if let Err(err) = formatter.fmt((*tree).clone(), &mut buf) {
+                    error::report(ForestError::Format(err));
+                }
+            }
+            if let Err(err) = make_writer.make_writer().write_all(&buf) {
+                error::report(ForestError::Write(err));
+            }
+        })
+    }
+
+    /// Reopen a file with `handle` whenever the process receives `signal`.
+    pub fn reopen(self, signal: c_int, handle: ReopenHandle) -> Self {
+        self.on(signal, move || {
+            if let Err(err) = handle.reopen() {
+                error::report(ForestError::Write(err));
+            }
+        })
+    }
+
+    /// Toggle whether verbosity is raised with `handle` whenever the process
+    /// receives `signal`.
+    pub fn toggle_verbosity(self, signal: c_int, handle: VerbosityHandle) -> Self {
+        self.on(signal, move || {
+            handle.toggle();
+        })
+    }
+
+    /// Start handling the signals, until the returned [`SignalGuard`] is
+    /// dropped.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if another [`Signals`] is already listening, if a
+    /// signal can't be handled, like `SIGKILL`, or if the thread can't be
+    /// spawned.
+    pub fn listen(self) -> io::Result<SignalGuard> {
+        if LISTENING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "signals are already being handled",
+            ));
+        }
+        let mut guard = SignalGuard {
+            previous: Vec::new(),
+            handle: None,
+        };
+        let read = pipe()?;
+
+        let mut actions = HashMap::<c_int, Vec<Action>>::new();
+        for (signal, action) in self.actions {
+            if !(1..=c_int::from(u8::MAX)).contains(&signal) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid signal {}", signal),
+                ));
+            }
+            actions.entry(signal).or_default().push(action);
+        }
+        for &signal in actions.keys() {
+            guard.previous.push((signal, install(signal)?));
+        }
+
+        guard.handle = Some(
+            thread::Builder::new()
+                .name("tracing-forest-signals".to_string())
+                .spawn(move || run(read, actions))?,
+        );
+        Ok(guard)
+    }
+}
+
+impl fmt::Debug for Signals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signals = self.actions.iter().map(|(signal, _)| signal);
+        f.debug_struct("Signals")
+            .field("signals", &signals.collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A guard that keeps handling signals until it's dropped, returned by
+/// [`Signals::listen`].
+///
+/// Dropping the guard restores how the signals were handled before, waits
+/// for any running action to finish, and stops the thread. The pipe that the
+/// signal handler wakes the thread through is kept open, and reused by the
+/// next [`Signals`] to listen.
+#[must_use = "dropping the guard stops handling signals"]
+pub struct SignalGuard {
+    previous: Vec<(c_int, libc::sigaction)>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl fmt::Debug for SignalGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signals = self.previous.iter().map(|(signal, _)| signal);
+        f.debug_struct("SignalGuard")
+            .field("signals", &signals.collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        for (signal, previous) in self.previous.drain(..) {
+            // SAFETY: `previous` was returned by `sigaction` for `signal`
+            unsafe { libc::sigaction(signal, &previous, std::ptr::null_mut()) };
+        }
+        if let Some(handle) = self.handle.take() {
+            // Signals are numbered from 1, so 0 stops the thread
+            wake(PIPE.load(Ordering::SeqCst), 0);
+            let _ = handle.join();
+        }
+        LISTENING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Returns the read end of the pipe, creating it if it doesn't exist yet.
+///
+/// Only the [`Signals`] that is listening calls this, so it can't race.
+fn pipe() -> io::Result<c_int> {
+    let read = READ.load(Ordering::SeqCst);
+    if read >= 0 {
+        return Ok(read);
+    }
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both ends of the pipe
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let [read, write] = fds;
+    // The handler must never block, and children shouldn't inherit the pipe
+    let flags = set_flags(read, libc::F_SETFD, libc::FD_CLOEXEC)
+        .and_then(|()| set_flags(write, libc::F_SETFD, libc::FD_CLOEXEC))
+        .and_then(|()| set_flags(write, libc::F_SETFL, libc::O_NONBLOCK));
+    if let Err(err) = flags {
+        // SAFETY: no handler was installed yet, so nothing else uses the pipe
+        unsafe {
+            libc::close(read);
+            libc::close(write);
+        }
+        return Err(err);
+    }
+
+    READ.store(read, Ordering::SeqCst);
+    PIPE.store(write, Ordering::SeqCst);
+    Ok(read)
+}
+
+fn set_flags(fd: c_int, set: c_int, flags: c_int) -> io::Result<()> {
+    let get = if set == libc::F_SETFD {
+        libc::F_GETFD
+    } else {
+        libc::F_GETFL
+    };
+    // SAFETY: `fd` is an open file descriptor
+    let result = unsafe {
+        let current = libc::fcntl(fd, get);
+        if current < 0 {
+            current
+        } else {
+            libc::fcntl(fd, set, current | flags)
+        }
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn install(signal: c_int) -> io::Result<libc::sigaction> {
+    // SAFETY: zeroed `sigaction`s are valid, and `notify` is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = notify as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = mem::zeroed();
+        if libc::sigaction(signal, &action, &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+}
+
+/// The signal handler, which can only call async-signal-safe functions.
+extern "C" fn notify(signal: c_int) {
+    // The interrupted code may be about to read `errno`, which `write` can set
+    // SAFETY: `errno` is thread-local, so only this thread accesses it
+    let errno = unsafe { *errno_location() };
+    let fd = PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        wake(fd, signal as u8);
+    }
+    // SAFETY: as above
+    unsafe { *errno_location() = errno };
+}
+
+#[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "redox"))]
+use libc::__errno_location as errno_location;
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+use libc::__errno as errno_location;
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+use libc::__error as errno_location;
+
+#[cfg(any(target_os = "solaris", target_os = "illumos"))]
+use libc::___errno as errno_location;
+
+fn wake(fd: c_int, byte: u8) {
+    // A full pipe means the thread already has signals to handle, so the
+    // write is allowed to fail
+    // SAFETY: `byte` is valid for reads of 1 byte
+    unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+}
+
+fn run(read: c_int, mut actions: HashMap<c_int, Vec<Action>>) {
+    loop {
+        let mut byte = 0u8;
+        // SAFETY: `byte` is valid for writes of 1 byte
+        let len = unsafe { libc::read(read, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if len < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        if len <= 0 || byte == 0 {
+            return;
+        }
+        for action in actions.get_mut(&c_int::from(byte)).into_iter().flatten() {
+            action();
+        }
+    }
+}
@@ -62,6 +62,47 @@
 //! ERROR    🔐 [security.critical]: the db has been breached
 //! ```
 //!
+//! ## Tagging by target and level
+//!
+//! Instead of writing a macro for each variant, variants can match events by
+//! their target and level, which tags them without an `__event_tag` field:
+//! ```
+//! # use tracing_forest::Tag;
+//! #[derive(Tag)]
+//! pub enum MyTag {
+//!     #[tag(custom('🔐'): "security.critical", target = "app::security", level = "error")]
+//!     SecurityCritical,
+//! }
+//! ```
+//! An explicit `__event_tag` field takes precedence over matching, and matching
+//! takes precedence over the tag inherited from the parent span.
+//!
+//! ## Tagging by target prefix
+//!
+//! Applications that tag whole modules can register their prefixes in a
+//! [`TagRegistry`] instead, and the most specific prefix for an event's target
+//! is used to tag it:
+//! ```
+//! # use tracing_forest::{Tag, TagRegistry};
+//! # #[derive(Tag)]
+//! # pub enum MyTag {
+//! #     #[tag(custom('🔐'): "security.critical")]
+//! #     SecurityCritical,
+//! #     #[tag(info: "security.audit")]
+//! #     SecurityAudit,
+//! # }
+//! let mut registry = TagRegistry::new();
+//! registry.insert("my_app::security", MyTag::SecurityCritical);
+//! registry.insert("my_app::security::audit", MyTag::SecurityAudit);
+//!
+//! let subscriber = tracing_forest::builder()
+//!     .tag::<MyTag>()
+//!     .tag_registry(registry)
+//!     .blocking_layer()
+//!     .into_subscriber();
+//! ```
+//! The registry is consulted after the matching variants of the [`Tag`] type.
+//!
 //! ## Note:
 //!
 //! Although the [`Tag`] trait is unsafe to implement, it is guaranteed that
@@ -70,6 +111,12 @@
 //! [deriving]: tracing_forest_macros::Tag
 use crate::cfg_json;
 use crate::fail;
+use core::cmp::Ordering;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+use tracing::{Event, Level};
 
 /// A type that can tag events with custom messages.
 ///
@@ -78,6 +125,11 @@ use crate::fail;
 /// implementation.
 ///
 /// See [module level documentation][self] for how to use [`Tag`]s.
+///
+/// # Safety
+///
+/// Implementations must map every value returned by `as_field` back to the
+/// matching [`TagData`] in `from_field`. The derive macro guarantees this.
 // There's nothing unsafe about this function other than if the implementor
 // makes a mistake, tags may not map to the correct TagData and there's nothing
 // to catch that. Using the derive macro guaranteeds a correct implementation.
@@ -87,9 +139,127 @@ pub unsafe trait Tag: 'static {
 
     #[doc(hidden)]
     fn from_field(value: u64) -> TagData;
+
+    #[doc(hidden)]
+    fn from_event(event: &Event<'_>) -> Option<TagData> {
+        let _ = event;
+        None
+    }
+}
+
+/// Resolves the tags of events for a [`Tag`] type.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub(crate) struct TagParser {
+    /// Resolves the value of an `__event_tag` field.
+    pub(crate) from_field: fn(u64) -> TagData,
+    /// Resolves the tag of an event without an `__event_tag` field from its
+    /// target and level.
+    pub(crate) from_event: fn(&Event<'_>) -> Option<TagData>,
+    /// Resolves the tag of an event that `from_event` didn't match from its
+    /// target's prefix.
+    pub(crate) registry: Option<Arc<TagRegistry>>,
+}
+
+#[cfg(feature = "std")]
+impl TagParser {
+    pub(crate) fn of<T: Tag>() -> Self {
+        TagParser {
+            from_field: T::from_field,
+            from_event: T::from_event,
+            registry: None,
+        }
+    }
+
+    /// Set the accepted [`Tag`] type, keeping the registry.
+    pub(crate) fn set<T: Tag>(&mut self) {
+        self.from_field = T::from_field;
+        self.from_event = T::from_event;
+    }
+
+    /// Resolves the tag of an event without an `__event_tag` field.
+    pub(crate) fn parse_event(&self, event: &Event<'_>) -> Option<TagData> {
+        (self.from_event)(event).or_else(|| {
+            let registry = self.registry.as_ref()?;
+            registry.get(event.metadata().target())
+        })
+    }
+}
+
+/// A map from target prefixes to the tags of events with those targets.
+///
+/// A prefix matches a target that is the same module or a module inside of
+/// it, so `my_app::db` matches `my_app::db::pool` but not `my_app::dbx`. When
+/// several prefixes match, the longest one wins. Lookups check each module
+/// boundary of the target once, so their cost doesn't grow with the number of
+/// prefixes.
+///
+/// See [module level documentation][self] for how to use it.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct TagRegistry {
+    tags: HashMap<String, TagData>,
+}
+
+#[cfg(feature = "std")]
+impl TagRegistry {
+    /// Construct a new, empty [`TagRegistry`].
+    pub fn new() -> Self {
+        TagRegistry::default()
+    }
+
+    /// Tag events with targets under `prefix` with `tag`, returning the tag
+    /// previously registered for it.
+    pub fn insert<T: Tag>(&mut self, prefix: impl Into<String>, tag: T) -> Option<TagData> {
+        self.insert_data(prefix, T::from_field(tag.as_field()))
+    }
+
+    /// Tag events with targets under `prefix` with `tag`, returning the tag
+    /// previously registered for it.
+    ///
+    /// This is like [`insert`], but for tags that aren't variants of a
+    /// [`Tag`] type.
+    ///
+    /// [`insert`]: TagRegistry::insert
+    pub fn insert_data(&mut self, prefix: impl Into<String>, tag: TagData) -> Option<TagData> {
+        self.tags.insert(prefix.into(), tag)
+    }
+
+    /// Returns the tag of the longest prefix matching `target`.
+    pub fn get(&self, target: &str) -> Option<TagData> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        let mut prefix = target;
+        loop {
+            if let Some(tag) = self.tags.get(prefix) {
+                return Some(*tag);
+            }
+            prefix = &prefix[..prefix.rfind("::")?];
+        }
+    }
+
+    /// Returns the number of registered prefixes.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Returns `true` if no prefixes are registered.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
 }
 
-pub(crate) type TagParser = fn(u64) -> TagData;
+pub(crate) const TAG_KEY: &str = "__event_tag";
+
+/// Returns whether `target` is `prefix` or a module inside of it.
+#[doc(hidden)]
+pub fn target_matches(target: &str, prefix: &str) -> bool {
+    match target.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
 
 #[doc(hidden)]
 pub fn unrecognized_tag_id(id: u64) -> ! {
@@ -97,12 +267,65 @@ pub fn unrecognized_tag_id(id: u64) -> ! {
 }
 
 /// The type that all tags resolve to once collected.
-#[derive(Debug)]
+///
+/// Tags are partially ordered by their [`Severity`]. Distinct tags with the
+/// same severity are incomparable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TagData {
     /// Minimalistic message denoting the tag category.
     pub message: &'static str,
     /// Icon associated with the category.
     pub icon: char,
+    /// How severe events with this tag are.
+    pub severity: Severity,
+}
+
+impl PartialOrd for TagData {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.severity.cmp(&other.severity) {
+            Ordering::Equal if self != other => None,
+            ordering => Some(ordering),
+        }
+    }
+}
+
+/// How severe a tagged event is, used to compare tags.
+///
+/// Tags derived with a level keyword, like `#[tag(warn: "...")]`, have the
+/// severity of that level. Custom tags default to [`Severity::Info`], which can
+/// be overridden with a trailing `severity` argument:
+///
+/// ```
+/// # use tracing_forest::Tag;
+/// #[derive(Tag)]
+/// enum MyTag {
+///     #[tag(error: "request.error")]
+///     RequestError,
+///     #[tag(custom('🔐'): "security.critical", severity = critical)]
+///     SecurityCritical,
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    /// More severe than any log level.
+    Critical,
+}
+
+impl From<Level> for Severity {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::TRACE => Severity::Trace,
+            Level::DEBUG => Severity::Debug,
+            Level::INFO => Severity::Info,
+            Level::WARN => Severity::Warn,
+            Level::ERROR => Severity::Error,
+        }
+    }
 }
 
 cfg_json! {
@@ -115,8 +338,10 @@ cfg_json! {
     }
 }
 
+#[cfg(feature = "std")]
 pub(crate) enum NoTag {}
 
+#[cfg(feature = "std")]
 unsafe impl Tag for NoTag {
     fn as_field(&self) -> u64 {
         match *self {}
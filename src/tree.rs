@@ -0,0 +1,1009 @@
+//! The trace tree data model.
+//!
+//! These types only depend on `core` and `alloc`, so they are available
+//! without the `std` feature for constructing and consuming trees in
+//! embedded or plugin contexts. Collecting trees with a [`TreeLayer`] and
+//! processing them requires `std`.
+//!
+//! # Quick reference
+//!
+//! Trace data is stored in the layer as a tree, where spans represent internal
+//! nodes and events represent leafs.
+//!
+//! * [`Tree`]: A node in the trace tree.
+//! * [`TreeAttrs`]: Common data used by spans and events, like a [`Uuid`] if
+//!   the `uuid` feature is enabled, a timestamp if the `timestamp` feature is
+//!   enabled, and a [`Level`].
+//! * [`TreeKind`]: Contains either a [`TreeSpan`] or a [`TreeEvent`].
+//! * [`TreeSpan`]: Data unique to span traces, including durations and other
+//!   [`Tree`] nodes.
+//! * [`TreeEvent`]: Data unique to event traces, like tags.
+//! * [`TreeVisitor`]: Walks a [`Tree`] with [`Tree::walk`], for writing
+//!   formatters and analyzers without recursing into spans by hand.
+//!
+//! [`TreeLayer`]: crate::layer::TreeLayer
+//! [`Uuid`]: ::uuid::Uuid
+
+use crate::fail;
+#[cfg(feature = "std")]
+use crate::formatter::Formatter;
+#[cfg(feature = "json")]
+use crate::ser;
+use crate::tag::{Severity, TagData};
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use core::fmt;
+use core::ops::Deref;
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::time::Duration;
+#[cfg(feature = "json")]
+use serde::Serialize;
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::time::Instant;
+use tracing::callsite::Identifier;
+use tracing::{Level, Metadata};
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+// Most spans and events have few fields, so storing them inline saves an
+// allocation for each. A larger capacity costs memory for every node, even
+// those without fields, so it's opt-in.
+#[cfg(all(feature = "smallvec", not(feature = "smallvec-large")))]
+pub(crate) type Fields = SmallVec<[KeyValue; 3]>;
+#[cfg(feature = "smallvec-large")]
+pub(crate) type Fields = SmallVec<[KeyValue; 8]>;
+#[cfg(not(feature = "smallvec"))]
+pub(crate) type Fields = Vec<KeyValue>;
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct KeyValue {
+    pub key: &'static str,
+    pub value: String,
+    // Private, so enabling `valuable` anywhere in the dependency graph
+    // doesn't change which fields a `KeyValue` is made of
+    #[cfg(feature = "valuable")]
+    pub(crate) structured: Option<serde_json::Value>,
+}
+
+impl KeyValue {
+    /// Construct a new [`KeyValue`] from a key and its formatted value.
+    pub fn new(key: &'static str, value: String) -> Self {
+        KeyValue {
+            key,
+            value,
+            #[cfg(feature = "valuable")]
+            structured: None,
+        }
+    }
+
+    /// Returns the value of a field recorded with [`valuable`], which JSON
+    /// output writes as nested arrays and maps instead of `value`.
+    ///
+    /// [`valuable`]: https://docs.rs/valuable
+    #[cfg(feature = "valuable")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "valuable")))]
+    pub fn structured(&self) -> Option<&serde_json::Value> {
+        self.structured.as_ref()
+    }
+}
+
+/// A node of a log tree.
+///
+/// This is non-exhaustive, so that fields can be added without breaking
+/// code that builds trees. To initialize a new [`Tree`], see [`Tree::new`],
+/// or [`Tree::root`] for building whole trees.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[non_exhaustive]
+pub struct Tree {
+    /// Shared fields associated with both spans and events.
+    #[cfg_attr(feature = "json", serde(flatten))]
+    pub attrs: TreeAttrs,
+    /// Fields specific to either a span or an event.
+    pub kind: TreeKind,
+    /// Notes attached to the tree by processors and transforms, which
+    /// formatters display alongside it.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub annotations: Vec<Annotation>,
+    /// When the root was closed, by the monotonic clock.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub(crate) closed: Option<Instant>,
+}
+
+/// A note attached to a [`Tree`] after it was collected, like a sampling
+/// processor noting `sampled 1:100`, or a redacting transform noting
+/// `redacted: 3`.
+///
+/// Annotations of a root describe the tree as a whole, so they're displayed
+/// in a footer instead of with any span or event. Annotations of nested spans
+/// and events, like the repetition counts added by [`Compress`], are
+/// displayed at the end of their line.
+///
+/// [`Compress`]: crate::processor::compress::Compress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Annotation {
+    /// A free-form note.
+    Note(Cow<'static, str>),
+    /// A key-value pair.
+    Field(Cow<'static, str>, String),
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Annotation::Note(note) => f.write_str(note),
+            Annotation::Field(key, value) => write!(f, "{}: {}", key, value),
+        }
+    }
+}
+
+impl Tree {
+    /// Create a new `Tree` from its attributes and the span or event it is,
+    /// without annotations.
+    pub fn new(attrs: TreeAttrs, kind: impl Into<TreeKind>) -> Self {
+        Tree {
+            attrs,
+            kind: kind.into(),
+            annotations: Vec::new(),
+            #[cfg(feature = "std")]
+            closed: None,
+        }
+    }
+
+    /// Create a root span named `name` at the `INFO` level, for building trees
+    /// that didn't come from `tracing`, like ones reconstructed from another
+    /// protocol.
+    ///
+    /// The span gets a new [`Uuid`] and the current time, which can be
+    /// overridden. To send the finished tree to the processor of the current
+    /// [`TreeLayer`], see [`TreeHandle`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing::Level;
+    /// # use tracing_forest::tree::Tree;
+    /// # use std::time::Duration;
+    /// let mut root = Tree::root("replayed request").with_duration(Duration::from_millis(12));
+    /// root.add_child(Tree::span(Level::DEBUG, "db").with_duration(Duration::from_millis(8)))
+    ///     .add_child(Tree::event(Level::INFO, "query").with_field("rows", "3"));
+    /// root.add_child(Tree::event(Level::WARN, "slow response"));
+    /// ```
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    /// [`TreeLayer`]: crate::layer::TreeLayer
+    /// [`TreeHandle`]: crate::layer::TreeHandle
+    #[cfg(feature = "std")]
+    pub fn root(name: &'static str) -> Self {
+        Tree::span(Level::INFO, name)
+    }
+
+    /// Create a span named `name` with no children.
+    ///
+    /// See [`Tree::root`] for more details.
+    #[cfg(feature = "std")]
+    pub fn span(level: Level, name: &'static str) -> Self {
+        Tree::new(TreeAttrs::now(level), TreeSpan::new(name))
+    }
+
+    /// Create an event with a message and no fields.
+    ///
+    /// See [`Tree::root`] for more details.
+    #[cfg(feature = "std")]
+    pub fn event(level: Level, message: impl Into<Cow<'static, str>>) -> Self {
+        Tree::new(TreeAttrs::now(level), TreeEvent::new(message))
+    }
+
+    /// Set the [`Uuid`] of the tree and all of its children.
+    #[cfg(feature = "uuid")]
+    pub fn with_uuid(mut self, uuid: Uuid) -> Self {
+        self.set_uuid(uuid);
+        self
+    }
+
+    /// Set when the span was opened or the event occurred.
+    #[cfg(feature = "chrono")]
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.attrs.timestamp = timestamp;
+        self
+    }
+
+    /// Set when the root was closed, by the monotonic clock.
+    ///
+    /// Roots collected by a [`TreeLayer`] have this set when they're closed or
+    /// logged, so that [`Deadline`] can tell how long ago that was, even if
+    /// the wall clock was stepped since.
+    ///
+    /// [`TreeLayer`]: crate::layer::TreeLayer
+    /// [`Deadline`]: crate::processor::deadline::Deadline
+    #[cfg(feature = "std")]
+    pub fn with_closed(mut self, closed: Instant) -> Self {
+        self.closed = Some(closed);
+        self
+    }
+
+    /// Returns when the root was closed, by the monotonic clock, if it's
+    /// known.
+    ///
+    /// See [`Tree::with_closed`] for more details.
+    #[cfg(feature = "std")]
+    pub fn closed(&self) -> Option<Instant> {
+        self.closed
+    }
+
+    /// Set the duration that the span was entered for.
+    ///
+    /// This has no effect on events.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        if let TreeKind::Span(span) = &mut self.kind {
+            span.duration_total = duration;
+        }
+        self
+    }
+
+    /// Add a field to the span or event.
+    pub fn with_field(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        let fields = match &mut self.kind {
+            TreeKind::Event(event) => &mut event.fields,
+            TreeKind::Span(span) => &mut span.fields,
+        };
+        fields.push(KeyValue::new(key, value.into()));
+        if key == ERROR_FIELD {
+            match &mut self.kind {
+                TreeKind::Event(event) => event.has_error = true,
+                TreeKind::Span(span) => span.failed = true,
+            }
+        }
+        self
+    }
+
+    /// Returns the value of the field named `key` of the root span or event,
+    /// if it has one.
+    ///
+    /// Values are recorded with their `Debug` representation, so strings
+    /// recorded as `key = "value"` are quoted, and ones recorded with
+    /// `key = %value` aren't.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        let fields = match &self.kind {
+            TreeKind::Event(event) => &event.fields,
+            TreeKind::Span(span) => &span.fields,
+        };
+        fields
+            .iter()
+            .find(|kv| kv.key == key)
+            .map(|kv| kv.value.as_str())
+    }
+
+    /// Set the format string that the message of the event was rendered
+    /// from.
+    ///
+    /// This has no effect on spans.
+    pub fn with_template(mut self, template: impl Into<Cow<'static, str>>) -> Self {
+        if let TreeKind::Event(event) = &mut self.kind {
+            event.template = Some(template.into());
+        }
+        self
+    }
+
+    /// Set the tag of the event.
+    ///
+    /// This has no effect on spans.
+    pub fn with_tag(mut self, tag: TagData) -> Self {
+        if let TreeKind::Event(event) = &mut self.kind {
+            event.tag = Some(tag);
+        }
+        self
+    }
+
+    /// Attach a free-form [`Annotation`] to the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tracing_forest::tree::Tree;
+    /// fn redact(mut tree: Tree) -> Tree {
+    ///     tree.annotate("fields redacted")
+    ///         .annotate_field("redacted", 3);
+    ///     tree
+    /// }
+    /// ```
+    pub fn annotate(&mut self, note: impl Into<Cow<'static, str>>) -> &mut Self {
+        self.annotations.push(Annotation::Note(note.into()));
+        self
+    }
+
+    /// Attach a key-value [`Annotation`] to the tree.
+    ///
+    /// See [`Tree::annotate`] for more details.
+    pub fn annotate_field(
+        &mut self,
+        key: impl Into<Cow<'static, str>>,
+        value: impl fmt::Display,
+    ) -> &mut Self {
+        self.annotations
+            .push(Annotation::Field(key.into(), value.to_string()));
+        self
+    }
+
+    /// Add a span or event as the last child of the span, returning it so
+    /// that it can have children of its own.
+    ///
+    /// The child takes on the [`Uuid`] of the span, and a child span's
+    /// duration is added to the span's nested duration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is an event, since events can't have children.
+    ///
+    /// [`Uuid`]: ::uuid::Uuid
+    pub fn add_child(&mut self, child: Tree) -> &mut Tree {
+        #[cfg(feature = "uuid")]
+        let mut child = child;
+        #[cfg(feature = "uuid")]
+        child.set_uuid(self.attrs.uuid);
+
+        let span = match &mut self.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => fail::event_child(),
+        };
+        if let TreeKind::Span(child) = &child.kind {
+            span.duration_nested += child.duration_total;
+        }
+        span.failed |= child.fails_parent();
+        span.children.push(child);
+        let last = span.children.len() - 1;
+        &mut span.children[last]
+    }
+
+    #[cfg(feature = "uuid")]
+    fn set_uuid(&mut self, uuid: Uuid) {
+        self.attrs.uuid = uuid;
+        if let TreeKind::Span(span) = &mut self.kind {
+            for child in span.children.iter_mut() {
+                child.set_uuid(uuid);
+            }
+        }
+    }
+
+    /// Format a copy of the tree into a string with `formatter`.
+    ///
+    /// See [`Formatter::render`] for more details.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the tree couldn't be formatted.
+    #[cfg(feature = "std")]
+    pub fn render_with<F: Formatter + ?Sized>(&self, formatter: &F) -> io::Result<String> {
+        formatter.render(self)
+    }
+
+    /// Returns the highest [`Severity`] of all tagged events in the tree, or
+    /// `None` if there are no tagged events.
+    pub fn max_tag_severity(&self) -> Option<Severity> {
+        match &self.kind {
+            TreeKind::Event(event) => event.tag.map(|tag| tag.severity),
+            TreeKind::Span(span) => span
+                .children
+                .iter()
+                .filter_map(Tree::max_tag_severity)
+                .max(),
+        }
+    }
+
+    /// Returns the most severe [`Level`] of all spans and events in the tree.
+    pub fn most_severe_level(&self) -> Level {
+        match &self.kind {
+            TreeKind::Event(_) => self.attrs.level,
+            // More severe levels compare as less verbose
+            TreeKind::Span(span) => span
+                .children
+                .iter()
+                .map(Tree::most_severe_level)
+                .fold(self.attrs.level, Ord::min),
+        }
+    }
+
+    /// Counts the events in the tree by level and tag.
+    pub(crate) fn event_counts(&self) -> EventCounts {
+        let mut counts = EventCounts::default();
+        let _ = self.walk(&mut counts);
+        counts
+    }
+
+    /// Returns whether the tree is a span that [failed][TreeSpan::is_failed].
+    ///
+    /// Since a failed span fails the spans it's inside of, this is `true` for
+    /// the root of every tree containing an error returned from an
+    /// `#[instrument(err)]` function.
+    pub fn has_failed(&self) -> bool {
+        match &self.kind {
+            TreeKind::Event(_) => false,
+            TreeKind::Span(span) => span.is_failed(),
+        }
+    }
+
+    /// Returns whether the tree fails the span it's a child of: a failed
+    /// span, or an `ERROR` event with an `error` field.
+    fn fails_parent(&self) -> bool {
+        match &self.kind {
+            TreeKind::Event(event) => self.attrs.level == Level::ERROR && event.has_error,
+            TreeKind::Span(span) => span.failed,
+        }
+    }
+
+    /// Walk the tree with `visitor`, calling it for every span and event in
+    /// the order they occurred.
+    ///
+    /// See [`TreeVisitor`] for more details.
+    ///
+    /// ## Errors
+    ///
+    /// Returns the first error returned by `visitor`, which stops the walk.
+    pub fn walk<V: TreeVisitor + ?Sized>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        match &self.kind {
+            TreeKind::Span(span) => visitor.visit_span(self, span),
+            TreeKind::Event(event) => visitor.visit_event(self, event),
+        }
+    }
+
+    /// Returns the tags of all tagged events in the tree, in the order they
+    /// were logged.
+    pub fn tags(&self) -> Vec<TagData> {
+        let mut tags = Vec::new();
+        self.collect_tags(&mut tags);
+        tags
+    }
+
+    fn collect_tags(&self, tags: &mut Vec<TagData>) {
+        match &self.kind {
+            TreeKind::Event(event) => tags.extend(event.tag),
+            TreeKind::Span(span) => span
+                .children
+                .iter()
+                .for_each(|child| child.collect_tags(tags)),
+        }
+    }
+}
+
+/// Visits the spans and events of a [`Tree`], as it's walked with
+/// [`Tree::walk`].
+///
+/// Every method does nothing by default, except for [`visit_span`], which
+/// walks the children of the span between calls to [`enter_span`] and
+/// [`exit_span`]. Override it to skip the children of some spans, or to walk
+/// them in a different order with [`walk_span`] and [`Tree::walk`].
+///
+/// Visitors that can't fail can set `Error` to [`Infallible`].
+///
+/// # Examples
+///
+/// Counting the events in a tree, by depth:
+///
+/// ```
+/// # use std::convert::Infallible;
+/// # use tracing::Level;
+/// # use tracing_forest::tree::{Tree, TreeEvent, TreeSpan, TreeVisitor};
+/// #[derive(Default)]
+/// struct Depths {
+///     depth: usize,
+///     events: Vec<usize>,
+/// }
+///
+/// impl TreeVisitor for Depths {
+///     type Error = Infallible;
+///
+///     fn enter_span(&mut self, _: &Tree, _: &TreeSpan) -> Result<(), Infallible> {
+///         self.depth += 1;
+///         Ok(())
+///     }
+///
+///     fn exit_span(&mut self, _: &Tree, _: &TreeSpan) -> Result<(), Infallible> {
+///         self.depth -= 1;
+///         Ok(())
+///     }
+///
+///     fn visit_event(&mut self, _: &Tree, _: &TreeEvent) -> Result<(), Infallible> {
+///         self.events.push(self.depth);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut root = Tree::root("request");
+/// root.add_child(Tree::span(Level::DEBUG, "db"))
+///     .add_child(Tree::event(Level::INFO, "query"));
+/// root.add_child(Tree::event(Level::WARN, "slow response"));
+///
+/// let mut depths = Depths::default();
+/// let _ = root.walk(&mut depths);
+/// assert_eq!(depths.events, [2, 1]);
+/// ```
+///
+/// [`visit_span`]: TreeVisitor::visit_span
+/// [`enter_span`]: TreeVisitor::enter_span
+/// [`exit_span`]: TreeVisitor::exit_span
+/// [`Infallible`]: core::convert::Infallible
+pub trait TreeVisitor {
+    /// The error that stops the walk, like an [`io::Error`] for formatters.
+    ///
+    /// [`io::Error`]: std::io::Error
+    type Error;
+
+    /// Visit a span, by default walking its children.
+    fn visit_span(&mut self, tree: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+        walk_span(self, tree, span)
+    }
+
+    /// Called with a span that's being walked, before its children.
+    fn enter_span(&mut self, tree: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+        let _ = (tree, span);
+        Ok(())
+    }
+
+    /// Called with a span that's being walked, after its children.
+    fn exit_span(&mut self, tree: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+        let _ = (tree, span);
+        Ok(())
+    }
+
+    /// Visit an event.
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> Result<(), Self::Error> {
+        let _ = (tree, event);
+        Ok(())
+    }
+}
+
+/// Walks the children of a span with `visitor`, between calls to
+/// [`TreeVisitor::enter_span`] and [`TreeVisitor::exit_span`].
+///
+/// This is what [`TreeVisitor::visit_span`] does by default, for visitors
+/// that override it and still want to walk some spans.
+///
+/// ## Errors
+///
+/// Returns the first error returned by `visitor`, which stops the walk.
+pub fn walk_span<V: TreeVisitor + ?Sized>(
+    visitor: &mut V,
+    tree: &Tree,
+    span: &TreeSpan,
+) -> Result<(), V::Error> {
+    visitor.enter_span(tree, span)?;
+    for child in span.children.iter() {
+        child.walk(visitor)?;
+    }
+    visitor.exit_span(tree, span)
+}
+
+/// Every [`Level`], from most to least severe.
+pub(crate) const LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+/// Returns the position of `level` in [`LEVELS`].
+pub(crate) fn level_index(level: Level) -> usize {
+    match level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+/// Counts of events by level and tag, for statistics and summaries of trees.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EventCounts {
+    /// Indexed by [`level_index`].
+    levels: [usize; 5],
+    /// In the order each tag first occurred.
+    tags: Vec<(&'static str, usize)>,
+}
+
+impl EventCounts {
+    /// Counts an event logged at `level`, and its tag.
+    pub(crate) fn add(&mut self, level: Level, tag: Option<TagData>) {
+        self.levels[level_index(level)] += 1;
+        if let Some(tag) = tag {
+            match self.tags.iter_mut().find(|(name, _)| *name == tag.message) {
+                Some((_, count)) => *count += 1,
+                None => self.tags.push((tag.message, 1)),
+            }
+        }
+    }
+
+    /// Returns the number of events counted.
+    pub(crate) fn total(&self) -> usize {
+        self.levels.iter().sum()
+    }
+
+    /// Returns the number of events logged at `level`.
+    pub(crate) fn level(&self, level: Level) -> usize {
+        self.levels[level_index(level)]
+    }
+
+    /// Returns the levels that events were logged at, and how many were,
+    /// from most to least severe.
+    pub(crate) fn levels(&self) -> impl Iterator<Item = (Level, usize)> + '_ {
+        LEVELS
+            .iter()
+            .zip(self.levels.iter())
+            .filter(|(_, &count)| count > 0)
+            .map(|(&level, &count)| (level, count))
+    }
+
+    /// Returns the number of events tagged with `tag`.
+    pub(crate) fn tag(&self, tag: &str) -> usize {
+        self.tags
+            .iter()
+            .find(|(name, _)| *name == tag)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    /// Returns every tag and how many events had it, in the order each tag
+    /// first occurred.
+    pub(crate) fn tags(&self) -> &[(&'static str, usize)] {
+        &self.tags
+    }
+}
+
+impl TreeVisitor for EventCounts {
+    type Error = core::convert::Infallible;
+
+    fn visit_event(&mut self, tree: &Tree, event: &TreeEvent) -> Result<(), Self::Error> {
+        self.add(tree.attrs.level, event.tag);
+        Ok(())
+    }
+}
+
+/// The shared attributes of both spans and events within a [`Tree`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct TreeAttrs {
+    /// The ID that this trace data is associated with.
+    #[cfg(feature = "uuid")]
+    pub uuid: Uuid,
+    /// When the trace data was collected.
+    ///
+    /// Only the root of a tree is timestamped with the wall clock. The rest
+    /// are offset from it by the monotonic clock, so they never go backwards
+    /// within a tree. See [`TreeLayer::wall_clock`].
+    ///
+    /// [`TreeLayer::wall_clock`]: crate::layer::TreeLayer::wall_clock
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(feature = "json", serde(serialize_with = "ser::timestamp"))]
+    pub timestamp: DateTime<Utc>,
+    /// Level the trace data was collected with.
+    #[cfg_attr(feature = "json", serde(serialize_with = "ser::level"))]
+    pub level: Level,
+}
+
+#[cfg(feature = "std")]
+impl TreeAttrs {
+    fn now(level: Level) -> Self {
+        TreeAttrs {
+            #[cfg(feature = "uuid")]
+            uuid: Uuid::new_v4(),
+            #[cfg(feature = "chrono")]
+            timestamp: Utc::now(),
+            level,
+        }
+    }
+}
+
+/// The kind of log, either a [`TreeEvent`] or a [`TreeSpan`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub enum TreeKind {
+    Event(TreeEvent),
+    Span(TreeSpan),
+}
+
+/// Information unique to logged events.
+///
+/// This is non-exhaustive, so that fields can be added without breaking
+/// code that builds events. To initialize a new [`TreeEvent`], see
+/// [`TreeEvent::new`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[non_exhaustive]
+pub struct TreeEvent {
+    /// An optional tag that the event was collected with.
+    pub tag: Option<TagData>,
+    /// The message associated with the event.
+    pub message: Cow<'static, str>,
+    /// The format string that the message was rendered from, like
+    /// `"retrying in {}ms"`, if it was recorded in the [`TEMPLATE_FIELD`].
+    ///
+    /// Unlike messages, templates are the same for every event logged by the
+    /// same macro invocation, so log backends can group events by them.
+    ///
+    /// [`TEMPLATE_FIELD`]: crate::layer::TEMPLATE_FIELD
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub template: Option<Cow<'static, str>>,
+    /// Key-value data.
+    #[cfg_attr(feature = "json", serde(serialize_with = "ser::fields"))]
+    pub fields: Fields,
+    /// The target of the event, usually its module path.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub target: &'static str,
+    /// The source file the event was logged in.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub file: Option<&'static str>,
+    /// The line the event was logged on.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub line: Option<u32>,
+    /// The [`Metadata`] of the `tracing` event, or `None` if the event was
+    /// built with [`Tree::event`].
+    ///
+    /// See the accessors of [`TreeEvent`] for its most useful parts.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub metadata: Option<EventMetadata>,
+    /// The spans that were entered when an error recorded by the event was
+    /// created, innermost first.
+    ///
+    /// This is only collected for errors recorded as `&dyn Error` that carry
+    /// a [`SpanTrace`], and requires an [`ErrorLayer`] in the subscriber.
+    ///
+    /// [`SpanTrace`]: tracing_error::SpanTrace
+    /// [`ErrorLayer`]: tracing_error::ErrorLayer
+    #[cfg(feature = "tracing-error")]
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub span_trace: Option<Vec<SpanTraceFrame>>,
+    /// Whether an `error` field was recorded, under its key from before any
+    /// [`FieldRules`] renamed it.
+    ///
+    /// [`FieldRules`]: crate::layer::FieldRules
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub(crate) has_error: bool,
+}
+
+/// A span captured in a [`SpanTrace`][tracing_error::SpanTrace].
+#[cfg(feature = "tracing-error")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing-error")))]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct SpanTraceFrame {
+    /// The target of the span.
+    pub target: &'static str,
+    /// The name of the span.
+    pub name: &'static str,
+    /// The formatted fields of the span.
+    pub fields: String,
+    /// The source file the span was created in.
+    pub file: Option<&'static str>,
+    /// The line the span was created on.
+    pub line: Option<u32>,
+}
+
+impl TreeEvent {
+    /// Construct a new [`TreeEvent`] with a message, and no tag, fields, or
+    /// metadata.
+    pub fn new(message: impl Into<Cow<'static, str>>) -> Self {
+        TreeEvent {
+            tag: None,
+            message: message.into(),
+            template: None,
+            fields: Fields::new(),
+            target: "tracing_forest::tree",
+            file: None,
+            line: None,
+            metadata: None,
+            #[cfg(feature = "tracing-error")]
+            span_trace: None,
+            has_error: false,
+        }
+    }
+
+    /// Returns the name of the event's callsite, like
+    /// `event src/main.rs:12`, or `None` if it has no [`metadata`].
+    ///
+    /// [`metadata`]: TreeEvent::metadata
+    pub fn name(&self) -> Option<&'static str> {
+        self.metadata.map(|metadata| metadata.name())
+    }
+
+    /// Returns the module path that the event was logged in, which may differ
+    /// from its [`target`] if one was set explicitly.
+    ///
+    /// [`target`]: TreeEvent::target
+    pub fn module_path(&self) -> Option<&'static str> {
+        self.metadata.and_then(|metadata| metadata.module_path())
+    }
+
+    /// Returns the identifier of the event's callsite, which is the same for
+    /// every event logged by the same macro invocation.
+    ///
+    /// This can be used as a key for per-callsite decisions, like rate limits,
+    /// without comparing targets, files, and lines.
+    pub fn callsite(&self) -> Option<Identifier> {
+        self.metadata.map(|metadata| metadata.callsite())
+    }
+}
+
+/// The [`Metadata`] of a `tracing` event, which it dereferences to.
+///
+/// Metadata is immutable once its callsite is registered, so unlike the
+/// callsite it refers to, this can be shared across a `catch_unwind`.
+#[derive(Debug, Clone, Copy)]
+pub struct EventMetadata(&'static Metadata<'static>);
+
+impl From<&'static Metadata<'static>> for EventMetadata {
+    fn from(metadata: &'static Metadata<'static>) -> Self {
+        EventMetadata(metadata)
+    }
+}
+
+impl Deref for EventMetadata {
+    type Target = Metadata<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl UnwindSafe for EventMetadata {}
+impl RefUnwindSafe for EventMetadata {}
+
+impl From<TreeEvent> for TreeKind {
+    fn from(event: TreeEvent) -> Self {
+        TreeKind::Event(event)
+    }
+}
+
+impl From<TreeSpan> for TreeKind {
+    fn from(span: TreeSpan) -> Self {
+        TreeKind::Span(span)
+    }
+}
+
+/// Information unique to logged spans.
+///
+/// When serialized, spans also include their [self time][TreeSpan::duration_self]
+/// as `nanos_self`.
+///
+/// This is non-exhaustive, so that fields can be added without breaking
+/// code that builds spans. To initialize a new [`TreeSpan`], see
+/// [`TreeSpan::new`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TreeSpan {
+    /// The name of the span.
+    pub name: &'static str,
+    /// Key-value data recorded on the span, like the arguments of an
+    /// instrumented function.
+    pub fields: Fields,
+    /// The duration that the span was entered for.
+    pub duration_total: Duration,
+    /// The duration that child spans of this span were entered for.
+    pub duration_nested: Duration,
+    /// Spans and events that occurred inside of this span.
+    // Unlike fields, children can't be stored inline with `smallvec`: a
+    // `Tree` would contain itself, and have infinite size
+    pub children: Vec<Tree>,
+    /// Whether the span failed, see [`TreeSpan::is_failed`].
+    pub(crate) failed: bool,
+}
+
+impl TreeSpan {
+    /// Construct a new [`TreeSpan`] with a name, and no fields, durations, or
+    /// children.
+    pub fn new(name: &'static str) -> Self {
+        TreeSpan {
+            name,
+            fields: Fields::new(),
+            duration_total: Duration::ZERO,
+            duration_nested: Duration::ZERO,
+            children: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Returns the duration that the span was entered for outside of its child
+    /// spans, which is the time spent in the span itself.
+    pub fn duration_self(&self) -> Duration {
+        self.duration_total.saturating_sub(self.duration_nested)
+    }
+
+    /// Returns whether the span failed, meaning it has an `error` field,
+    /// directly contains an `ERROR` event with one, or contains a span that
+    /// failed.
+    ///
+    /// This is how functions annotated with `#[instrument(err)]` record that
+    /// they returned an error, so their spans are failed without any changes,
+    /// and so are the spans they were called from.
+    ///
+    /// This is worked out as the span is collected, so it's cheap to check.
+    /// Fields are checked under their keys from before any [`FieldRules`]
+    /// renamed them. Spans built by hand fail when an `error` field or a
+    /// failed child is added with [`Tree::with_field`] or
+    /// [`Tree::add_child`], so children should be built before they're added.
+    ///
+    /// [`FieldRules`]: crate::layer::FieldRules
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Works out whether the span failed from its children, whose own
+    /// failures are already known.
+    ///
+    /// Its own fields are checked with [`has_error`] as they're recorded,
+    /// before they can be renamed.
+    pub(crate) fn update_failed(&mut self) {
+        self.failed |= self.children.iter().any(Tree::fails_parent);
+    }
+}
+
+/// The field that errors returned from instrumented functions are recorded in.
+const ERROR_FIELD: &str = "error";
+
+/// Returns whether `fields` has an `error` field.
+pub(crate) fn has_error(fields: &Fields) -> bool {
+    fields.iter().any(|kv| kv.key == ERROR_FIELD)
+}
+
+/// Returns the string that a field value was recorded from.
+///
+/// Field values are recorded with their `Debug` representation, so strings
+/// recorded as `key = "value"` are quoted and escaped, unlike ones recorded
+/// with `key = %value`. This undoes both, so features that look fields up by
+/// name treat them the same.
+pub(crate) fn unquote(value: &str) -> Cow<'_, str> {
+    let inner = match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(inner) => inner,
+        None => return Cow::Borrowed(value),
+    };
+    if !inner.contains('\\') {
+        return Cow::Borrowed(inner);
+    }
+
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => unescaped.push('\0'),
+            Some('u') => {
+                // Unicode escapes look like `\u{1b}`
+                let rest = chars.as_str();
+                let escaped = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .and_then(|(hex, rest)| {
+                        let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?;
+                        Some((c, rest))
+                    });
+                match escaped {
+                    Some((c, rest)) => {
+                        unescaped.push(c);
+                        chars = rest.chars();
+                    }
+                    None => unescaped.push_str("\\u"),
+                }
+            }
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    Cow::Owned(unescaped)
+}
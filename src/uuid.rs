@@ -4,6 +4,8 @@
 
 use crate::fail;
 use crate::layer::TreeSpanOpened;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::Subscriber;
 use tracing_subscriber::registry::LookupSpan;
 use uuid::Uuid;
@@ -31,6 +33,12 @@ pub fn into_u64_pair(id: &Uuid) -> (u64, u64) {
     (msb, lsb)
 }
 
+// For internal macro usage only, so the ID is only evaluated once
+#[doc(hidden)]
+pub fn into_u128(id: &Uuid) -> u128 {
+    id.as_u128()
+}
+
 // For internal macro usage only
 #[doc(hidden)]
 pub fn from_u64_pair(msb: u64, lsb: u64) -> Uuid {
@@ -54,6 +62,80 @@ pub fn from_u64_pair(msb: u64, lsb: u64) -> Uuid {
     ])
 }
 
+/// The version of [`Uuid`]s generated for root spans.
+///
+/// See [`TreeLayer::uuid_version`] for details.
+///
+/// [`TreeLayer::uuid_version`]: crate::TreeLayer::uuid_version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UuidVersion {
+    /// Randomly generated IDs.
+    V4,
+    /// Timestamp-ordered IDs, where the first 48 bits are the milliseconds
+    /// since the Unix epoch. IDs generated by the same process are strictly
+    /// increasing, even within the same millisecond.
+    V7,
+}
+
+impl UuidVersion {
+    pub(crate) fn generator(self) -> fn() -> Uuid {
+        match self {
+            UuidVersion::V4 => Uuid::new_v4,
+            UuidVersion::V7 => new_v7,
+        }
+    }
+}
+
+/// The last milliseconds timestamp and counter used, packed as
+/// `millis << 12 | counter`.
+static LAST_V7: AtomicU64 = AtomicU64::new(0);
+
+fn new_v7() -> Uuid {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+
+    // Use the 12 bits after the timestamp as a counter, keeping IDs monotonic
+    // within the same millisecond and across small clock steps backwards
+    let mut last = LAST_V7.load(Ordering::Relaxed);
+    let packed = loop {
+        let next = (millis << 12).max(last + 1);
+        match LAST_V7.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break next,
+            Err(actual) => last = actual,
+        }
+    };
+    let millis = packed >> 12;
+    let counter = (packed & 0xfff) as u16;
+
+    let mut bytes = *Uuid::new_v4().as_bytes();
+    bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    bytes[6] = 0x70 | (counter >> 8) as u8;
+    bytes[7] = counter as u8;
+    // The variant bits were already set by `new_v4`
+    Uuid::from_bytes(bytes)
+}
+
+/// Returns the [`Uuid`] given by the value of an ID field, which is either
+/// the [`Uuid`] itself, or any other string, which is hashed into a version 8
+/// [`Uuid`] so that the same string always gives the same ID.
+pub(crate) fn from_field_value(value: &str) -> Uuid {
+    if let Ok(uuid) = Uuid::parse_str(value) {
+        return uuid;
+    }
+
+    // 128-bit FNV-1a
+    let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+    for byte in value.bytes() {
+        hash ^= byte as u128;
+        hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+    }
+    let mut bytes = hash.to_be_bytes();
+    bytes[6] = 0x80 | (bytes[6] & 0x0f);
+    bytes[8] = 0x80 | (bytes[8] & 0x3f);
+    Uuid::from_bytes(bytes)
+}
+
 /// Gets the current [`Uuid`] of an entered span within a [`TreeLayer`]
 /// subscriber.
 ///
@@ -99,13 +181,36 @@ where
         let span = subscriber
             .span(id)
             .unwrap_or_else(fail::span_not_in_context);
+        let span = crate::layer::collected(span).unwrap_or_else(fail::no_tree_layer);
 
         let uuid = span
             .extensions()
             .get::<TreeSpanOpened>()
             .unwrap_or_else(fail::no_tree_layer)
             .uuid();
+        if !uuid.is_nil() {
+            return uuid;
+        }
 
+        // The root span may have a lazy ID, which is generated now and shared
+        // with the spans that were opened inside of it before
+        let root = span
+            .scope()
+            .filter(|span| span.extensions().get::<TreeSpanOpened>().is_some())
+            .last();
+        let uuid = match root {
+            Some(root) => root
+                .extensions_mut()
+                .get_mut::<TreeSpanOpened>()
+                .unwrap_or_else(fail::no_tree_layer)
+                .generate_uuid(),
+            None => uuid,
+        };
+        for span in span.scope() {
+            if let Some(opened) = span.extensions_mut().get_mut::<TreeSpanOpened>() {
+                opened.fill_uuid(uuid);
+            }
+        }
         uuid
     })
 }
@@ -0,0 +1,306 @@
+//! Writers for the destinations of formatted trees.
+//!
+//! See [`RotatingFile`] and [`ProgressWriter`] for more details.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// A [`MakeWriter`] that appends to a file, and rotates it once it grows past
+/// a maximum size.
+///
+/// When a write would make the file larger than the maximum size, the file
+/// is renamed to `{path}.1`, any existing `{path}.1` is renamed to
+/// `{path}.2`, and so on, keeping at most [`keep`] old files. Writes are never
+/// split across files, so a file can exceed the maximum size if a single
+/// tree is larger than it.
+///
+/// # Examples
+///
+/// ```
+/// # use tracing_forest::writer::RotatingFile;
+/// # let dir = std::env::temp_dir().join("tracing-forest-rotating-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let writer = RotatingFile::new(dir.join("app.log"), 10 * 1024 * 1024)
+///     .expect("failed to open log file")
+///     .keep(3);
+///
+/// let _guard = tracing::subscriber::set_default({
+///     tracing_forest::builder()
+///         .writer(writer)
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+/// ```
+///
+/// # External rotation
+///
+/// When the file is rotated by another program, like `logrotate`, it keeps
+/// being written to after it's moved until it's [reopened]. A
+/// [`ReopenHandle`] reopens it from another thread, like one handling
+/// `SIGHUP`.
+///
+/// [`keep`]: RotatingFile::keep
+/// [reopened]: RotatingFile::reopen
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    /// Open the file at `path` for appending, creating it if it doesn't exist,
+    /// and rotate it once it grows past `max_bytes`.
+    ///
+    /// By default, 5 old files are kept.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn new<P: AsRef<Path>>(path: P, max_bytes: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open(&path)?;
+        let len = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            keep: 5,
+            state: Arc::new(Mutex::new(State { file, len })),
+        })
+    }
+
+    /// Reopen the file at its path, creating it if it doesn't exist.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the file cannot be opened, in which case writes
+    /// continue to the previous file.
+    pub fn reopen(&self) -> io::Result<()> {
+        reopen(&self.path, &self.state)
+    }
+
+    /// Returns a handle that [reopens] the file, which can be cloned and sent
+    /// to other threads.
+    ///
+    /// [reopens]: RotatingFile::reopen
+    pub fn reopen_handle(&self) -> ReopenHandle {
+        ReopenHandle {
+            path: self.path.clone(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Set how many old files are kept. With `0`, the file is truncated
+    /// instead of renamed when it's rotated.
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    fn rotate(&self, state: &mut State) -> io::Result<()> {
+        rotate(&self.path, &mut state.file, self.keep)?;
+        state.len = 0;
+        Ok(())
+    }
+}
+
+pub(crate) fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated(path: &Path, idx: usize) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(format!(".{}", idx));
+    path.into()
+}
+
+/// Renames the file at `path` to `{path}.1`, after renaming older files, and
+/// replaces `file` with a new empty file at `path`. With a `keep` of `0`,
+/// `file` is truncated instead.
+pub(crate) fn rotate(path: &Path, file: &mut File, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return file.set_len(0);
+    }
+    let _ = fs::remove_file(rotated(path, keep));
+    for idx in (1..keep).rev() {
+        let from = rotated(path, idx);
+        if from.exists() {
+            fs::rename(from, rotated(path, idx + 1))?;
+        }
+    }
+    fs::rename(path, rotated(path, 1))?;
+    *file = open(path)?;
+    Ok(())
+}
+
+fn reopen(path: &Path, state: &Mutex<State>) -> io::Result<()> {
+    let file = open(path)?;
+    let len = file.metadata()?.len();
+    let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+    *state = State { file, len };
+    Ok(())
+}
+
+/// A handle for reopening a [`RotatingFile`], returned by
+/// [`RotatingFile::reopen_handle`].
+#[derive(Debug, Clone)]
+pub struct ReopenHandle {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+impl ReopenHandle {
+    /// Reopen the file at its path, creating it if it doesn't exist.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the file cannot be opened, in which case writes
+    /// continue to the previous file.
+    pub fn reopen(&self) -> io::Result<()> {
+        reopen(&self.path, &self.state)
+    }
+}
+
+/// The [`Write`] handle returned by [`RotatingFile`].
+#[derive(Debug)]
+pub struct RotatingWriter<'a> {
+    rotating: &'a RotatingFile,
+    state: MutexGuard<'a, State>,
+}
+
+impl Write for RotatingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.state.len;
+        if len > 0 && len + buf.len() as u64 > self.rotating.max_bytes {
+            self.rotating.rotate(&mut self.state)?;
+        }
+
+        let written = self.state.file.write(buf)?;
+        self.state.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = RotatingWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RotatingWriter {
+            rotating: self,
+            // A panic while writing can't leave the file in an invalid state
+            state: self.state.lock().unwrap_or_else(|err| err.into_inner()),
+        }
+    }
+}
+
+/// A [`MakeWriter`] that writes to stderr without corrupting active
+/// [`indicatif`] progress bars.
+///
+/// Each write hides the progress bars, writes the output, and redraws them
+/// underneath, using [`ProgressBar::suspend`] or [`MultiProgress::suspend`].
+/// Since processors write each tree in a single write, trees are never
+/// interleaved with progress bars.
+///
+/// # Examples
+///
+/// ```
+/// # use indicatif::ProgressBar;
+/// # use tracing_forest::writer::ProgressWriter;
+/// let bar = ProgressBar::new(100);
+///
+/// let _guard = tracing::subscriber::set_default({
+///     tracing_forest::builder()
+///         .writer(ProgressWriter::new(bar.clone()))
+///         .blocking_layer()
+///         .into_subscriber()
+/// });
+///
+/// for _ in 0..100 {
+///     tracing::info!("written above the progress bar");
+///     bar.inc(1);
+/// }
+/// bar.finish();
+/// ```
+///
+/// [`ProgressBar::suspend`]: indicatif::ProgressBar::suspend
+/// [`MultiProgress::suspend`]: indicatif::MultiProgress::suspend
+#[cfg(feature = "indicatif")]
+#[cfg_attr(docsrs, doc(cfg(feature = "indicatif")))]
+#[derive(Debug, Clone)]
+pub struct ProgressWriter {
+    progress: Progress,
+}
+
+#[cfg(feature = "indicatif")]
+#[derive(Debug, Clone)]
+enum Progress {
+    Bar(indicatif::ProgressBar),
+    Multi(indicatif::MultiProgress),
+}
+
+#[cfg(feature = "indicatif")]
+impl ProgressWriter {
+    /// Construct a new [`ProgressWriter`] that suspends a
+    /// [`ProgressBar`][indicatif::ProgressBar] or
+    /// [`MultiProgress`][indicatif::MultiProgress] while writing.
+    pub fn new(progress: impl Into<ProgressWriter>) -> Self {
+        progress.into()
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl From<indicatif::ProgressBar> for ProgressWriter {
+    fn from(bar: indicatif::ProgressBar) -> Self {
+        ProgressWriter {
+            progress: Progress::Bar(bar),
+        }
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl From<indicatif::MultiProgress> for ProgressWriter {
+    fn from(multi: indicatif::MultiProgress) -> Self {
+        ProgressWriter {
+            progress: Progress::Multi(multi),
+        }
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl Write for &ProgressWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write = || io::stderr().write_all(buf).map(|_| buf.len());
+        match &self.progress {
+            Progress::Bar(bar) => bar.suspend(write),
+            Progress::Multi(multi) => multi.suspend(write),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl<'a> MakeWriter<'a> for ProgressWriter {
+    type Writer = &'a ProgressWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
@@ -2,6 +2,68 @@ use tracing::{info, trace_span};
 use tracing_forest::uuid_trace_span;
 use tracing_subscriber::Registry;
 
+/// A writer that appends to a shared buffer, for inspecting formatted output.
+struct SharedBuf(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats everything logged in `f` with `formatter` and returns the output.
+fn render<F>(formatter: F, f: impl FnOnce()) -> String
+where
+    F: 'static + tracing_forest::formatter::Formatter + Send + Sync,
+{
+    use tracing_forest::Processor;
+
+    let out = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let writer = out.clone();
+    let processor = tracing_forest::blocking(formatter, move || SharedBuf(writer.clone()));
+    tracing::subscriber::with_default(processor.into_layer().into_subscriber(), f);
+    let out = out.lock().unwrap();
+    String::from_utf8(out.clone()).unwrap()
+}
+
+/// Accepts one HTTP request on a local port, returning the URL to send it
+/// to and a handle that joins to its request line, headers, and body.
+fn http_server() -> (String, std::thread::JoinHandle<(Vec<String>, String)>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = Vec::new();
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                len = value.trim().parse().unwrap();
+            }
+            head.push(line.trim_end().to_string());
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        (head, String::from_utf8(body).unwrap())
+    });
+    (url, server)
+}
+
 mod uuid_tests {
     use super::*;
     use uuid::Uuid;
@@ -89,6 +151,34 @@ mod uuid_tests {
         });
     }
 
+    #[test]
+    fn test_uuid_v7_roots_are_ordered() {
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::{Processor, UuidVersion};
+
+        let recent = RecentTrees::new(100);
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .uuid_version(UuidVersion::V7)
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..100 {
+                trace_span!("root").in_scope(|| {});
+            }
+        });
+
+        let ids = recent
+            .snapshot()
+            .iter()
+            .map(|tree| tree.attrs.uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 100);
+        assert!(ids.iter().all(|id| id.get_version_num() == 7));
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
     #[tracing_forest::test]
     fn test_get_many_times() {
         trace_span!("first").in_scope(|| {
@@ -140,6 +230,104 @@ use kanidm::KanidmTag;
 
 mod tag_tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::layer::Tree;
+    use tracing_forest::tag::Severity;
+    use tracing_forest::{Processor, Tag};
+
+    struct Collect(Arc<Mutex<Vec<Tree>>>);
+
+    impl Processor for Collect {
+        fn process(&self, tree: Tree) {
+            self.0.lock().unwrap().push(tree);
+        }
+    }
+
+    #[derive(Tag)]
+    enum AuditTag {
+        #[tag(custom('🔐'): "audit.breach", severity = critical)]
+        Breach,
+        #[tag(error: "audit.denied")]
+        Denied,
+        #[tag(info: "audit.login")]
+        Login,
+    }
+
+    #[test]
+    fn test_tag_severity_ordering() {
+        let critical = AuditTag::from_field(AuditTag::Breach.as_field());
+        let error = AuditTag::from_field(AuditTag::Denied.as_field());
+        let info = AuditTag::from_field(AuditTag::Login.as_field());
+
+        assert_eq!(critical.severity, Severity::Critical);
+        assert_eq!(error.severity, Severity::Error);
+        assert!(critical > error);
+        assert!(error > info);
+
+        // Custom tags default to `Info`
+        let custom = KanidmTag::from_field(KanidmTag::SecurityCritical.as_field());
+        assert_eq!(custom.severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_filter_by_severity() {
+        let trees = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collect(trees.clone())
+            .filter(|tree| tree.max_tag_severity() >= Some(Severity::Critical))
+            .into_layer()
+            .tag::<AuditTag>()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            trace_span!("benign").in_scope(|| {
+                tracing::error!(__event_tag = AuditTag::Denied.as_field(), "access denied");
+            });
+            trace_span!("breach").in_scope(|| {
+                info!(__event_tag = AuditTag::Login.as_field(), "logged in");
+                tracing::error!(
+                    __event_tag = AuditTag::Breach.as_field(),
+                    "the db has been breached"
+                );
+            });
+        });
+
+        let trees = trees.lock().unwrap();
+        assert_eq!(trees.len(), 1);
+        assert_eq!(trees[0].max_tag_severity(), Some(Severity::Critical));
+    }
+
+    #[test]
+    fn test_route_by_tag() {
+        let security = Arc::new(Mutex::new(Vec::new()));
+        let rest = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collect(rest.clone())
+            .route(
+                |tree| {
+                    tree.tags()
+                        .iter()
+                        .any(|tag| tag.message.starts_with("security."))
+                },
+                Collect(security.clone()),
+            )
+            .into_layer()
+            .tag::<KanidmTag>()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            trace_span!("benign").in_scope(|| {
+                admin_info!("some info for the admin");
+            });
+            trace_span!("breach").in_scope(|| {
+                admin_info!("some info for the admin");
+                security_critical!("the db has been breached");
+            });
+        });
+
+        let security = security.lock().unwrap();
+        assert_eq!(security.len(), 1);
+        assert_eq!(security[0].tags().len(), 2);
+        assert_eq!(rest.lock().unwrap().len(), 1);
+    }
 
     #[tracing_forest::test(tag = "KanidmTag")]
     fn test_macros() {
@@ -153,6 +341,7 @@ mod attribute_tests {
     use super::*;
 
     #[tracing_forest::test(fmt = "json")]
+    #[allow(clippy::needless_return)]
     fn test_sync_early_return() {
         // tests that returning in the test doesn't prevent logging
         info!("a log");
@@ -161,6 +350,7 @@ mod attribute_tests {
 
     #[tracing_forest::test]
     #[tokio::test]
+    #[allow(clippy::needless_return)]
     async fn test_async_early_return() {
         // test that returning in the test doesn't prevent
         // the processing thread handle from being awaited
@@ -241,4 +431,6482 @@ mod attribute_tests {
     async fn test_tokio2() {
         tracing::info!("Hello from Tokio!");
     }
+
+    #[tracing_forest::test(artifact)]
+    #[should_panic(expected = "checkout failed")]
+    fn test_artifact_on_failure() {
+        tracing::info_span!("checkout").in_scope(|| {
+            info!("charging card");
+            panic!("checkout failed");
+        });
+    }
+
+    #[tracing_forest::test(artifact)]
+    #[tokio::test]
+    #[should_panic(expected = "checkout failed")]
+    async fn test_async_artifact_on_failure() {
+        tracing::info_span!("checkout").in_scope(|| {
+            info!("charging card");
+            panic!("checkout failed");
+        });
+    }
+
+    #[test]
+    fn test_artifact_written_only_on_failure() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::private::Artifact;
+        use tracing_forest::Processor;
+
+        fn run(name: &'static str, fail: bool) -> std::path::PathBuf {
+            let path = Artifact::new(name).path();
+            let _ = std::fs::remove_file(&path);
+            let _ = std::panic::catch_unwind(|| {
+                let artifact = Artifact::new(name);
+                let processor = tracing_forest::blocking(Pretty::new(), artifact.writer());
+                tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+                    tracing::info_span!("checkout").in_scope(|| {
+                        info!("charging card");
+                        assert!(!fail, "checkout failed");
+                    });
+                });
+            });
+            path
+        }
+
+        let failed = run("test::attribute_tests::artifact_failed", true);
+        assert!(failed.ends_with("forest/test-attribute_tests-artifact_failed.log"));
+        let log = std::fs::read_to_string(&failed).unwrap();
+        assert!(log.contains("checkout"));
+        assert!(log.contains("charging card"));
+
+        let passed = run("test::attribute_tests::artifact_passed", false);
+        assert!(!passed.exists());
+    }
+}
+
+mod wal_tests {
+    use super::SharedBuf;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::processor::wal::wal;
+    use tracing_forest::Processor;
+
+    /// The header of an empty log, which is where its first record starts.
+    const HEADER: [u8; 8] = 8u64.to_le_bytes();
+
+    #[test]
+    fn test_wal_recovers_unwritten_trees() {
+        let path = std::env::temp_dir().join(format!("forest-{}.wal", std::process::id()));
+        let written = b"INFO     written tree\n";
+        let pending = b"INFO     recovered tree\n";
+        let mut log = (8 + 4 + written.len() as u64).to_le_bytes().to_vec();
+        log.extend_from_slice(&(written.len() as u32).to_le_bytes());
+        log.extend_from_slice(written);
+        log.extend_from_slice(&(pending.len() as u32).to_le_bytes());
+        log.extend_from_slice(pending);
+        // A torn record that was never fully appended
+        log.extend_from_slice(&100u32.to_le_bytes());
+        log.extend_from_slice(b"partial");
+        std::fs::write(&path, log).unwrap();
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (processor, guard) =
+            wal(Pretty::new(), move || SharedBuf(writer.clone()), &path).unwrap();
+
+        assert_eq!(&out.lock().unwrap()[..], &pending[..]);
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info!("after recovery");
+        });
+        drop(guard);
+
+        assert!(String::from_utf8_lossy(&out.lock().unwrap()).contains("after recovery"));
+        assert_eq!(std::fs::read(&path).unwrap(), HEADER);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A writer that fails every write, like one to a full disk.
+    struct Failing;
+
+    impl std::io::Write for Failing {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_wal_journals_queued_trees() {
+        let path = std::env::temp_dir().join(format!("forest-{}-queued.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Trees are in the log as soon as they're queued, before the thread
+        // gets to them
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let armed = Arc::new(Mutex::new(None::<std::sync::mpsc::Receiver<()>>));
+        let blocking = armed.clone();
+        let (processor, guard) = wal(
+            Pretty::new(),
+            move || {
+                let blocked = blocking.lock().unwrap().take();
+                if let Some(blocked) = blocked {
+                    blocked.recv().unwrap();
+                }
+                std::io::sink()
+            },
+            &path,
+        )
+        .unwrap();
+        // Recovering the log also makes a writer, which shouldn't block
+        *armed.lock().unwrap() = Some(blocked);
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info!("queued");
+        });
+        let log = std::fs::read(&path).unwrap();
+        assert!(String::from_utf8_lossy(&log).contains("queued"));
+        release.send(()).unwrap();
+        drop(guard);
+        assert_eq!(std::fs::read(&path).unwrap(), HEADER);
+
+        // Trees that failed to be written stay in the log for the next start
+        let (processor, guard) = wal(Pretty::new(), || Failing, &path).unwrap();
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+        drop(guard);
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (_processor, guard) =
+            wal(Pretty::new(), move || SharedBuf(writer.clone()), &path).unwrap();
+        drop(guard);
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(out.contains("first") && out.contains("second"), "{}", out);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+mod index_tests {
+    use std::fs::File;
+    use std::io::BufReader;
+    use tracing::Level;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::processor::index::{indexed, read_index, IndexEntry, IndexFormat};
+    use tracing_forest::Processor;
+
+    fn write_trees(format: IndexFormat, ext: &str) -> Vec<(IndexEntry, String)> {
+        let dir = std::env::temp_dir().join(format!("forest-index-{}-{}", std::process::id(), ext));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (log, index) = (dir.join("app.log"), dir.join("app.idx"));
+
+        let processor = indexed(Pretty::new(), &log, &index).unwrap().format(format);
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info_span!("checkout").in_scope(|| {
+                tracing::warn!("slow, \"retrying\"");
+            });
+            tracing::error!("disk\nfull");
+        });
+
+        let mut file = File::open(&log).unwrap();
+        let entries = read_index(BufReader::new(File::open(&index).unwrap()), format)
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let tree = entry.read_tree(&mut file).unwrap();
+                (entry, String::from_utf8(tree).unwrap())
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        entries
+    }
+
+    #[test]
+    fn test_index_locates_trees() {
+        let entries = write_trees(IndexFormat::Csv, "csv");
+
+        assert_eq!(entries.len(), 2);
+        let (checkout, tree) = &entries[0];
+        assert_eq!(checkout.root, "checkout");
+        assert_eq!(checkout.level, Level::WARN);
+        assert_eq!(checkout.offset, 0);
+        assert!(tree.contains(&checkout.id.to_string()), "{}", tree);
+        assert!(tree.ends_with("[warn]: slow, \"retrying\"\n"), "{}", tree);
+
+        let (disk, tree) = &entries[1];
+        assert_eq!(disk.root, "disk full");
+        assert_eq!(disk.level, Level::ERROR);
+        assert_eq!(disk.offset, checkout.len);
+        assert!(tree.ends_with("[error]: disk\nfull\n"), "{}", tree);
+    }
+
+    #[test]
+    fn test_index_json_lines() {
+        let entries = write_trees(IndexFormat::JsonLines, "jsonl");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0.root, "checkout");
+        assert_eq!(entries[1].0.root, "disk full");
+        assert_eq!(entries[1].0.offset, entries[0].0.len);
+    }
+
+    #[test]
+    fn test_index_rotates_with_log() {
+        let dir = std::env::temp_dir().join(format!("forest-index-{}-rotate", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (log, index) = (dir.join("app.log"), dir.join("app.idx"));
+
+        let processor = indexed(Pretty::new(), &log, &index)
+            .unwrap()
+            .rotate(1)
+            .keep(1);
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info!("first");
+            tracing::info!("second");
+            tracing::info!("third");
+        });
+
+        // The oldest file and its index are removed together
+        assert!(!dir.join("app.log.2").exists());
+        assert!(!dir.join("app.idx.2").exists());
+        for (suffix, message) in [("", "third"), (".1", "second")] {
+            let path = |name: &str| dir.join(format!("{}{}", name, suffix));
+            let mut file = File::open(path("app.log")).unwrap();
+            let entries: Vec<_> = read_index(
+                BufReader::new(File::open(path("app.idx")).unwrap()),
+                IndexFormat::Csv,
+            )
+            .map(Result::unwrap)
+            .collect();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].root, message);
+            assert_eq!(entries[0].offset, 0);
+            let tree = String::from_utf8(entries[0].read_tree(&mut file).unwrap()).unwrap();
+            assert!(
+                tree.ends_with(&format!("[info]: {}\n", message)),
+                "{}",
+                tree
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_entry_round_trip() {
+        let line = "67e55044-10b1-426f-9247-bb680e5fe0c8,\"a, \"\"b\"\"\",2024-01-02T03:04:05+00:00,1500,INFO,10,20";
+        let entry = IndexEntry::parse(line, IndexFormat::Csv).unwrap();
+        assert_eq!(entry.root, "a, \"b\"");
+        assert_eq!(entry.duration.as_nanos(), 1500);
+
+        let mut out = Vec::new();
+        entry.write(IndexFormat::Csv, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", line));
+
+        let mut json = Vec::new();
+        entry.write(IndexFormat::JsonLines, &mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(
+            IndexEntry::parse(json.trim_end(), IndexFormat::JsonLines).unwrap(),
+            entry
+        );
+
+        let header = tracing_forest::processor::index::CSV_HEADER;
+        assert!(IndexEntry::parse(header, IndexFormat::Csv).is_err());
+    }
+}
+
+mod pretty_tests {
+    use super::*;
+    use tracing_forest::formatter::pretty::Pretty;
+
+    #[test]
+    fn test_fixed_duration_units() {
+        use tracing_forest::formatter::pretty::DurationFormat;
+
+        let out = render(
+            Pretty::new()
+                .with_duration_format(DurationFormat::Secs(4))
+                .with_span_start(true),
+            || trace_span!("units").in_scope(|| {}),
+        );
+
+        assert!(out.contains("units [ 0.0000s | 100.000% ] @ "), "{}", out);
+    }
+
+    #[test]
+    fn test_wrap_long_messages() {
+        let out = render(Pretty::new().with_width(120), || {
+            trace_span!("wrapping").in_scope(|| {
+                info!("a very long message that will not fit on a single line of output at all");
+                info!("short");
+            });
+        });
+
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 3, "{}", out);
+        assert!(lines.iter().all(|line| columns(line) <= 120), "{}", out);
+        assert!(
+            lines[lines.len() - 2].trim_end().ends_with("at all"),
+            "{}",
+            out
+        );
+        assert!(lines[lines.len() - 1].ends_with("[info]: short"), "{}", out);
+
+        // Continuation lines start where the message does
+        let message = columns(&lines[1][..lines[1].find("a very").unwrap()]);
+        let continued = lines[2].len() - lines[2].trim_start_matches([' ', '│']).len();
+        assert_eq!(columns(&lines[2][..continued]), message, "{}", out);
+    }
+
+    /// Counts wide characters, like CJK and emoji, as two columns, and box
+    /// drawing characters as one.
+    fn columns(line: &str) -> usize {
+        line.chars()
+            .map(|c| match c {
+                '\u{2500}'..='\u{257f}' => 1,
+                c if c.len_utf8() > 2 => 2,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_wrap_wide_characters() {
+        let out = render(Pretty::new().with_snapshot(true).with_width(40), || {
+            info!("日本語のメッセージ 日本語のメッセージ 日本語のメッセージ 日本語のメッセージ");
+        });
+
+        let lines = out.lines().collect::<Vec<_>>();
+        // Each piece takes up 18 columns, so only one fits after the prefix
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert!(lines.iter().all(|line| columns(line) <= 40), "{}", out);
+        assert!(lines[1].starts_with(&format!("{:20}日本語", "")), "{}", out);
+    }
+
+    fn ordered(order: tracing_forest::formatter::pretty::ChildOrder) -> String {
+        render(
+            Pretty::new().with_snapshot(true).with_child_order(order),
+            || {
+                trace_span!("request").in_scope(|| {
+                    info!("start");
+                    trace_span!("auth").in_scope(|| {});
+                    tracing::warn!("slow");
+                    trace_span!("query").in_scope(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                    });
+                    tracing::error!("failed");
+                });
+            },
+        )
+    }
+
+    #[test]
+    fn test_failed_span_is_red() {
+        #[tracing::instrument(err)]
+        fn charge(amount: u32) -> Result<(), String> {
+            Err("card declined".to_string())
+        }
+
+        let out = render(Pretty::new().with_snapshot(true).with_ansi(true), || {
+            trace_span!("checkout").in_scope(|| {
+                let _ = charge(3);
+            });
+        });
+
+        // Failures propagate up to the spans they happened in
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with("\x1b[31mcheckout\x1b[0m"), "{}", out);
+        assert!(lines[1].ends_with("\x1b[31mcharge\x1b[0m"), "{}", out);
+
+        let out = render(Pretty::new().with_snapshot(true).with_ansi(true), || {
+            trace_span!("checkout").in_scope(|| tracing::info!("paid"));
+        });
+        assert!(
+            out.lines().next().unwrap().ends_with(" checkout"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_children_longest_first() {
+        use tracing_forest::formatter::pretty::ChildOrder;
+
+        let names = ordered(ChildOrder::LongestFirst)
+            .lines()
+            .map(|line| line.rsplit([' ', ':']).next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["request", "query", "auth", "start", "slow", "failed"]
+        );
+    }
+
+    #[test]
+    fn test_children_by_level() {
+        use tracing_forest::formatter::pretty::ChildOrder;
+
+        let levels = ordered(ChildOrder::ByLevel)
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(levels, ["ERROR", "WARN", "INFO", "TRACE", "TRACE"]);
+        assert!(ordered(ChildOrder::Chronological)
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("start"));
+    }
+
+    #[test]
+    fn test_collapse_single_child_spans() {
+        let out = render(
+            Pretty::new().with_snapshot(true).with_collapse(true),
+            || {
+                trace_span!("http").in_scope(|| {
+                    trace_span!("auth").in_scope(|| {
+                        trace_span!("handler").in_scope(|| {
+                            info!("handled");
+                            trace_span!("db").in_scope(|| {});
+                        });
+                    });
+                });
+            },
+        );
+
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3, "{}", out);
+        assert!(lines[0].ends_with("http > auth > handler"), "{}", out);
+        assert!(lines[1].ends_with("[info]: handled"), "{}", out);
+        assert!(lines[2].ends_with("┕━ db"), "{}", out);
+    }
+
+    #[test]
+    fn test_fold_below_level() {
+        use tracing::Level;
+
+        let pretty = Pretty::new()
+            .with_snapshot(true)
+            .with_fold_below(Some(Level::INFO));
+        let out = render(pretty, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::debug_span!("db_query").in_scope(|| {
+                    for _ in 0..12 {
+                        tracing::debug!("row");
+                    }
+                    tracing::trace_span!("retry").in_scope(|| tracing::warn!("slow"));
+                    tracing::error!("failed");
+                });
+                tracing::debug_span!("cache").in_scope(|| tracing::debug!("hit"));
+                info!("done");
+            });
+        });
+
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert!(lines[0].ends_with("request"), "{}", out);
+        assert!(
+            lines[1].ends_with("▸ db_query [ 14 events, 1 ERROR, 1 WARN ]"),
+            "{}",
+            out
+        );
+        assert!(lines[2].ends_with("▸ cache [ 1 event ]"), "{}", out);
+        assert!(lines[3].ends_with("[info]: done"), "{}", out);
+    }
+
+    #[test]
+    fn test_icons() {
+        use tracing::Level;
+        use tracing_forest::formatter::{Formatter, Icons};
+        use tracing_forest::tag::{Severity, TagData};
+        use tracing_forest::tree::Tree;
+
+        let tag = TagData {
+            message: "security.access",
+            icon: '🔓',
+            severity: Severity::Info,
+        };
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::event(Level::WARN, "slow"));
+        tree.add_child(Tree::event(Level::INFO, "token accepted").with_tag(tag));
+        let lines = |icons| {
+            let pretty = Pretty::new().with_snapshot(true).with_icons(icons);
+            let out = pretty.render(&tree).unwrap();
+            out.lines().skip(1).map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let emoji = lines(Icons::Emoji);
+        assert!(emoji[0].ends_with("🚧 [warn]: slow"), "{:?}", emoji);
+        assert!(
+            emoji[1].ends_with("🔓 [security.access]: token accepted"),
+            "{:?}",
+            emoji
+        );
+
+        let text = lines(Icons::Text);
+        assert!(text[0].ends_with("━ [WRN] [warn]: slow"), "{:?}", text);
+        assert!(
+            text[1].ends_with("━ [SEC] [security.access]: token accepted"),
+            "{:?}",
+            text
+        );
+
+        let off = lines(Icons::Off);
+        assert!(off[0].ends_with("━ [warn]: slow"), "{:?}", off);
+        assert!(
+            off[1].ends_with("━ [security.access]: token accepted"),
+            "{:?}",
+            off
+        );
+    }
+
+    #[test]
+    fn test_builder_icons() {
+        use tracing_forest::formatter::Icons;
+
+        let out = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::builder()
+            .writer(move || SharedBuf(writer.clone()))
+            .icons(Icons::Text)
+            .blocking_layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || info!("hello"));
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(out.ends_with("INFO     [INF] [info]: hello\n"), "{}", out);
+    }
+
+    #[test]
+    fn test_pool_reuses_buffers() {
+        use tracing::Level;
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::tree::Tree;
+
+        let mut big = Tree::root("request");
+        let mut query = Tree::span(Level::DEBUG, "query");
+        query.add_child(Tree::event(
+            Level::DEBUG,
+            "fetched 3 rows from the users table",
+        ));
+        big.add_child(query);
+        big.add_child(Tree::event(Level::INFO, "handled"));
+        let mut small = Tree::root("ping");
+        small.add_child(Tree::event(Level::INFO, "pong"));
+
+        let pooled = Pretty::new().with_collapse(true);
+        let tiny = Pretty::new().with_collapse(true).with_pool_capacity(1);
+        let unpooled = Pretty::new().with_collapse(true).with_pool_size(0);
+        // Buffers left over from a bigger tree don't leak into a smaller one
+        for tree in [&big, &small, &big, &small] {
+            let expected = unpooled.render(tree).unwrap();
+            assert_eq!(pooled.render(tree).unwrap(), expected);
+            assert_eq!(tiny.render(tree).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_sibling_deltas() {
+        use chrono::{TimeZone, Utc};
+        use tracing::Level;
+        use tracing_forest::formatter::pretty::Timestamps;
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::tree::Tree;
+
+        let at = |millis: i64| {
+            Utc.timestamp_millis_opt(1_600_000_000_000 + millis)
+                .unwrap()
+        };
+        let tree = || {
+            let mut tree = Tree::root("request").with_timestamp(at(0));
+            tree.add_child(Tree::event(Level::INFO, "parsed").with_timestamp(at(5)));
+            tree.add_child(Tree::span(Level::INFO, "query").with_timestamp(at(17)));
+            tree.add_child(Tree::event(Level::INFO, "responded").with_timestamp(at(20)));
+            tree
+        };
+        let fmt = |timestamps| {
+            let mut out = Vec::new();
+            Pretty::new()
+                .with_timestamps(timestamps)
+                .fmt(tree(), &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let delta = fmt(Timestamps::Delta);
+        let lines: Vec<&str> = delta.lines().collect();
+        assert!(!lines[0].contains('+'), "{}", delta);
+        assert!(lines[1].contains(" +5.00ms    INFO"), "{}", delta);
+        assert!(lines[2].contains(" +12.0ms    INFO"), "{}", delta);
+        assert!(lines[3].contains(" +3.00ms    INFO"), "{}", delta);
+        assert!(!delta.contains("2020-09-13"), "{}", delta);
+
+        let both = fmt(Timestamps::Both);
+        assert!(
+            both.contains("2020-09-13T12:26:40.005+00:00    +5.00ms"),
+            "{}",
+            both
+        );
+    }
+
+    #[test]
+    fn test_output_budget() {
+        use tracing_forest::formatter::pretty::OutputBudget;
+
+        let import = || {
+            trace_span!("import").in_scope(|| {
+                info!("importing");
+                for row in 0..5 {
+                    tracing::debug!(row, "imported");
+                }
+                tracing::warn!("skipped a row");
+            });
+        };
+
+        let pretty = || Pretty::new().with_snapshot(true);
+        let out = render(pretty().with_budget(Some(OutputBudget::Lines(3))), import);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert!(lines[2].ends_with("imported | row: 0"), "{}", out);
+        assert_eq!(lines[3], "... 5 omitted [ WARN: 1 | DEBUG: 4 ]");
+
+        let out = render(pretty().with_budget(Some(OutputBudget::Bytes(10))), import);
+        assert_eq!(
+            out,
+            "... 8 omitted [ WARN: 1 | INFO: 1 | DEBUG: 5 | TRACE: 1 ]\n"
+        );
+
+        let out = render(pretty().with_budget(Some(OutputBudget::Lines(8))), import);
+        assert!(!out.contains("omitted"), "{}", out);
+
+        // Collapsed spans are one row, whether or not they fit
+        let nested = || {
+            tracing::info_span!("outer").in_scope(|| {
+                tracing::info_span!("inner").in_scope(|| {
+                    for row in 0..3 {
+                        tracing::debug!(row, "imported");
+                    }
+                })
+            });
+        };
+        let collapsed = |lines| {
+            pretty()
+                .with_collapse(true)
+                .with_budget(Some(OutputBudget::Lines(lines)))
+        };
+        let out = render(collapsed(1), nested);
+        assert_eq!(out, "INFO     outer > inner\n... 3 omitted [ DEBUG: 3 ]\n");
+        let out = render(collapsed(0), nested);
+        assert_eq!(out, "... 4 omitted [ INFO: 1 | DEBUG: 3 ]\n");
+    }
+
+    #[test]
+    fn test_self_time() {
+        use std::time::Duration;
+        use tracing::Level;
+        use tracing_forest::formatter::pretty::DurationFormat;
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::tree::Tree;
+
+        let mut tree = Tree::root("request").with_duration(Duration::from_millis(10));
+        tree.add_child(Tree::span(Level::INFO, "query").with_duration(Duration::from_millis(7)));
+
+        let mut out = Vec::new();
+        Pretty::new()
+            .with_self_time(true)
+            .with_duration_format(DurationFormat::Millis(1))
+            .fmt(tree, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(
+            out.contains("request [ 10.0ms | 3.0ms self | 30.000% / 100.000% ]"),
+            "{}",
+            out
+        );
+        assert!(out.contains("query [ 7.0ms | 70.000% ]"), "{}", out);
+    }
+
+    #[test]
+    fn test_duration_column() {
+        use std::time::Duration;
+        use tracing::Level;
+        use tracing_forest::formatter::pretty::DurationFormat;
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::tree::Tree;
+
+        let mut tree = Tree::root("request").with_duration(Duration::from_millis(10));
+        tree.add_child(Tree::span(Level::INFO, "query").with_duration(Duration::from_millis(5)));
+        tree.add_child(
+            Tree::span(Level::INFO, "a very long span name")
+                .with_duration(Duration::from_millis(5)),
+        );
+
+        // The uuid and timestamp columns depend on the enabled features
+        let format = |column| {
+            let mut out = Vec::new();
+            Pretty::new()
+                .with_duration_column(column)
+                .with_duration_format(DurationFormat::Millis(1))
+                .fmt(tree.clone(), &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let prefix = format(None).find("request").unwrap();
+        let out = format(Some(prefix + 40));
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0].chars().count(), prefix + 40, "{}", out);
+        assert!(
+            lines[0].ends_with("request   [ 10.0ms | 0.000% / 100.000% ]"),
+            "{}",
+            out
+        );
+        assert_eq!(lines[1].chars().count(), prefix + 40, "{}", out);
+        assert!(
+            lines[1].ends_with("query             [ 5.0ms | 50.000% ]"),
+            "{}",
+            out
+        );
+        assert!(
+            lines[2].ends_with("a very long span name [ 5.0ms | 50.000% ]"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_annotations_footer() {
+        use tracing_forest::formatter::pretty::OutputBudget;
+        use tracing_forest::formatter::Transformed;
+        use tracing_forest::layer::Tree;
+
+        fn annotate(mut tree: Tree) -> Tree {
+            tree.annotate("sampled 1:100").annotate_field("redacted", 3);
+            tree
+        }
+
+        let request = || {
+            tracing::info_span!("request").in_scope(|| {
+                info!("parsed");
+                info!("done");
+            });
+        };
+        let pretty = || Pretty::new().with_snapshot(true);
+
+        let out = render(Transformed::new(pretty(), annotate), request);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5, "{}", out);
+        assert_eq!(
+            lines[3..],
+            ["NOTE     sampled 1:100", "NOTE     redacted: 3"]
+        );
+
+        let budgeted = pretty().with_budget(Some(OutputBudget::Lines(1)));
+        let out = render(Transformed::new(budgeted, annotate), request);
+        assert!(
+            out.ends_with("omitted [ INFO: 2 ]\nNOTE     sampled 1:100\nNOTE     redacted: 3\n"),
+            "{}",
+            out
+        );
+    }
+}
+
+mod live_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_live_events_precede_tree() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let (tree_writer, live_writer) = (out.clone(), out.clone());
+
+        let subscriber =
+            tracing_forest::blocking(Pretty::new(), move || SharedBuf(tree_writer.clone()))
+                .into_layer()
+                .live(move || SharedBuf(live_writer.clone()))
+                .into_subscriber();
+
+        let id = uuid::Uuid::new_v4();
+        tracing::subscriber::with_default(subscriber, || {
+            uuid_trace_span!(id, "live").in_scope(|| {
+                info!("first");
+                info!("second");
+            });
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5, "{}", out);
+        assert!(lines[0].starts_with(&id.to_string()), "{}", out);
+        assert!(lines[0].ends_with("[info]: first"), "{}", out);
+        assert!(lines[1].ends_with("[info]: second"), "{}", out);
+        assert!(lines[2].contains("live ["), "{}", out);
+    }
+
+    #[test]
+    fn test_live_formatter() {
+        use tracing_forest::formatter::Icons;
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let live_writer = out.clone();
+
+        let subscriber = tracing_forest::blocking(Pretty::new(), std::io::sink)
+            .into_layer()
+            .live(move || SharedBuf(live_writer.clone()))
+            .live_formatter(Pretty::new().with_icons(Icons::Text).with_ansi(true))
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || info!("first"));
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(out.contains('\x1b'), "{:?}", out);
+        assert!(out.ends_with("[INF] [info]: first\n"), "{:?}", out);
+    }
+}
+
+mod builder_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::Level;
+    use tracing_forest::builder::Preset;
+
+    #[test]
+    fn test_preset_with_overrides() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+
+        let subscriber = tracing_forest::builder()
+            .preset(Preset::Test)
+            .writer(move || SharedBuf(writer.clone()))
+            .max_level(Level::INFO)
+            .blocking_layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            trace_span!("ignored").in_scope(|| {
+                info!("orphan");
+            });
+            tracing::info_span!("kept").in_scope(|| {
+                tracing::debug!("ignored");
+                info!("kept");
+            });
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            out,
+            "INFO     💬 [info]: orphan\nINFO     kept\nINFO     ┕━ 💬 [info]: kept\n"
+        );
+    }
+
+    #[test]
+    fn test_max_level_only_filters_tree_layer() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tracing_forest::layer::TreeKind;
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::Processor;
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+        use tracing_subscriber::Layer;
+
+        struct Counter(Arc<AtomicUsize>);
+
+        impl<S: tracing::Subscriber> Layer<S> for Counter {
+            fn on_event(&self, _: &tracing::Event<'_>, _: Context<'_, S>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let recent = RecentTrees::new(8);
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .max_level(Level::INFO)
+            .into_subscriber()
+            .with(Counter(count.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("outer").in_scope(|| {
+                tracing::debug!("hidden");
+                tracing::debug_span!("inner").in_scope(|| {
+                    tracing::debug!("hidden");
+                    info!("kept");
+                });
+            });
+        });
+
+        // Every event still reaches the other layer
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        let trees = recent.snapshot();
+        assert_eq!(trees.len(), 1);
+        match &trees[0].kind {
+            TreeKind::Span(span) => {
+                assert_eq!(span.name, "outer");
+                assert_eq!(span.children.len(), 1);
+                match &span.children[0].kind {
+                    TreeKind::Event(event) => assert_eq!(event.message, "kept"),
+                    TreeKind::Span(_) => panic!("expected an event"),
+                }
+            }
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_transform_before_formatting() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::layer::{Tree, TreeKind};
+
+        fn shout(mut tree: Tree) -> Tree {
+            if let TreeKind::Event(event) = &mut tree.kind {
+                event.message = event.message.to_uppercase().into();
+            }
+            tree.attrs.level = Level::WARN;
+            tree
+        }
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::builder()
+            .formatter(Pretty::new().with_snapshot(true))
+            .set_transform(shout)
+            .writer(move || SharedBuf(writer.clone()))
+            .blocking_layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || info!("hello"));
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out, "WARN     🚧 [warn]: HELLO\n");
+    }
+
+    #[test]
+    fn test_transform_survives_formatter_and_processor() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::layer::Tree;
+        use tracing_forest::processor::recent::RecentTrees;
+
+        fn warn(mut tree: Tree) -> Tree {
+            tree.attrs.level = Level::WARN;
+            tree
+        }
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::builder()
+            .set_transform(warn)
+            .preset(tracing_forest::builder::Preset::Test)
+            .formatter(Pretty::new().with_snapshot(true))
+            .writer(move || SharedBuf(writer.clone()))
+            .blocking_layer()
+            .into_subscriber();
+        tracing::subscriber::with_default(subscriber, || info!("hello"));
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out, "WARN     🚧 [warn]: hello\n");
+
+        let recent = RecentTrees::new(1);
+        let subscriber = tracing_forest::builder()
+            .set_transform(warn)
+            .set_processor(recent.clone())
+            .layer()
+            .into_subscriber();
+        tracing::subscriber::with_default(subscriber, || info!("hello"));
+        assert_eq!(recent.snapshot()[0].attrs.level, Level::WARN);
+    }
+
+    #[test]
+    fn test_transform_runs_on_worker() {
+        use tracing_forest::layer::Tree;
+        use tracing_forest::processor::recent::RecentTrees;
+
+        fn thread_name(tree: Tree) -> Tree {
+            let name = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            tree.with_field("thread", name)
+        }
+
+        let recent = RecentTrees::new(1);
+        let (layer, guard) = tracing_forest::builder()
+            .set_transform(thread_name)
+            .set_processor(recent.clone())
+            .worker_layer();
+        tracing::subscriber::with_default(layer.into_subscriber(), || info!("hello"));
+        drop(guard);
+
+        assert_eq!(recent.snapshot()[0].field("thread"), Some("tracing-forest"));
+    }
+
+    #[test]
+    fn test_transform_runs_on_dyn_worker() {
+        use tracing_forest::layer::Tree;
+        use tracing_forest::processor::recent::RecentTrees;
+
+        fn thread_name(tree: Tree) -> Tree {
+            let name = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            tree.with_field("thread", name)
+        }
+
+        let recent = RecentTrees::new(1);
+        let (layer, guard) = tracing_forest::builder()
+            .into_dyn()
+            .set_transform(thread_name)
+            .set_processor(recent.clone())
+            .worker_layer();
+        tracing::subscriber::with_default(layer.into_subscriber(), || info!("hello"));
+        drop(guard);
+
+        assert_eq!(recent.snapshot()[0].field("thread"), Some("tracing-forest"));
+    }
+
+    #[test]
+    fn test_dyn_builder_branches() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::formatter::summary::Summary;
+        use tracing_forest::processor::recent::RecentTrees;
+
+        let logged = |summary: bool, filter: bool| {
+            let out = Arc::new(Mutex::new(Vec::new()));
+            let writer = out.clone();
+            let copied = RecentTrees::new(10);
+            let mut builder = tracing_forest::builder()
+                .into_dyn()
+                .formatter(Pretty::new().with_snapshot(true))
+                .writer(move || SharedBuf(writer.clone()))
+                .tee(copied.clone());
+            if summary {
+                builder = builder.formatter(Summary::new().with_id(false));
+            }
+            if filter {
+                builder = builder.filter(|tree| tree.field("skip").is_none());
+            }
+            let subscriber = builder.max_level(Level::INFO).layer().into_subscriber();
+
+            tracing::subscriber::with_default(subscriber, || {
+                info!(skip = true, "skipped");
+                tracing::debug!("hidden");
+                info!("hello");
+            });
+            let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+            (out, copied.snapshot().len())
+        };
+
+        let (out, copied) = logged(false, true);
+        assert_eq!(out, "INFO     💬 [info]: hello\n");
+        // The filter was added after the tee, so it wraps the tee
+        assert_eq!(copied, 1);
+
+        let (out, copied) = logged(true, false);
+        assert_eq!(
+            out,
+            "INFO     skipped | events: 1 INFO\nINFO     hello | events: 1 INFO\n"
+        );
+        assert_eq!(copied, 2);
+    }
+
+    #[test]
+    fn test_dyn_builder_processor() {
+        use tracing_forest::processor::recent::RecentTrees;
+
+        let recent = RecentTrees::new(10);
+        let subscriber = tracing_forest::builder()
+            .into_dyn()
+            .set_processor(recent.clone())
+            .sample(0.5)
+            .layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..4 {
+                info!("hello");
+            }
+        });
+        assert_eq!(recent.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_set_ansi() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::formatter::Ansi;
+
+        let logged = |ansi| {
+            let out = Arc::new(Mutex::new(Vec::new()));
+            let writer = out.clone();
+            let subscriber = tracing_forest::builder()
+                .set_ansi(ansi)
+                .formatter(Pretty::new().with_snapshot(true).with_ansi(true))
+                .set_transform(|tree| tree)
+                .writer(move || SharedBuf(writer.clone()))
+                .blocking_layer()
+                .into_subscriber();
+
+            tracing::subscriber::with_default(subscriber, || info!("hello"));
+            let out = out.lock().unwrap();
+            String::from_utf8(out.clone()).unwrap()
+        };
+
+        assert_eq!(
+            logged(Ansi::Always),
+            "\x1b[32mINFO    \x1b[0m 💬 [info]: hello\n"
+        );
+        assert_eq!(logged(Ansi::Never), "INFO     💬 [info]: hello\n");
+        // Writers that aren't stdout or stderr are never terminals
+        if std::env::var_os("CLICOLOR_FORCE").is_none() {
+            assert_eq!(logged(Ansi::Auto), "INFO     💬 [info]: hello\n");
+        }
+    }
+
+    #[test]
+    fn test_detect_ansi() {
+        use std::ffi::OsStr;
+        use tracing_forest::formatter::Ansi;
+
+        // The environment is shared by every test, so it's passed in instead
+        let set = |value| Some(OsStr::new(value));
+        assert!(!Ansi::Auto.resolve(None, None, false));
+        assert!(Ansi::Auto.resolve(None, None, true));
+        assert!(Ansi::Always.resolve(None, None, false));
+        assert!(!Ansi::Never.resolve(None, None, true));
+
+        assert!(!Ansi::Auto.resolve(None, set("0"), false));
+        assert!(!Ansi::Auto.resolve(None, set(""), false));
+        assert!(Ansi::Auto.resolve(None, set("1"), false));
+
+        assert!(!Ansi::Auto.resolve(set("1"), set("1"), true));
+        assert!(Ansi::Auto.resolve(set(""), set("1"), false));
+        assert!(Ansi::Always.resolve(set("1"), None, false));
+
+        let file = std::fs::File::open(file!()).unwrap();
+        assert!(Ansi::Always.enabled_for(&file));
+        assert!(!Ansi::Never.enabled_for(&file));
+    }
+}
+
+mod alert_tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::Level;
+    use tracing_forest::layer::Tree;
+    use tracing_forest::processor::alert::Alert;
+    use tracing_forest::Processor;
+
+    struct Discard;
+
+    impl Processor for Discard {
+        fn process(&self, _tree: Tree) {}
+    }
+
+    #[test]
+    fn test_alerts_are_deduplicated() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = alerts.clone();
+
+        let processor = Alert::new(
+            Discard,
+            |tree| tree.attrs.level == Level::ERROR,
+            move |tree, suppressed| {
+                let key = tracing_forest::processor::alert::default_key(tree);
+                sink.lock().unwrap().push((key, suppressed));
+            },
+        )
+        .dedup_window(Duration::from_secs(60))
+        .rate_limit(2, Duration::from_secs(60));
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            for _ in 0..5 {
+                tracing::error!("disk full");
+            }
+            tracing::info!("not an alert");
+            tracing::error!("connection lost");
+            tracing::error!("rate limited");
+        });
+
+        assert_eq!(
+            *alerts.lock().unwrap(),
+            vec![
+                ("disk full".to_string(), 0),
+                ("connection lost".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_alerts_are_not_deduplicated() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = alerts.clone();
+
+        let processor = Alert::new(
+            Discard,
+            |tree| tree.attrs.level == Level::ERROR,
+            move |tree, suppressed| {
+                let key = tracing_forest::processor::alert::default_key(tree);
+                sink.lock().unwrap().push((key, suppressed));
+            },
+        )
+        .dedup_window(Duration::from_secs(60))
+        .rate_limit(1, Duration::from_millis(50));
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::error!("disk full");
+            tracing::error!("connection lost");
+            tracing::error!("connection lost");
+            std::thread::sleep(Duration::from_millis(100));
+            tracing::error!("connection lost");
+        });
+
+        assert_eq!(
+            *alerts.lock().unwrap(),
+            vec![
+                ("disk full".to_string(), 0),
+                ("connection lost".to_string(), 2),
+            ]
+        );
+    }
+}
+
+mod recent_tests {
+    use super::*;
+    use std::time::Duration;
+    use tracing_forest::layer::TreeKind;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_ring_buffer_keeps_latest() {
+        let recent = RecentTrees::new(2);
+
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            for i in 0..3 {
+                info!("{}", i);
+            }
+        });
+
+        let messages = recent
+            .snapshot()
+            .iter()
+            .map(|tree| match &tree.kind {
+                TreeKind::Event(event) => event.message.to_string(),
+                TreeKind::Span(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["1", "2"]);
+    }
+
+    #[test]
+    fn test_ring_buffer_max_age() {
+        let recent = RecentTrees::new(10).max_age(Duration::ZERO);
+
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            info!("expired immediately");
+        });
+
+        assert!(recent.snapshot().is_empty());
+    }
+}
+
+mod capture_tests {
+    use tracing::{debug, debug_span, info, info_span, warn};
+    use tracing_forest::assert_tree;
+
+    fn request() {
+        info_span!("request").in_scope(|| {
+            debug_span!("db").in_scope(|| {
+                warn!(rows = 0, table = "users", "empty result");
+            });
+            info!("done");
+        });
+    }
+
+    #[test]
+    fn test_assert_tree_matches() {
+        let trees = tracing_forest::capture().run(request);
+        assert_eq!(trees.len(), 1);
+
+        assert_tree!(trees[0], span INFO "request" [
+            span DEBUG "d?" [
+                event WARN "empty*" (rows = "0", table = "us*"),
+            ],
+            event INFO "done",
+        ]);
+        assert_tree!(trees[0], span "req*" [span "db" [..], ..]);
+    }
+
+    #[test]
+    fn test_expect_levels() {
+        use tracing::Level;
+        use tracing_forest::capture::Expect;
+
+        let trees = tracing_forest::capture().run(request);
+        trees.expect_no_errors();
+        trees.expect_levels_at_most(Level::WARN);
+        trees[0].expect_levels_at_most(Level::WARN);
+
+        let panic = std::panic::catch_unwind(|| trees.expect_levels_at_most(Level::INFO));
+        let message = *panic.unwrap_err().downcast::<String>().unwrap();
+        assert!(
+            message.starts_with("expected levels at most INFO, but found WARN:\n"),
+            "{}",
+            message
+        );
+        assert!(message.contains("db\n"), "{}", message);
+        assert!(
+            message.contains("🚧 [warn]: empty result | rows: 0"),
+            "{}",
+            message
+        );
+
+        let trees = tracing_forest::capture().run(|| tracing::error!("failed"));
+        let panic = std::panic::catch_unwind(|| trees.expect_no_errors());
+        assert!(panic.is_err());
+    }
+
+    #[test]
+    fn test_run_with_stats() {
+        use tracing::Level;
+        use tracing_forest::TagRegistry;
+
+        #[derive(tracing_forest::Tag)]
+        enum AppTag {
+            #[tag(info: "db.query")]
+            Query,
+        }
+
+        let mut registry = TagRegistry::new();
+        registry.insert("app::db", AppTag::Query);
+        let (trees, stats) = tracing_forest::capture()
+            .tag_registry(registry)
+            .run_with_stats(|| {
+                request();
+                info!(target: "app::db", "connected");
+                info_span!("batch").in_scope(|| {
+                    info!(target: "app::db", "inserted");
+                    info!(target: "app::db", "inserted");
+                });
+            });
+
+        assert_eq!(stats.trees, trees.len());
+        assert_eq!(stats.trees, 3);
+        assert_eq!(stats.spans, 3);
+        assert_eq!(stats.events, 5);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.level(Level::WARN), 1);
+        assert_eq!(stats.level(Level::INFO), 4);
+        assert_eq!(stats.level(Level::ERROR), 0);
+        assert_eq!(stats.tag("db.query"), 3);
+        assert_eq!(stats.tag("security"), 0);
+        assert_eq!(stats.tags().collect::<Vec<_>>(), [("db.query", 3)]);
+
+        let (trees, stats) = tracing_forest::capture().run_with_stats(|| {});
+        assert!(trees.is_empty());
+        assert_eq!(stats, Default::default());
+    }
+
+    #[test]
+    fn test_assert_tree_reports_mismatch() {
+        let trees = tracing_forest::capture().run(request);
+
+        let panic = std::panic::catch_unwind(|| {
+            assert_tree!(trees[0], span "request" [
+                span "db" [event ERROR "empty*"],
+                event INFO "done",
+            ]);
+        })
+        .unwrap_err();
+
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("at: <root> > request > [0] > db > [0]"));
+        assert!(message.contains("expected: event ERROR \"empty*\""));
+        assert!(message.contains("found: event WARN \"empty result\""));
+    }
+
+    #[test]
+    fn test_assert_tree_child_count() {
+        let trees = tracing_forest::capture().run(request);
+
+        let pattern = tracing_forest::__tree_pattern!(span "request" [span "db"]);
+        let mismatch = pattern.matches(&trees[0]).unwrap_err();
+        assert_eq!(mismatch.path, ["<root>", "request", "[0]", "db"]);
+        assert_eq!(mismatch.expected, "0 children");
+    }
+
+    #[tokio::test]
+    async fn test_capture_async() {
+        let trees = tracing_forest::capture()
+            .run_async(async {
+                info!("from a future");
+            })
+            .await;
+
+        assert_tree!(trees[0], event INFO "from a future");
+    }
+
+    #[test]
+    fn test_capture_env_filter() {
+        let filter = tracing_subscriber::EnvFilter::new("test::capture_tests=info");
+        let trees = tracing_forest::capture().set_filter(filter).run(|| {
+            request();
+            info!(target: "dependency", "incidental");
+        });
+
+        assert_eq!(trees.len(), 1);
+        assert_tree!(trees[0], span INFO "request" [
+            event WARN "empty result",
+            event INFO "done",
+        ]);
+    }
+
+    #[test]
+    fn test_capture_allow_targets() {
+        let trees = tracing_forest::capture()
+            .allow_targets(["dependency::client"])
+            .set_filter(tracing::level_filters::LevelFilter::INFO)
+            .run(|| {
+                request();
+                info!(target: "dependency::client::pool", "checked out");
+                debug!(target: "dependency::client", "too verbose");
+                info!(target: "dependency::server", "other module");
+            });
+
+        assert_eq!(trees.len(), 1);
+        assert_tree!(trees[0], event INFO "checked out");
+    }
+}
+
+mod span_trace_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::{error, info_span};
+    use tracing_error::{ErrorLayer, TracedError};
+    use tracing_forest::formatter::{json::Json, pretty::Pretty, Formatter};
+    use tracing_forest::Processor;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Debug)]
+    struct DbError;
+
+    impl std::fmt::Display for DbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("connection reset")
+        }
+    }
+
+    impl std::error::Error for DbError {}
+
+    fn render_with_errors<F>(formatter: F) -> String
+    where
+        F: 'static + Formatter + Send + Sync,
+    {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::blocking(formatter, move || SharedBuf(writer.clone()))
+            .into_layer()
+            .into_subscriber()
+            .with(ErrorLayer::default());
+
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!("request", user = "alice").in_scope(|| {
+                let err = info_span!("query").in_scope(|| TracedError::from(DbError));
+                error!(
+                    error = &err as &(dyn std::error::Error + 'static),
+                    "query failed"
+                );
+            });
+        });
+
+        let out = out.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_pretty_span_trace() {
+        let out = render_with_errors(Pretty::new().with_snapshot(true));
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines[1],
+            "ERROR    ┕━ 🚨 [error]: query failed | error: connection reset"
+        );
+        assert!(lines[2].starts_with("ERROR       ┝━ test::span_trace_tests::query at "));
+        assert!(lines[3]
+            .starts_with("ERROR       ┕━ test::span_trace_tests::request{user=\"alice\"} at "));
+    }
+
+    #[test]
+    fn test_json_span_trace() {
+        let out = render_with_errors(Json::new(true));
+        let tree: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        let event = &tree["kind"]["Span"]["children"][0]["kind"]["Event"];
+
+        assert_eq!(event["span_trace"][0]["name"], "query");
+        assert_eq!(event["span_trace"][1]["name"], "request");
+        assert_eq!(event["span_trace"][1]["fields"], "user=\"alice\"");
+    }
+}
+
+mod tree_tests {
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use tracing::Level;
+    use tracing_forest::tag::{Severity, TagData};
+    use tracing_forest::tree::{Tree, TreeAttrs, TreeEvent, TreeKind, TreeSpan};
+
+    fn attrs(level: Level) -> TreeAttrs {
+        TreeAttrs {
+            uuid: uuid::Uuid::nil(),
+            timestamp: chrono::Utc::now(),
+            level,
+        }
+    }
+
+    #[test]
+    fn test_construct_tree() {
+        let tag = TagData {
+            message: "audit.login",
+            icon: '🔑',
+            severity: Severity::Warn,
+        };
+        let mut event = TreeEvent::new(Cow::Borrowed("user logged in"));
+        event.tag = Some(tag);
+        event.target = "tree_tests";
+        let event = Tree::new(attrs(Level::INFO), event);
+        let mut span = TreeSpan::new("login");
+        span.duration_total = Duration::from_millis(2);
+        span.children.push(event);
+        let tree = Tree::new(attrs(Level::INFO), span);
+
+        assert_eq!(tree.tags(), [tag]);
+        assert_eq!(tree.max_tag_severity(), Some(Severity::Warn));
+    }
+
+    #[test]
+    fn test_span_self_time() {
+        let mut tree = Tree::root("request").with_duration(Duration::from_millis(10));
+        tree.add_child(Tree::span(Level::INFO, "query").with_duration(Duration::from_millis(7)));
+
+        let span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => unreachable!(),
+        };
+        assert_eq!(span.duration_self(), Duration::from_millis(3));
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["kind"]["Span"]["nanos_self"], 3_000_000);
+        assert_eq!(
+            json["kind"]["Span"]["children"][0]["kind"]["Span"]["nanos_self"],
+            7_000_000
+        );
+    }
+
+    #[test]
+    fn test_walk() {
+        use tracing_forest::tree::{walk_span, TreeVisitor};
+
+        /// Records the order it's called in, skipping spans named `skip` and
+        /// stopping at events named `stop`.
+        #[derive(Default)]
+        struct Order(Vec<String>);
+
+        impl Order {
+            fn push(&mut self, step: impl Into<String>) -> Result<(), &'static str> {
+                self.0.push(step.into());
+                Ok(())
+            }
+        }
+
+        impl TreeVisitor for Order {
+            type Error = &'static str;
+
+            fn visit_span(&mut self, tree: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+                match span.name {
+                    "skip" => self.push("skipped"),
+                    _ => walk_span(self, tree, span),
+                }
+            }
+
+            fn enter_span(&mut self, _: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+                self.push(format!("enter {}", span.name))
+            }
+
+            fn exit_span(&mut self, _: &Tree, span: &TreeSpan) -> Result<(), Self::Error> {
+                self.push(format!("exit {}", span.name))
+            }
+
+            fn visit_event(&mut self, _: &Tree, event: &TreeEvent) -> Result<(), Self::Error> {
+                match event.message.as_ref() {
+                    "stop" => Err("stopped"),
+                    message => self.push(message),
+                }
+            }
+        }
+
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::span(Level::INFO, "db"))
+            .add_child(Tree::event(Level::INFO, "query"));
+        tree.add_child(Tree::span(Level::INFO, "skip"))
+            .add_child(Tree::event(Level::INFO, "hidden"));
+        tree.add_child(Tree::event(Level::INFO, "done"));
+
+        let mut order = Order::default();
+        assert_eq!(tree.walk(&mut order), Ok(()));
+        assert_eq!(
+            order.0,
+            [
+                "enter request",
+                "enter db",
+                "query",
+                "exit db",
+                "skipped",
+                "done",
+                "exit request"
+            ]
+        );
+
+        tree.add_child(Tree::event(Level::INFO, "stop"));
+        tree.add_child(Tree::event(Level::INFO, "after"));
+        let mut order = Order::default();
+        assert_eq!(tree.walk(&mut order), Err("stopped"));
+        assert_eq!(order.0.last().map(String::as_str), Some("done"));
+    }
+
+    #[test]
+    fn test_failed_spans() {
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::span(Level::INFO, "auth"))
+            .add_child(Tree::event(Level::WARN, "retrying").with_field("error", "timeout"));
+        assert!(!tree.has_failed());
+
+        let mut charge = Tree::span(Level::INFO, "charge");
+        charge.add_child(Tree::event(Level::ERROR, "").with_field("error", "card declined"));
+        tree.add_child(charge);
+        assert!(tree.has_failed());
+
+        let span = match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => unreachable!(),
+        };
+        // The failure of `charge` propagates up to `request`
+        assert!(span.is_failed());
+        assert!(span.children.iter().map(Tree::has_failed).eq([false, true]));
+
+        let failed = Tree::root("request").with_field("error", "timeout");
+        assert!(failed.has_failed());
+        assert!(!Tree::event(Level::ERROR, "")
+            .with_field("error", "x")
+            .has_failed());
+
+        // Spans collected by the layer know whether they failed once closed
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info_span!("auth").in_scope(|| tracing::info!("ok"));
+                tracing::info_span!("charge").in_scope(|| {
+                    tracing::error!(error = "card declined", "charge failed");
+                });
+            });
+        });
+        let span = match &trees[0].kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => unreachable!(),
+        };
+        assert!(span.is_failed());
+        assert!(span.children.iter().map(Tree::has_failed).eq([false, true]));
+    }
+
+    #[test]
+    fn test_failed_spans_with_renamed_error() {
+        use tracing_forest::layer::FieldRules;
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::Processor;
+
+        let recent = RecentTrees::new(2);
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .field_rules(FieldRules::new().rename("error", "err"))
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("charge").in_scope(|| tracing::error!(error = "boom"));
+            tracing::info_span!("refund", error = "boom").in_scope(|| {});
+        });
+
+        let trees = recent.snapshot();
+        assert_eq!(trees.len(), 2);
+        assert!(trees.iter().all(|tree| tree.has_failed()));
+        assert_eq!(trees[1].field("err"), Some("\"boom\""));
+    }
+
+    #[test]
+    fn test_annotations() {
+        use tracing_forest::layer::Annotation;
+
+        let mut tree = Tree::root("request");
+        let json = serde_json::to_value(&tree).unwrap();
+        assert!(json.get("annotations").is_none());
+
+        tree.annotate("sampled 1:100").annotate_field("redacted", 3);
+        assert_eq!(
+            tree.annotations,
+            [
+                Annotation::Note("sampled 1:100".into()),
+                Annotation::Field("redacted".into(), "3".to_string()),
+            ]
+        );
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(
+            json["annotations"],
+            serde_json::json!(["sampled 1:100", { "redacted": "3" }])
+        );
+
+        let parsed = tracing_forest::bridge::parse_tree(&json.to_string()).unwrap();
+        assert_eq!(parsed.annotations, tree.annotations);
+    }
+
+    #[test]
+    fn test_event_metadata() {
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::Processor;
+
+        let recent = RecentTrees::new(10);
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            for _ in 0..2 {
+                tracing::info!(target: "billing", "charged");
+            }
+        });
+
+        let trees = recent.snapshot();
+        let events = trees
+            .iter()
+            .map(|tree| match &tree.kind {
+                TreeKind::Event(event) => event,
+                TreeKind::Span(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        let event = events[0];
+        assert_eq!(event.target, "billing");
+        assert_eq!(event.module_path(), Some(module_path!()));
+        assert!(event.name().unwrap().starts_with("event tests/test.rs:"));
+        assert_eq!(event.metadata.unwrap().line(), event.line);
+        assert_eq!(event.callsite(), events[1].callsite());
+
+        let built = Tree::event(Level::INFO, "built");
+        match &built.kind {
+            TreeKind::Event(event) => {
+                assert!(event.metadata.is_none());
+                assert_eq!(event.callsite(), None);
+            }
+            TreeKind::Span(_) => unreachable!(),
+        }
+    }
+}
+
+mod sharded_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::processor::sync::async_spawn_sharded;
+    use tracing_forest::Processor;
+
+    #[tokio::test]
+    async fn test_sharded_workers_process_everything() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (processor, handle) = async_spawn_sharded(
+            Pretty::new().with_snapshot(true),
+            move || SharedBuf(writer.clone()),
+            4,
+        );
+
+        let guard = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+        for i in 0..20 {
+            info!("{}", i);
+        }
+        drop(guard);
+        handle.await.unwrap();
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let mut messages = out
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().parse::<u32>().unwrap())
+            .collect::<Vec<_>>();
+        messages.sort_unstable();
+        assert_eq!(messages, (0..20).collect::<Vec<_>>());
+    }
+}
+
+mod latency_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::formatter::{json::Json, pretty::Pretty};
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_json_latency_field() {
+        let out = render(Json::new(true).with_latency(true), || {
+            info!("hello");
+        });
+
+        let value: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!(value["kind"]["Event"]["message"], "hello");
+        assert!(value["latency"]["queued_nanos"].is_null());
+        assert!(value["latency"]["formatting_nanos"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_pretty_latency_footer() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (processor, handle) =
+            tracing_forest::async_spawn(Pretty::new().with_latency(true), move || {
+                SharedBuf(writer.clone())
+            });
+
+        let guard = tracing::subscriber::set_default(processor.into_layer().into_subscriber());
+        info!("hello");
+        drop(guard);
+        handle.await.unwrap();
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with("💬 [info]: hello"), "{}", out);
+        assert!(lines[1].starts_with("latency [ queued "));
+        assert!(lines[1].contains(" | formatted "));
+    }
+
+    #[test]
+    fn test_no_latency_footer_in_snapshots() {
+        let out = render(Pretty::new().with_snapshot(true).with_latency(true), || {
+            info!("hello");
+        });
+        assert_eq!(out, "INFO     💬 [info]: hello\n");
+    }
+
+    #[test]
+    fn test_json_latency_keeps_layout() {
+        let pretty = render(Json::new(false).with_latency(true), || {
+            tracing::info_span!("request").in_scope(|| info!("hello"));
+        });
+        let value: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert!(value["latency"]["formatting_nanos"].is_u64());
+        assert!(pretty.ends_with("}\n"), "{}", pretty);
+
+        // Keys are kept in the order they were written, with latency last
+        let compact = render(Json::new(true).with_latency(true), || info!("hello"));
+        let keys = [
+            "\"uuid\"",
+            "\"timestamp\"",
+            "\"level\"",
+            "\"kind\"",
+            "\"latency\"",
+        ];
+        let offsets = keys.iter().map(|key| compact.find(key).unwrap());
+        assert!(offsets
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|pair| pair[0] < pair[1]));
+        assert!(compact.ends_with("}}\n"), "{}", compact);
+    }
+
+    #[test]
+    fn test_no_latency_without_output() {
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::processor::Latency;
+
+        let latency = Latency {
+            queued: None,
+            formatting: std::time::Duration::ZERO,
+        };
+        let mut empty = Vec::new();
+        Json::new(true)
+            .with_latency(true)
+            .fmt_latency(&latency, &mut empty)
+            .unwrap();
+        assert!(empty.is_empty());
+
+        let mut text = b"not json\n".to_vec();
+        let result = Json::new(true)
+            .with_latency(true)
+            .fmt_latency(&latency, &mut text);
+        assert!(result.is_err());
+    }
+}
+
+mod markdown_tests {
+    use super::*;
+    use tracing_forest::formatter::markdown::{Markdown, MarkdownStyle};
+
+    fn request() {
+        tracing::info_span!("request").in_scope(|| {
+            tracing::warn!(user = "alice", "slow *query*");
+        });
+    }
+
+    #[test]
+    fn test_markdown_list_with_details() {
+        let out = render(
+            Markdown::new(MarkdownStyle::List).with_details(true),
+            request,
+        );
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "<details>");
+        assert!(lines[1].starts_with("<summary><b>INFO</b> <code>request</code> ("));
+        assert_eq!(lines[2], "");
+        assert!(lines[3].starts_with("- **INFO** `request` ("));
+        assert_eq!(
+            lines[4],
+            "  - **WARN** 🚧 [warn]: slow \\*query\\* | user: \"alice\""
+        );
+        assert_eq!(lines[5..], ["", "</details>", ""]);
+    }
+
+    #[test]
+    fn test_markdown_code_blocks() {
+        let fenced = render(Markdown::new(MarkdownStyle::Fenced), request);
+        assert!(fenced.starts_with("```log\nINFO     request\n"));
+        assert!(fenced.ends_with("```\n\n"));
+
+        let indented = render(Markdown::new(MarkdownStyle::Indented), request);
+        assert!(indented.starts_with("    INFO     request\n    WARN     ┕━ 🚧"));
+    }
+}
+
+mod dot_tests {
+    use super::*;
+    use tracing_forest::formatter::dot::Dot;
+
+    #[test]
+    fn test_dot_graph() {
+        let out = render(Dot::new(), || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info_span!("db").in_scope(|| {
+                    tracing::error!("said \"no\"");
+                });
+                info!("done");
+            });
+        });
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "digraph forest {");
+        assert!(lines[2].starts_with("    n0 [shape=box, label=\"request\\nINFO | "));
+        assert!(lines[3].starts_with("    n1 [shape=box, label=\"db\\nINFO | "));
+        assert_eq!(
+            lines[4],
+            "    n2 [shape=note, label=\"🚨 [error]: said \\\"no\\\"\\nERROR\", color=red];"
+        );
+        assert_eq!(lines[5], "    n1 -> n2;");
+        assert_eq!(lines[6], "    n0 -> n1;");
+        assert_eq!(
+            lines[7],
+            "    n3 [shape=note, label=\"💬 [info]: done\\nINFO\"];"
+        );
+        assert_eq!(lines[8], "    n0 -> n3;");
+        assert_eq!(lines[9], "}");
+    }
+}
+
+mod summary_formatter_tests {
+    use tracing::Level;
+    use tracing_forest::formatter::summary::Summary;
+    use tracing_forest::formatter::Formatter;
+    use tracing_forest::tag::{Severity, TagData};
+    use tracing_forest::tree::Tree;
+
+    #[test]
+    fn test_summary_line() {
+        let tag = TagData {
+            message: "security.access",
+            icon: '🔓',
+            severity: Severity::Info,
+        };
+        let mut query = Tree::span(Level::DEBUG, "query");
+        query.add_child(Tree::event(Level::WARN, "slow"));
+        query.add_child(Tree::event(Level::INFO, "fetched").with_tag(tag));
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::event(Level::INFO, "received").with_tag(tag));
+        tree.add_child(query);
+        tree.add_child(Tree::event(Level::INFO, "responded"));
+
+        let out = Summary::new().with_id(false).render(&tree).unwrap();
+        assert!(out.starts_with("INFO     request [ "), "{}", out);
+        assert!(
+            out.ends_with(" ] | events: 1 WARN, 3 INFO | tags: security.access\n"),
+            "{}",
+            out
+        );
+
+        let out = Summary::new().render(&tree).unwrap();
+        assert!(
+            out.ends_with(&format!(" | id: {}\n", tree.attrs.uuid)),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_summary_of_event() {
+        let tree = Tree::event(Level::ERROR, "disk full");
+        let out = Summary::new().with_id(false).render(&tree).unwrap();
+        assert_eq!(out, "ERROR    disk full | events: 1 ERROR\n");
+
+        let out = Summary::new()
+            .with_id(false)
+            .render(&Tree::root("idle"))
+            .unwrap();
+        assert!(out.ends_with(" ] | events: 0\n"), "{}", out);
+    }
+}
+
+mod ascii_formatter_tests {
+    use std::io;
+    use std::time::Duration;
+    use tracing::Level;
+    use tracing_forest::formatter::ascii::Ascii;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::formatter::Formatter;
+    use tracing_forest::tree::Tree;
+
+    #[test]
+    fn test_pretty_is_ascii() {
+        let mut tree = Tree::root("request").with_duration(Duration::from_micros(12));
+        tree.add_child(Tree::span(Level::DEBUG, "db"))
+            .add_child(Tree::event(Level::INFO, "query").with_field("table", "\"café\""));
+        tree.add_child(Tree::event(Level::WARN, "slow 🐌"));
+
+        let out = Ascii::new(Pretty::new().with_snapshot(true))
+            .render(&tree)
+            .unwrap();
+        assert!(out.is_ascii(), "{}", out);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "INFO     request", "{}", out);
+        assert_eq!(lines[1], "DEBUG    `- db", "{}", out);
+        assert!(
+            lines[2].ends_with("`- [INF] [info]: query | table: \"caf\\u{e9}\""),
+            "{}",
+            out
+        );
+        assert!(
+            lines[3].ends_with("[WRN] [warn]: slow \\u{1f40c}"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_escaped() {
+        struct Raw;
+
+        impl Formatter for Raw {
+            fn fmt(&self, _: Tree, writer: &mut Vec<u8>) -> io::Result<()> {
+                writer.extend_from_slice(b"ok \xff 12\xc2\xb5s \xe2\x94");
+                Ok(())
+            }
+        }
+
+        let out = Ascii::new(Raw).render(&Tree::root("raw")).unwrap();
+        assert_eq!(out, "ok \\xff 12us \\xe2\\x94");
+    }
+}
+
+mod mermaid_tests {
+    use super::*;
+    use tracing_forest::formatter::mermaid::Mermaid;
+
+    #[test]
+    fn test_mermaid_gantt() {
+        let out = render(Mermaid::new(), || {
+            tracing::info_span!("server::request").in_scope(|| {
+                std::thread::sleep(std::time::Duration::from_millis(2));
+                tracing::info_span!("db").in_scope(|| {
+                    std::thread::sleep(std::time::Duration::from_millis(3));
+                    info!("ignored");
+                });
+            });
+        });
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines[..5],
+            [
+                "```mermaid",
+                "gantt",
+                "    title server∶∶request",
+                "    dateFormat x",
+                "    axisFormat %S.%L"
+            ]
+        );
+        assert!(lines[5].starts_with("    server∶∶request ("));
+        assert!(lines[5].contains(") :n0, 0, "));
+        assert!(lines[6].starts_with("    db ("));
+        let start = lines[6]
+            .split(":n1, ")
+            .nth(1)
+            .unwrap()
+            .split(',')
+            .next()
+            .unwrap();
+        assert!(start.parse::<u64>().unwrap() >= 2);
+        assert_eq!(lines[7..], ["```"]);
+    }
+
+    #[test]
+    fn test_mermaid_skips_events() {
+        assert_eq!(render(Mermaid::new(), || info!("no spans")), "");
+    }
+}
+
+mod sqlite_tests {
+    use super::*;
+    use tracing_forest::processor::sqlite::sqlite;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_sqlite_is_queryable() {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-forest-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("traces.db");
+        let _ = std::fs::remove_file(&path);
+
+        let processor = sqlite(&path).unwrap().batch_size(2);
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            for i in 0..3 {
+                tracing::info_span!("request", i).in_scope(|| {
+                    tracing::info_span!("db").in_scope(|| {
+                        info!(user = "alice", "query {}", i);
+                    });
+                });
+            }
+        });
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+
+        assert_eq!(count("SELECT COUNT(*) FROM trees"), 3);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM spans WHERE parent_id IS NULL"),
+            3
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM spans AS child JOIN spans AS parent ON child.parent_id = parent.id
+                 WHERE child.name = 'db' AND parent.name = 'request'"
+            ),
+            3
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM events JOIN fields ON fields.event_id = events.id
+                 WHERE fields.key = 'user' AND fields.value = '\"alice\"' AND events.message = 'query 2'"
+            ),
+            1
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+mod bulk_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_forest::processor::bulk::{bulk, BulkError, BulkSink, EventRow};
+    use tracing_forest::Processor;
+
+    #[derive(Clone, Default)]
+    struct Mock {
+        batches: Arc<Mutex<Vec<Vec<EventRow>>>>,
+        failing: Arc<Mutex<bool>>,
+        attempts: Arc<Mutex<usize>>,
+    }
+
+    impl BulkSink for Mock {
+        fn insert(&mut self, rows: &[EventRow]) -> Result<(), BulkError> {
+            *self.attempts.lock().unwrap() += 1;
+            if *self.failing.lock().unwrap() {
+                return Err("database unavailable".into());
+            }
+            self.batches.lock().unwrap().push(rows.to_vec());
+            Ok(())
+        }
+    }
+
+    fn log(processor: &impl Processor, count: usize) {
+        for i in 0..count {
+            let tree = tracing_forest::capture().run(|| {
+                tracing::info_span!("request").in_scope(|| {
+                    tracing::info_span!("db").in_scope(|| {
+                        info!(user = "alice", "query {}", i);
+                    });
+                });
+            });
+            tree.into_iter().for_each(|tree| processor.process(tree));
+        }
+    }
+
+    /// Waits for the thread of a processor to do what `done` checks for.
+    fn wait_for(done: impl Fn() -> bool) {
+        let start = std::time::Instant::now();
+        while !done() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn messages(mock: &Mock) -> Vec<String> {
+        let batches = mock.batches.lock().unwrap();
+        batches
+            .iter()
+            .flatten()
+            .map(|row| row.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_bulk_batches_and_flattens() {
+        let mock = Mock::default();
+        let processor = bulk(mock.clone()).batch_size(2);
+        log(&processor, 3);
+
+        wait_for(|| !mock.batches.lock().unwrap().is_empty());
+        let batches = mock.batches.lock().unwrap().clone();
+        assert_eq!(batches[0].len(), 2);
+        let row = &batches[0][1];
+        assert_eq!(row.span_path, "request > db");
+        assert_eq!(row.message, "query 1");
+        assert_eq!(row.level, "INFO");
+        assert_eq!(row.fields, vec![("user", "\"alice\"".to_string())]);
+
+        drop(processor);
+        assert_eq!(messages(&mock), vec!["query 0", "query 1", "query 2"]);
+    }
+
+    #[test]
+    fn test_bulk_flushes_on_interval() {
+        let mock = Mock::default();
+        let processor = bulk(mock.clone())
+            .batch_size(100)
+            .flush_interval(Duration::from_millis(20));
+        log(&processor, 3);
+
+        wait_for(|| !mock.batches.lock().unwrap().is_empty());
+        assert_eq!(mock.batches.lock().unwrap()[0].len(), 3);
+    }
+
+    #[test]
+    fn test_bulk_backs_off_after_failure() {
+        let mock = Mock::default();
+        *mock.failing.lock().unwrap() = true;
+        let processor = bulk(mock.clone())
+            .batch_size(1)
+            .backoff(Duration::from_secs(60), Duration::from_secs(60));
+
+        log(&processor, 3);
+        wait_for(|| *mock.attempts.lock().unwrap() > 0);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(*mock.attempts.lock().unwrap(), 1);
+
+        *mock.failing.lock().unwrap() = false;
+        processor.flush().unwrap();
+        assert_eq!(messages(&mock), vec!["query 0", "query 1", "query 2"]);
+    }
+
+    #[test]
+    fn test_bulk_drops_oldest_past_max_pending() {
+        let mock = Mock::default();
+        *mock.failing.lock().unwrap() = true;
+        let processor = bulk(mock.clone())
+            .batch_size(1)
+            .max_pending(2)
+            .backoff(Duration::from_secs(60), Duration::from_secs(60));
+
+        log(&processor, 5);
+        assert!(processor.flush().is_err());
+        assert_eq!(processor.dropped(), 3);
+
+        *mock.failing.lock().unwrap() = false;
+        processor.flush().unwrap();
+        assert_eq!(messages(&mock), vec!["query 3", "query 4"]);
+    }
+
+    #[test]
+    fn test_seq_posts_clef() {
+        use tracing::Level;
+        use tracing_forest::processor::bulk::Seq;
+
+        let (url, server) = http_server();
+        let processor = bulk(Seq::new(&url).api_key("secret")).batch_size(2);
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("request").in_scope(|| {
+                tracing_forest::templated!(Level::WARN, "retrying in {}ms", 250);
+                info!(user = "alice", "@t" = 1, "done");
+            });
+        });
+        trees.into_iter().for_each(|tree| processor.process(tree));
+
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "POST /api/events/raw HTTP/1.1");
+        assert!(head.iter().any(|line| line == "X-Seq-ApiKey: secret"));
+        assert!(head
+            .iter()
+            .any(|line| line == "Content-Type: application/vnd.serilog.clef"));
+
+        let events = body
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["@l"], "Warning");
+        assert_eq!(events[0]["@m"], "retrying in 250ms");
+        assert_eq!(events[0]["@mt"], "retrying in {}ms");
+        assert_eq!(events[0]["span_path"], "request");
+        assert!(events[0]["@t"].is_string());
+        assert_eq!(events[1]["@l"], "Information");
+        assert!(events[1].get("@mt").is_none());
+        assert_eq!(events[1]["user"], "\"alice\"");
+        assert_eq!(events[1]["@@t"], "1");
+    }
+}
+
+#[cfg(unix)]
+mod socket_tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::net::{UnixDatagram, UnixListener};
+    use std::time::Duration;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::processor::socket::{unix_datagram, unix_stream};
+    use tracing_forest::Processor;
+
+    fn dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-forest-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn records(mut bytes: &[u8]) -> Vec<String> {
+        let mut records = Vec::new();
+        while bytes.len() >= 4 {
+            let len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+            records.push(String::from_utf8(bytes[4..4 + len].to_vec()).unwrap());
+            bytes = &bytes[4 + len..];
+        }
+        records
+    }
+
+    #[test]
+    fn test_stream_spills_until_collector_is_up() {
+        let dir = dir("socket-stream");
+        let path = dir.join("collector.sock");
+        let spill = dir.join("spill.log");
+        let trees = tracing_forest::capture().run(|| {
+            info!("first");
+            info!("second");
+            info!("third");
+        });
+        let mut trees = trees.into_iter();
+
+        // Trees that are still queued when the processor is dropped are spilled
+        let processor = unix_stream(Pretty::new(), &path).spill(&spill).unwrap();
+        processor.process(trees.next().unwrap());
+        processor.process(trees.next().unwrap());
+        assert!(processor.flush().is_err());
+        drop(processor);
+        assert_eq!(records(&std::fs::read(&spill).unwrap()).len(), 2);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let processor = unix_stream(Pretty::new(), &path).spill(&spill).unwrap();
+        processor.process(trees.next().unwrap());
+        processor.flush().unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut bytes = Vec::new();
+        let _ = stream.read_to_end(&mut bytes);
+
+        let records = records(&bytes);
+        assert_eq!(records.len(), 3);
+        assert!(records[0].contains("first"));
+        assert!(records[1].contains("second"));
+        assert!(records[2].contains("third"));
+
+        assert_eq!(std::fs::metadata(&spill).unwrap().len(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_datagram_drops_without_spill() {
+        let dir = dir("socket-dgram");
+        let path = dir.join("collector.sock");
+        let processor = unix_datagram(Pretty::new(), &path)
+            .reconnect_delay(Duration::ZERO)
+            .queue_capacity(0);
+
+        let trees = tracing_forest::capture().run(|| {
+            info!("lost");
+            info!("sent");
+        });
+        let mut trees = trees.into_iter();
+
+        processor.process(trees.next().unwrap());
+        let _ = processor.flush();
+        assert_eq!(processor.dropped(), 1);
+
+        let collector = UnixDatagram::bind(&path).unwrap();
+        processor.process(trees.next().unwrap());
+        processor.flush().unwrap();
+
+        let mut buf = vec![0; 4096];
+        let len = collector.recv(&mut buf).unwrap();
+        let records = records(&buf[..len]);
+        assert_eq!(records.len(), 1);
+        assert!(records[0].contains("sent"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+mod gcp_tests {
+    use super::*;
+    use tracing::{error, info_span, trace, warn};
+    use tracing_forest::formatter::json::Json;
+
+    #[test]
+    fn test_gcp_writes_one_entry_per_event() {
+        let out = render(Json::new(false).with_gcp("my-project"), || {
+            info_span!("request").in_scope(|| {
+                info_span!("fetch").in_scope(|| {
+                    warn!(attempt = 2, "retrying");
+                });
+                trace!("done");
+            });
+            error!("outside");
+        });
+
+        let entries = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 3);
+
+        let retry = &entries[0];
+        assert_eq!(retry["severity"], "WARNING");
+        assert_eq!(retry["message"], "retrying");
+        assert_eq!(retry["spans"], serde_json::json!(["request", "fetch"]));
+        assert_eq!(retry["fields"]["attempt"], "2");
+        assert!(retry["time"].is_string());
+        assert_eq!(
+            retry["logging.googleapis.com/sourceLocation"]["file"],
+            file!()
+        );
+        assert_eq!(
+            retry["logging.googleapis.com/sourceLocation"]["function"],
+            module_path!()
+        );
+
+        let trace = retry["logging.googleapis.com/trace"].as_str().unwrap();
+        assert!(trace.starts_with("projects/my-project/traces/"));
+        assert_eq!(trace.len(), "projects/my-project/traces/".len() + 32);
+        assert_eq!(entries[1]["logging.googleapis.com/trace"], trace);
+        assert_eq!(entries[1]["severity"], "DEBUG");
+
+        assert_eq!(entries[2]["severity"], "ERROR");
+        assert!(entries[2].get("logging.googleapis.com/trace").is_none());
+    }
+}
+
+mod ecs_tests {
+    use super::*;
+    use tracing::{info_span, warn};
+    use tracing_forest::formatter::json::Json;
+
+    #[test]
+    fn test_ecs_uses_ecs_field_names() {
+        let out = render(Json::new(false).with_ecs(), || {
+            info_span!("request").in_scope(|| {
+                info_span!("fetch").in_scope(|| {
+                    warn!(attempt = 2, "retrying");
+                });
+            });
+        });
+
+        let docs = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(docs.len(), 3);
+
+        let (request, fetch, retry) = (&docs[0], &docs[1], &docs[2]);
+        assert_eq!(request["message"], "request");
+        assert_eq!(request["log"]["level"], "INFO");
+        let duration = |doc: &serde_json::Value| doc["event"]["duration"].as_u64().unwrap();
+        assert!(duration(request) >= duration(fetch));
+        assert!(request["@timestamp"].is_string());
+        assert_eq!(request["ecs"]["version"], "8.11.0");
+
+        let trace = request["trace"]["id"].as_str().unwrap();
+        assert_eq!(trace.len(), 32);
+        assert_eq!(fetch["trace"]["id"], trace);
+        assert_eq!(retry["trace"]["id"], trace);
+        assert_ne!(request["span"]["id"], fetch["span"]["id"]);
+        assert_eq!(retry["span"]["id"], fetch["span"]["id"]);
+
+        assert_eq!(retry["message"], "retrying");
+        assert_eq!(retry["log"]["level"], "WARN");
+        assert_eq!(retry["log"]["logger"], module_path!());
+        assert_eq!(retry["log"]["origin"]["file"]["name"], file!());
+        assert_eq!(retry["labels"]["attempt"], "2");
+        assert!(retry.get("event").is_none());
+    }
+}
+
+mod schema_tests {
+    use super::*;
+    use tracing::info_span;
+    use tracing_forest::formatter::json::schema::{json_schema, SchemaVersion};
+    use tracing_forest::formatter::json::Json;
+
+    #[test]
+    fn test_v2_separates_events_and_children() {
+        let out = render(Json::new(true).with_schema(SchemaVersion::V2), || {
+            info_span!("request").in_scope(|| {
+                info_span!("db").in_scope(|| {});
+                info!(user = "alice", "done");
+            });
+            info!("outside");
+        });
+
+        let docs = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(docs.len(), 2);
+
+        let span = &docs[0]["span"];
+        assert_eq!(docs[0]["version"], 2);
+        assert_eq!(span["attributes"]["name"], "request");
+        let timing = &span["timing"];
+        assert!(
+            timing["total_nanos"].as_u64().unwrap() >= timing["nested_nanos"].as_u64().unwrap()
+        );
+        assert_eq!(span["children"][0]["attributes"]["name"], "db");
+        assert_eq!(span["children"][0]["position"], 0);
+        assert_eq!(span["events"][0]["message"], "done");
+        assert_eq!(span["events"][0]["position"], 1);
+        assert_eq!(span["events"][0]["fields"]["user"], "\"alice\"");
+
+        assert_eq!(docs[1]["event"]["message"], "outside");
+        assert!(docs[1].get("span").is_none());
+    }
+
+    #[test]
+    fn test_v2_relative_timestamps() {
+        use tracing_forest::formatter::json::Timestamps;
+
+        let json = Json::new(true)
+            .with_schema(SchemaVersion::V2)
+            .with_timestamps(Timestamps::Relative);
+        let out = render(json, || {
+            info_span!("request").in_scope(|| {
+                info_span!("db").in_scope(|| info!("query"));
+                info!("done");
+            });
+        });
+
+        let doc = serde_json::from_str::<serde_json::Value>(out.trim_end()).unwrap();
+        let span = &doc["span"];
+        assert!(span["attributes"]["timestamp"].is_string());
+        assert!(span["attributes"].get("offset_nanos").is_none());
+
+        let db = &span["children"][0]["attributes"];
+        assert!(db["timestamp"].is_null());
+        let query = &span["children"][0]["events"][0];
+        let done = &span["events"][0];
+        assert!(done["timestamp"].is_null());
+        assert_eq!(
+            query["root_offset_nanos"].as_u64().unwrap(),
+            db["offset_nanos"].as_u64().unwrap() + query["offset_nanos"].as_u64().unwrap()
+        );
+        assert!(
+            done["offset_nanos"].as_u64().unwrap() >= query["root_offset_nanos"].as_u64().unwrap()
+        );
+        assert_eq!(done["offset_nanos"], done["root_offset_nanos"]);
+    }
+
+    #[test]
+    fn test_published_schema_is_up_to_date() {
+        let published = include_str!("../schema/tree-v2.json");
+        assert_eq!(
+            published.trim_end(),
+            json_schema(),
+            "regenerate schema/tree-v2.json from `json_schema()`"
+        );
+    }
+}
+
+mod config_tests {
+    use super::*;
+    use tracing_forest::builder::LayerBuilder;
+    use tracing_forest::config::{ForestConfig, Format, Output};
+    use tracing_forest::writer::RotatingFile;
+
+    fn dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-forest-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_config_defaults_and_errors() {
+        let config: ForestConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, ForestConfig::default());
+
+        let config: ForestConfig =
+            serde_json::from_str(r#"{ "format": "json", "filter": "warn", "output": "stderr" }"#)
+                .unwrap();
+        assert_eq!(config.format, Format::Json);
+        assert_eq!(config.filter, tracing::level_filters::LevelFilter::WARN);
+        assert_eq!(config.output, Output::Stderr);
+
+        assert!(serde_json::from_str::<ForestConfig>(r#"{ "filter": "loud" }"#).is_err());
+        assert!(serde_json::from_str::<ForestConfig>(r#"{ "colour": true }"#).is_err());
+    }
+
+    #[test]
+    fn test_from_config_writes_sampled_json_to_file() {
+        let dir = dir("config");
+        let path = dir.join("app.log");
+        let config: ForestConfig = serde_json::from_value(serde_json::json!({
+            "format": "json",
+            "filter": "info",
+            "output": path,
+            "sampling": 0.5,
+        }))
+        .unwrap();
+
+        let layer = LayerBuilder::from_config(&config).unwrap().blocking_layer();
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            for i in 0..4 {
+                info!("tree {}", i);
+            }
+            tracing::debug!("filtered");
+        });
+
+        let out = std::fs::read_to_string(&path).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{}", out);
+        assert!(lines.iter().all(|line| line.starts_with('{')), "{}", out);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotating_file_keeps_old_files() {
+        use std::io::Write;
+        use tracing_subscriber::fmt::MakeWriter;
+
+        let dir = dir("rotating");
+        let path = dir.join("app.log");
+        let file = RotatingFile::new(&path, 10).unwrap().keep(2);
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.make_writer().write_all(line.as_bytes()).unwrap();
+        }
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("app.log"), "fourth\n");
+        assert_eq!(read("app.log.1"), "third\n");
+        assert_eq!(read("app.log.2"), "second\n");
+        assert!(!dir.join("app.log.3").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+mod pause_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::processor::pause::PausePolicy;
+    use tracing_forest::Processor;
+
+    fn messages(trees: &Mutex<Vec<String>>) -> Vec<String> {
+        trees.lock().unwrap().clone()
+    }
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Processor for Recorder {
+        fn process(&self, tree: tracing_forest::tree::Tree) {
+            if let tracing_forest::tree::TreeKind::Event(event) = tree.kind {
+                self.0.lock().unwrap().push(event.message.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_pause_buffers_until_resumed() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (processor, handle) = Recorder(seen.clone()).pausable(PausePolicy::Buffer(2));
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            info!("before");
+            handle.pause();
+            assert!(handle.is_paused());
+            info!("first");
+            info!("second");
+            info!("third");
+            assert_eq!(messages(&seen), ["before"]);
+
+            handle.resume();
+            info!("after");
+        });
+
+        assert_eq!(messages(&seen), ["before", "second", "third", "after"]);
+        assert_eq!(handle.dropped(), 1);
+    }
+
+    #[test]
+    fn test_pause_drops_while_paused() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (processor, handle) = Recorder(seen.clone()).pausable(PausePolicy::Drop);
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            handle.paused(|| info!("lost"));
+            assert!(!handle.is_paused());
+            info!("kept");
+        });
+
+        assert_eq!(messages(&seen), ["kept"]);
+        assert_eq!(handle.dropped(), 1);
+    }
+}
+
+mod progress_tests {
+    use std::io::Write;
+    use tracing_forest::writer::ProgressWriter;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    #[test]
+    fn test_progress_writer_keeps_bars_usable() {
+        let bar = indicatif::ProgressBar::hidden();
+        bar.set_length(2);
+        let writer = ProgressWriter::new(bar.clone());
+
+        writer.make_writer().write_all(b"a tree\n").unwrap();
+        bar.inc(1);
+        ProgressWriter::new(indicatif::MultiProgress::new())
+            .make_writer()
+            .write_all(b"another tree\n")
+            .unwrap();
+        bar.inc(1);
+
+        assert!(!bar.is_finished());
+        assert_eq!(bar.position(), 2);
+    }
+}
+
+mod scoped_tests {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use tracing::info;
+    use tracing_forest::assert_tree;
+    use tracing_forest::layer::TreeKind;
+
+    #[test]
+    fn test_concurrent_captures_are_isolated() {
+        let barrier = Arc::new(Barrier::new(4));
+        let handles = (0..4)
+            .map(|i| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    tracing_forest::capture().run(|| {
+                        barrier.wait();
+                        info!(thread = i, "logged");
+                        barrier.wait();
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let trees = handle.join().unwrap();
+            assert_eq!(trees.len(), 1);
+            match &trees[0].kind {
+                TreeKind::Event(event) => assert_eq!(event.fields[0].value, i.to_string()),
+                TreeKind::Span(_) => panic!("expected an event"),
+            }
+        }
+    }
+
+    #[tracing_forest::test]
+    fn test_capture_inside_test_attribute() {
+        info!("written by the test subscriber");
+        let trees = tracing_forest::capture().run(|| info!("captured"));
+        info!("written by the test subscriber again");
+
+        assert_eq!(trees.len(), 1);
+        assert_tree!(trees[0], event INFO "captured");
+    }
+}
+
+mod derive_ui_tests {
+    #[test]
+    fn test_derive_tag_diagnostics() {
+        let cases = trybuild::TestCases::new();
+        cases.compile_fail("tests/ui/*.rs");
+    }
+}
+
+mod instrument_tests {
+    use super::KanidmTag;
+    use tracing::{error, info};
+    use tracing_forest::assert_tree;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use uuid::Uuid;
+
+    #[tracing_forest::instrument(tag = "KanidmTag::AdminInfo", skip(id))]
+    fn admin(id: Uuid) {
+        info!("untagged");
+        error!(
+            __event_tag = tracing_forest::Tag::as_field(&KanidmTag::RequestError),
+            "tagged"
+        );
+        nested(id);
+    }
+
+    #[tracing_forest::instrument(level = "debug", uuid = id, fields(user = "alice"))]
+    fn nested(id: Uuid) {
+        info!("inherits the tag");
+    }
+
+    #[tracing_forest::instrument(skip_tree)]
+    fn chatty() {
+        info!("never collected");
+        tracing::info_span!("inner").in_scope(|| info!("also never collected"));
+    }
+
+    fn tag(tree: &Tree) -> Option<&'static str> {
+        match &tree.kind {
+            TreeKind::Event(event) => event.tag.map(|tag| tag.message),
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+
+    #[test]
+    fn test_instrument_tags_events() {
+        let trees = tracing_forest::capture()
+            .tag::<KanidmTag>()
+            .run(|| admin(Uuid::nil()));
+
+        assert_tree!(trees[0], span INFO "admin" [
+            event INFO "untagged",
+            event ERROR "tagged",
+            span DEBUG "nested" [event INFO "inherits the tag"],
+        ]);
+        let children = match &trees[0].kind {
+            TreeKind::Span(span) => &span.children,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(tag(&children[0]), Some("admin.info"));
+        assert_eq!(tag(&children[1]), Some("request.error"));
+        match &children[2].kind {
+            TreeKind::Span(span) => assert_eq!(tag(&span.children[0]), Some("admin.info")),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_instrument_overrides_uuid() {
+        let id = Uuid::new_v4();
+        let trees = tracing_forest::capture().run(|| nested(id));
+
+        assert_eq!(trees[0].attrs.uuid, id);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert_eq!(span.children[0].attrs.uuid, id),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_instrument_uuid_evaluated_once() {
+        use std::sync::Mutex;
+
+        static IDS: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+        fn new_id() -> Uuid {
+            let id = Uuid::new_v4();
+            IDS.lock().unwrap().push(id);
+            id
+        }
+
+        #[tracing_forest::instrument(uuid = new_id())]
+        fn fresh() {
+            info!("logged");
+        }
+
+        let trees = tracing_forest::capture().run(fresh);
+
+        // Both halves come from the one `Uuid` that was generated
+        let ids = IDS.lock().unwrap().clone();
+        assert_eq!(ids, [trees[0].attrs.uuid]);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert_eq!(span.children[0].attrs.uuid, ids[0]),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_instrument_skip_tree() {
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("outer").in_scope(|| {
+                chatty();
+                info!("kept");
+            });
+            chatty();
+        });
+
+        assert_eq!(trees.len(), 1);
+        assert_tree!(trees[0], span "outer" [event INFO "kept"]);
+    }
+}
+
+mod handle_tests {
+    use std::time::Duration;
+    use tracing::{info, Level};
+    use tracing_forest::assert_tree;
+    use tracing_forest::layer::{TreeHandle, TreeKind};
+    use tracing_forest::tree::Tree;
+
+    #[test]
+    fn test_submit_built_tree() {
+        let trees = tracing_forest::capture().run(|| {
+            info!("live");
+
+            let mut root = Tree::root("replayed").with_duration(Duration::from_millis(10));
+            root.add_child(Tree::span(Level::DEBUG, "db").with_duration(Duration::from_millis(4)))
+                .add_child(Tree::event(Level::INFO, "query").with_field("rows", "3"));
+            root.add_child(Tree::event(Level::WARN, "slow"));
+
+            let handle = TreeHandle::current().unwrap();
+            std::thread::spawn(move || handle.submit(root))
+                .join()
+                .unwrap();
+        });
+
+        assert_eq!(trees.len(), 2);
+        assert_tree!(trees[0], event INFO "live");
+        assert_tree!(trees[1], span INFO "replayed" [
+            span DEBUG "db" [event INFO "query" (rows = "3")],
+            event WARN "slow",
+        ]);
+
+        match &trees[1].kind {
+            TreeKind::Span(span) => {
+                assert_eq!(span.duration_nested, Duration::from_millis(4));
+                assert!(span
+                    .children
+                    .iter()
+                    .all(|child| child.attrs.uuid == trees[1].attrs.uuid));
+            }
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_no_handle_without_tree_layer() {
+        assert!(TreeHandle::current().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Events can't have children")]
+    fn test_event_has_no_children() {
+        Tree::event(Level::INFO, "leaf").add_child(Tree::event(Level::INFO, "child"));
+    }
+}
+
+mod bridge_tests {
+    use super::*;
+    use tracing::{info_span, warn};
+    use tracing_forest::assert_tree;
+    use tracing_forest::bridge;
+    use tracing_forest::formatter::json::Json;
+    use tracing_forest::layer::TreeKind;
+
+    fn child_output() -> String {
+        render(Json::new(true), || {
+            info_span!("compile").in_scope(|| {
+                warn!(file = "lib.rs", "unused import");
+            });
+            info!("finished");
+        })
+    }
+
+    #[test]
+    fn test_parse_tree_roundtrip() {
+        let output = child_output();
+        let line = output.lines().next().unwrap();
+        let tree = bridge::parse_tree(line).unwrap();
+        assert_tree!(tree, span INFO "compile" [event WARN "unused import" (file = "lib.rs")]);
+        assert!(bridge::parse_tree("not json").is_err());
+    }
+
+    #[test]
+    fn test_graft_into_current_span() {
+        let output = format!("{}plain output\n", child_output());
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("build").in_scope(|| {
+                info!("started");
+                bridge::graft(output.as_bytes(), &tracing::Span::current()).unwrap();
+            });
+        });
+
+        assert_eq!(trees.len(), 1);
+        assert_tree!(trees[0], span "build" [
+            event INFO "started",
+            span INFO "compile" [event WARN "unused import"],
+            event INFO "finished",
+            event INFO "plain output",
+        ]);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert!(span
+                .children
+                .iter()
+                .all(|child| child.attrs.uuid == trees[0].attrs.uuid)),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_graft_without_span() {
+        let output = child_output();
+        let trees = tracing_forest::capture().run(|| {
+            bridge::graft(output.as_bytes(), &tracing::Span::none()).unwrap();
+        });
+
+        assert_eq!(trees.len(), 2);
+        assert_tree!(trees[0], span "compile" [..]);
+    }
+
+    #[test]
+    fn test_replay() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::replay::{Replayed, Skipped};
+
+        let recorded = format!("{}\nplain output\n", child_output());
+
+        let out = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let pretty = tracing_forest::blocking(Pretty::new().with_snapshot(true), move || {
+            SharedBuf(writer.clone())
+        });
+        let replayed = tracing_forest::replay(recorded.as_bytes(), &pretty).unwrap();
+        assert_eq!(
+            replayed,
+            Replayed {
+                trees: 2,
+                skipped: vec![Skipped {
+                    record: 4,
+                    error: "expected value at line 1 column 1".to_string(),
+                }],
+            }
+        );
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            out,
+            "INFO     compile\nWARN     ┕━ 🚧 [warn]: unused import | file: \"lib.rs\"\n\
+             INFO     💬 [info]: finished\n"
+        );
+
+        // Trees keep the IDs and timestamps they were recorded with
+        let last = bridge::parse_tree(recorded.lines().nth(1).unwrap()).unwrap();
+        let recent = tracing_forest::processor::recent::RecentTrees::new(1);
+        tracing_forest::replay(recorded.as_bytes(), &recent).unwrap();
+        let trees = recent.snapshot();
+        assert_eq!(trees[0].attrs.uuid, last.attrs.uuid);
+        assert_eq!(trees[0].attrs.timestamp, last.attrs.timestamp);
+    }
+
+    #[test]
+    fn test_replay_cbor() {
+        use tracing_forest::formatter::{cbor::Cbor, Formatter};
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::replay::replay_cbor;
+
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("compile").in_scope(|| {
+                warn!(file = "lib.rs", "unused import");
+            });
+            info!("finished");
+        });
+        let mut recorded = Vec::new();
+        for tree in &trees {
+            Cbor::new().fmt(tree.clone(), &mut recorded).unwrap();
+        }
+        // A well-formed item that isn't a tree, then a tree cut off by a crash
+        recorded.push(0x01);
+        let mut first = Vec::new();
+        Cbor::new().fmt(trees[0].clone(), &mut first).unwrap();
+        recorded.extend_from_slice(&first[..first.len() / 2]);
+
+        let recent = RecentTrees::new(4);
+        let replayed = replay_cbor(&recorded[..], &recent).unwrap();
+        assert_eq!(replayed.trees, 2);
+        let skipped: Vec<_> = replayed.skipped.iter().map(|s| s.record).collect();
+        assert_eq!(skipped, [3, 4]);
+        assert_eq!(replayed.skipped[0].error, "expected a serialized tree");
+
+        let replayed = recent.snapshot();
+        assert_tree!(replayed[0], span "compile" [event WARN "unused import"]);
+        assert_eq!(replayed[0].attrs.uuid, trees[0].attrs.uuid);
+        assert_eq!(replayed[1].attrs.timestamp, trees[1].attrs.timestamp);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command() {
+        let output = child_output();
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("build").in_scope(|| {
+                let mut command = std::process::Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg("printf '%s' \"$TREES\"; echo 'from stderr' >&2")
+                    .env("TREES", &output);
+                assert!(bridge::run(&mut command).unwrap().success());
+            });
+        });
+
+        assert_eq!(trees.len(), 1);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert_eq!(span.children.len(), 3),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+}
+
+mod escalate_tests {
+    use super::KanidmTag;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::{info, info_span, Level};
+    use tracing_forest::processor::escalate::Rules;
+    use tracing_forest::tree::Tree;
+    use tracing_forest::{Processor, Tag};
+
+    fn rules() -> Rules {
+        Rules::new()
+            .slower_than(Duration::from_secs(5), Level::WARN)
+            .tag("security.*", Level::ERROR)
+    }
+
+    #[test]
+    fn test_escalate_slow_span() {
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::span(Level::DEBUG, "db").with_duration(Duration::from_secs(6)));
+        assert_eq!(rules().level(&tree), Some(Level::WARN));
+
+        let fast = Tree::root("request").with_duration(Duration::from_secs(1));
+        assert_eq!(rules().level(&fast), None);
+    }
+
+    struct Levels(Arc<Mutex<Vec<Level>>>);
+
+    impl Processor for Levels {
+        fn process(&self, tree: tracing_forest::layer::Tree) {
+            self.0.lock().unwrap().push(tree.attrs.level);
+        }
+    }
+
+    #[test]
+    fn test_escalate_processor() {
+        let levels = Arc::new(Mutex::new(Vec::new()));
+        let layer = Levels(levels.clone())
+            .escalate(
+                rules().rule(|tree| (tree.attrs.level == Level::TRACE).then_some(Level::DEBUG)),
+            )
+            .into_layer()
+            .tag::<KanidmTag>();
+
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            info_span!("login").in_scope(|| {
+                info!(
+                    __event_tag = KanidmTag::SecurityCritical.as_field(),
+                    "bad password"
+                );
+            });
+            info_span!("admin").in_scope(|| {
+                info!(
+                    __event_tag = KanidmTag::AdminInfo.as_field(),
+                    "listed users"
+                );
+            });
+            tracing::error!("already severe");
+            tracing::trace!("custom rule");
+        });
+
+        assert_eq!(
+            *levels.lock().unwrap(),
+            [Level::ERROR, Level::INFO, Level::ERROR, Level::DEBUG]
+        );
+    }
+}
+
+mod inherit_tests {
+    use super::*;
+    use tracing::info_span;
+    use tracing_forest::assert_tree;
+    use tracing_forest::formatter::json::Json;
+
+    #[test]
+    fn test_inherited_fields() {
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("request").in_scope(|| {
+                info!("before");
+                tracing_forest::inherit("user_id", 42);
+                tracing_forest::inherit("tenant", "acme");
+
+                info_span!("db").in_scope(|| {
+                    tracing_forest::inherit("tenant", "other");
+                    info!("query");
+                });
+                info!(user_id = 7, "impersonating");
+            });
+            tracing_forest::inherit("ignored", true);
+            info!("outside");
+        });
+
+        assert_tree!(trees[0], span "request" [
+            event INFO "before" (user_id = "42", tenant = "acme"),
+            span "db" [event INFO "query" (user_id = "42", tenant = "other")],
+            event INFO "impersonating" (user_id = "7", tenant = "acme"),
+        ]);
+        match &trees[1].kind {
+            tracing_forest::layer::TreeKind::Event(event) => assert!(event.fields.is_empty()),
+            tracing_forest::layer::TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+
+    #[test]
+    fn test_inherited_fields_in_json() {
+        let out = render(Json::new(true), || {
+            info_span!("request").in_scope(|| {
+                tracing_forest::inherit("user_id", 42);
+                info!("query");
+            });
+        });
+        assert!(out.contains(r#""fields":{"user_id":"42"}"#));
+    }
+}
+
+mod levels_tests {
+    use std::sync::{Arc, Mutex};
+    use tracing::{debug, error, info, info_span, warn, Level};
+    use tracing_forest::processor::levels::Levels;
+    use tracing_forest::Processor;
+
+    #[derive(Clone, Default)]
+    struct Names(Arc<Mutex<Vec<String>>>);
+
+    impl Processor for Names {
+        fn process(&self, tree: tracing_forest::layer::Tree) {
+            let name = match &tree.kind {
+                tracing_forest::layer::TreeKind::Span(span) => span.name.to_string(),
+                tracing_forest::layer::TreeKind::Event(event) => event.message.to_string(),
+            };
+            self.0.lock().unwrap().push(name);
+        }
+    }
+
+    impl Names {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_bind_levels() {
+        let [verbose, info, severe, alerts, fallback] = <[Names; 5]>::default();
+
+        let hooked = alerts.clone();
+        let levels = Levels::new()
+            .bind(Level::DEBUG, Level::TRACE, verbose.clone())
+            .bind(Level::INFO, Level::INFO, info.clone())
+            .bind(Level::WARN, Level::ERROR, severe.clone())
+            .hook(Level::ERROR, Level::ERROR, move |tree| {
+                hooked.process(tracing_forest::layer::Tree::clone(tree))
+            })
+            .bind(Level::ERROR, Level::ERROR, info.clone())
+            .fallback(fallback.clone());
+
+        tracing::subscriber::with_default(levels.into_layer().into_subscriber(), || {
+            debug!("debug");
+            info!("info");
+            warn!("warn");
+            info_span!("request").in_scope(|| {
+                debug!("query");
+                error!("failed");
+            });
+        });
+
+        assert_eq!(verbose.take(), ["debug"]);
+        assert_eq!(info.take(), ["info", "request"]);
+        assert_eq!(severe.take(), ["warn", "request"]);
+        assert_eq!(alerts.take(), ["request"]);
+        assert!(fallback.take().is_empty());
+    }
+
+    #[test]
+    fn test_builder_levels_fallback() {
+        let severe = Names::default();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+
+        let layer = tracing_forest::builder()
+            .writer(move || super::SharedBuf(writer.clone()))
+            .max_level(Level::INFO)
+            .levels_layer(Levels::new().bind(Level::WARN, Level::ERROR, severe.clone()));
+
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            debug!("filtered");
+            info!("to the writer");
+            error!("to the processor");
+        });
+
+        assert_eq!(severe.take(), ["to the processor"]);
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(out.contains("to the writer"));
+        assert!(!out.contains("to the processor"));
+        assert!(!out.contains("filtered"));
+    }
+}
+
+mod summary_tests {
+    use super::KanidmTag;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::{error, info, info_span, warn, Level};
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::{Processor, Tag};
+
+    #[derive(Clone, Default)]
+    struct Trees(Arc<Mutex<Vec<Tree>>>);
+
+    impl Processor for Trees {
+        fn process(&self, tree: Tree) {
+            self.0.lock().unwrap().push(tree);
+        }
+    }
+
+    #[test]
+    fn test_summarize_run() {
+        let trees = Trees::default();
+        let (processor, summary) = trees.clone().summarize();
+
+        processor.process(Tree::root("slow").with_duration(Duration::from_secs(3)));
+        let layer = processor.into_layer().tag::<KanidmTag>();
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            info_span!("request").in_scope(|| {
+                info!("started");
+                warn!(
+                    __event_tag = KanidmTag::SecurityCritical.as_field(),
+                    "bad password"
+                );
+            });
+            error!("failed");
+        });
+        summary.report();
+
+        let trees = trees.0.lock().unwrap();
+        assert_eq!(trees.len(), 4);
+        let summary = match &trees[3].kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(summary.name, "run summary");
+        assert_eq!(summary.duration_nested, Duration::ZERO);
+
+        let fields = |idx: usize| match &summary.children[idx].kind {
+            TreeKind::Event(event) => event
+                .fields
+                .iter()
+                .map(|kv| format!("{}={}", kv.key, kv.value))
+                .collect::<Vec<_>>(),
+            TreeKind::Span(_) => panic!("expected an event"),
+        };
+        assert_eq!(fields(0), ["ERROR=1", "WARN=1", "INFO=1"]);
+        assert_eq!(fields(1), ["security.critical=1"]);
+
+        let slowest = summary.children[2..]
+            .iter()
+            .map(|child| match &child.kind {
+                TreeKind::Span(span) => (child.attrs.level, span.name),
+                TreeKind::Event(_) => panic!("expected a span"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(slowest, [(Level::INFO, "slow"), (Level::INFO, "request")]);
+    }
+
+    #[tracing_forest::main(summary)]
+    fn summarized() {
+        info!("summarized");
+    }
+
+    #[tracing_forest::main(summary)]
+    #[tokio::main(flavor = "current_thread")]
+    async fn summarized_async() {
+        info!("summarized");
+    }
+
+    #[test]
+    fn test_main_summary() {
+        summarized();
+        summarized_async();
+    }
+}
+
+mod tag_matcher_tests {
+    use tracing_forest::Tag;
+
+    #[derive(Tag)]
+    enum AppTag {
+        #[tag(custom('🔐'): "security.critical", target = "app::security", level = "error")]
+        SecurityCritical,
+        #[tag(custom('🔓'): "security.access", target = "app::security")]
+        SecurityAccess,
+        #[tag(warn: "app.warn", level = "warn")]
+        Warn,
+        #[tag(info: "app.explicit")]
+        Explicit,
+    }
+
+    fn messages(trees: &[tracing_forest::layer::Tree]) -> Vec<Option<&'static str>> {
+        trees
+            .iter()
+            .map(|tree| tree.tags().first().map(|tag| tag.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_match_target_and_level() {
+        let trees = tracing_forest::capture().tag::<AppTag>().run(|| {
+            tracing::error!(target: "app::security", "breached");
+            tracing::info!(target: "app::security::login", "logged in");
+            tracing::warn!(target: "app::security", "suspicious");
+            tracing::warn!(target: "app::db", "slow query");
+            tracing::info!(target: "app::securityx", "unrelated");
+            tracing::error!(
+                target: "app::security",
+                __event_tag = AppTag::Explicit.as_field(),
+                "explicit"
+            );
+        });
+
+        assert_eq!(
+            messages(&trees),
+            [
+                Some("security.critical"),
+                Some("security.access"),
+                Some("security.access"),
+                Some("app.warn"),
+                None,
+                Some("app.explicit"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_overrides_span_tag() {
+        let trees = tracing_forest::capture().tag::<AppTag>().run(|| {
+            tracing::info_span!("request", __event_tag = AppTag::Explicit.as_field()).in_scope(
+                || {
+                    tracing::info!("inherited");
+                    tracing::warn!("matched");
+                },
+            );
+        });
+
+        let tags = trees[0].tags();
+        let tags = tags.iter().map(|tag| tag.message).collect::<Vec<_>>();
+        assert_eq!(tags, ["app.explicit", "app.warn"]);
+    }
+}
+
+mod net_tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::processor::net::{NetStats, Resilient, Transport};
+    use tracing_forest::Processor;
+
+    #[derive(Default)]
+    struct Collector {
+        up: bool,
+        connects: usize,
+        received: Vec<String>,
+    }
+
+    #[derive(Clone, Default)]
+    struct Mock(Arc<Mutex<Collector>>);
+
+    impl Mock {
+        fn set_up(&self, up: bool) {
+            self.0.lock().unwrap().up = up;
+        }
+
+        fn received(&self) -> Vec<String> {
+            self.0.lock().unwrap().received.clone()
+        }
+    }
+
+    impl Transport for Mock {
+        type Connection = ();
+
+        fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+            match tree.kind {
+                TreeKind::Event(event) => record.extend_from_slice(event.message.as_bytes()),
+                TreeKind::Span(_) => return Err(io::ErrorKind::InvalidData.into()),
+            }
+            Ok(())
+        }
+
+        fn connect(&self) -> io::Result<()> {
+            let mut collector = self.0.lock().unwrap();
+            collector.connects += 1;
+            match collector.up {
+                true => Ok(()),
+                false => Err(io::ErrorKind::ConnectionRefused.into()),
+            }
+        }
+
+        fn send(&self, _: &mut (), record: &[u8]) -> io::Result<()> {
+            let mut collector = self.0.lock().unwrap();
+            if !collector.up {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            collector
+                .received
+                .push(String::from_utf8_lossy(record).into());
+            Ok(())
+        }
+    }
+
+    fn send(processor: &Resilient<Mock>, message: &'static str) {
+        processor.process(Tree::event(tracing::Level::INFO, message));
+    }
+
+    #[test]
+    fn test_retry_in_order() {
+        let mock = Mock::default();
+        let processor = Resilient::new(mock.clone()).initial_backoff(Duration::from_millis(10));
+
+        send(&processor, "first");
+        send(&processor, "second");
+        assert!(processor.flush().is_err());
+        assert_eq!(processor.stats().queued, 2);
+
+        mock.set_up(true);
+        send(&processor, "third");
+        processor.flush().unwrap();
+        assert_eq!(mock.received(), ["first", "second", "third"]);
+
+        // A failed send closes the connection and is retried after reconnecting
+        mock.set_up(false);
+        send(&processor, "fourth");
+        assert!(processor.flush().is_err());
+        mock.set_up(true);
+        send(&processor, "fifth");
+        processor.flush().unwrap();
+        assert_eq!(mock.received()[3..], ["fourth", "fifth"]);
+
+        processor.process(Tree::root("unencodable"));
+        assert_eq!(
+            processor.stats(),
+            NetStats {
+                sent: 5,
+                dropped: 1,
+                spilled: 0,
+                connects: 2,
+                queued: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_sends_off_the_logging_thread() {
+        let mock = Mock::default();
+        mock.set_up(true);
+        let processor = Resilient::new(mock.clone());
+
+        // The thread can't connect while the collector is locked
+        let collector = mock.0.lock().unwrap();
+        send(&processor, "first");
+        send(&processor, "second");
+        assert_eq!(processor.stats().queued, 2);
+        drop(collector);
+
+        processor.flush().unwrap();
+        assert_eq!(mock.received(), ["first", "second"]);
+    }
+
+    #[test]
+    fn test_batches_on_size_or_delay() {
+        let wait_for = |mock: &Mock, count| {
+            let start = std::time::Instant::now();
+            while mock.received().len() < count {
+                assert!(
+                    start.elapsed() < Duration::from_secs(5),
+                    "batch was never sent"
+                );
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        let mock = Mock::default();
+        mock.set_up(true);
+        let full = Resilient::new(mock.clone()).batch(2, Duration::from_secs(3600));
+        send(&full, "a");
+        send(&full, "b");
+        wait_for(&mock, 2);
+
+        let waited = Resilient::new(mock.clone()).batch(10, Duration::from_millis(20));
+        send(&waited, "c");
+        wait_for(&mock, 3);
+        assert_eq!(mock.received(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_bounded_queue() {
+        let mock = Mock::default();
+        let processor = Resilient::new(mock.clone())
+            .initial_backoff(Duration::from_millis(10))
+            .queue_capacity(2);
+
+        for message in ["a", "b", "c", "d"] {
+            send(&processor, message);
+        }
+        assert!(processor.flush().is_err());
+        assert_eq!(processor.stats().dropped, 2);
+
+        mock.set_up(true);
+        processor.flush().unwrap();
+        send(&processor, "e");
+        processor.flush().unwrap();
+        assert_eq!(mock.received(), ["c", "d", "e"]);
+
+        let dropping = Resilient::new(mock.clone()).queue_capacity(0);
+        send(&dropping, "sent");
+        dropping.flush().unwrap();
+        mock.set_up(false);
+        send(&dropping, "dropped");
+        // The thread may have dropped it already, leaving nothing to flush
+        let _ = dropping.flush();
+        assert_eq!(dropping.stats().sent, 1);
+        assert_eq!(dropping.stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mock = Mock::default();
+        let processor = Resilient::new(mock.clone()).initial_backoff(Duration::from_secs(3600));
+
+        send(&processor, "refused");
+        assert!(processor.flush().is_err());
+        mock.set_up(true);
+        send(&processor, "waiting");
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(mock.0.lock().unwrap().connects, 1);
+        assert!(mock.received().is_empty());
+        assert_eq!(processor.stats().queued, 2);
+
+        // Flushing doesn't wait for the backoff
+        processor.flush().unwrap();
+        assert_eq!(mock.received(), ["refused", "waiting"]);
+    }
+
+    #[test]
+    fn test_spills_on_overflow_and_drop() {
+        #[derive(Clone, Default)]
+        struct Spilling(Mock, Arc<Mutex<Vec<String>>>);
+
+        impl Transport for Spilling {
+            type Connection = ();
+
+            fn encode(&self, tree: Tree, record: &mut Vec<u8>) -> io::Result<()> {
+                self.0.encode(tree, record)
+            }
+
+            fn connect(&self) -> io::Result<()> {
+                self.0.connect()
+            }
+
+            fn send(&self, conn: &mut (), record: &[u8]) -> io::Result<()> {
+                self.0.send(conn, record)
+            }
+
+            fn spill(&self, record: &[u8]) -> io::Result<bool> {
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(record).into());
+                Ok(true)
+            }
+        }
+
+        let spilling = Spilling::default();
+        let processor = Resilient::new(spilling.clone())
+            .initial_backoff(Duration::from_secs(3600))
+            .queue_capacity(1);
+        let send = |message| processor.process(Tree::event(tracing::Level::INFO, message));
+        send("a");
+        assert!(processor.flush().is_err());
+        send("b");
+        send("c");
+        assert_eq!(processor.stats().spilled, 2);
+
+        drop(processor);
+        assert_eq!(*spilling.1.lock().unwrap(), ["a", "b", "c"]);
+    }
+}
+
+mod error_tests {
+    use std::io;
+    use std::sync::{Mutex, OnceLock};
+    use std::thread::ThreadId;
+    use tracing_forest::error::{ErrorKind, ForestError};
+    use tracing_forest::formatter::Formatter;
+    use tracing_forest::layer::Tree;
+    use tracing_forest::Processor;
+
+    static REPORTED: Mutex<Vec<ErrorKind>> = Mutex::new(Vec::new());
+    static THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+    // Other tests report errors too, so only the ones reported here count
+    fn record(error: ForestError) {
+        if THREAD.get() == Some(&std::thread::current().id()) {
+            REPORTED.lock().unwrap().push(error.kind());
+        }
+    }
+
+    struct Failing;
+
+    impl Formatter for Failing {
+        fn fmt(&self, _: Tree, _: &mut Vec<u8>) -> io::Result<()> {
+            Err(io::Error::other("unformattable"))
+        }
+    }
+
+    struct Closed;
+
+    impl io::Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // The handler is global, so everything that reports errors is checked in
+    // one test
+    #[tokio::test]
+    async fn test_error_handler() {
+        THREAD.set(std::thread::current().id()).unwrap();
+        tracing_forest::set_error_handler(record);
+
+        let tree = || Tree::event(tracing::Level::INFO, "lost");
+        tracing_forest::blocking(Failing, io::sink).process(tree());
+        tracing_forest::blocking(tracing_forest::formatter::pretty::Pretty::new(), || Closed)
+            .process(tree());
+
+        let (processor, handle) =
+            tracing_forest::async_spawn(tracing_forest::formatter::pretty::Pretty::new(), io::sink);
+        handle.abort();
+        let _ = handle.await;
+        processor.process(tree());
+
+        let rejected = |_: ()| Err(io::Error::other("rejected"));
+        tracing_forest::processor::sink::map(|_: &Tree| ())
+            .sink(rejected)
+            .process(tree());
+
+        assert_eq!(
+            *REPORTED.lock().unwrap(),
+            [
+                ErrorKind::Format,
+                ErrorKind::Write,
+                ErrorKind::ChannelClosed,
+                ErrorKind::Processor
+            ]
+        );
+
+        let error = ForestError::Processor {
+            processor: "sqlite",
+            error: io::Error::other("disk full"),
+        };
+        assert_eq!(error.to_string(), "the sqlite processor failed: disk full");
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
+
+mod lazy_uuid_tests {
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::Processor;
+    use tracing_subscriber::Registry;
+    use uuid::Uuid;
+
+    struct Collect(Arc<Mutex<Vec<Tree>>>);
+
+    impl Processor for Collect {
+        fn process(&self, tree: Tree) {
+            self.0.lock().unwrap().push(tree);
+        }
+    }
+
+    fn uuids(tree: &Tree, out: &mut Vec<Uuid>) {
+        out.push(tree.attrs.uuid);
+        if let TreeKind::Span(span) = &tree.kind {
+            for child in &span.children {
+                uuids(child, out);
+            }
+        }
+    }
+
+    fn collect(lazy: bool, f: impl FnOnce()) -> Vec<Tree> {
+        let trees = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collect(trees.clone())
+            .into_layer()
+            .lazy_uuids(lazy)
+            .into_subscriber();
+        tracing::subscriber::with_default(subscriber, f);
+        let trees = trees.lock().unwrap().clone();
+        trees
+    }
+
+    #[test]
+    fn test_unrequested_id_is_generated_on_close() {
+        let trees = collect(true, || {
+            for _ in 0..2 {
+                tracing::info_span!("request").in_scope(|| {
+                    tracing::info!("handled");
+                });
+            }
+        });
+
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        uuids(&trees[0], &mut first);
+        uuids(&trees[1], &mut second);
+        assert!(!first[0].is_nil());
+        assert_eq!(first, [first[0]; 2]);
+        assert_ne!(first[0], second[0]);
+    }
+
+    #[test]
+    fn test_requested_id_is_shared() {
+        let mut requested = Uuid::nil();
+        let trees = collect(true, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!("before");
+                tracing::info_span!("db").in_scope(|| {
+                    tracing::info!("query");
+                    requested = tracing_forest::id::<Registry>();
+                });
+                assert_eq!(tracing_forest::id::<Registry>(), requested);
+                tracing::info!("after");
+            });
+        });
+
+        assert!(!requested.is_nil());
+        let mut ids = Vec::new();
+        uuids(&trees[0], &mut ids);
+        assert_eq!(ids.len(), 5);
+        assert!(ids.iter().all(|id| *id == requested));
+    }
+
+    #[test]
+    fn test_eager_ids() {
+        let trees = collect(false, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!("handled");
+            });
+        });
+
+        let mut ids = Vec::new();
+        uuids(&trees[0], &mut ids);
+        assert!(!ids[0].is_nil());
+        assert_eq!(ids[0], ids[1]);
+    }
+}
+
+mod id_field_tests {
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::Processor;
+    use uuid::Uuid;
+
+    struct Collect(Arc<Mutex<Vec<Tree>>>);
+
+    impl Processor for Collect {
+        fn process(&self, tree: Tree) {
+            self.0.lock().unwrap().push(tree);
+        }
+    }
+
+    fn collect(f: impl FnOnce()) -> Vec<Tree> {
+        let trees = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collect(trees.clone())
+            .into_layer()
+            .id_field("trace_id")
+            .into_subscriber();
+        tracing::subscriber::with_default(subscriber, f);
+        let trees = trees.lock().unwrap().clone();
+        trees
+    }
+
+    #[test]
+    fn test_uuid_value() {
+        let id = Uuid::new_v4();
+        let trees = collect(|| {
+            tracing::info_span!("request", trace_id = %id).in_scope(|| {
+                tracing::info!("handled");
+            });
+            tracing::info_span!("request", trace_id = id.to_string().as_str()).in_scope(|| {});
+        });
+
+        assert_eq!(trees[0].attrs.uuid, id);
+        assert_eq!(trees[1].attrs.uuid, id);
+        let span = match &trees[0].kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(span.children[0].attrs.uuid, id);
+        assert_eq!(span.fields[0].key, "trace_id");
+    }
+
+    #[test]
+    fn test_string_value_is_hashed() {
+        let trees = collect(|| {
+            tracing::info_span!("request", trace_id = "7a1f9c").in_scope(|| {});
+            tracing::info_span!("request", trace_id = "7a1f9c").in_scope(|| {});
+            tracing::info_span!("request", trace_id = 42).in_scope(|| {});
+            tracing::info_span!("request", trace_id = %"7a1f9c").in_scope(|| {});
+            tracing::info_span!("request", trace_id = "a\"b").in_scope(|| {});
+            tracing::info_span!("request", trace_id = %"a\"b").in_scope(|| {});
+        });
+
+        assert_eq!(trees[0].attrs.uuid, trees[1].attrs.uuid);
+        assert_ne!(trees[0].attrs.uuid, trees[2].attrs.uuid);
+        assert!(!trees[0].attrs.uuid.is_nil());
+        // Strings give the same ID whether they're recorded with `=` or `%`
+        assert_eq!(trees[0].attrs.uuid, trees[3].attrs.uuid);
+        assert_eq!(trees[4].attrs.uuid, trees[5].attrs.uuid);
+    }
+
+    #[test]
+    fn test_only_root_spans() {
+        let trees = collect(|| {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info_span!("inner", trace_id = "7a1f9c").in_scope(|| {});
+            });
+            tracing::info_span!("request").in_scope(|| {});
+        });
+
+        let span = match &trees[0].kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(span.children[0].attrs.uuid, trees[0].attrs.uuid);
+        assert_ne!(trees[0].attrs.uuid, trees[1].attrs.uuid);
+    }
+}
+
+mod compress_tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::Level;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::formatter::Formatter;
+    use tracing_forest::layer::{Annotation, Tree, TreeKind};
+    use tracing_forest::processor::compress::compress;
+    use tracing_forest::Processor;
+
+    fn children(tree: &Tree) -> &[Tree] {
+        match &tree.kind {
+            TreeKind::Span(span) => &span.children,
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    fn field(key: &'static str, value: &str) -> Annotation {
+        Annotation::Field(key.into(), value.to_string())
+    }
+
+    fn batch(items: &[u64]) -> Tree {
+        let mut root = Tree::root("batch");
+        for &millis in items {
+            root.add_child(
+                Tree::span(Level::INFO, "item").with_duration(Duration::from_millis(millis)),
+            )
+            .add_child(
+                Tree::event(Level::DEBUG, "processed").with_field("item", millis.to_string()),
+            );
+        }
+        root.add_child(Tree::event(Level::INFO, "done"));
+        root
+    }
+
+    #[test]
+    fn test_fold_repeated_spans() {
+        let tree = compress(batch(&[2, 4, 6]));
+
+        let children = children(&tree);
+        assert_eq!(children.len(), 2);
+        assert_eq!(
+            children[0].annotations,
+            [
+                field("repeated", "3"),
+                field("min", "2.00ms"),
+                field("avg", "4.00ms"),
+                field("max", "6.00ms"),
+            ]
+        );
+        assert!(children[1].annotations.is_empty());
+        assert!(tree.annotations.is_empty());
+    }
+
+    #[test]
+    fn test_different_shapes_are_kept() {
+        let mut root = Tree::root("batch");
+        root.add_child(Tree::span(Level::INFO, "item"));
+        root.add_child(Tree::span(Level::INFO, "item"))
+            .add_child(Tree::event(Level::INFO, "retried"));
+        root.add_child(Tree::span(Level::WARN, "item"));
+        root.add_child(Tree::event(Level::INFO, "done").with_field("items", "3"));
+        root.add_child(Tree::event(Level::INFO, "done"));
+
+        let tree = compress(root);
+        assert_eq!(children(&tree).len(), 5);
+        assert!(children(&tree)
+            .iter()
+            .all(|child| child.annotations.is_empty()));
+    }
+
+    #[test]
+    fn test_fold_nested_runs() {
+        let mut root = Tree::root("batch");
+        for _ in 0..2 {
+            let chunk = root.add_child(Tree::span(Level::INFO, "chunk"));
+            for _ in 0..3 {
+                chunk.add_child(Tree::event(Level::DEBUG, "row"));
+            }
+        }
+
+        let tree = compress(root);
+        let chunks = children(&tree);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].annotations[0], field("repeated", "2"));
+        let rows = children(&chunks[0]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].annotations, [field("repeated", "3")]);
+    }
+
+    #[test]
+    fn test_min_repeats() {
+        struct Collect(Arc<Mutex<Vec<Tree>>>);
+
+        impl Processor for Collect {
+            fn process(&self, tree: Tree) {
+                self.0.lock().unwrap().push(tree);
+            }
+        }
+
+        let trees = Arc::new(Mutex::new(Vec::new()));
+        let processor = Collect(trees.clone()).compress().min_repeats(3);
+        processor.process(batch(&[1, 1]));
+        processor.process(batch(&[1, 1, 1]));
+
+        let trees = trees.lock().unwrap();
+        assert_eq!(children(&trees[0]).len(), 3);
+        assert_eq!(children(&trees[1]).len(), 2);
+    }
+
+    #[test]
+    fn test_pretty_renders_nested_annotations() {
+        let mut out = Vec::new();
+        Pretty::new()
+            .with_snapshot(true)
+            .fmt(compress(batch(&[2, 4])), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert!(
+            lines[1].ends_with("item | repeated: 2 | min: 2.00ms | avg: 3.00ms | max: 4.00ms"),
+            "{}",
+            out
+        );
+        assert!(lines[2].ends_with("processed | item: 2"), "{}", out);
+    }
+}
+
+mod sink_tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::processor::sink::map;
+    use tracing_forest::Processor;
+
+    fn name(tree: &Tree) -> &'static str {
+        match &tree.kind {
+            TreeKind::Span(span) => span.name,
+            TreeKind::Event(_) => "event",
+        }
+    }
+
+    #[test]
+    fn test_map_to_channel() {
+        let (sender, receiver) = mpsc::channel();
+        let processor = map(name).sink(sender);
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info_span!("request").in_scope(|| tracing::info!("inside"));
+            tracing::info!("outside");
+        });
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["request", "event"]
+        );
+    }
+
+    #[test]
+    fn test_map_after_processor() {
+        use tracing_forest::processor::recent::RecentTrees;
+
+        let recent = RecentTrees::new(10);
+        let (sender, receiver) = mpsc::channel();
+        let processor = recent.clone().map(name).sink(sender);
+
+        processor.process(Tree::root("request"));
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["request"]);
+        assert_eq!(name(&recent.snapshot()[0]), "request");
+    }
+
+    // Sinks that fail are checked with the other errors in `error_tests`
+    #[test]
+    fn test_map_to_closure() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = {
+            let seen = seen.clone();
+            move |name: &'static str| -> io::Result<()> {
+                seen.borrow_mut().push(name);
+                Ok(())
+            }
+        };
+        let processor = map(name).sink(sink);
+
+        processor.process(Tree::root("request"));
+        processor.process(Tree::event(tracing::Level::INFO, "logged"));
+
+        assert_eq!(*seen.borrow(), ["request", "event"]);
+    }
+}
+
+mod suppress_tests {
+    use tracing_forest::layer::{Tree, TreeKind};
+
+    fn names(tree: &Tree) -> Vec<String> {
+        match &tree.kind {
+            TreeKind::Span(span) => span
+                .children
+                .iter()
+                .map(|child| match &child.kind {
+                    TreeKind::Span(span) => span.name.to_string(),
+                    TreeKind::Event(event) => event.message.to_string(),
+                })
+                .collect(),
+            TreeKind::Event(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_suppress() {
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!("before");
+                let value = tracing_forest::suppress(|| {
+                    tracing::info!("chatty");
+                    tracing::info_span!("inner").in_scope(|| tracing::info!("chattier"));
+                    7
+                });
+                assert_eq!(value, 7);
+                tracing::info!("after");
+            });
+            tracing_forest::suppress(|| tracing::info!("root"));
+        });
+
+        assert_eq!(trees.len(), 1);
+        assert_eq!(names(&trees[0]), ["before", "after"]);
+    }
+
+    #[tokio::test]
+    async fn test_suppress_future() {
+        let trees = tracing_forest::capture()
+            .run_async(async {
+                use tracing::Instrument;
+                async {
+                    tracing::info!("before");
+                    tracing_forest::suppress_future(async {
+                        tracing::info!("chatty");
+                        tokio::task::yield_now().await;
+                        tracing::info!("chattier");
+                    })
+                    .await;
+                    tracing::info!("after");
+                }
+                .instrument(tracing::info_span!("request"))
+                .await
+            })
+            .await;
+
+        assert_eq!(names(&trees[0]), ["before", "after"]);
+    }
+
+    #[test]
+    fn test_suppress_opens_no_span() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::Processor;
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+        use tracing_subscriber::Layer;
+
+        #[derive(Default)]
+        struct Counts {
+            spans: AtomicUsize,
+            events: AtomicUsize,
+        }
+
+        struct Counter(Arc<Counts>);
+
+        impl<S: tracing::Subscriber> Layer<S> for Counter {
+            fn on_new_span(
+                &self,
+                _: &tracing::span::Attributes<'_>,
+                _: &tracing::span::Id,
+                _: Context<'_, S>,
+            ) {
+                self.0.spans.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn on_event(&self, _: &tracing::Event<'_>, _: Context<'_, S>) {
+                self.0.events.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let recent = RecentTrees::new(8);
+        let counts = Arc::new(Counts::default());
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .into_subscriber()
+            .with(Counter(counts.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing_forest::suppress(|| tracing::info!("chatty"));
+        });
+
+        // Other layers see the event, but no span of its own
+        assert_eq!(counts.spans.load(Ordering::SeqCst), 0);
+        assert_eq!(counts.events.load(Ordering::SeqCst), 1);
+        assert!(recent.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_skip_field() {
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("request", user = "alice").in_scope(|| {
+                tracing::info!("before");
+                tracing::info_span!("poll", forest.skip = true).in_scope(|| {
+                    tracing::info_span!("inner").in_scope(|| tracing::info!("chatty"));
+                });
+                tracing::info_span!("kept", forest.skip = false).in_scope(|| {});
+                tracing::info!("after");
+            });
+        });
+
+        assert_eq!(trees.len(), 1);
+        assert_eq!(names(&trees[0]), ["before", "kept", "after"]);
+        match &trees[0].kind {
+            TreeKind::Span(span) => match &span.children[1].kind {
+                TreeKind::Span(kept) => assert!(kept.fields.is_empty()),
+                TreeKind::Event(_) => panic!("expected a span"),
+            },
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    #[test]
+    fn test_skip_field_on_filtered_span() {
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::Processor;
+
+        let recent = RecentTrees::new(8);
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .max_level(tracing::Level::INFO)
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                tracing::debug_span!("hot", forest.skip = true).in_scope(|| {
+                    tracing::info!("chatty");
+                    tracing::info_span!("inner").in_scope(|| tracing::info!("chatty"));
+                });
+                tracing::debug_span!("cold").in_scope(|| tracing::info!("kept"));
+            });
+        });
+
+        let trees = recent.snapshot();
+        assert_eq!(trees.len(), 1);
+        assert_eq!(names(&trees[0]), ["kept"]);
+    }
+
+    #[test]
+    fn test_skip_span_reaches_other_layers() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tracing::Level;
+        use tracing_forest::processor::recent::RecentTrees;
+        use tracing_forest::{skip_span, Processor};
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+        use tracing_subscriber::Layer;
+
+        struct Counter(Arc<AtomicUsize>);
+
+        impl<S: tracing::Subscriber> Layer<S> for Counter {
+            fn on_event(&self, _: &tracing::Event<'_>, _: Context<'_, S>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let recent = RecentTrees::new(8);
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .into_subscriber()
+            .with(Counter(count.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            skip_span!(Level::INFO, "heartbeat", peer = 7).in_scope(|| {
+                tracing::info!("ping");
+                tracing::info!("pong");
+            });
+            skip_span!(Level::INFO, "heartbeat").in_scope(|| tracing::info!("ping"));
+            tracing::info!("kept");
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+        let trees = recent.snapshot();
+        assert_eq!(trees.len(), 1);
+        match &trees[0].kind {
+            TreeKind::Event(event) => assert_eq!(event.message, "kept"),
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+}
+
+mod spawn_in_tree_tests {
+    use tracing::Instrument;
+    use tracing_forest::layer::TreeKind;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spawned_task_is_child() {
+        let trees = tracing_forest::capture()
+            .run_async(async {
+                async {
+                    let task = tracing_forest::spawn_in_tree(async {
+                        tracing::info_span!("refresh").in_scope(|| tracing::info!("refreshing"));
+                        3
+                    });
+                    assert_eq!(task.await.unwrap(), 3);
+                    tracing::info!("responded");
+                }
+                .instrument(tracing::info_span!("request"))
+                .await
+            })
+            .await;
+
+        assert_eq!(trees.len(), 1);
+        let children = match &trees[0].kind {
+            TreeKind::Span(span) => &span.children,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(children.len(), 2);
+        assert!(matches!(&children[0].kind, TreeKind::Span(span) if span.name == "refresh"));
+        assert_eq!(children[0].attrs.uuid, trees[0].attrs.uuid);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tree_waits_for_task() {
+        let trees = tracing_forest::capture()
+            .run_async(async {
+                let (sender, receiver) = tokio::sync::oneshot::channel::<()>();
+                let task = tracing::info_span!("request").in_scope(|| {
+                    tracing_forest::spawn_in_tree(async {
+                        receiver.await.unwrap();
+                        tracing::info!("finished later");
+                    })
+                });
+                sender.send(()).unwrap();
+                task.await.unwrap();
+            })
+            .await;
+
+        assert_eq!(trees.len(), 1);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert_eq!(span.children.len(), 1),
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+}
+
+mod json_hardening_tests {
+    use super::*;
+    use serde_json::Value;
+    use tracing::{info, info_span};
+    use tracing_forest::formatter::json::Json;
+
+    fn event(out: &str) -> Value {
+        let value = serde_json::from_str::<Value>(out.trim_end()).unwrap();
+        value["kind"]["Event"].clone()
+    }
+
+    #[test]
+    fn test_control_characters_round_trip() {
+        let hostile = "line\nbreak\r\t\u{0}\u{1b}[31mred\u{1b}[0m \"quoted\" \\ \u{2028}";
+        let replaced = String::from_utf8_lossy(b"bad \xff\xfe utf-8").into_owned();
+        let out = render(Json::new(true), || {
+            info!(value = hostile, lossy = %replaced, "{}", hostile);
+        });
+
+        assert_eq!(out.lines().count(), 1, "{}", out);
+        let event = event(&out);
+        assert_eq!(event["message"], hostile);
+        assert_eq!(event["fields"]["value"], format!("{:?}", hostile));
+        assert_eq!(event["fields"]["lossy"], replaced.as_str());
+    }
+
+    #[test]
+    fn test_max_string_len() {
+        let huge = "é".repeat(2 << 20);
+        let out = render(Json::new(true).with_max_string_len(Some(9)), || {
+            info!(value = %huge, "short");
+        });
+
+        assert!(out.len() < 300, "{}", out);
+        let event = event(&out);
+        assert_eq!(event["message"], "short");
+        // 9 bytes cut inside of a 2 byte character keeps 4 characters
+        let omitted = huge.len() - 8;
+        assert_eq!(
+            event["fields"]["value"],
+            format!("éééé…({} bytes omitted)", omitted)
+        );
+    }
+
+    #[test]
+    fn test_max_record_len() {
+        let formatter = || Json::new(true).with_max_record_len(Some(200));
+        let out = render(formatter(), || {
+            info!(value = %"x".repeat(1000), "long");
+            info!("short");
+        });
+
+        let lines = out.lines().collect::<Vec<_>>();
+        let placeholder = serde_json::from_str::<Value>(lines[0]).unwrap();
+        assert_eq!(placeholder["level"], "INFO");
+        assert!(placeholder["truncated"]["bytes"].as_u64().unwrap() > 1000);
+        assert_eq!(event(lines[1])["message"], "short");
+
+        // Pretty printed trees are limited as a whole
+        let out = render(Json::new(false).with_max_record_len(Some(100)), || {
+            info_span!("request").in_scope(|| info!("done"));
+        });
+        assert_eq!(out.lines().count(), 1, "{}", out);
+
+        // Each line is a record with ECS
+        let out = render(
+            Json::new(true).with_ecs().with_max_record_len(Some(400)),
+            || {
+                info_span!("request").in_scope(|| info!(value = %"x".repeat(1000), "long"));
+            },
+        );
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<Value>(lines[0]).unwrap()["message"],
+            "request"
+        );
+        assert!(lines[1].contains("truncated"));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let text = "café 🌲 \u{2028} ok";
+        let out = render(Json::new(true).with_ascii(true), || {
+            info!(value = %text, "{}", text);
+        });
+
+        assert!(out.is_ascii(), "{}", out);
+        assert!(out.contains("\\ud83c\\udf32"), "{}", out);
+        let event = event(&out);
+        assert_eq!(event["message"], text);
+        assert_eq!(event["fields"]["value"], text);
+    }
+}
+
+mod span_field_tests {
+    #[test]
+    fn test_span_fields_are_recorded() {
+        let trees = tracing_forest::capture().run(|| {
+            let status = tracing::field::Empty;
+            let span = tracing::info_span!("request", tenant_id = %"acme", user = "alice", status);
+            span.record("status", 200);
+            span.record("user", "bob");
+            span.in_scope(|| tracing::info!("handled"));
+        });
+
+        assert_eq!(trees[0].field("tenant_id"), Some("acme"));
+        assert_eq!(trees[0].field("user"), Some("\"bob\""));
+        assert_eq!(trees[0].field("status"), Some("200"));
+        assert_eq!(trees[0].field("missing"), None);
+    }
+}
+
+mod tenant_tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::processor::tenant::Tenants;
+    use tracing_forest::Processor;
+
+    type Log = Arc<Mutex<Vec<(String, String)>>>;
+
+    struct Collect {
+        tenant: String,
+        log: Log,
+    }
+
+    impl Processor for Collect {
+        fn process(&self, tree: Tree) {
+            let name = match &tree.kind {
+                TreeKind::Span(span) => span.name.to_string(),
+                TreeKind::Event(event) => event.message.to_string(),
+            };
+            self.log.lock().unwrap().push((self.tenant.clone(), name));
+        }
+    }
+
+    fn tenants(log: &Log) -> Tenants<impl Fn(&str) -> Collect, Collect> {
+        let log = log.clone();
+        Tenants::new("tenant_id", move |tenant: &str| Collect {
+            tenant: tenant.to_string(),
+            log: log.clone(),
+        })
+    }
+
+    #[test]
+    fn test_route_by_tenant() {
+        let log = Log::default();
+        let untenanted = Collect {
+            tenant: "none".to_string(),
+            log: log.clone(),
+        };
+        let processor = tenants(&log).untenanted(untenanted);
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info_span!("first", tenant_id = %"acme").in_scope(|| {});
+            tracing::info_span!("second", tenant_id = %"globex").in_scope(|| {});
+            // Strings are the same tenant whether they're recorded with `=` or `%`
+            tracing::info!(tenant_id = "acme", "event");
+            tracing::info_span!("third").in_scope(|| {});
+            tracing::info_span!("fourth", tenant_id = "a\"b").in_scope(|| {});
+        });
+
+        let log = log.lock().unwrap();
+        assert_eq!(
+            *log,
+            [
+                ("acme".to_string(), "first".to_string()),
+                ("globex".to_string(), "second".to_string()),
+                ("acme".to_string(), "event".to_string()),
+                ("none".to_string(), "third".to_string()),
+                ("a\"b".to_string(), "fourth".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_make_can_log() {
+        let log = Log::default();
+        let make_log = log.clone();
+        let processor = Tenants::new("tenant_id", move |tenant: &str| {
+            // Creating a processor may log, which is routed by the same
+            // processor
+            if tenant != "audit" {
+                tracing::info!(tenant_id = %"audit", "opened");
+            }
+            Collect {
+                tenant: tenant.to_string(),
+                log: make_log.clone(),
+            }
+        });
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), || {
+            tracing::info_span!("request", tenant_id = %"acme").in_scope(|| {});
+        });
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                ("audit".to_string(), "opened".to_string()),
+                ("acme".to_string(), "request".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idle_tenants_are_pruned() {
+        let log = Log::default();
+        let processor = tenants(&log).idle_timeout(Duration::from_millis(50));
+
+        processor.process(Tree::root("request").with_field("tenant_id", "acme"));
+        processor.process(Tree::root("request").with_field("tenant_id", "globex"));
+        assert_eq!(processor.tenants(), ["acme", "globex"]);
+
+        std::thread::sleep(Duration::from_millis(60));
+        processor.process(Tree::root("request").with_field("tenant_id", "globex"));
+        assert_eq!(processor.tenants(), ["globex"]);
+
+        // Trees without the field are dropped without an untenanted processor
+        processor.process(Tree::root("request"));
+        assert_eq!(log.lock().unwrap().len(), 3);
+    }
+}
+
+mod render_tests {
+    use std::io;
+    use tracing_forest::formatter::{json::Json, pretty::Pretty, Formatter};
+    use tracing_forest::layer::Tree;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_render_recent_trees() {
+        let recent = RecentTrees::new(10);
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            tracing::info_span!("request").in_scope(|| tracing::warn!("slow"));
+        });
+        let tree = &recent.snapshot()[0];
+
+        let pretty = Pretty::new().with_snapshot(true).render(tree).unwrap();
+        assert!(
+            pretty.lines().next().unwrap().ends_with("request"),
+            "{}",
+            pretty
+        );
+        assert!(pretty.ends_with("[warn]: slow\n"), "{}", pretty);
+
+        let formatter: Box<dyn Formatter> = Box::new(Json::new(true));
+        let json = tree.render_with(&*formatter).unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(value["kind"]["Span"]["name"], "request");
+
+        // Rendering doesn't consume the tree
+        assert_eq!(
+            tree.render_with(&Pretty::new().with_snapshot(true))
+                .unwrap(),
+            pretty
+        );
+    }
+
+    #[test]
+    fn test_render_error() {
+        struct Failing;
+
+        impl Formatter for Failing {
+            fn fmt(&self, _: Tree, _: &mut Vec<u8>) -> io::Result<()> {
+                Err(io::Error::other("unformattable"))
+            }
+        }
+
+        let error = Tree::root("request").render_with(&Failing).unwrap_err();
+        assert_eq!(error.to_string(), "unformattable");
+    }
+}
+
+mod duration_tests {
+    use std::time::Duration;
+    use tracing::Level;
+    use tracing_forest::capture::Expect;
+    use tracing_forest::layer::Tree;
+
+    fn request(queries: &[u64]) -> Tree {
+        let mut root = Tree::root("request").with_duration(Duration::from_millis(100));
+        for &millis in queries {
+            root.add_child(
+                Tree::span(Level::DEBUG, "db_query").with_duration(Duration::from_millis(millis)),
+            );
+        }
+        root
+    }
+
+    #[test]
+    fn test_span_durations() {
+        let trees = [request(&[10, 20]), request(&[30])];
+        let queries = trees.span("db_query");
+        queries
+            .expect_count(3)
+            .expect_duration_lt(Duration::from_millis(50))
+            .expect_duration_gt(Duration::from_millis(5))
+            .expect_total_duration_lt(Duration::from_millis(61));
+        assert_eq!(
+            queries.durations(),
+            [10, 20, 30].map(Duration::from_millis).to_vec()
+        );
+
+        trees[0].span("request").expect_count(1);
+        trees[0].span("db_*").expect_count(2);
+    }
+
+    #[test]
+    fn test_captured_span_durations() {
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info_span!("db_query").in_scope(|| {});
+            });
+        });
+        trees
+            .span("db_query")
+            .expect_duration_lt(Duration::from_secs(5));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "expected span \"db_query\" to take less than 50ms, but match 2 of 3 took 70ms"
+    )]
+    fn test_span_too_slow() {
+        request(&[10, 70, 20])
+            .span("db_query")
+            .expect_duration_lt(Duration::from_millis(50));
+    }
+
+    #[test]
+    #[should_panic(expected = "in total, but they took 60ms")]
+    fn test_spans_too_slow_in_total() {
+        request(&[20, 20, 20])
+            .span("db_query")
+            .expect_total_duration_lt(Duration::from_millis(50));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a span named \"cache\", but found none")]
+    fn test_missing_span() {
+        request(&[10])
+            .span("cache")
+            .expect_duration_lt(Duration::from_millis(50));
+    }
+}
+
+mod init_tests {
+    #[test]
+    fn test_init_test() {
+        let guard = tracing_forest::init_test();
+        assert!(!tracing::info_span!("installed").is_disabled());
+        tracing::info!("written through the test harness");
+
+        drop(guard);
+        assert!(tracing::info_span!("uninstalled").is_disabled());
+    }
+}
+
+mod worker_tests {
+    use std::sync::{Arc, Mutex};
+    use tracing::Level;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::layer::{Annotation, Tree, TreeKind};
+    use tracing_forest::processor::worker::{self, Degrade};
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_guard_drains_worker() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (processor, guard) =
+            tracing_forest::worker(Pretty::new(), move || super::SharedBuf(writer.clone()));
+
+        // The subscriber outlives the guard, like a global default would
+        let subscriber = processor.into_layer().into_subscriber();
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..100 {
+                tracing::info!(i, "queued");
+            }
+            drop(guard);
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out.lines().count(), 100, "{}", out);
+    }
+
+    /// Processes trees once the gate is unlocked.
+    struct Gated {
+        gate: Arc<Mutex<()>>,
+        trees: Arc<Mutex<Vec<Tree>>>,
+    }
+
+    impl Processor for Gated {
+        fn process(&self, tree: Tree) {
+            let _gate = self.gate.lock().unwrap();
+            self.trees.lock().unwrap().push(tree);
+        }
+    }
+
+    fn request() -> Tree {
+        let mut tree = Tree::root("request");
+        tree.add_child(Tree::span(Level::INFO, "db"))
+            .add_child(Tree::event(Level::INFO, "query"));
+        tree.add_child(Tree::event(Level::WARN, "slow"));
+        tree
+    }
+
+    fn overload(degrade: Degrade) -> Vec<Tree> {
+        let gate = Arc::new(Mutex::new(()));
+        let trees = Arc::new(Mutex::new(Vec::new()));
+        let closed = gate.lock().unwrap();
+        let (processor, guard) = worker::spawn(Gated {
+            gate: gate.clone(),
+            trees: trees.clone(),
+        });
+        let processor = processor.degrade(2, degrade);
+
+        for _ in 0..6 {
+            processor.process(request());
+        }
+        assert!(processor.is_degraded());
+
+        drop(closed);
+        while processor.queued() > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        processor.process(request());
+        assert!(!processor.is_degraded());
+
+        drop(guard);
+        let trees = trees.lock().unwrap().clone();
+        trees
+    }
+
+    #[test]
+    fn test_degrade_to_summary() {
+        let trees = overload(Degrade::Summary);
+        assert_eq!(trees.len(), 7);
+
+        let summarized = trees
+            .iter()
+            .filter(|tree| match &tree.kind {
+                TreeKind::Span(span) => span.children.is_empty(),
+                TreeKind::Event(_) => false,
+            })
+            .collect::<Vec<_>>();
+        assert!(!summarized.is_empty());
+        assert!(summarized.iter().all(|tree| tree.annotations
+            == [Annotation::Field(
+                "overloaded".into(),
+                "1 spans and 2 events dropped".into()
+            )]));
+        assert_eq!(trees[0].annotations, []);
+        assert_eq!(trees[6].annotations, []);
+    }
+
+    #[test]
+    fn test_degrade_to_flat() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let trees = overload(Degrade::flat(move || super::SharedBuf(writer.clone())));
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2 * (7 - trees.len()), "{}", out);
+        assert!(lines[0].contains("INFO     💬 [info]: query"), "{}", out);
+        assert!(lines[1].contains("WARN     🚧 [warn]: slow"), "{}", out);
+    }
+
+    #[test]
+    fn test_degrade_to_flat_with_formatter() {
+        use tracing_forest::formatter::pretty::Pretty;
+        use tracing_forest::formatter::Icons;
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let pretty = Pretty::new().with_icons(Icons::Text);
+        overload(Degrade::flat_with(pretty, move || {
+            super::SharedBuf(writer.clone())
+        }));
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].contains("INFO     [INF] [info]: query"), "{}", out);
+        assert!(lines[1].contains("WARN     [WRN] [warn]: slow"), "{}", out);
+    }
+}
+
+// Run with `RUSTFLAGS="--cfg tracing_unstable" cargo test --features valuable`
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+mod valuable_tests {
+    use std::collections::BTreeMap;
+    use tracing_forest::formatter::json::Json;
+    use tracing_forest::formatter::pretty::Pretty;
+
+    #[test]
+    fn test_structured_fields() {
+        let ids = vec![1, 2, 3];
+        let mut owners = BTreeMap::new();
+        owners.insert("db", vec!["alice"]);
+
+        let json = super::render(Json::new(true), || {
+            tracing::info!(
+                ids = tracing::field::valuable(&ids),
+                owners = tracing::field::valuable(&owners),
+                plain = 4,
+                "checked"
+            );
+        });
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        let fields = &value["kind"]["Event"]["fields"];
+        assert_eq!(fields["ids"], serde_json::json!([1, 2, 3]));
+        assert_eq!(fields["owners"], serde_json::json!({ "db": ["alice"] }));
+        assert_eq!(fields["plain"], "4");
+
+        let pretty = super::render(Pretty::new(), || {
+            tracing::info!(ids = tracing::field::valuable(&ids), "checked");
+        });
+        assert!(pretty.contains("ids: [1, 2, 3]"), "{}", pretty);
+
+        let trees = tracing_forest::capture().run(|| {
+            tracing::info!(ids = tracing::field::valuable(&ids), plain = 4, "checked");
+        });
+        let fields = match &trees[0].kind {
+            tracing_forest::layer::TreeKind::Event(event) => &event.fields,
+            tracing_forest::layer::TreeKind::Span(_) => panic!("expected an event"),
+        };
+        assert_eq!(fields[0].structured(), Some(&serde_json::json!([1, 2, 3])));
+        assert_eq!(fields[1].structured(), None);
+    }
+}
+
+mod field_rules_tests {
+    use tracing_forest::layer::{FieldRules, TreeKind};
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_rename_and_drop_fields() {
+        let recent = RecentTrees::new(10);
+        let rules = FieldRules::new()
+            .rename("err", "error.message")
+            .drop("private.*")
+            .rename("private.id", "id");
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .field_rules(rules)
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "request",
+                private.token = "secret",
+                err = tracing::field::Empty
+            );
+            span.in_scope(|| {
+                tracing::warn!(err = "timed out", private.id = 7, attempt = 2, "retrying");
+            });
+            span.record("err", "gave up");
+        });
+
+        let tree = &recent.snapshot()[0];
+        assert_eq!(tree.field("error.message"), Some("\"gave up\""));
+        assert_eq!(tree.field("private.token"), None);
+        assert_eq!(tree.field("err"), None);
+
+        let event = match &tree.kind {
+            TreeKind::Span(span) => &span.children[0],
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(event.field("error.message"), Some("\"timed out\""));
+        assert_eq!(event.field("attempt"), Some("2"));
+        // The first matching rule applies
+        assert_eq!(event.field("private.id"), None);
+        assert_eq!(event.field("id"), None);
+    }
+}
+
+mod coarse_clock_tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tracing_forest::layer::TreeKind;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    // The clock thread is shared, so tests that start it can't run in parallel
+    static CLOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_coarse_timestamps() {
+        let _lock = CLOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let recent = RecentTrees::new(10);
+        let layer = recent.clone().into_layer().coarse_timestamps(true);
+
+        let before = chrono::Utc::now();
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            tracing::info_span!("request").in_scope(|| tracing::info!("handled"));
+        });
+        let after = chrono::Utc::now();
+
+        let tree = &recent.snapshot()[0];
+        let event = match &tree.kind {
+            TreeKind::Span(span) => &span.children[0],
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        // The coarse clock lags behind, and is only precise to the millisecond
+        let slack = chrono::Duration::seconds(1);
+        for timestamp in [tree.attrs.timestamp, event.attrs.timestamp] {
+            assert!(
+                timestamp >= before - slack && timestamp <= after,
+                "{}",
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn test_coarse_timestamps_inside_tree() {
+        let _lock = CLOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let recent = RecentTrees::new(10);
+        let layer = recent.clone().into_layer().coarse_timestamps(true);
+
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            tracing::info_span!("request").in_scope(|| {
+                for i in 0..100 {
+                    tracing::info!(i, "step");
+                }
+                std::thread::sleep(Duration::from_millis(5));
+                tracing::info!("done");
+            });
+        });
+
+        let tree = &recent.snapshot()[0];
+        let children = match &tree.kind {
+            TreeKind::Span(span) => &span.children,
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        // Reading the monotonic clock for each event would almost never land
+        // on a whole millisecond
+        for child in children {
+            let offset = child.attrs.timestamp - tree.attrs.timestamp;
+            assert_eq!(offset.subsec_nanos() % 1_000_000, 0, "{}", offset);
+        }
+        let last = children[children.len() - 1].attrs.timestamp;
+        assert!(last > tree.attrs.timestamp);
+    }
+
+    /// Waits for the clock thread to be running or not, since it names itself
+    /// after it's spawned, and only stops on its next tick.
+    #[cfg(target_os = "linux")]
+    fn clock_running(expected: bool) -> bool {
+        let running = || {
+            std::fs::read_dir("/proc/self/task").unwrap().any(|task| {
+                let comm = std::fs::read_to_string(task.unwrap().path().join("comm"));
+                comm.is_ok_and(|comm| comm.starts_with("tracing-forest"))
+            })
+        };
+        for _ in 0..100 {
+            if running() == expected {
+                return expected;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        !expected
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_clock_stops_with_last_layer() {
+        let _lock = CLOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let first = RecentTrees::new(1).into_layer().coarse_timestamps(true);
+        let second = RecentTrees::new(1).into_layer().coarse_timestamps(true);
+        assert!(clock_running(true));
+
+        drop(first);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(clock_running(true));
+
+        let second = second.coarse_timestamps(false);
+        assert!(!clock_running(false));
+
+        let _second = second.coarse_timestamps(true);
+        assert!(clock_running(true));
+    }
+}
+
+mod wall_clock_tests {
+    use chrono::{DateTime, Duration, Utc};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use tracing_forest::formatter::json::{Json, Timestamps};
+    use tracing_forest::formatter::Formatter;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    fn children(tree: &Tree) -> &[Tree] {
+        match &tree.kind {
+            TreeKind::Span(span) => &span.children,
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    fn request() {
+        tracing::info_span!("request").in_scope(|| {
+            tracing::info!("started");
+            tracing::info_span!("db").in_scope(|| tracing::info!("query"));
+            tracing::info!("finished");
+        });
+    }
+
+    #[test]
+    fn test_stepped_back() {
+        // Steps back by an hour each time it's read, like repeated NTP steps
+        static HOURS: AtomicI64 = AtomicI64::new(0);
+        fn stepped_back() -> DateTime<Utc> {
+            DateTime::UNIX_EPOCH - Duration::hours(HOURS.fetch_add(1, Ordering::Relaxed))
+        }
+
+        let recent = RecentTrees::new(10);
+        let layer = recent.clone().into_layer().wall_clock(stepped_back);
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            request();
+            request();
+        });
+
+        let trees = recent.snapshot();
+        assert_eq!(trees[0].attrs.timestamp, DateTime::UNIX_EPOCH);
+        assert_eq!(
+            trees[1].attrs.timestamp,
+            DateTime::UNIX_EPOCH - Duration::hours(1)
+        );
+
+        for tree in &trees {
+            let root = tree.attrs.timestamp;
+            let children = children(tree);
+            let query = &self::children(&children[1])[0];
+            let timestamps = [
+                root,
+                children[0].attrs.timestamp,
+                children[1].attrs.timestamp,
+                query.attrs.timestamp,
+                children[2].attrs.timestamp,
+            ];
+            assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(timestamps[4] - root < Duration::seconds(1));
+        }
+    }
+
+    #[test]
+    fn test_suspended_json_offsets() {
+        // Jumps forward by an hour each time it's read, like resuming after
+        // the machine was suspended
+        static HOURS: AtomicI64 = AtomicI64::new(0);
+        fn suspended() -> DateTime<Utc> {
+            DateTime::UNIX_EPOCH + Duration::hours(HOURS.fetch_add(1, Ordering::Relaxed))
+        }
+
+        let recent = RecentTrees::new(10);
+        let layer = recent.clone().into_layer().wall_clock(suspended);
+        tracing::subscriber::with_default(layer.into_subscriber(), request);
+
+        let mut out = Vec::new();
+        Json::new(true)
+            .with_timestamps(Timestamps::Both)
+            .fmt((*recent.snapshot()[0]).clone(), &mut out)
+            .unwrap();
+        let tree: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(tree["timestamp"], "1970-01-01T00:00:00+00:00");
+
+        let children = &tree["kind"]["Span"]["children"];
+        let finished = &children[2];
+        assert!(finished["timestamp"].is_string());
+        let offset = finished["root_offset_nanos"].as_u64().unwrap();
+        assert!(offset < 1_000_000_000, "{}", offset);
+    }
+}
+
+mod deadline_tests {
+    use chrono::Utc;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tracing_forest::layer::Tree;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_drop_stale_trees() {
+        let live = RecentTrees::new(10);
+        let archive = RecentTrees::new(10);
+        let deadline = Arc::new(live.clone().deadline(Duration::from_secs(10)));
+
+        let ago = |duration| {
+            let closed = Instant::now().checked_sub(duration).unwrap();
+            Tree::root("request").with_closed(closed)
+        };
+        let processor = archive.clone().tee(deadline.clone());
+        processor.process(ago(Duration::from_secs(20)));
+        processor.process(ago(Duration::from_secs(1)));
+        // Trees that weren't collected don't know how old they are
+        processor.process(Tree::root("replayed"));
+
+        assert_eq!(archive.snapshot().len(), 3);
+        assert_eq!(live.snapshot().len(), 2);
+        assert_eq!(deadline.dropped(), 1);
+    }
+
+    #[test]
+    fn test_age_ignores_wall_clock() {
+        let live = RecentTrees::new(10);
+        let deadline = live.clone().deadline(Duration::from_secs(60));
+
+        // Opened two hours ago by the wall clock, and closed just now
+        let opened = Utc::now() - chrono::Duration::hours(2);
+        deadline.process(
+            Tree::root("migration")
+                .with_timestamp(opened)
+                .with_closed(Instant::now()),
+        );
+        // Trees collected by a layer are closed when they're processed
+        let subscriber = deadline.into_layer().into_subscriber();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {});
+        });
+
+        let trees = live.snapshot();
+        assert_eq!(trees.len(), 2);
+        assert!(trees[1].closed().is_some());
+    }
+}
+
+mod propagation_tests {
+    use std::collections::HashMap;
+    use tracing::Level;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::propagation::{self, Remote, ID_HEADER, PATH_HEADER};
+    use tracing_forest::{remote_span, Processor};
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn test_stitch_trees() {
+        let recent = RecentTrees::new(10);
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            let mut headers = HashMap::new();
+            let caller = tracing::info_span!("checkout").in_scope(|| {
+                tracing::info_span!("payment").in_scope(|| {
+                    propagation::inject::<Registry>(&mut headers);
+                });
+                tracing_forest::id::<Registry>()
+            });
+            assert_eq!(headers[ID_HEADER], caller.to_string());
+            assert_eq!(headers[PATH_HEADER], "checkout > payment");
+
+            let mut forwarded = HashMap::new();
+            remote_span!(headers, Level::INFO, "charge", amount = 42).in_scope(|| {
+                assert_eq!(tracing_forest::id::<Registry>(), caller);
+                tracing::debug_span!("card").in_scope(|| {
+                    propagation::inject::<Registry>(&mut forwarded);
+                });
+            });
+            assert_eq!(
+                propagation::extract(&forwarded),
+                Some(Remote {
+                    id: caller,
+                    path: "checkout > payment > charge > card".to_string(),
+                })
+            );
+        });
+
+        let trees = recent.snapshot();
+        let charge = trees
+            .iter()
+            .find(|tree| tree.field("amount").is_some())
+            .unwrap();
+        assert_eq!(charge.attrs.uuid, trees[0].attrs.uuid);
+        assert_eq!(charge.field("remote.path"), Some("checkout > payment"));
+    }
+
+    #[test]
+    fn test_missing_or_invalid_headers() {
+        let mut headers = HashMap::new();
+        propagation::inject::<Registry>(&mut headers);
+        assert!(headers.is_empty());
+        assert_eq!(propagation::extract(&headers), None);
+
+        headers.insert(ID_HEADER.to_string(), "not a uuid".to_string());
+        assert_eq!(propagation::extract(&headers), None);
+
+        let recent = RecentTrees::new(10);
+        tracing::subscriber::with_default(recent.clone().into_layer().into_subscriber(), || {
+            remote_span!(headers, Level::INFO, "charge").in_scope(|| {});
+        });
+        let tree = &recent.snapshot()[0];
+        assert!(!tree.attrs.uuid.is_nil());
+        assert_eq!(tree.field("remote.path"), None);
+    }
+}
+
+mod per_tree_tests {
+    use std::time::Duration;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::layer::Tree;
+    use tracing_forest::processor::per_tree::PerTreeFile;
+    use tracing_forest::Processor;
+
+    fn files(dir: &std::path::Path) -> Vec<String> {
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_one_file_per_tree() {
+        let dir = std::env::temp_dir().join("tracing-forest-per-tree-files");
+        let _ = std::fs::remove_dir_all(&dir);
+        let processor = PerTreeFile::new(&dir, Pretty::new()).unwrap();
+
+        let uuid = uuid::Uuid::new_v4();
+        processor.process(Tree::root("checkout").with_uuid(uuid));
+        processor.process(Tree::root("refund"));
+
+        let files = files(&dir);
+        assert_eq!(files.len(), 2);
+        let checkout = files
+            .iter()
+            .find(|name| name.ends_with(&format!("-{}.log", uuid)))
+            .unwrap();
+        assert!(checkout.starts_with(&chrono::Utc::now().format("%Y%m%dT").to_string()));
+        let log = std::fs::read_to_string(dir.join(checkout)).unwrap();
+        assert!(log.contains("checkout"));
+    }
+
+    #[test]
+    fn test_same_name_gets_suffix() {
+        let dir = std::env::temp_dir().join("tracing-forest-per-tree-suffix");
+        let _ = std::fs::remove_dir_all(&dir);
+        let processor = PerTreeFile::new(&dir, Pretty::new())
+            .unwrap()
+            .extension("txt");
+
+        let tree = Tree::root("checkout");
+        processor.process(tree.clone());
+        processor.process(tree);
+
+        let files = files(&dir);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("-1.txt"));
+        assert_eq!(files[1], files[0].replace("-1.txt", ".txt"));
+    }
+
+    #[test]
+    fn test_retention() {
+        let dir = std::env::temp_dir().join("tracing-forest-per-tree-retention");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("unrelated.json"), "{}").unwrap();
+
+        let processor = PerTreeFile::new(&dir, Pretty::new()).unwrap().max_files(2);
+        for name in ["first", "second", "third"] {
+            processor.process(Tree::root(name));
+        }
+        let remaining = files(&dir);
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.contains(&"unrelated.json".to_string()));
+        let logs = remaining
+            .iter()
+            .filter(|name| name.ends_with(".log"))
+            .map(|name| std::fs::read_to_string(dir.join(name)).unwrap())
+            .collect::<Vec<_>>();
+        assert!(logs.iter().all(|log| !log.contains("first")));
+
+        let processor = PerTreeFile::new(&dir, Pretty::new())
+            .unwrap()
+            .max_age(Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(100));
+        processor.process(Tree::root("fourth"));
+        assert_eq!(files(&dir).len(), 2);
+    }
+}
+
+mod retroactive_tests {
+    use tracing::Level;
+    use tracing_forest::layer::{Tree, TreeKind};
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    fn events(tree: &Tree, out: &mut Vec<String>) {
+        match &tree.kind {
+            TreeKind::Event(event) => out.push(event.message.to_string()),
+            TreeKind::Span(span) => span.children.iter().for_each(|child| events(child, out)),
+        }
+    }
+
+    #[test]
+    fn test_verbose_events_only_kept_with_warnings() {
+        let recent = RecentTrees::new(10);
+        let subscriber = recent
+            .clone()
+            .into_layer()
+            .retroactive_verbosity(true)
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("ok").in_scope(|| {
+                tracing::trace!("ok trace");
+                tracing::debug_span!("db").in_scope(|| tracing::debug!("ok debug"));
+                tracing::info!("ok info");
+            });
+            tracing::info_span!("failed").in_scope(|| {
+                tracing::debug_span!("db").in_scope(|| tracing::debug!("failed debug"));
+                tracing::warn!("failed warn");
+            });
+            tracing::debug!("root debug");
+            tracing::info!("root info");
+        });
+
+        let trees = recent.snapshot();
+        assert_eq!(trees.len(), 3);
+
+        let mut ok = Vec::new();
+        events(&trees[0], &mut ok);
+        assert_eq!(ok, ["ok info"]);
+        match &trees[0].kind {
+            TreeKind::Span(span) => assert_eq!(span.children[0].attrs.level, Level::DEBUG),
+            TreeKind::Event(_) => unreachable!(),
+        }
+
+        let mut failed = Vec::new();
+        events(&trees[1], &mut failed);
+        assert_eq!(failed, ["failed debug", "failed warn"]);
+
+        let mut root = Vec::new();
+        events(&trees[2], &mut root);
+        assert_eq!(root, ["root info"]);
+    }
+
+    #[test]
+    fn test_builder_flag() {
+        let out = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::builder()
+            .writer(move || super::SharedBuf(writer.clone()))
+            .retroactive_verbosity(true)
+            .blocking_layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("ok").in_scope(|| tracing::debug!("hidden detail"));
+            tracing::info_span!("failed").in_scope(|| {
+                tracing::debug!("shown detail");
+                tracing::error!("request failed");
+            });
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(!out.contains("hidden detail"));
+        assert!(out.contains("shown detail"));
+    }
+}
+
+mod processor_builder_tests {
+    use tracing::Level;
+    use tracing_forest::layer::TreeKind;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    fn names(recent: &RecentTrees) -> Vec<&'static str> {
+        recent
+            .snapshot()
+            .iter()
+            .map(|tree| match &tree.kind {
+                TreeKind::Span(span) => span.name,
+                TreeKind::Event(_) => "event",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample_and_tee() {
+        let all = RecentTrees::new(100);
+        let sampled = RecentTrees::new(100);
+        let processor = all.clone().tee(sampled.clone().sample(0.25));
+
+        for _ in 0..8 {
+            processor.process(tracing_forest::tree::Tree::root("request"));
+        }
+        assert_eq!(all.snapshot().len(), 8);
+        assert_eq!(sampled.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_sample_adaptive() {
+        use std::time::Duration;
+        use tracing_forest::tree::Tree;
+
+        let error = || {
+            let mut tree = Tree::root("failed");
+            tree.add_child(Tree::event(Level::ERROR, "boom"));
+            tree
+        };
+        let recent = RecentTrees::new(100);
+        let processor = recent
+            .clone()
+            .sample_adaptive(0.0, Duration::from_millis(50));
+
+        processor.process(Tree::root("dropped"));
+        assert!(!processor.is_boosted());
+        processor.process(error());
+        assert!(processor.is_boosted());
+        processor.process(Tree::root("boosted"));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!processor.is_boosted());
+        processor.process(Tree::root("dropped"));
+
+        assert_eq!(names(&recent), ["failed", "boosted"]);
+
+        let recent = RecentTrees::new(100);
+        let processor = recent
+            .clone()
+            .sample_adaptive(0.0, Duration::from_secs(60))
+            .boosted_rate(0.5);
+        processor.process(error());
+        for _ in 0..4 {
+            processor.process(Tree::root("boosted"));
+        }
+        assert_eq!(names(&recent), ["failed", "boosted", "boosted"]);
+    }
+
+    #[test]
+    fn test_set_processor_keeps_options() {
+        let kept = RecentTrees::new(10);
+        let copied = RecentTrees::new(10);
+        let subscriber = tracing_forest::builder()
+            .max_level(Level::INFO)
+            .set_processor(kept.clone())
+            .filter(|tree| tree.field("skip").is_none())
+            .tee(copied.clone())
+            .layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("first").in_scope(|| {});
+            tracing::debug_span!("verbose").in_scope(|| {});
+            tracing::info_span!("skipped", skip = true).in_scope(|| {});
+            tracing::info_span!("second").in_scope(|| {});
+        });
+
+        assert_eq!(names(&kept), ["first", "second"]);
+        // The copy is sent outside of the filter
+        assert_eq!(names(&copied), ["first", "skipped", "second"]);
+    }
+
+    #[test]
+    fn test_worker_layer() {
+        let recent = RecentTrees::new(10);
+        let (layer, guard) = tracing_forest::builder()
+            .set_processor(recent.clone())
+            .wrap(Processor::compress)
+            .worker_layer();
+
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            tracing::info_span!("request").in_scope(|| tracing::info!("handled"));
+        });
+        drop(guard);
+
+        assert_eq!(names(&recent), ["request"]);
+    }
+}
+
+mod tag_registry_tests {
+    use tracing_forest::{Tag, TagRegistry};
+
+    #[derive(Tag)]
+    enum AppTag {
+        #[tag(custom('🔐'): "security", target = "app::security::admin")]
+        Security,
+        #[tag(info: "security.audit")]
+        Audit,
+        #[tag(warn: "db")]
+        Db,
+        #[tag(info: "app.explicit")]
+        Explicit,
+    }
+
+    #[test]
+    fn test_longest_prefix() {
+        let mut registry = TagRegistry::new();
+        assert!(registry.is_empty());
+        registry.insert("app::security", AppTag::Security);
+        registry.insert("app::security::audit", AppTag::Audit);
+
+        let message = |target| registry.get(target).map(|tag| tag.message);
+        assert_eq!(message("app::security"), Some("security"));
+        assert_eq!(message("app::security::login"), Some("security"));
+        assert_eq!(message("app::security::audit::log"), Some("security.audit"));
+        assert_eq!(message("app::securityx"), None);
+        assert_eq!(message("app"), None);
+
+        let previous = registry.insert("app::security", AppTag::Db);
+        assert_eq!(previous.map(|tag| tag.message), Some("security"));
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn test_tag_events() {
+        let mut registry = TagRegistry::new();
+        registry.insert("app::security", AppTag::Security);
+        registry.insert("app::security::audit", AppTag::Audit);
+        registry.insert("app::db", AppTag::Db);
+
+        let trees = tracing_forest::capture()
+            .tag::<AppTag>()
+            .tag_registry(registry)
+            .run(|| {
+                tracing::info!(target: "app::db::pool", "connected");
+                tracing::info!(target: "app::security::audit", "audited");
+                tracing::info!(target: "app::security::admin", "matched by the tag type");
+                tracing::info!(
+                    target: "app::db",
+                    __event_tag = AppTag::Explicit.as_field(),
+                    "explicit"
+                );
+                tracing::info!(target: "app::web", "untagged");
+            });
+
+        let messages = trees
+            .iter()
+            .map(|tree| tree.tags().first().map(|tag| tag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                Some("db"),
+                Some("security.audit"),
+                Some("security"),
+                Some("app.explicit"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builder_registry() {
+        use std::sync::{Arc, Mutex};
+        use tracing_forest::builder::Preset;
+
+        let mut registry = TagRegistry::new();
+        registry.insert("app::db", AppTag::Db);
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let subscriber = tracing_forest::builder()
+            .preset(Preset::Test)
+            .writer(move || super::SharedBuf(writer.clone()))
+            .tag::<AppTag>()
+            .tag_registry(registry)
+            .blocking_layer()
+            .into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "app::db", "slow query");
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out, "INFO     🚧 [db]: slow query\n");
+    }
+}
+
+mod json_timestamps_tests {
+    use super::*;
+    use tracing::info_span;
+    use tracing_forest::formatter::json::{Json, Timestamps};
+
+    fn request() {
+        info_span!("request").in_scope(|| {
+            info!("started");
+            info_span!("db").in_scope(|| info!("query"));
+        });
+        info!("outside");
+    }
+
+    fn trees(timestamps: Timestamps) -> Vec<serde_json::Value> {
+        render(Json::new(true).with_timestamps(timestamps), request)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_absolute_by_default() {
+        let trees = trees(Timestamps::Absolute);
+        let children = &trees[0]["kind"]["Span"]["children"];
+        assert!(children[0]["timestamp"].is_string());
+        assert!(children[0].get("offset_nanos").is_none());
+    }
+
+    #[test]
+    fn test_both() {
+        let trees = trees(Timestamps::Both);
+        let root = &trees[0];
+        assert!(root["timestamp"].is_string());
+        assert!(root.get("offset_nanos").is_none());
+
+        let children = &root["kind"]["Span"]["children"];
+        let started = &children[0];
+        assert!(started["timestamp"].is_string());
+        assert_eq!(started["offset_nanos"], started["root_offset_nanos"]);
+
+        let db = &children[1];
+        let query = &db["kind"]["Span"]["children"][0];
+        assert!(query["timestamp"].is_string());
+        assert_eq!(
+            query["root_offset_nanos"].as_u64().unwrap(),
+            db["offset_nanos"].as_u64().unwrap() + query["offset_nanos"].as_u64().unwrap()
+        );
+
+        // Root events have no span to be relative to
+        assert!(trees[1]["timestamp"].is_string());
+        assert!(trees[1].get("offset_nanos").is_none());
+    }
+
+    #[test]
+    fn test_relative() {
+        let trees = trees(Timestamps::Relative);
+        let root = &trees[0];
+        assert!(root["timestamp"].is_string());
+
+        let db = &root["kind"]["Span"]["children"][1];
+        assert!(db.get("timestamp").is_none());
+        assert!(db["offset_nanos"].is_u64());
+        let query = &db["kind"]["Span"]["children"][0];
+        assert!(query.get("timestamp").is_none());
+        assert!(
+            query["root_offset_nanos"].as_u64().unwrap() >= db["offset_nanos"].as_u64().unwrap()
+        );
+    }
+}
+
+mod anonymize_tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::info_span;
+    use tracing_forest::layer::{Tree, TreeEvent, TreeKind, TreeSpan};
+    use tracing_forest::processor::anonymize;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::Processor;
+
+    fn request() {
+        info_span!("login", user = "alice").in_scope(|| {
+            info!(user = "alice", attempt = 2, "logged in as alice");
+            tracing::warn!(user = "bob", "locked out");
+        });
+    }
+
+    fn span(tree: &Tree) -> &TreeSpan {
+        match &tree.kind {
+            TreeKind::Span(span) => span,
+            TreeKind::Event(_) => panic!("expected a span"),
+        }
+    }
+
+    fn event(tree: &Tree) -> &TreeEvent {
+        match &tree.kind {
+            TreeKind::Event(event) => event,
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+
+    #[test]
+    fn test_mask() {
+        let trees = tracing_forest::capture().run(request);
+        let tree = anonymize::mask(trees.into_iter().next().unwrap());
+
+        let span = span(&tree);
+        assert_eq!(span.name, "login");
+        assert_eq!(span.fields[0].key, "user");
+        assert_eq!(span.fields[0].value, anonymize::MASK);
+
+        let logged_in = event(&span.children[0]);
+        assert_eq!(logged_in.message, anonymize::MASK);
+        let fields = logged_in
+            .fields
+            .iter()
+            .map(|kv| (kv.key, kv.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(fields, [("user", "***"), ("attempt", "***")]);
+        assert_eq!(span.children[1].attrs.level, tracing::Level::WARN);
+    }
+
+    #[test]
+    fn test_hash_keeps_equality() {
+        let trees = tracing_forest::capture().run(request);
+        let tree = anonymize::hash(trees.into_iter().next().unwrap());
+
+        let span = span(&tree);
+        let alice = &span.fields[0].value;
+        assert!(alice.starts_with('#') && alice.len() == 9);
+        assert_ne!(alice, "alice");
+
+        assert_eq!(&event(&span.children[0]).fields[0].value, alice);
+        assert_ne!(&event(&span.children[1]).fields[0].value, alice);
+    }
+
+    #[test]
+    fn test_processor_keeps_messages() {
+        let recent = Arc::new(RecentTrees::new(1));
+        let processor = recent.clone().anonymize().keep_messages(true);
+
+        tracing::subscriber::with_default(processor.into_layer().into_subscriber(), request);
+
+        let trees = recent.snapshot();
+        let span = span(&trees[0]);
+        let logged_in = event(&span.children[0]);
+        assert_eq!(logged_in.message, "logged in as alice");
+        assert_eq!(logged_in.fields[0].value, anonymize::MASK);
+    }
+}
+
+mod switch_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_forest::formatter::json::Json;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::formatter::switch::Switchable;
+    use tracing_forest::formatter::Icons;
+
+    #[test]
+    fn test_switch_formatter() {
+        let (formatter, handle) = Switchable::new(Pretty::new().with_snapshot(true));
+        let out = render(formatter, || {
+            info!("pretty");
+            handle.set(Json::new(true));
+            info!("json");
+            handle.set(Pretty::new().with_snapshot(true));
+            info!("pretty again");
+        });
+
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "INFO     💬 [info]: pretty");
+        let json = serde_json::from_str::<serde_json::Value>(lines[1]).unwrap();
+        assert_eq!(json["kind"]["Event"]["message"], "json");
+        assert_eq!(lines[2], "INFO     💬 [info]: pretty again");
+    }
+
+    #[test]
+    fn test_builder_settings_carry_over() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let (builder, handle) = tracing_forest::builder()
+            .writer(move || SharedBuf(writer.clone()))
+            .icons(Icons::Text)
+            .switchable(Pretty::new().with_snapshot(true));
+        let subscriber = builder.blocking_layer().into_subscriber();
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("before");
+            handle.clone().set(Pretty::new().with_snapshot(true));
+            info!("after");
+        });
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            out,
+            "INFO     [INF] [info]: before\nINFO     [INF] [info]: after\n"
+        );
+    }
+
+    #[test]
+    fn test_switch_during_tree_keeps_formatter() {
+        use std::io::Write;
+        use std::sync::OnceLock;
+        use tracing_forest::formatter::switch::SwitchHandle;
+        use tracing_forest::formatter::Formatter;
+        use tracing_forest::layer::Tree;
+        use tracing_forest::processor::Latency;
+
+        static HANDLE: OnceLock<SwitchHandle> = OnceLock::new();
+
+        struct Named(&'static str);
+
+        impl Formatter for Named {
+            fn fmt(&self, _: Tree, writer: &mut Vec<u8>) -> std::io::Result<()> {
+                // Switch while the tree is being formatted
+                HANDLE.get().unwrap().set(Named("second"));
+                writeln!(writer, "tree by {}", self.0)
+            }
+
+            fn fmt_latency(&self, _: &Latency, writer: &mut Vec<u8>) -> std::io::Result<()> {
+                writeln!(writer, "latency by {}", self.0)
+            }
+        }
+
+        let (formatter, handle) = Switchable::new(Named("first"));
+        HANDLE.set(handle).unwrap();
+
+        let mut buf = Vec::new();
+        formatter
+            .fmt_with_latency(Tree::root("request"), None, &mut buf)
+            .unwrap();
+        assert_eq!(buf, b"tree by first\nlatency by first\n");
+
+        buf.clear();
+        formatter
+            .fmt_with_latency(Tree::root("request"), None, &mut buf)
+            .unwrap();
+        assert_eq!(buf, b"tree by second\nlatency by second\n");
+    }
+}
+
+mod signals_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tracing::Level;
+    use tracing_forest::formatter::pretty::Pretty;
+    use tracing_forest::processor::recent::RecentTrees;
+    use tracing_forest::signals::{Signals, SIGHUP, SIGUSR1, SIGUSR2};
+    use tracing_forest::writer::RotatingFile;
+    use tracing_forest::Processor;
+
+    fn kill(signal: &str) {
+        let status = std::process::Command::new("kill")
+            .args([signal, &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Signals are handled process-wide, so everything is tested in one test
+    #[test]
+    fn test_signal_actions() {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-forest-signals-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let file = RotatingFile::new(&path, 1 << 20).unwrap();
+        let reopen = file.reopen_handle();
+
+        let recent = RecentTrees::new(10);
+        let layer = tracing_forest::blocking(Pretty::new().with_snapshot(true), file)
+            .tee(recent.clone())
+            .into_layer()
+            .max_level(Level::INFO);
+        let verbosity = layer.verbosity_handle();
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = out.clone();
+        let signals = Signals::new()
+            .dump_recent(
+                SIGUSR1,
+                recent,
+                Pretty::new().with_snapshot(true),
+                move || SharedBuf(writer.clone()),
+            )
+            .reopen(SIGHUP, reopen)
+            .toggle_verbosity(SIGUSR2, verbosity.clone())
+            .listen()
+            .unwrap();
+
+        let err = Signals::new().on(SIGUSR1, || {}).listen().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        tracing::subscriber::with_default(layer.into_subscriber(), || {
+            info!("first");
+            tracing::debug!("hidden");
+
+            kill("-USR1");
+            wait_until(|| !out.lock().unwrap().is_empty());
+            let dumped = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+            assert_eq!(dumped, "INFO     💬 [info]: first\n");
+
+            kill("-USR2");
+            wait_until(|| verbosity.is_verbose());
+            tracing::debug!("shown");
+
+            std::fs::rename(&path, dir.join("app.log.old")).unwrap();
+            kill("-HUP");
+            wait_until(|| path.exists());
+            info!("reopened");
+        });
+        drop(signals);
+
+        let old = std::fs::read_to_string(dir.join("app.log.old")).unwrap();
+        assert_eq!(
+            old,
+            "INFO     💬 [info]: first\nDEBUG    🐛 [debug]: shown\n"
+        );
+        let new = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(new, "INFO     💬 [info]: reopened\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The pipe outlives the guard, and is reused by the next listener
+        let count = Arc::new(Mutex::new(0));
+        let counter = count.clone();
+        let signals = Signals::new()
+            .on(SIGUSR1, move || *counter.lock().unwrap() += 1)
+            .listen()
+            .unwrap();
+        kill("-USR1");
+        wait_until(|| *count.lock().unwrap() == 1);
+        drop(signals);
+    }
+}
+
+mod template_tests {
+    use super::*;
+    use tracing::Level;
+    use tracing_forest::formatter::json::schema::SchemaVersion;
+    use tracing_forest::formatter::json::Json;
+    use tracing_forest::layer::{Tree, TreeEvent, TreeKind};
+    use tracing_forest::{bridge, templated};
+
+    fn event(tree: &Tree) -> &TreeEvent {
+        match &tree.kind {
+            TreeKind::Event(event) => event,
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+
+    #[test]
+    fn test_capture_template() {
+        let delay = 250;
+        let trees = tracing_forest::capture().run(|| {
+            templated!(Level::WARN, "retrying in {}ms", delay);
+            tracing::warn!(
+                message.template = "failed after {} attempts",
+                attempt = 3,
+                "failed after {} attempts",
+                3
+            );
+            info!("no template");
+        });
+
+        assert_eq!(trees.len(), 3);
+        let retry = event(&trees[0]);
+        assert_eq!(retry.message, "retrying in 250ms");
+        assert_eq!(retry.template.as_deref(), Some("retrying in {}ms"));
+        assert!(retry.fields.is_empty());
+
+        let failed = event(&trees[1]);
+        assert_eq!(failed.template.as_deref(), Some("failed after {} attempts"));
+        assert_eq!(failed.fields.len(), 1);
+        assert_eq!(failed.fields[0].key, "attempt");
+
+        assert!(event(&trees[2]).template.is_none());
+    }
+
+    #[test]
+    fn test_serialize_template() {
+        let log = || {
+            templated!(Level::INFO, "handled {} requests", 7);
+            info!("no template");
+        };
+
+        let out = render(Json::new(true), log);
+        let docs = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let body = &docs[0]["kind"]["Event"];
+        assert_eq!(body["message"], "handled 7 requests");
+        assert_eq!(body["template"], "handled {} requests");
+        assert!(docs[1]["kind"]["Event"].get("template").is_none());
+
+        let out = render(Json::new(true).with_schema(SchemaVersion::V2), log);
+        let docs = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(docs[0]["event"]["template"], "handled {} requests");
+        assert!(docs[1]["event"].get("template").is_none());
+    }
+
+    #[test]
+    fn test_graft_keeps_template() {
+        let output = render(Json::new(true), || {
+            templated!(Level::INFO, "built {} crates", 12);
+        });
+        let tree = bridge::parse_tree(output.trim_end()).unwrap();
+        assert_eq!(event(&tree).template.as_deref(), Some("built {} crates"));
+    }
+}
+
+mod honeycomb_tests {
+    use super::*;
+    use std::time::Duration;
+    use tracing::info_span;
+    use tracing_forest::processor::honeycomb::Honeycomb;
+    use tracing_forest::processor::net::Resilient;
+    use tracing_forest::Processor;
+
+    #[test]
+    fn test_flattens_spans_into_events() {
+        let (url, server) = http_server();
+        let honeycomb = Honeycomb::new("secret", "checkout")
+            .api_url(&url)
+            .service_name("checkout-api")
+            .sample_rate(0.25);
+        let processor = Resilient::new(honeycomb).queue_capacity(0);
+
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("request", method = "GET").in_scope(|| {
+                info_span!("db").in_scope(|| std::thread::sleep(Duration::from_millis(2)));
+                info!(user = "alice", "done");
+            });
+        });
+        let trace = trees[0].attrs.uuid.to_simple().to_string();
+        trees.into_iter().for_each(|tree| processor.process(tree));
+
+        let (head, body) = server.join().unwrap();
+        processor.flush().unwrap();
+        assert_eq!(head[0], "POST /1/batch/checkout HTTP/1.1");
+        assert!(head.iter().any(|line| line == "X-Honeycomb-Team: secret"));
+        assert_eq!(processor.stats().sent, 1);
+
+        let batch = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let batch = batch.as_array().unwrap();
+        assert_eq!(batch.len(), 3);
+        assert!(batch.iter().all(|entry| entry["samplerate"] == 4));
+
+        let root = &batch[0]["data"];
+        assert_eq!(root["name"], "request");
+        assert_eq!(root["method"], "\"GET\"");
+        assert_eq!(root["trace.trace_id"], trace.as_str());
+        assert_eq!(root["service.name"], "checkout-api");
+        assert!(root.get("trace.parent_id").is_none());
+
+        let db = &batch[1]["data"];
+        assert_eq!(db["name"], "db");
+        assert_eq!(db["trace.parent_id"], root["trace.span_id"]);
+        assert_ne!(db["trace.span_id"], root["trace.span_id"]);
+        assert!(db["duration_ms"].as_f64().unwrap() >= 2.0);
+
+        let done = &batch[2]["data"];
+        assert_eq!(done["name"], "done");
+        assert_eq!(done["meta.annotation_type"], "span_event");
+        assert_eq!(done["trace.parent_id"], root["trace.span_id"]);
+        assert_eq!(done["user"], "\"alice\"");
+    }
+
+    #[test]
+    fn test_batches_trees_into_one_request() {
+        let (url, server) = http_server();
+        let honeycomb = Honeycomb::new("secret", "checkout").api_url(&url);
+        let processor = Resilient::new(honeycomb).batch(10, Duration::from_secs(60));
+
+        let trees = tracing_forest::capture().run(|| {
+            info_span!("request").in_scope(|| info!("handled"));
+            info!("outside");
+        });
+        trees.into_iter().for_each(|tree| processor.process(tree));
+        assert_eq!(processor.stats().queued, 2);
+        processor.flush().unwrap();
+
+        let (_, body) = server.join().unwrap();
+        let batch = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let names = batch
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["data"]["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["request", "handled", "outside"]);
+        assert_eq!(processor.stats().sent, 2);
+    }
+}
+
+mod datadog_tests {
+    use super::*;
+    use tracing::{error, info_span};
+    use tracing_forest::processor::datadog::Datadog;
+    use tracing_forest::processor::net::Resilient;
+    use tracing_forest::Processor;
+
+    fn request() -> Vec<tracing_forest::layer::Tree> {
+        tracing_forest::capture().run(|| {
+            info_span!("request", route = "POST /cart").in_scope(|| {
+                info_span!("db").in_scope(|| error!(rows = 0, "insert failed"));
+                info!("responded");
+            });
+        })
+    }
+
+    #[test]
+    fn test_agent_spans() {
+        let (url, server) = http_server();
+        let datadog = Datadog::agent(&url)
+            .service("checkout")
+            .env("production")
+            .resource_field("route");
+        let processor = Resilient::new(datadog).queue_capacity(0);
+
+        let trees = request();
+        let trace_id = trees[0].attrs.uuid.as_u128() as u64;
+        trees.into_iter().for_each(|tree| processor.process(tree));
+        processor.flush().unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "PUT /v0.3/traces HTTP/1.1");
+        let traces = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let spans = traces[0].as_array().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let (root, db) = (&spans[0], &spans[1]);
+        assert_eq!(root["trace_id"], trace_id);
+        assert_eq!(root["parent_id"], 0);
+        assert_eq!(root["name"], "request");
+        assert_eq!(root["resource"], "POST /cart");
+        assert_eq!(root["service"], "checkout");
+        assert_eq!(root["error"], 0);
+        assert_eq!(root["meta"]["env"], "production");
+        assert_eq!(root["meta"]["events.0"], "INFO responded");
+
+        assert_eq!(db["trace_id"], trace_id);
+        assert_eq!(db["parent_id"], root["span_id"]);
+        assert_eq!(db["resource"], "db");
+        assert_eq!(db["error"], 1);
+    }
+
+    #[test]
+    fn test_logs_correlate_with_spans() {
+        let (url, server) = http_server();
+        let datadog = Datadog::logs("secret")
+            .api_url(&url)
+            .service("checkout")
+            .version("1.4.2");
+        let processor = Resilient::new(datadog).queue_capacity(0);
+
+        request()
+            .into_iter()
+            .for_each(|tree| processor.process(tree));
+        processor.flush().unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "POST /api/v2/logs HTTP/1.1");
+        assert!(head.iter().any(|line| line == "DD-API-KEY: secret"));
+
+        let logs = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let logs = logs.as_array().unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0]["message"], "insert failed");
+        assert_eq!(logs[0]["status"], "error");
+        assert_eq!(logs[0]["rows"], "0");
+        assert_eq!(logs[0]["ddtags"], "version:1.4.2");
+        assert_eq!(logs[1]["status"], "info");
+        assert_eq!(logs[0]["dd.trace_id"], logs[1]["dd.trace_id"]);
+        assert_ne!(logs[0]["dd.span_id"], logs[1]["dd.span_id"]);
+    }
+
+    #[test]
+    fn test_batches_traces_into_one_request() {
+        let (url, server) = http_server();
+        let processor = Resilient::new(Datadog::agent(&url));
+
+        let mut trees = request();
+        trees.extend(tracing_forest::capture().run(|| {
+            info!("outside");
+            info_span!("checkout").in_scope(|| info!("paid"));
+        }));
+        trees.into_iter().for_each(|tree| processor.process(tree));
+        processor.flush().unwrap();
+
+        let (_, body) = server.join().unwrap();
+        let traces = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let names = traces
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|trace| trace[0]["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["request", "checkout"]);
+        assert_eq!(processor.stats().sent, 3);
+    }
+}
+
+mod baggage_tests {
+    use tracing::Instrument;
+    use tracing_forest::baggage;
+    use tracing_forest::layer::{Tree, TreeKind};
+
+    fn field<'a>(tree: &'a Tree, key: &str) -> Option<&'a str> {
+        match &tree.kind {
+            TreeKind::Event(event) => event.fields.iter().find(|kv| kv.key == key),
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+        .map(|kv| kv.value.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_baggage_attached_to_events() {
+        let trees = tracing_forest::capture()
+            .run_async(baggage::scope(async {
+                assert!(baggage::insert("request_id", 42));
+                tracing::info!("received");
+                async {
+                    tokio::task::yield_now().await;
+                    tracing::info!(request_id = "override", "charged");
+                }
+                .instrument(tracing::info_span!("charge"))
+                .await;
+            }))
+            .await;
+
+        assert_eq!(trees.len(), 2);
+        assert_eq!(field(&trees[0], "request_id"), Some("42"));
+        let charged = match &trees[1].kind {
+            TreeKind::Span(span) => &span.children[0],
+            TreeKind::Event(_) => panic!("expected a span"),
+        };
+        assert_eq!(field(charged, "request_id"), Some("\"override\""));
+        match &charged.kind {
+            TreeKind::Event(event) => assert_eq!(event.fields.len(), 1),
+            TreeKind::Span(_) => panic!("expected an event"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nested_scopes() {
+        assert!(!baggage::insert("request_id", 1));
+
+        baggage::scope(async {
+            baggage::insert("request_id", 1);
+            baggage::scope(async {
+                assert_eq!(baggage::get("request_id").as_deref(), Some("1"));
+                baggage::insert("user", "alice");
+                assert_eq!(baggage::remove("request_id").as_deref(), Some("1"));
+            })
+            .await;
+            assert_eq!(baggage::get("request_id").as_deref(), Some("1"));
+            assert_eq!(baggage::get("user"), None);
+        })
+        .await;
+
+        assert_eq!(baggage::get("request_id"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spawned_task_keeps_baggage() {
+        let trees = tracing_forest::capture()
+            .run_async(baggage::scope(async {
+                baggage::insert("request_id", 7);
+                tracing_forest::spawn_in_tree(async { tracing::info!("refreshing") })
+                    .await
+                    .unwrap();
+            }))
+            .await;
+
+        assert_eq!(trees.len(), 1);
+        assert_eq!(field(&trees[0], "request_id"), Some("7"));
+    }
+
+    #[test]
+    fn test_sync_scope() {
+        let trees = tracing_forest::capture().run(|| {
+            baggage::sync_scope(|| {
+                baggage::insert("job", "cleanup");
+                tracing::info!("started");
+            })
+        });
+
+        assert_eq!(field(&trees[0], "job"), Some("\"cleanup\""));
+    }
+}
+
+#[cfg(feature = "arena")]
+mod arena_tests {
+    use tracing::{info, Level};
+    use tracing_forest::arena;
+    use tracing_forest::layer::Tree;
+
+    #[test]
+    fn test_recycled_strings_are_reused() {
+        let trees = tracing_forest::capture().run(|| info!(a = 1, b = 2, "first"));
+        let recycled = [
+            trees[0].field("a").unwrap().as_ptr(),
+            trees[0].field("b").unwrap().as_ptr(),
+        ];
+        trees.into_iter().for_each(arena::recycle);
+
+        // Recycled buffers go to this thread's arena first, so other tests
+        // can't take them
+        let trees = tracing_forest::capture().run(|| info!(a = 3, "second"));
+        assert!(recycled.contains(&trees[0].field("a").unwrap().as_ptr()));
+    }
+
+    #[test]
+    fn test_shared_arena_is_bounded() {
+        for _ in 0..200 {
+            let mut root = Tree::span(Level::INFO, "request");
+            for idx in 0..15 {
+                root.add_child(Tree::event(Level::INFO, "step").with_field("idx", idx.to_string()));
+            }
+            arena::recycle(root);
+        }
+
+        let pooled = arena::pooled();
+        assert!(pooled.strings <= 2048, "{:?}", pooled);
+        assert!(pooled.children <= 64, "{:?}", pooled);
+    }
 }
@@ -0,0 +1,11 @@
+use tracing_forest::Tag;
+
+#[derive(Tag)]
+enum MyTag {
+    #[tag(error: "request.error")]
+    RequestError,
+    #[tag(warn: "request.error")]
+    RequestWarn,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use tracing_forest::Tag;
+
+#[derive(Tag)]
+enum MyTag {
+    #[tag(custom('🔐'): "security.critical", severity = critical, severity = error)]
+    SecurityCritical,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use tracing_forest::Tag;
+
+#[derive(Tag)]
+enum MyTag {
+    #[tag(info: "request.info")]
+    RequestInfo,
+    RequestDebug,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use tracing_forest::Tag;
+
+#[derive(Tag)]
+enum MyTag {
+    #[tag("request.error")]
+    RequestError,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use tracing_forest::Tag;
+
+#[derive(Tag)]
+enum MyTag {
+    #[tag(custom('🔐'): "security.critical", target = "app::security", level = "fatal")]
+    SecurityCritical,
+}
+
+fn main() {}
@@ -3,7 +3,7 @@ use quote::{quote, ToTokens};
 use syn::parse::Parser;
 type AttributeArgs = syn::punctuated::Punctuated<syn::NestedMeta, syn::Token![,]>;
 
-fn token_stream_to_compile_err(mut tokens: TokenStream, err: syn::Error) -> TokenStream {
+pub(crate) fn token_stream_to_compile_err(mut tokens: TokenStream, err: syn::Error) -> TokenStream {
     tokens.extend(TokenStream::from(err.into_compile_error()));
     tokens
 }
@@ -25,7 +25,7 @@ pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
 
     if let Some(attr) = input.attrs.iter().find(|attr| attr.path.is_ident("test")) {
         let msg = "Second #[test] attribute is supplied";
-        return token_stream_to_compile_err(item, syn::Error::new_spanned(&attr, msg));
+        return token_stream_to_compile_err(item, syn::Error::new_spanned(attr, msg));
     }
 
     impl_attribute(input, args, true).unwrap_or_else(|e| token_stream_to_compile_err(item, e))
@@ -102,6 +102,31 @@ fn impl_async(config: Config, mut input: syn::ItemFn) -> syn::Result<TokenStream
 
     let guard = quote! { ::tracing_forest::private::set_default(#layer.into_subscriber()) };
 
+    let (summarize, report) = summary_tokens(config.summary);
+    let (record, artifact) = artifact_tokens(config.artifact, &input.sig.ident);
+
+    // Trees of an artifact are written as spans close, so they're all in the
+    // buffer if the test panics before the processing thread is awaited
+    let (spawn, join) = if config.artifact {
+        (
+            quote! {
+                let #processor = ::tracing_forest::blocking(#formatter, #make_writer);
+                let handle = ();
+            },
+            quote! {},
+        )
+    } else {
+        (
+            quote! {
+                let (#processor, handle) = ::tracing_forest::async_spawn(#formatter, #make_writer);
+            },
+            quote! {
+                #[allow(clippy::unwrap_used)]
+                __handle.await.unwrap();
+            },
+        )
+    };
+
     let brace_token = input.block.brace_token;
     let inner_ident = quote::format_ident!("{}_inner", input.sig.ident);
     let mut inner = input.clone();
@@ -109,17 +134,20 @@ fn impl_async(config: Config, mut input: syn::ItemFn) -> syn::Result<TokenStream
     inner.sig.ident = inner_ident.clone();
     input.block = syn::parse2(quote! {
         {
-            let (__guard, __handle) = {
-                let (#processor, handle) = ::tracing_forest::async_spawn(#formatter, #make_writer);
-                (#guard, handle)
+            let (__artifact, __guard, __summary, __handle) = {
+                #record
+                #spawn
+                #summarize
+                (#artifact, #guard, #report, handle)
             };
             let result = {
+                let __moved_artifact = __artifact;
                 let __moved_guard = __guard;
+                let __moved_summary = __summary;
                 #inner
                 #inner_ident().await
             };
-            #[allow(clippy::unwrap_used)]
-            __handle.await.unwrap();
+            #join
             result
         }
     })
@@ -139,11 +167,8 @@ fn impl_sync(config: Config, mut input: syn::ItemFn) -> syn::Result<TokenStream>
         quote! {}
     };
 
-    let mut layer = quote! {
-        ::tracing_forest::TreeLayer::new(
-            ::tracing_forest::blocking(#formatter, #make_writer)
-        )
-    };
+    let processor = quote::format_ident!("processor");
+    let mut layer = quote! { ::tracing_forest::TreeLayer::new(#processor) };
 
     if let Some(tag) = config.tag {
         layer = quote! { #layer.tag::<#tag>() };
@@ -151,12 +176,20 @@ fn impl_sync(config: Config, mut input: syn::ItemFn) -> syn::Result<TokenStream>
 
     let guard = quote! { ::tracing_forest::private::set_default(#layer.into_subscriber()) };
 
+    let (summarize, report) = summary_tokens(config.summary);
+    let (record, artifact) = artifact_tokens(config.artifact, &input.sig.ident);
+
     let brace_token = input.block.brace_token;
     let block = &input.block;
 
     input.block = syn::parse2(quote! {
         {
-            let __guard = #guard;
+            let (__artifact, __guard, __summary) = {
+                #record
+                let #processor = ::tracing_forest::blocking(#formatter, #make_writer);
+                #summarize
+                (#artifact, #guard, #report)
+            };
             #block
         }
     })?;
@@ -170,6 +203,52 @@ fn impl_sync(config: Config, mut input: syn::ItemFn) -> syn::Result<TokenStream>
     .into())
 }
 
+/// Returns the statements that wrap `processor` to summarize the run, and
+/// the expression for the guard that reports the summary when dropped.
+fn summary_tokens(summary: bool) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    if summary {
+        (
+            quote! {
+                let (processor, summary) = ::tracing_forest::Processor::summarize(processor);
+            },
+            quote! { ::tracing_forest::private::ReportOnDrop(::core::option::Option::Some(summary)) },
+        )
+    } else {
+        (
+            quote! {},
+            quote! { ::tracing_forest::private::ReportOnDrop(::core::option::Option::None) },
+        )
+    }
+}
+
+/// Returns the statements that create the artifact of a failed test, and the
+/// expression for the guard that writes it when dropped while panicking.
+///
+/// The guard is bound before the subscriber's guard, so it's dropped after
+/// every span of the test has closed.
+fn artifact_tokens(
+    artifact: bool,
+    name: &syn::Ident,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    if artifact {
+        (
+            quote! {
+                let artifact = ::tracing_forest::private::Artifact::new(::core::concat!(
+                    ::core::module_path!(),
+                    "::",
+                    ::core::stringify!(#name),
+                ));
+            },
+            quote! { ::core::option::Option::Some(artifact) },
+        )
+    } else {
+        (
+            quote! {},
+            quote! { ::core::option::Option::<::tracing_forest::private::Artifact>::None },
+        )
+    }
+}
+
 enum Formatter {
     Json,
     Pretty,
@@ -178,6 +257,7 @@ enum Formatter {
 enum MakeWriter {
     TestWriter,
     Stdout,
+    Artifact,
     // add more variants later...
 }
 
@@ -195,6 +275,7 @@ impl ToTokens for MakeWriter {
         tokens.extend(match self {
             MakeWriter::TestWriter => quote! { ::tracing_forest::private::TestWriter::new() },
             MakeWriter::Stdout => quote! { ::std::io::stdout },
+            MakeWriter::Artifact => quote! { artifact.writer() },
         })
     }
 }
@@ -202,6 +283,8 @@ impl ToTokens for MakeWriter {
 struct ConfigBuilder {
     formatter: Option<Formatter>,
     tag: Option<proc_macro2::Ident>,
+    summary: bool,
+    artifact: bool,
     is_test: bool,
 }
 
@@ -210,6 +293,8 @@ impl ConfigBuilder {
         ConfigBuilder {
             formatter: None,
             tag: None,
+            summary: false,
+            artifact: false,
             is_test,
         }
     }
@@ -259,8 +344,44 @@ impl ConfigBuilder {
         }
     }
 
+    fn set_summary(&mut self, path: &syn::Path) -> syn::Result<()> {
+        if self.is_test {
+            Err(syn::Error::new_spanned(
+                path,
+                "Argument `summary` is only supported by #[tracing_forest::main]",
+            ))
+        } else if self.summary {
+            Err(syn::Error::new_spanned(
+                path,
+                "Argument `summary` is defined multiple times",
+            ))
+        } else {
+            self.summary = true;
+            Ok(())
+        }
+    }
+
+    fn set_artifact(&mut self, path: &syn::Path) -> syn::Result<()> {
+        if !self.is_test {
+            Err(syn::Error::new_spanned(
+                path,
+                "Argument `artifact` is only supported by #[tracing_forest::test]",
+            ))
+        } else if self.artifact {
+            Err(syn::Error::new_spanned(
+                path,
+                "Argument `artifact` is defined multiple times",
+            ))
+        } else {
+            self.artifact = true;
+            Ok(())
+        }
+    }
+
     fn finish(self) -> Config {
-        let make_writer = if self.is_test {
+        let make_writer = if self.artifact {
+            MakeWriter::Artifact
+        } else if self.is_test {
             MakeWriter::TestWriter
         } else {
             MakeWriter::Stdout
@@ -270,6 +391,8 @@ impl ConfigBuilder {
             formatter: self.formatter.unwrap_or(Formatter::Pretty),
             make_writer,
             tag: self.tag,
+            summary: self.summary,
+            artifact: self.artifact,
             is_test: self.is_test,
         }
     }
@@ -279,6 +402,8 @@ struct Config {
     formatter: Formatter,
     make_writer: MakeWriter,
     tag: Option<proc_macro2::Ident>,
+    summary: bool,
+    artifact: bool,
     is_test: bool,
 }
 
@@ -308,6 +433,12 @@ fn build_config(args: AttributeArgs, is_test: bool) -> syn::Result<Config> {
                     }
                 }
             }
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("summary") => {
+                builder.set_summary(&path)?
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("artifact") => {
+                builder.set_artifact(&path)?
+            }
             other => {
                 return Err(syn::Error::new_spanned(
                     other,
@@ -24,30 +24,148 @@ mod kw {
     syn::custom_keyword!(warn);
     syn::custom_keyword!(error);
     syn::custom_keyword!(custom);
+    syn::custom_keyword!(severity);
+    syn::custom_keyword!(target);
+    syn::custom_keyword!(level);
 }
 
 struct TagRepr {
     icon: Icon,
     _colon: syn::Token![:],
     message: syn::LitStr,
+    severity: Option<syn::Ident>,
+    target: Option<syn::LitStr>,
+    level: Option<syn::LitStr>,
 }
 
 impl Parse for TagRepr {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::LitStr) || input.peek(syn::Token![:]) {
+            return Err(input.error(
+                "missing icon, expected `trace`, `debug`, `info`, `warn`, `error`, or `custom('...')` before the message",
+            ));
+        }
+
+        let icon = input.parse()?;
+        let _colon = input.parse()?;
+        let message = input.parse()?;
+
+        let mut severity: Option<syn::Ident> = None;
+        let mut target: Option<syn::LitStr> = None;
+        let mut level: Option<syn::LitStr> = None;
+        while !input.is_empty() {
+            input.parse::<syn::Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            if input.peek(kw::target) {
+                let keyword = input.parse::<kw::target>()?;
+                input.parse::<syn::Token![=]>()?;
+                let value = input.parse::<syn::LitStr>()?;
+                if target.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        keyword,
+                        "`target` is specified multiple times",
+                    ));
+                }
+                target = Some(value);
+                continue;
+            }
+            if input.peek(kw::level) {
+                let keyword = input.parse::<kw::level>()?;
+                input.parse::<syn::Token![=]>()?;
+                let value = input.parse::<syn::LitStr>()?;
+                if level.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        keyword,
+                        "`level` is specified multiple times",
+                    ));
+                }
+                match value.value().as_str() {
+                    "trace" | "debug" | "info" | "warn" | "error" => {}
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "level must be one of `\"trace\"`, `\"debug\"`, `\"info\"`, `\"warn\"`, or `\"error\"`",
+                        ))
+                    }
+                }
+                level = Some(value);
+                continue;
+            }
+            let keyword = input.parse::<kw::severity>().map_err(|err| {
+                syn::Error::new(err.span(), "expected `severity`, `target`, or `level`")
+            })?;
+            input.parse::<syn::Token![=]>()?;
+            let value = input.parse::<syn::Ident>()?;
+            if severity.is_some() {
+                return Err(syn::Error::new_spanned(
+                    keyword,
+                    "`severity` is specified multiple times",
+                ));
+            }
+            match value.to_string().as_str() {
+                "trace" | "debug" | "info" | "warn" | "error" | "critical" => {}
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        value,
+                        "severity must be one of `trace`, `debug`, `info`, `warn`, `error`, or `critical`",
+                    ))
+                }
+            }
+            severity = Some(value);
+        }
+
         Ok(TagRepr {
-            icon: input.parse()?,
-            _colon: input.parse()?,
-            message: input.parse()?,
+            icon,
+            _colon,
+            message,
+            severity,
+            target,
+            level,
         })
     }
 }
 
+impl TagRepr {
+    /// Returns the condition for an event's metadata to match this tag, or
+    /// `None` if it has no matchers.
+    fn matcher(&self) -> Option<TokenStream2> {
+        if self.target.is_none() && self.level.is_none() {
+            return None;
+        }
+        let target = self.target.as_ref().map(|target| {
+            quote! { ::tracing_forest::private::target_matches(metadata.target(), #target) }
+        });
+        let level = self.level.as_ref().map(|level| {
+            let level = proc_macro2::Ident::new(&level.value().to_uppercase(), level.span());
+            quote! { *metadata.level() == ::tracing_forest::private::Level::#level }
+        });
+        let conditions = target.into_iter().chain(level);
+        Some(quote! { #( #conditions )&&* })
+    }
+}
+
 impl ToTokens for TagRepr {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
         let message = &self.message;
         let icon = self.icon.value();
-        (quote! { ::tracing_forest::private::TagData { message: #message, icon: #icon } })
-            .to_tokens(tokens)
+        let severity = match &self.severity {
+            Some(severity) => {
+                let mut name = severity.to_string();
+                name[..1].make_ascii_uppercase();
+                proc_macro2::Ident::new(&name, severity.span())
+            }
+            None => self.icon.default_severity(),
+        };
+        (quote! {
+            ::tracing_forest::private::TagData {
+                message: #message,
+                icon: #icon,
+                severity: ::tracing_forest::tag::Severity::#severity,
+            }
+        })
+        .to_tokens(tokens)
     }
 }
 
@@ -85,6 +203,17 @@ impl Icon {
             Icon::Custom { icon, .. } => quote! { #icon },
         }
     }
+
+    fn default_severity(&self) -> proc_macro2::Ident {
+        let name = match self {
+            Icon::Trace { .. } => "Trace",
+            Icon::Debug { .. } => "Debug",
+            Icon::Info { .. } | Icon::Custom { .. } => "Info",
+            Icon::Warn { .. } => "Warn",
+            Icon::Error { .. } => "Error",
+        };
+        proc_macro2::Ident::new(name, proc_macro2::Span::call_site())
+    }
 }
 
 impl Parse for Icon {
@@ -111,11 +240,37 @@ impl Parse for Icon {
             }
         } else if input.peek(kw::custom) {
             let content;
+            let _custom = input.parse::<kw::custom>()?;
+            let _paren = syn::parenthesized!(content in input);
+            let icon = match content.parse::<syn::Lit>() {
+                Ok(syn::Lit::Char(icon)) => icon,
+                Ok(syn::Lit::Str(icon)) if icon.value().chars().count() == 1 => {
+                    let msg = format!(
+                        "custom icon must be a char literal, like `custom('{}')`",
+                        icon.value()
+                    );
+                    return Err(syn::Error::new_spanned(icon, msg));
+                }
+                Ok(other) => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "custom icon must be a char literal, like `custom('🔐')`",
+                    ))
+                }
+                Err(_) => return Err(content.error("expected a char literal, like `custom('🔐')`")),
+            };
             Icon::Custom {
-                _custom: input.parse::<kw::custom>()?,
-                _paren: syn::parenthesized!(content in input),
-                icon: content.parse::<syn::LitChar>()?,
+                _custom,
+                _paren,
+                icon,
             }
+        } else if input.peek(syn::Ident) {
+            let ident = input.parse::<syn::Ident>()?;
+            let msg = format!(
+                "unknown icon `{}`, expected `trace`, `debug`, `info`, `warn`, `error`, or `custom('...')`",
+                ident
+            );
+            return Err(syn::Error::new_spanned(ident, msg));
         } else {
             return Err(input
                 .error("must begin with `trace`, `debug`, `info`, `warn`, `error`, or `custom`"));
@@ -159,8 +314,9 @@ fn impl_struct(data: &syn::DataStruct, input: &syn::DeriveInput) -> syn::Result<
 
     let into_arms = quote! { _ => 0, };
     let from_arms = quote! { 0 => #tag, };
+    let matchers = matchers(std::slice::from_ref(&tag), [quote! { Self }]);
 
-    Ok(impl_trait(&input.ident, into_arms, from_arms))
+    Ok(impl_trait(&input.ident, into_arms, from_arms, matchers))
 }
 
 fn impl_enum(data: &syn::DataEnum, input: &syn::DeriveInput) -> syn::Result<TokenStream2> {
@@ -170,6 +326,18 @@ fn impl_enum(data: &syn::DataEnum, input: &syn::DeriveInput) -> syn::Result<Toke
         .map(|variant| parse_tag_attr(variant, &variant.fields, &variant.attrs))
         .collect::<syn::Result<Vec<TagRepr>>>()?;
 
+    for (idx, tag) in tags.iter().enumerate() {
+        let message = tag.message.value();
+        if let Some(first) = tags[..idx]
+            .iter()
+            .position(|t| t.message.value() == message)
+        {
+            let variant = &data.variants[first].ident;
+            let msg = format!("tag message `{}` is already used by `{}`", message, variant);
+            return Err(syn::Error::new_spanned(&tag.message, msg));
+        }
+    }
+
     let len = data.variants.len();
     let variant_names = data.variants.iter().map(|v| &v.ident);
     let ids = 0..len as u64;
@@ -177,14 +345,38 @@ fn impl_enum(data: &syn::DataEnum, input: &syn::DeriveInput) -> syn::Result<Toke
 
     let ids = 0..len as u64;
     let from_arms = quote! { #( #ids => #tags, )* };
+    let variant_names = data.variants.iter().map(|v| &v.ident);
+    let matchers = matchers(&tags, variant_names.map(|name| quote! { Self::#name }));
 
-    Ok(impl_trait(&input.ident, into_arms, from_arms))
+    Ok(impl_trait(&input.ident, into_arms, from_arms, matchers))
+}
+
+/// Generates the checks of `Tag::from_event`, in declaration order so that
+/// the first matching tag wins.
+///
+/// Matching tags are resolved through their values, so variants that are only
+/// matched and never written in code aren't reported as dead code.
+fn matchers(tags: &[TagRepr], values: impl IntoIterator<Item = TokenStream2>) -> TokenStream2 {
+    let checks = tags.iter().zip(values).filter_map(|(tag, value)| {
+        let matcher = tag.matcher()?;
+        Some(quote! {
+            if #matcher {
+                return ::core::option::Option::Some(
+                    <Self as ::tracing_forest::Tag>::from_field(
+                        ::tracing_forest::Tag::as_field(&#value),
+                    ),
+                );
+            }
+        })
+    });
+    quote! { #( #checks )* }
 }
 
 fn impl_trait(
     name: &proc_macro2::Ident,
     into_arms: TokenStream2,
     from_arms: TokenStream2,
+    matchers: TokenStream2,
 ) -> TokenStream2 {
     quote! {
         unsafe impl ::tracing_forest::Tag for #name {
@@ -200,6 +392,15 @@ fn impl_trait(
                     _ => ::tracing_forest::private::unrecognized_tag_id(value),
                 }
             }
+
+            #[allow(unused_variables)]
+            fn from_event(
+                event: &::tracing_forest::private::Event<'_>,
+            ) -> ::core::option::Option<::tracing_forest::private::TagData> {
+                let metadata = event.metadata();
+                #matchers
+                ::core::option::Option::None
+            }
         }
     }
 }
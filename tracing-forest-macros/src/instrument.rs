@@ -0,0 +1,122 @@
+use crate::attribute::token_stream_to_compile_err;
+use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::spanned::Spanned;
+
+pub fn instrument(args: TokenStream, item: TokenStream) -> TokenStream {
+    impl_instrument(args.into(), item.clone().into())
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| token_stream_to_compile_err(item, e))
+}
+
+fn impl_instrument(args: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    let mut forwarded = Vec::new();
+    let mut fields = Vec::new();
+    let mut forest_fields = Vec::new();
+    let mut seen = Vec::new();
+
+    for arg in split_commas(args) {
+        let name = match arg.clone().into_iter().next() {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => String::new(),
+        };
+
+        match name.as_str() {
+            "tag" | "uuid" | "skip_tree" => {}
+            "fields" => match arg.clone().into_iter().nth(1) {
+                Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                    fields.extend(split_commas(group.stream()));
+                    continue;
+                }
+                _ => {
+                    forwarded.push(arg);
+                    continue;
+                }
+            },
+            _ => {
+                forwarded.push(arg);
+                continue;
+            }
+        }
+
+        if seen.contains(&name) {
+            let msg = format!("Argument `{}` is defined multiple times", name);
+            return Err(syn::Error::new(arg.span(), msg));
+        }
+
+        match name.as_str() {
+            "tag" => {
+                let lit: syn::LitStr = syn::parse2(value(&arg, "tag = \"MyTag::Variant\"")?)?;
+                let path: syn::Path = lit.parse()?;
+                forest_fields.push(quote! {
+                    __event_tag = ::tracing_forest::Tag::as_field(&#path)
+                });
+            }
+            "uuid" => {
+                let expr: syn::Expr = syn::parse2(value(&arg, "uuid = <expression>")?)?;
+                // A single field, so the expression is only evaluated once
+                forest_fields.push(quote! {
+                    __uuid = ::tracing_forest::private::into_u128(&(#expr))
+                });
+            }
+            _ => {
+                if arg.clone().into_iter().nth(1).is_some() {
+                    return Err(syn::Error::new(
+                        arg.span(),
+                        "Argument `skip_tree` doesn't take a value",
+                    ));
+                }
+                forest_fields.push(quote! { __forest_skip = true });
+            }
+        }
+        seen.push(name);
+    }
+
+    fields.extend(forest_fields);
+    if !fields.is_empty() {
+        forwarded.push(quote! { fields(#(#fields),*) });
+    }
+
+    Ok(quote! {
+        #[::tracing_forest::private::instrument(#(#forwarded),*)]
+        #item
+    })
+}
+
+/// Splits a token stream at its top-level commas, skipping empty segments.
+fn split_commas(tokens: TokenStream2) -> Vec<TokenStream2> {
+    let mut segments = vec![TokenStream2::new()];
+    for token in tokens {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                segments.push(TokenStream2::new());
+            }
+            _ => segments
+                .last_mut()
+                .expect("there is always a segment")
+                .extend(Some(token)),
+        }
+    }
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+/// Returns the tokens after the `=` in a `name = value` argument.
+fn value(arg: &TokenStream2, expected: &str) -> syn::Result<TokenStream2> {
+    let mut tokens = arg.clone().into_iter().skip(1);
+    match tokens.next() {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {}
+        _ => {
+            let msg = format!("Expected an argument of the form `{}`", expected);
+            return Err(syn::Error::new(arg.span(), msg));
+        }
+    }
+
+    let value = tokens.collect::<TokenStream2>();
+    if value.is_empty() {
+        let msg = format!("Expected an argument of the form `{}`", expected);
+        return Err(syn::Error::new(arg.span(), msg));
+    }
+    Ok(value)
+}
@@ -6,6 +6,8 @@ use proc_macro::TokenStream;
 mod attribute;
 #[cfg(feature = "derive")]
 mod derive;
+#[cfg(feature = "attributes")]
+mod instrument;
 
 #[cfg(feature = "derive")]
 #[proc_macro_derive(Tag, attributes(tag))]
@@ -13,14 +15,43 @@ pub fn tag(input: TokenStream) -> TokenStream {
     derive::tag(input)
 }
 
+/// Run a test with a `TreeLayer` that writes trees to the test's output.
+///
+/// The subscriber is only the default for the thread running the test, and
+/// is removed when the test returns, so tests using this attribute can run
+/// concurrently in one process with each other and with tests that install
+/// their own subscribers, like `tracing_forest::capture()`. Nothing is
+/// installed globally.
+///
+/// For async tests, tasks that are spawned onto other threads of a
+/// multi-threaded runtime don't inherit the default subscriber. Use
+/// `tracing::instrument::WithSubscriber::with_current_subscriber` to send
+/// their trees to the test.
+///
+/// With the `artifact` argument, trees are buffered instead of printed, and
+/// written to `target/forest/<test path>.log` only if the test fails.
 #[cfg(feature = "attributes")]
 #[proc_macro_attribute]
 pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
     attribute::test(args, item)
 }
 
+/// Run `main` with a `TreeLayer` that writes trees to stdout.
+///
+/// Like `#[tracing_forest::test]`, the subscriber is the default for the
+/// thread running `main` rather than the global default.
+///
+/// With the `summary` argument, a summary of the run is written when `main`
+/// returns.
 #[cfg(feature = "attributes")]
 #[proc_macro_attribute]
 pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     attribute::main(args, item)
 }
+
+/// Instrument a function with a span. See `tracing_forest::instrument`.
+#[cfg(feature = "attributes")]
+#[proc_macro_attribute]
+pub fn instrument(args: TokenStream, item: TokenStream) -> TokenStream {
+    instrument::instrument(args, item)
+}